@@ -0,0 +1,46 @@
+//! Measures how long it takes to create a [`WasmRunner`], both from scratch and from an
+//! already-compiled [`TexEngine`].
+//!
+//! Run with `cargo bench --bench runner_startup`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rust_tikz::{TexEngine, WasmRunner};
+
+const ITERATIONS: u32 = 20;
+
+fn time_per_iteration(mut f: impl FnMut()) -> Duration {
+    // Warm up so that one-time work (e.g., indexing the TeX file archive) is not measured.
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let compile = time_per_iteration(|| {
+        black_box(TexEngine::new().unwrap());
+    });
+    println!("TexEngine::new                 {compile:>12.2?} per engine");
+
+    let from_scratch = time_per_iteration(|| {
+        black_box(WasmRunner::new().unwrap());
+    });
+    println!("WasmRunner::new                {from_scratch:>12.2?} per runner");
+
+    let tex_engine = TexEngine::new().unwrap();
+    let from_engine = time_per_iteration(|| {
+        black_box(tex_engine.new_runner().unwrap());
+    });
+    println!("TexEngine::new_runner          {from_engine:>12.2?} per runner");
+
+    let mut config = wasmi::Config::default();
+    config.compilation_mode(wasmi::CompilationMode::Lazy);
+    let lazy_compile = time_per_iteration(|| {
+        black_box(TexEngine::with_config(&config).unwrap());
+    });
+    println!("TexEngine::with_config (lazy)  {lazy_compile:>12.2?} per engine");
+}
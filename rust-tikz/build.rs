@@ -0,0 +1,94 @@
+//! Build script that converts the bundled `tex_files.tar.gz` into an indexed archive.
+//! Files in `src/assets/tex` are added to the archive as well, replacing files of the same name
+//! in the tarball, so that files maintained with the crate do not have to be repacked.
+//!
+//! A gzipped tarball can only be read front to back, so every file would have to be inflated
+//! just to find the few that a given document needs. Instead, each file is compressed
+//! individually and written to `$OUT_DIR/tex_files.idx`, which can be indexed without
+//! decompressing anything. The format is
+//!
+//! ```text
+//! magic "RTZA"
+//! u32 number of entries
+//! for each entry:
+//!     u16 length of the file name, followed by the UTF-8 file name
+//!     u32 uncompressed length
+//!     u32 compressed length, followed by the raw DEFLATE stream
+//! ```
+//!
+//! All integers are little endian.
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::DeflateEncoder;
+use tar::Archive;
+
+const TEX_FILES: &str = "src/assets/tex_files.tar.gz";
+const EXTRA_TEX_FILES: &str = "src/assets/tex";
+
+fn main() {
+    println!("cargo::rerun-if-changed={TEX_FILES}");
+    println!("cargo::rerun-if-changed={EXTRA_TEX_FILES}");
+
+    let bytes = fs::read(TEX_FILES).expect("Failed to read the bundled TeX files");
+    let mut archive = Archive::new(GzDecoder::new(bytes.as_slice()));
+
+    let mut entries = Vec::new();
+    for entry in archive.entries().expect("Failed to read the TeX file archive") {
+        let mut entry = entry.expect("Failed to read an entry of the TeX file archive");
+        let file_name = entry
+            .path()
+            .expect("Archive entry has an invalid path")
+            .to_string_lossy()
+            .into_owned();
+        // Trim off a leading "./"
+        let file_name = file_name.trim_start_matches("./").to_string();
+        if file_name.is_empty() || !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let mut file_data = Vec::new();
+        entry
+            .read_to_end(&mut file_data)
+            .expect("Failed to read the contents of an archive entry");
+        entries.push((file_name, file_data));
+    }
+    for entry in fs::read_dir(EXTRA_TEX_FILES).expect("Failed to read the extra TeX files") {
+        let path = entry.expect("Failed to read an extra TeX file").path();
+        let file_name = path
+            .file_name()
+            .expect("Extra TeX file has no name")
+            .to_string_lossy()
+            .into_owned();
+        let file_data = fs::read(&path).expect("Failed to read an extra TeX file");
+        entries.retain(|(name, _)| *name != file_name);
+        entries.push((file_name, file_data));
+    }
+    // Sort the entries so that the generated index does not depend on the order of the tarball.
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut index = Vec::new();
+    index.extend_from_slice(b"RTZA");
+    index.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for (file_name, file_data) in entries {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&file_data)
+            .expect("Failed to compress an archive entry");
+        let compressed = encoder.finish().expect("Failed to compress an archive entry");
+
+        index.extend_from_slice(&(file_name.len() as u16).to_le_bytes());
+        index.extend_from_slice(file_name.as_bytes());
+        index.extend_from_slice(&(file_data.len() as u32).to_le_bytes());
+        index.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        index.extend_from_slice(&compressed);
+    }
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is not set");
+    fs::write(Path::new(&out_dir).join("tex_files.idx"), index)
+        .expect("Failed to write the indexed TeX file archive");
+}
@@ -0,0 +1,194 @@
+//! An indexed archive of the TeX files bundled with the crate. Files are only decompressed
+//! the first time they are requested and the decompressed bytes are shared by every
+//! [`crate::WasmRunner`] in the process.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Error, Result};
+use flate2::read::DeflateDecoder;
+
+/// The indexed archive produced by `build.rs` from `assets/tex_files.tar.gz`.
+const TEX_FILE_INDEX: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/tex_files.idx"));
+
+static BUNDLED_ARCHIVE: OnceLock<Arc<TexArchive>> = OnceLock::new();
+
+/// A read-only collection of files where each file is compressed individually.
+#[derive(Debug)]
+pub(crate) struct TexArchive {
+    entries: HashMap<&'static str, ArchiveEntry>,
+}
+
+#[derive(Debug)]
+struct ArchiveEntry {
+    /// The raw DEFLATE stream for the file.
+    compressed: &'static [u8],
+    /// The size of the file once it is decompressed.
+    size: usize,
+    /// The decompressed contents. This is populated the first time the file is requested.
+    contents: OnceLock<Arc<[u8]>>,
+}
+
+impl TexArchive {
+    /// The archive of TeX files that ships with the crate. The index is only built once;
+    /// subsequent calls return a handle to the same archive.
+    pub(crate) fn bundled() -> Arc<TexArchive> {
+        BUNDLED_ARCHIVE
+            .get_or_init(|| {
+                Arc::new(
+                    TexArchive::parse(TEX_FILE_INDEX)
+                        .expect("The bundled TeX file index is malformed"),
+                )
+            })
+            .clone()
+    }
+
+    /// Build an index over an archive in the format written by `build.rs`. No file
+    /// contents are decompressed.
+    pub(crate) fn parse(bytes: &'static [u8]) -> Result<Self> {
+        let mut reader = IndexReader { bytes };
+        if reader.take(4)? != b"RTZA" {
+            return Err(Error::msg("TeX file index has an invalid header"));
+        }
+        let count = reader.read_u32()? as usize;
+        let mut entries = HashMap::with_capacity(count);
+        for _ in 0..count {
+            let name_len = reader.read_u16()? as usize;
+            let name = std::str::from_utf8(reader.take(name_len)?)?;
+            let size = reader.read_u32()? as usize;
+            let compressed_len = reader.read_u32()? as usize;
+            let compressed = reader.take(compressed_len)?;
+            entries.insert(
+                name,
+                ArchiveEntry {
+                    compressed,
+                    size,
+                    contents: OnceLock::new(),
+                },
+            );
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Build an archive of `files`, in the format written by `build.rs`.
+    #[cfg(test)]
+    pub(crate) fn from_files(files: &[(&str, &[u8])]) -> TexArchive {
+        TexArchive::parse(write_index(files).leak()).unwrap()
+    }
+
+    /// Returns whether the archive contains a file called `name`.
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Get the contents of the file `name`, decompressing it if this is the first time
+    /// it has been requested.
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<[u8]>> {
+        let entry = self.entries.get(name)?;
+        let contents = entry.contents.get_or_init(|| {
+            let mut decoder = DeflateDecoder::new(entry.compressed);
+            let mut data = Vec::with_capacity(entry.size);
+            decoder
+                .read_to_end(&mut data)
+                .expect("The bundled TeX files are corrupt");
+            data.into()
+        });
+        Some(contents.clone())
+    }
+}
+
+/// Write an index of `files` in the format of `build.rs`.
+#[cfg(test)]
+fn write_index(files: &[(&str, &[u8])]) -> Vec<u8> {
+    use flate2::Compression;
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+
+    let mut index = b"RTZA".to_vec();
+    index.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    for (name, data) in files {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        index.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        index.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        index.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        index.extend_from_slice(&compressed);
+    }
+    index
+}
+
+/// A cursor over the bytes of a TeX file index.
+struct IndexReader {
+    bytes: &'static [u8],
+}
+
+impl IndexReader {
+    fn take(&mut self, len: usize) -> Result<&'static [u8]> {
+        if len > self.bytes.len() {
+            return Err(Error::msg("TeX file index is truncated"));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let mut buf = [0u8; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILES: [(&str, &[u8]); 2] = [("a.tex", b"\\relax"), ("empty.tex", b"")];
+
+    #[test]
+    fn files_are_decompressed_when_they_are_requested() {
+        let archive = TexArchive::from_files(&FILES);
+        assert!(archive.contains("a.tex") && archive.contains("empty.tex"));
+        assert!(!archive.contains("b.tex"));
+        assert!(archive.entries["a.tex"].contents.get().is_none());
+        assert_eq!(archive.get("a.tex").as_deref(), Some(&b"\\relax"[..]));
+        assert!(archive.entries["a.tex"].contents.get().is_some());
+        assert!(archive.entries["empty.tex"].contents.get().is_none());
+        assert_eq!(archive.get("empty.tex").as_deref(), Some(&b""[..]));
+        assert_eq!(archive.get("b.tex"), None);
+    }
+
+    #[test]
+    fn indexes_without_the_magic_number_are_errors() {
+        for magic in [b"RTZB", b"rtza", b"\0\0\0\0"] {
+            let mut index = write_index(&FILES);
+            index[..4].copy_from_slice(magic);
+            let error = TexArchive::parse(index.leak()).unwrap_err();
+            assert_eq!(error.to_string(), "TeX file index has an invalid header");
+        }
+    }
+
+    #[test]
+    fn truncated_indexes_are_errors() {
+        let index = write_index(&FILES);
+        // Every shorter index ends in the magic number, the count, a name, a size or a stream.
+        for len in 0..index.len() {
+            let error = TexArchive::parse(index[..len].to_vec().leak()).unwrap_err();
+            assert_eq!(error.to_string(), "TeX file index is truncated", "{len}");
+        }
+        // A count that is larger than the number of entries.
+        let mut index = index;
+        index[4..8].copy_from_slice(&3u32.to_le_bytes());
+        assert!(TexArchive::parse(index.leak()).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Rendering that can be awaited. TeX is run in short slices and control is handed back to the
+//! executor between slices, so a long render does not block an executor thread. Nothing here
+//! depends on a particular async runtime.
+//!
+//! A slice is measured in system calls (reading and writing files, the terminal and the
+//! clock), not in executed instructions, so it is only a coarse measure of time: TeX makes
+//! system calls often while it reads its input and writes the DVI file and the log, but it may
+//! compute for a while between them, e.g. while TikZ does arithmetic. `wasmi` fuel would count
+//! instructions, but in the `wasmi` version we use, running out of fuel is a trap that ends the
+//! run rather than a pause that can be resumed, and metering fuel would slow down every render.
+//! System calls are the only points where the host can pause TeX and resume it later.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+
+use crate::wasm_runner::RunSlice;
+use crate::{RenderOptions, RenderOutput, Renderer};
+
+/// The number of system calls TeX makes before yielding to the executor. See the module
+/// documentation for why this is not a number of instructions.
+const SLICE_LEN: u32 = 5_000;
+
+impl Renderer {
+    /// Render the TeX document `input`. This behaves like [`Renderer::render`], but it
+    /// periodically yields to the executor while TeX is running. TeX yields every few thousand
+    /// system calls, so how long it runs between yields depends on the document.
+    ///
+    /// Dropping the returned future cancels the render. The runner that was being used is
+    /// discarded rather than returned to the pool.
+    pub async fn render_async(&self, input: &str, options: &RenderOptions) -> Result<RenderOutput> {
+        let mut runner = self.get_runner(options)?;
+        runner.set_input(input.as_bytes());
+
+        let mut slice = runner.start_run(SLICE_LEN);
+        while let Ok(RunSlice::Paused(paused)) = slice {
+            YieldNow { yielded: false }.await;
+            slice = runner.resume_run(paused);
+        }
+        let result = slice.and_then(|_| runner.run_to_dvi());
+
+        self.finish_render(runner, result, input, options)
+    }
+}
+
+/// A future that is pending the first time it is polled. This gives the executor a chance
+/// to run other tasks before it is polled again.
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The future of `render_async` has to be `Send` so that it can be spawned on
+    /// multi-threaded executors. The future is only built, never polled.
+    #[test]
+    fn render_async_future_is_send() {
+        fn assert_send<T: Send>(_: T) {}
+        let renderer = Renderer::new().unwrap();
+        assert_send(renderer.render_async("", &RenderOptions::default()));
+    }
+}
@@ -0,0 +1,157 @@
+//! A display list is a flat list of everything drawn on a page, for consumers that want to lay
+//! out or inspect the output themselves rather than parse SVG or PDF. With the `json` feature
+//! the types can be serialized and deserialized with `serde`.
+//!
+//! Coordinates are in TeX points, with `x` increasing to the right and `y` increasing
+//! downwards. The origin is TeX's reference point, which is one inch from the top left corner
+//! of the page, so the page itself starts at `(-72, -72)`.
+
+pub use crate::dvi2svg::raw_svg::PathSegment;
+
+/// A color as red, green and blue components between 0 and 1.
+pub type Color = [f64; 3];
+
+/// Everything drawn on a page.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisplayList {
+    /// The bounds of the page.
+    pub bbox: BoundingBox,
+    /// The items in the order they are drawn.
+    pub items: Vec<DisplayItem>,
+}
+
+/// A rectangle with its top left corner at `(x, y)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoundingBox {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A single thing that is drawn.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(tag = "type", rename_all = "snake_case"))]
+pub enum DisplayItem {
+    /// A character from a TeX font.
+    Glyph {
+        /// The name of the TeX font, e.g. `cmr10`.
+        font: String,
+        /// The character code in the font's encoding.
+        code: u32,
+        /// The Unicode character the code stands for.
+        unicode: char,
+        /// The position of the glyph's reference point, which is on the baseline.
+        x: f64,
+        y: f64,
+        /// The font size in points.
+        size: f64,
+        color: Color,
+    },
+    /// A filled rectangle with its top left corner at `(x, y)`.
+    Rule {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        color: Color,
+    },
+    /// A vector path drawn by TikZ. A path may be filled, stroked, both or neither.
+    Path {
+        segments: Vec<PathSegment>,
+        fill: Option<Color>,
+        stroke: Option<Color>,
+        stroke_width: f64,
+        /// Whether the path is filled with the even-odd rule rather than the nonzero rule.
+        even_odd: bool,
+    },
+    /// The items up to the matching [`DisplayItem::EndClip`] are clipped to `segments`, as well
+    /// as to the clip paths that are already in effect.
+    BeginClip {
+        segments: Vec<PathSegment>,
+        /// Whether the inside of the path is given by the even-odd rule rather than the nonzero
+        /// rule.
+        even_odd: bool,
+    },
+    /// The end of the innermost clip path that is in effect.
+    EndClip,
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_are_tagged_with_their_type_and_read_back() {
+        let display_list = DisplayList {
+            bbox: BoundingBox {
+                x: -72.0,
+                y: -72.0,
+                width: 200.0,
+                height: 100.0,
+            },
+            items: vec![
+                DisplayItem::Glyph {
+                    font: "cmr10".to_string(),
+                    code: 0x0B,
+                    unicode: '\u{FB00}',
+                    x: 1.5,
+                    y: 2.0,
+                    size: 10.0,
+                    color: [0.0, 0.0, 0.0],
+                },
+                DisplayItem::Rule {
+                    x: 0.0,
+                    y: -5.0,
+                    width: 10.0,
+                    height: 5.0,
+                    color: [1.0, 0.0, 0.0],
+                },
+                DisplayItem::BeginClip {
+                    segments: vec![
+                        PathSegment::MoveTo(0.0, 0.0),
+                        PathSegment::LineTo(10.0, 0.0),
+                        PathSegment::LineTo(10.0, 10.0),
+                        PathSegment::Close,
+                    ],
+                    even_odd: true,
+                },
+                DisplayItem::Path {
+                    segments: vec![
+                        PathSegment::MoveTo(0.0, 0.0),
+                        PathSegment::CurveTo(1.0, 2.0, 3.0, 4.0, 5.0, 6.0),
+                    ],
+                    fill: None,
+                    stroke: Some([0.0, 0.0, 1.0]),
+                    stroke_width: 0.4,
+                    even_odd: false,
+                },
+                DisplayItem::EndClip,
+            ],
+        };
+
+        let json = serde_json::to_value(&display_list).unwrap();
+        let types = json["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["glyph", "rule", "begin_clip", "path", "end_clip"]);
+        assert_eq!(json["items"][0]["unicode"], "\u{FB00}");
+        assert_eq!(
+            json["items"][3]["segments"],
+            serde_json::json!([
+                {"move_to": [0.0, 0.0]},
+                {"curve_to": [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]}
+            ])
+        );
+        assert_eq!(json["items"][2]["segments"][3], "close");
+
+        let read_back: DisplayList = serde_json::from_value(json).unwrap();
+        assert_eq!(read_back, display_list);
+    }
+}
@@ -0,0 +1,595 @@
+//! A DVI interpreter for writing custom backends. [`Interpreter::run`] reads a DVI file, keeps
+//! track of the position and the fonts, and reports everything that is drawn to a [`Machine`].
+//!
+//! All lengths passed to a [`Machine`] are in TeX points, magnified by the document's `\mag`.
+//! Positions are measured from TeX's reference point, which is one inch from the top left
+//! corner of the page, with `h` increasing to the right and `v` increasing downwards.
+//!
+//! Positions are kept in DVI units, and the widths of glyphs are scaled from their TFM metrics
+//! to DVI units with TeX's integer arithmetic, so that text is advanced exactly as TeX did.
+//! Only the final positions and sizes are converted to points.
+//!
+//! Characters of virtual fonts are never reported themselves. The interpreter runs their
+//! packets instead, so a [`Machine`] only sees the glyphs of real fonts and the rules and
+//! specials the packets draw.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ::dvi::{FontDef, Instruction};
+use anyhow::{Error, Result};
+
+use crate::font_files::FontFiles;
+use crate::logging::{LogCategory, Logger};
+use crate::tfm::{FontDataHelper, FontMetrics, compiled_in_fonts};
+use crate::vf::{VirtualCharacter, VirtualFont};
+
+/// How deeply virtual fonts may be built out of other virtual fonts. Anything deeper is most
+/// likely a virtual font that refers to itself.
+const MAX_VIRTUAL_FONT_DEPTH: usize = 8;
+
+/// The preamble of a DVI file.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Preamble {
+    pub numerator: u32,
+    pub denominator: u32,
+    pub magnification: u32,
+    /// The comment TeX writes, which usually says when the file was made.
+    pub comment: String,
+}
+
+/// A font that is used in a DVI file.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Font {
+    /// The name of the font, e.g. `cmr10`.
+    pub name: String,
+    /// The size the font is drawn at, which is the size it was loaded at (e.g. 12pt for
+    /// `cmr10 at 12pt`) times the magnification of the document.
+    pub size: f64,
+    /// The size the font was designed for, which is not magnified.
+    pub design_size: f64,
+}
+
+/// A character from a font.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Glyph<'a> {
+    /// The character code in the font's encoding.
+    pub code: u32,
+    pub font: &'a Font,
+    /// The position of the glyph's reference point, which is on the baseline.
+    pub h: f64,
+    pub v: f64,
+    /// The metrics of the glyph from the font's TFM file, scaled to the size of the font.
+    pub width: f64,
+    pub height: f64,
+    pub depth: f64,
+}
+
+/// A filled rectangle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Rule {
+    /// The position of the bottom left corner.
+    pub h: f64,
+    pub v: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A `\special` command, which passes `text` to the backend.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Special<'a> {
+    /// The position at which the special appeared.
+    pub h: f64,
+    pub v: f64,
+    pub text: &'a str,
+}
+
+/// A backend that is driven by an [`Interpreter`]. Every method does nothing by default, so a
+/// backend only needs to implement the ones it is interested in.
+pub trait Machine {
+    /// Called once before anything else.
+    fn preamble(&mut self, _preamble: &Preamble) {}
+    /// Called at the start of every page with the values of TeX's `\count0` to `\count9`.
+    fn begin_page(&mut self, _counts: [i32; 10]) {}
+    fn end_page(&mut self) {}
+    fn glyph(&mut self, _glyph: &Glyph) {}
+    fn rule(&mut self, _rule: &Rule) {}
+    fn special(&mut self, _special: &Special) {}
+}
+
+/// Reads DVI files and drives a [`Machine`]. The metrics of the bundled TeX fonts are loaded
+/// once, when the first `Interpreter` is created, and shared by all of them.
+pub struct Interpreter {
+    font_helper: &'static FontDataHelper,
+    font_files: FontFiles,
+    logger: Logger,
+}
+
+impl Interpreter {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            font_helper: compiled_in_fonts()?,
+            font_files: FontFiles::bundled(),
+            logger: Logger::default(),
+        })
+    }
+
+    /// Look for font metrics and virtual fonts in `font_files` rather than in the files bundled
+    /// with the crate.
+    ///
+    /// Use [`crate::WasmRunner::font_files`] for the files TeX had when it wrote the DVI file.
+    pub fn set_font_files(&mut self, font_files: FontFiles) {
+        self.font_files = font_files;
+    }
+
+    /// Send warnings, and traces of the fonts and specials in the DVI file, to `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = logger;
+    }
+
+    /// Interpret the DVI file `input`, reporting everything it draws to `machine`.
+    pub fn run<M: Machine + ?Sized>(&self, input: &[u8], machine: &mut M) -> Result<()> {
+        let mut state = State {
+            font_helper: self.font_helper,
+            font_files: &self.font_files,
+            logger: &self.logger,
+            // Replaced when the preamble is read.
+            points_per_dvi_unit: 1.0,
+            magnification: 1.0,
+            position: Position::default(),
+            position_stack: Vec::new(),
+            fonts: Vec::new(),
+            metrics: HashMap::new(),
+            font_numbers: HashMap::new(),
+            font: None,
+            packet: None,
+        };
+        let mut rest = input;
+        while !rest.is_empty() {
+            let (remaining, instruction) = Instruction::parse(rest).map_err(|_| {
+                Error::msg(format!(
+                    "Cannot parse DVI instruction at byte {}",
+                    input.len() - rest.len()
+                ))
+            })?;
+            rest = remaining;
+            state.execute(&instruction, machine)?;
+        }
+        Ok(())
+    }
+}
+
+/// A position in DVI units, with the spacing registers `w`, `x`, `y` and `z`.
+#[derive(Copy, Clone, Debug, Default)]
+struct Position {
+    h: f64,
+    v: f64,
+    right: [i32; 2],
+    down: [i32; 2],
+}
+
+impl Position {
+    fn change_right(&mut self, o: Option<i32>, first: bool) {
+        let index = if first { 0 } else { 1 };
+        if let Some(v) = o {
+            self.right[index] = v;
+        }
+        self.h += self.right[index] as f64;
+    }
+
+    fn change_down(&mut self, o: Option<i32>, first: bool) {
+        let index = if first { 0 } else { 1 };
+        if let Some(v) = o {
+            self.down[index] = v;
+        }
+        self.v += self.down[index] as f64;
+    }
+}
+
+/// A font that has been defined, with its metrics or its packets.
+struct LoadedFont {
+    font: Font,
+    /// The size the font is loaded at in DVI units, which TFM metrics and the lengths in the
+    /// packets of a virtual font are relative to.
+    scale_factor: u32,
+    kind: FontKind,
+}
+
+enum FontKind {
+    /// A font whose glyphs are reported to the machine.
+    Real(Rc<FontMetrics>),
+    /// A virtual font, whose characters are drawn by running their packets.
+    Virtual(Rc<LoadedVirtualFont>),
+}
+
+/// A virtual font, with the fonts its packets use loaded at the right size.
+struct LoadedVirtualFont {
+    characters: HashMap<u32, VirtualCharacter>,
+    /// The indices in [`State::fonts`] of the fonts the packets use, by their number in the
+    /// virtual font.
+    fonts: HashMap<u32, usize>,
+    /// The font that is selected when a packet starts, which is the first one defined.
+    default_font: Option<usize>,
+}
+
+/// The packet of a character of a virtual font, while it runs.
+struct Packet {
+    font: Rc<LoadedVirtualFont>,
+    /// The size the virtual font is loaded at in DVI units, which the lengths in the packet are
+    /// relative to.
+    scale_factor: u32,
+}
+
+/// The state of the interpreter while it runs.
+struct State<'a> {
+    font_helper: &'a FontDataHelper,
+    font_files: &'a FontFiles,
+    logger: &'a Logger,
+    /// The length of a DVI unit in TeX points, including the magnification.
+    points_per_dvi_unit: f64,
+    /// The magnification of the document, e.g. 2 for `\mag=2000`.
+    magnification: f64,
+    position: Position,
+    position_stack: Vec<Position>,
+    /// Every font that has been loaded, including the fonts that virtual fonts use.
+    fonts: Vec<LoadedFont>,
+    /// The metrics that have been read, by font name.
+    metrics: HashMap<String, Rc<FontMetrics>>,
+    /// The indices in `fonts` of the fonts defined in the DVI file, by their number.
+    font_numbers: HashMap<u32, usize>,
+    /// The index in `fonts` of the current font.
+    font: Option<usize>,
+    /// The packet that is running, if any. Its virtual font decides what font numbers mean.
+    packet: Option<Packet>,
+}
+
+impl State<'_> {
+    fn execute<M: Machine + ?Sized>(
+        &mut self,
+        instruction: &Instruction,
+        machine: &mut M,
+    ) -> Result<()> {
+        match instruction {
+            Instruction::Set(c) => {
+                let width = self.glyph(*c, machine)?;
+                self.position.h += width;
+            }
+            Instruction::Put(c) => {
+                self.glyph(*c, machine)?;
+            }
+            Instruction::SetRule(a, b) => {
+                let (height, width) = (self.length(*a), self.length(*b));
+                self.rule(height, width, machine);
+                self.position.h += width as f64;
+            }
+            Instruction::PutRule(a, b) => {
+                let (height, width) = (self.length(*a), self.length(*b));
+                self.rule(height, width, machine);
+            }
+            Instruction::Nop => (),
+            Instruction::Bop(counts, _previous_bop) => {
+                self.position = Position::default();
+                self.position_stack.clear();
+                machine.begin_page(*counts);
+            }
+            Instruction::Eop => machine.end_page(),
+            Instruction::Push => self.position_stack.push(self.position),
+            Instruction::Pop => {
+                self.position = self
+                    .position_stack
+                    .pop()
+                    .ok_or(Error::msg("Found `pop` without a matching `push`"))?;
+            }
+            Instruction::Right(d) => self.position.h += self.length(*d) as f64,
+            Instruction::W(o) => {
+                let o = o.map(|d| self.length(d));
+                self.position.change_right(o, true);
+            }
+            Instruction::X(o) => {
+                let o = o.map(|d| self.length(d));
+                self.position.change_right(o, false);
+            }
+            Instruction::Down(d) => self.position.v += self.length(*d) as f64,
+            Instruction::Y(o) => {
+                let o = o.map(|d| self.length(d));
+                self.position.change_down(o, true);
+            }
+            Instruction::Z(o) => {
+                let o = o.map(|d| self.length(d));
+                self.position.change_down(o, false);
+            }
+            Instruction::Font(f) => {
+                let font_numbers = match &self.packet {
+                    Some(packet) => &packet.font.fonts,
+                    None => &self.font_numbers,
+                };
+                let index = font_numbers
+                    .get(f)
+                    .ok_or_else(|| Error::msg(format!("Font {f} is used but not defined")))?;
+                self.font = Some(*index);
+            }
+            Instruction::Xxx(bytes) => {
+                let text = String::from_utf8_lossy(bytes);
+                let special = Special {
+                    h: self.position.h * self.points_per_dvi_unit,
+                    v: self.position.v * self.points_per_dvi_unit,
+                    text: &text,
+                };
+                self.logger.log(LogCategory::Specials, || {
+                    format!("({}, {}) {}", special.h, special.v, special.text)
+                });
+                machine.special(&special);
+            }
+            Instruction::FontDef(def) => self.define_font(def)?,
+            Instruction::Pre {
+                format: _,
+                numerator,
+                denominator,
+                magnification,
+                comment,
+            } => {
+                //TODO: error if numerator <= 0 or denominator <= 0 or format != 2
+                // A DVI unit is `numerator / denominator` tenths of a micrometre, which is a
+                // scaled point for files written by TeX.
+                let micrometres = *numerator as f64 / *denominator as f64 / 10.0;
+                self.magnification = *magnification as f64 / 1000.0;
+                self.points_per_dvi_unit = micrometres * self.magnification * 72.27 / 25_400.0;
+                machine.preamble(&Preamble {
+                    numerator: *numerator,
+                    denominator: *denominator,
+                    magnification: *magnification,
+                    comment: String::from_utf8_lossy(comment).to_string(),
+                });
+            }
+            Instruction::Post { .. } | Instruction::PostPost { .. } => (),
+        }
+        Ok(())
+    }
+
+    /// Convert a length from an instruction to DVI units. Lengths in packets are relative to
+    /// the size of their virtual font.
+    fn length(&self, length: i32) -> i32 {
+        match &self.packet {
+            Some(packet) => scale_fix_word(length, packet.scale_factor),
+            None => length,
+        }
+    }
+
+    fn define_font(&mut self, def: &FontDef) -> Result<()> {
+        // The postamble repeats every definition.
+        if self.font_numbers.contains_key(&def.number) {
+            return Ok(());
+        }
+        let name = String::from_utf8_lossy(&def.filename).to_string();
+        let design_size = def.design_size as f64 * self.points_per_dvi_unit / self.magnification;
+        let index = self.load_font(name, def.scale_factor, design_size, 0)?;
+        self.font_numbers.insert(def.number, index);
+        Ok(())
+    }
+
+    /// Load the font `name` at `scale_factor` DVI units and return its index in `fonts`.
+    /// `design_size` is in points and `depth` is the number of virtual fonts that use it.
+    fn load_font(
+        &mut self,
+        name: String,
+        scale_factor: u32,
+        design_size: f64,
+        depth: usize,
+    ) -> Result<usize> {
+        // The sizes do not depend on the design size in the TFM file, which may be that of the
+        // fallback font.
+        let size = scale_factor as f64 * self.points_per_dvi_unit;
+        let kind = match self.font_files.get(&format!("{name}.vf")) {
+            Some(bytes) => {
+                let font = self.load_virtual_font(&name, &bytes, scale_factor, depth)?;
+                FontKind::Virtual(Rc::new(font))
+            }
+            None => FontKind::Real(self.font_metrics(&name)?),
+        };
+        self.logger.log(LogCategory::Fonts, || {
+            let size = size / self.magnification;
+            match &kind {
+                FontKind::Real(_) => format!("{name} at {size}pt"),
+                FontKind::Virtual(_) => format!("{name} at {size}pt, a virtual font"),
+            }
+        });
+        self.fonts.push(LoadedFont {
+            font: Font {
+                name,
+                size,
+                design_size,
+            },
+            scale_factor,
+            kind,
+        });
+        Ok(self.fonts.len() - 1)
+    }
+
+    /// The metrics of the font `name` from its `.tfm` file, or the compiled-in metrics if it
+    /// has none. Fonts without any metrics are reported and get the metrics of `cmb10`.
+    fn font_metrics(&mut self, name: &str) -> Result<Rc<FontMetrics>> {
+        if let Some(metrics) = self.metrics.get(name) {
+            return Ok(metrics.clone());
+        }
+        let metrics = match self.font_files.get(&format!("{name}.tfm")) {
+            Some(bytes) => FontMetrics::parse(&bytes)
+                .map_err(|e| Error::msg(format!("Cannot read {name}.tfm: {e}")))?,
+            None => match FontMetrics::compiled_in(self.font_helper, name) {
+                Some(metrics) => metrics,
+                None => {
+                    self.logger.log(LogCategory::Error, || {
+                        format!(
+                            "No metrics for font {name}: there is no {name}.tfm, so its glyphs \
+                             are spaced with the metrics of cmb10"
+                        )
+                    });
+                    FontMetrics::compiled_in(self.font_helper, "cmb10")
+                        .ok_or(Error::msg(format!("Cannot find metrics for font {name}")))?
+                }
+            },
+        };
+        let metrics = Rc::new(metrics);
+        self.metrics.insert(name.to_string(), metrics.clone());
+        Ok(metrics)
+    }
+
+    /// Parse the virtual font `name` and load the fonts its packets use, scaled along with it.
+    fn load_virtual_font(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        scale_factor: u32,
+        depth: usize,
+    ) -> Result<LoadedVirtualFont> {
+        if depth >= MAX_VIRTUAL_FONT_DEPTH {
+            return Err(Error::msg(format!(
+                "Virtual font {name} is nested too deeply"
+            )));
+        }
+        let virtual_font = VirtualFont::parse(bytes)
+            .map_err(|e| Error::msg(format!("Cannot read virtual font {name}: {e}")))?;
+        let mut fonts = HashMap::new();
+        let mut default_font = None;
+        for local in virtual_font.fonts {
+            let local_scale_factor = scale_fix_word(local.scale_factor, scale_factor) as u32;
+            // Design sizes in virtual fonts are in units of 2^-20 points.
+            let design_size = local.design_size as f64 / (1 << 20) as f64;
+            let index = self.load_font(local.name, local_scale_factor, design_size, depth + 1)?;
+            fonts.insert(local.number, index);
+            default_font.get_or_insert(index);
+        }
+        Ok(LoadedVirtualFont {
+            characters: virtual_font.characters,
+            fonts,
+            default_font,
+        })
+    }
+
+    /// Draw the character `code` of the current font and return its width in DVI units.
+    fn glyph<M: Machine + ?Sized>(&mut self, code: u32, machine: &mut M) -> Result<f64> {
+        let index = self
+            .font
+            .ok_or(Error::msg("A character was set before selecting a font"))?;
+        let font = &self.fonts[index];
+        let font_metrics = match &font.kind {
+            FontKind::Real(font_metrics) => font_metrics,
+            FontKind::Virtual(virtual_font) => {
+                let virtual_font = virtual_font.clone();
+                let scale_factor = font.scale_factor;
+                let Some(character) = virtual_font.characters.get(&code) else {
+                    self.logger.log(LogCategory::Warning, || {
+                        format!("No character {code} in virtual font {}", font.font.name)
+                    });
+                    return Ok(0.0);
+                };
+                self.run_packet(&virtual_font, scale_factor, &character.packet, machine)?;
+                return Ok(scale_fix_word(character.width, scale_factor) as f64);
+            }
+        };
+        let metrics = match font_metrics.characters.get(&code) {
+            Some(metrics) => metrics,
+            None => {
+                //TODO: Handle this better. Error only happens for c === 127
+                self.logger.log(LogCategory::Warning, || {
+                    format!("No metrics for character {code} of {}", font.font.name)
+                });
+                match font_metrics.characters.get(&126) {
+                    Some(metrics) => metrics,
+                    None => return Ok(0.0),
+                }
+            }
+        };
+
+        let [width, height, depth] = [metrics.width, metrics.height, metrics.depth]
+            .map(|fix_word| scale_fix_word(fix_word, font.scale_factor) as f64);
+        machine.glyph(&Glyph {
+            code,
+            font: &font.font,
+            h: self.position.h * self.points_per_dvi_unit,
+            v: self.position.v * self.points_per_dvi_unit,
+            width: width * self.points_per_dvi_unit,
+            height: height * self.points_per_dvi_unit,
+            depth: depth * self.points_per_dvi_unit,
+        });
+        Ok(width)
+    }
+
+    /// Run `packet`, which draws a character of `font`, a virtual font loaded at
+    /// `scale_factor` DVI units. The packet starts at the current position with the spacing
+    /// registers cleared and the first font of the virtual font selected. Everything it changes
+    /// is restored when it ends.
+    fn run_packet<M: Machine + ?Sized>(
+        &mut self,
+        font: &Rc<LoadedVirtualFont>,
+        scale_factor: u32,
+        packet: &[u8],
+        machine: &mut M,
+    ) -> Result<()> {
+        let outer_packet = self.packet.replace(Packet {
+            font: font.clone(),
+            scale_factor,
+        });
+        let outer_font = std::mem::replace(&mut self.font, font.default_font);
+        let outer_position = self.position;
+        let outer_stack = std::mem::take(&mut self.position_stack);
+        self.position.right = [0; 2];
+        self.position.down = [0; 2];
+
+        let mut result = Ok(());
+        let mut rest = packet;
+        while !rest.is_empty() && result.is_ok() {
+            result = match Instruction::parse(rest) {
+                Ok((remaining, instruction)) => {
+                    rest = remaining;
+                    self.execute(&instruction, machine)
+                }
+                Err(_) => Err(Error::msg("Cannot parse the packet of a virtual font")),
+            };
+        }
+
+        self.packet = outer_packet;
+        self.font = outer_font;
+        self.position = outer_position;
+        self.position_stack = outer_stack;
+        result
+    }
+
+    fn rule<M: Machine + ?Sized>(&self, height: i32, width: i32, machine: &mut M) {
+        // Rules with a non-positive size are not drawn.
+        if height <= 0 || width <= 0 {
+            return;
+        }
+        machine.rule(&Rule {
+            h: self.position.h * self.points_per_dvi_unit,
+            v: self.position.v * self.points_per_dvi_unit,
+            width: width as f64 * self.points_per_dvi_unit,
+            height: height as f64 * self.points_per_dvi_unit,
+        });
+    }
+}
+
+/// Scale a TFM `fix_word`, which is relative to the design size of the font in units of 2^-20,
+/// to a font loaded at `size` DVI units. This is the integer arithmetic of TeX (§572 of
+/// _TeX: The Program_), which rounds the same way as TeX did when it set the text.
+fn scale_fix_word(fix_word: i32, size: u32) -> i32 {
+    let mut z = size.min(i32::MAX as u32) as i64;
+    let mut alpha: i64 = 16;
+    while z >= 0o40000000 {
+        z /= 2;
+        alpha += alpha;
+    }
+    let beta = 256 / alpha;
+    let alpha = alpha * z;
+    let [b0, b1, b2, b3] = fix_word.to_be_bytes().map(i64::from);
+    let scaled = (((b3 * z) / 256 + b2 * z) / 256 + b1 * z) / beta;
+    match b0 {
+        0 => scaled as i32,
+        // Negative values, which TFM files only use for kerns and italic corrections.
+        _ => (scaled - alpha) as i32,
+    }
+}
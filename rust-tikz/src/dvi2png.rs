@@ -0,0 +1,188 @@
+//! Rasterize the SVG produced by [`crate::dvi2svg`] into a PNG. Rendering is done by `resvg`,
+//! which is pure Rust, using fonts that are bundled with the crate so that no system fonts
+//! are needed (e.g., when running as a Typst plugin).
+
+use std::sync::{Arc, OnceLock};
+
+use resvg::usvg::fontdb::{Database, ID};
+use resvg::{tiny_skia, usvg};
+
+use crate::dvi2svg::{SvgOptions, dvi2svg};
+use crate::fonts::{self, FONTS};
+use crate::{FontFiles, Logger};
+
+/// SVG lengths are converted to pixels at 96 pixels per inch.
+const SVG_DPI: f32 = 96.0;
+
+static FONT_DATABASE: OnceLock<Arc<Database>> = OnceLock::new();
+
+fn font_database() -> Arc<Database> {
+    FONT_DATABASE
+        .get_or_init(|| {
+            let mut database = Database::new();
+            for font in FONTS {
+                database.load_font_data(font.data.to_vec());
+            }
+            database.set_serif_family("DejaVu Serif");
+            database.set_monospace_family("DejaVu Sans Mono");
+            Arc::new(database)
+        })
+        .clone()
+}
+
+/// Pick the bundled font that stands in for the TeX font named by a `<text>` element (or a
+/// `<g>` around it), using the same rules as PDF output.
+fn select_font(font: &usvg::Font, database: &mut Arc<Database>) -> Option<ID> {
+    if let Some(usvg::FontFamily::Named(tex_name)) = font.families().first() {
+        let substitute = fonts::substitute(tex_name);
+        let face = database
+            .faces()
+            .find(|face| face.post_script_name == substitute.name);
+        if let Some(face) = face {
+            return Some(face.id);
+        }
+    }
+    (usvg::FontResolver::default_font_selector())(font, database)
+}
+
+/// Convert DVI data into a PNG image with a resolution of `dpi`. If `background` is `None`,
+/// the background is transparent; otherwise it is filled with the given RGBA color.
+pub fn dvi2png(
+    input: &[u8],
+    dpi: f32,
+    background: Option<[u8; 4]>,
+    font_files: &FontFiles,
+    logger: &Logger,
+) -> Result<Vec<u8>, String> {
+    let svg = dvi2svg(input, SvgOptions::default(), font_files, logger)?;
+    svg2png(&svg, dpi, background)
+}
+
+/// Rasterize an SVG string into a PNG image with a resolution of `dpi`. If `background` is
+/// `None`, the background is transparent; otherwise it is filled with the given RGBA color.
+pub fn svg2png(svg: &str, dpi: f32, background: Option<[u8; 4]>) -> Result<Vec<u8>, String> {
+    let options = usvg::Options {
+        fontdb: font_database(),
+        font_resolver: usvg::FontResolver {
+            select_font: Box::new(select_font),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+
+    let scale = dpi / SVG_DPI;
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or(format!("Cannot rasterize an image at {dpi} dpi"))?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or("Cannot rasterize an empty image".to_string())?;
+    if let Some([r, g, b, a]) = background {
+        pixmap.fill(tiny_skia::Color::from_rgba8(r, g, b, a));
+    }
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+/// Parse a background color for [`svg2png`]. Accepts `transparent`, `white`, `black` and hex
+/// colors of the form `#rgb`, `#rrggbb` or `#rrggbbaa`. `transparent` returns `None`.
+pub fn parse_background(color: &str) -> Result<Option<[u8; 4]>, String> {
+    let color = color.trim();
+    match color {
+        "transparent" | "none" => return Ok(None),
+        "white" => return Ok(Some([255, 255, 255, 255])),
+        "black" => return Ok(Some([0, 0, 0, 255])),
+        _ => {}
+    }
+    let invalid = || format!("Invalid background color `{color}`");
+    let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+    let digits = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    match digits.as_slice() {
+        [r, g, b] => Ok(Some([r * 17, g * 17, b * 17, 255])),
+        [r1, r2, g1, g2, b1, b2] => Ok(Some([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2, 255])),
+        [r1, r2, g1, g2, b1, b2, a1, a2] => Ok(Some([
+            r1 * 16 + r2,
+            g1 * 16 + g2,
+            b1 * 16 + b2,
+            a1 * 16 + a2,
+        ])),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An SVG of one inch by half an inch, filled in red on the left half.
+    const SVG: &str = concat!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="72pt" height="36pt" "#,
+        r##"viewBox="0 0 72 36"><rect width="36" height="36" fill="#f00"/></svg>"##
+    );
+
+    #[test]
+    fn background_colors_are_parsed() {
+        let cases = [
+            ("transparent", None),
+            ("none", None),
+            (" white ", Some([255, 255, 255, 255])),
+            ("black", Some([0, 0, 0, 255])),
+            ("#fa0", Some([255, 170, 0, 255])),
+            ("#FFAA00", Some([255, 170, 0, 255])),
+            ("#ffaa0080", Some([255, 170, 0, 128])),
+        ];
+        for (color, expected) in cases {
+            assert_eq!(parse_background(color), Ok(expected), "{color}");
+        }
+        for color in ["red", "ffffff", "#ff", "#ffff", "#gggggg", "#ffffff0", ""] {
+            assert_eq!(
+                parse_background(color),
+                Err(format!("Invalid background color `{}`", color.trim())),
+                "{color}"
+            );
+        }
+    }
+
+    #[test]
+    fn images_have_the_size_of_the_svg_at_the_resolution() {
+        for (dpi, width, height) in [(96.0, 96, 48), (300.0, 300, 150), (48.0, 48, 24)] {
+            let png = svg2png(SVG, dpi, None).unwrap();
+            let pixmap = tiny_skia::Pixmap::decode_png(&png).unwrap();
+            assert_eq!(
+                (pixmap.width(), pixmap.height()),
+                (width, height),
+                "{dpi} dpi"
+            );
+        }
+    }
+
+    #[test]
+    fn the_background_fills_what_is_not_drawn() {
+        let pixel = |background| {
+            let png = svg2png(SVG, 96.0, background).unwrap();
+            let pixmap = tiny_skia::Pixmap::decode_png(&png).unwrap();
+            // The left half is red, and the right half is the background.
+            let red = pixmap.pixel(10, 24).unwrap();
+            assert_eq!(
+                [red.red(), red.green(), red.blue(), red.alpha()],
+                [255, 0, 0, 255]
+            );
+            let pixel = pixmap.pixel(80, 24).unwrap();
+            [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+        };
+        assert_eq!(pixel(None), [0, 0, 0, 0]);
+        assert_eq!(pixel(Some([255, 255, 255, 255])), [255, 255, 255, 255]);
+        assert_eq!(pixel(Some([0, 0, 255, 255])), [0, 0, 255, 255]);
+    }
+}
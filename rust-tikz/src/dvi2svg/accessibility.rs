@@ -0,0 +1,208 @@
+//! Accessibility metadata for SVG output. An SVG with `role="img"`, an `aria-label` and a
+//! `<title>` is announced by screen readers as a single image with that name, instead of as a
+//! jumble of the characters in its `<text>` elements.
+
+use super::utils::escape_attribute;
+
+/// Where the name of an SVG comes from when no title is given.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AltText {
+    /// The SVG has no name unless a title or label is given.
+    #[default]
+    None,
+    /// The TeX source of the document, with runs of whitespace collapsed.
+    Source,
+    /// The text typeset in the document, with a space wherever there is a gap between words.
+    Text,
+}
+
+/// The accessibility metadata of an SVG.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SvgAccessibility {
+    /// The content of the `<title>` element. If `None`, the alt text is used.
+    pub title: Option<String>,
+    /// The content of the `<desc>` element, a longer description of the image.
+    pub description: Option<String>,
+    /// The `aria-label` of the `<svg>` element. If `None`, the title is used.
+    pub label: Option<String>,
+    /// Where the title comes from when it is `None`.
+    pub alt_text: AltText,
+}
+
+impl SvgAccessibility {
+    /// Resolve [`AltText::Source`] by using `source` as the title, if there is no title yet.
+    pub(crate) fn with_source(&self, source: &str) -> SvgAccessibility {
+        let mut accessibility = self.clone();
+        if accessibility.alt_text == AltText::Source {
+            accessibility
+                .title
+                .get_or_insert_with(|| collapse_whitespace(source));
+            accessibility.alt_text = AltText::None;
+        }
+        accessibility
+    }
+
+    /// The attributes of the `<svg>` element and the elements that have to come first inside
+    /// it. `text` is the text typeset in the document, for [`AltText::Text`].
+    pub(crate) fn markup(&self, text: &str) -> (String, String) {
+        let title = match (&self.title, self.alt_text) {
+            (Some(title), _) => Some(title.clone()),
+            (None, AltText::Text) => Some(collapse_whitespace(text)),
+            (None, _) => None,
+        };
+        let title = title.filter(|title| !title.is_empty());
+        let mut attributes = r#" role="img""#.to_string();
+        if let Some(label) = self.label.as_ref().or(title.as_ref()) {
+            attributes.push_str(&format!(r#" aria-label="{}""#, escape_attribute(label)));
+        }
+        let mut elements = String::new();
+        if let Some(title) = &title {
+            elements.push_str(&format!("<title>{}</title>", escape_attribute(title)));
+        }
+        if let Some(description) = &self.description {
+            elements.push_str(&format!("<desc>{}</desc>", escape_attribute(description)));
+        }
+        (attributes, elements)
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Collects the text typeset in a document for [`AltText::Text`]. Glyphs are in the order
+/// TeX set them, which is reading order for everything but the most unusual documents.
+#[derive(Debug, Default)]
+pub(crate) struct TextContent {
+    text: String,
+    /// The baseline and the right edge of the last glyph.
+    last: Option<(f64, f64)>,
+}
+
+impl TextContent {
+    /// Add `character`, drawn at `(h, v)` with the given width in a font of `size` points.
+    pub fn push(&mut self, character: char, h: f64, v: f64, width: f64, size: f64) {
+        if let Some((baseline, right)) = self.last {
+            // Kerns are much smaller than a fifth of an em, and interword spaces are larger.
+            if baseline != v || h - right > size / 5.0 || h < right - size {
+                self.text.push(' ');
+            }
+        }
+        self.text.push(character);
+        self.last = Some((v, h + width));
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accessibility(
+        title: Option<&str>,
+        label: Option<&str>,
+        alt_text: AltText,
+    ) -> SvgAccessibility {
+        SvgAccessibility {
+            title: title.map(str::to_string),
+            description: None,
+            label: label.map(str::to_string),
+            alt_text,
+        }
+    }
+
+    #[test]
+    fn titles_take_precedence_over_the_alt_text_and_labels_over_titles() {
+        for (accessibility, attributes, elements) in [
+            (
+                accessibility(Some("Title"), None, AltText::Text),
+                r#" role="img" aria-label="Title""#,
+                "<title>Title</title>",
+            ),
+            (
+                accessibility(Some("Title"), Some("Label"), AltText::None),
+                r#" role="img" aria-label="Label""#,
+                "<title>Title</title>",
+            ),
+            (
+                accessibility(None, None, AltText::Text),
+                r#" role="img" aria-label="typeset text""#,
+                "<title>typeset text</title>",
+            ),
+            (
+                accessibility(None, Some("Label"), AltText::None),
+                r#" role="img" aria-label="Label""#,
+                "",
+            ),
+            (
+                accessibility(None, None, AltText::None),
+                r#" role="img""#,
+                "",
+            ),
+            // An empty title is left out.
+            (
+                accessibility(Some(""), None, AltText::None),
+                r#" role="img""#,
+                "",
+            ),
+        ] {
+            assert_eq!(
+                accessibility.markup("  typeset \n text "),
+                (attributes.to_string(), elements.to_string()),
+                "{accessibility:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn metadata_is_escaped() {
+        let accessibility = SvgAccessibility {
+            title: Some("a < b & \"c\"".to_string()),
+            description: Some("<script>".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            accessibility.markup(""),
+            (
+                r#" role="img" aria-label="a &lt; b &amp; &quot;c&quot;""#.to_string(),
+                "<title>a &lt; b &amp; &quot;c&quot;</title><desc>&lt;script&gt;</desc>"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn the_source_is_only_used_without_a_title() {
+        let source = "\\begin{document}\n  $x^2$\n\\end{document}\n";
+        let from_source = accessibility(None, None, AltText::Source).with_source(source);
+        assert_eq!(
+            from_source.title.as_deref(),
+            Some("\\begin{document} $x^2$ \\end{document}")
+        );
+        assert_eq!(from_source.alt_text, AltText::None);
+        let titled = accessibility(Some("Title"), None, AltText::Source).with_source(source);
+        assert_eq!(titled.title.as_deref(), Some("Title"));
+        let from_text = accessibility(None, None, AltText::Text).with_source(source);
+        assert_eq!(from_text, accessibility(None, None, AltText::Text));
+    }
+
+    #[test]
+    fn words_are_separated_by_gaps_and_line_breaks() {
+        let mut text = TextContent::default();
+        // A kern between `A` and `V` does not separate them.
+        text.push('A', 0.0, 10.0, 7.0, 10.0);
+        text.push('V', 6.5, 10.0, 7.0, 10.0);
+        // An interword space of a third of an em does.
+        text.push('x', 16.8, 10.0, 5.0, 10.0);
+        // So does moving to another line, even right below the last glyph.
+        text.push('y', 21.8, 22.0, 5.0, 10.0);
+        // And moving back more than an em, e.g. to the left margin.
+        text.push('z', 0.0, 22.0, 5.0, 10.0);
+        // Exactly a fifth of an em is still a kern.
+        text.push('!', 7.0, 22.0, 3.0, 10.0);
+        assert_eq!(text.as_str(), "AV x y z!");
+    }
+}
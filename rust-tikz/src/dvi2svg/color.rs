@@ -0,0 +1,335 @@
+//! The colors of the dvips `color` specials, which the `color` and `xcolor` packages write.
+//! Colors keep the model they were given in, so that the PDF and PostScript backends can use
+//! CMYK colors as they are.
+
+use super::utils::num;
+
+/// A color in one of the models that DVI color specials use. All components are between 0
+/// and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Gray(f64),
+    Rgb([f64; 3]),
+    Cmyk([f64; 4]),
+}
+
+impl Color {
+    pub const BLACK: Color = Color::Gray(0.0);
+
+    /// Parse a color specification like `rgb 1 0 0`, `cmyk 0 1 1 0`, `gray 0.5`, `hsb 0 1 1`
+    /// or a dvips color name like `ForestGreen`.
+    pub fn parse(spec: &str) -> Option<Color> {
+        let mut words = spec.split_whitespace();
+        let model = words.next()?;
+        let components = words
+            .map(|c| c.parse::<f64>().ok().map(|c| c.clamp(0.0, 1.0)))
+            .collect::<Option<Vec<_>>>()?;
+        match (model, components.as_slice()) {
+            ("gray", &[g]) => Some(Color::Gray(g)),
+            ("rgb", &[r, g, b]) => Some(Color::Rgb([r, g, b])),
+            ("cmyk", &[c, m, y, k]) => Some(Color::Cmyk([c, m, y, k])),
+            ("hsb", &[h, s, b]) => Some(Color::Rgb(hsb_to_rgb(h, s, b))),
+            (name, &[]) => DVIPS_NAMES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|&(_, cmyk)| Color::Cmyk(cmyk)),
+            _ => None,
+        }
+    }
+
+    /// The color as red, green and blue components.
+    pub fn to_rgb(self) -> [f64; 3] {
+        match self {
+            Color::Gray(g) => [g; 3],
+            Color::Rgb(rgb) => rgb,
+            // The conversion of the PostScript Language Reference, which dvips uses.
+            Color::Cmyk([c, m, y, k]) => [c, m, y].map(|v| 1.0 - (v + k).min(1.0)),
+        }
+    }
+
+    /// The color as an SVG hex color, e.g. `#ff0000`.
+    pub fn to_hex(self) -> String {
+        let [r, g, b] = self
+            .to_rgb()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+
+    /// The PDF operator that sets this color for filling, or for stroking if `stroke` is true.
+    #[cfg(feature = "pdf")]
+    pub fn pdf_operator(self, stroke: bool) -> String {
+        let (components, operator) = match self {
+            Color::Gray(g) => (vec![g], "g"),
+            Color::Rgb(rgb) => (rgb.to_vec(), "rg"),
+            Color::Cmyk(cmyk) => (cmyk.to_vec(), "k"),
+        };
+        let operator = if stroke {
+            operator.to_uppercase()
+        } else {
+            operator.to_string()
+        };
+        let components = components.into_iter().map(num).collect::<Vec<_>>();
+        format!("{} {operator}", components.join(" "))
+    }
+
+    /// The PostScript operator that sets this color.
+    pub fn postscript_operator(self) -> String {
+        let (components, operator) = match self {
+            Color::Gray(g) => (vec![g], "setgray"),
+            Color::Rgb(rgb) => (rgb.to_vec(), "setrgbcolor"),
+            Color::Cmyk(cmyk) => (cmyk.to_vec(), "setcmykcolor"),
+        };
+        let components = components.into_iter().map(num).collect::<Vec<_>>();
+        format!("{} {operator}", components.join(" "))
+    }
+}
+
+/// Convert a color in the HSB model, with the hue between 0 and 1, to RGB.
+fn hsb_to_rgb(hue: f64, saturation: f64, brightness: f64) -> [f64; 3] {
+    let hue = (hue * 6.0) % 6.0;
+    let sector = hue.floor();
+    let fraction = hue - sector;
+    let p = brightness * (1.0 - saturation);
+    let q = brightness * (1.0 - saturation * fraction);
+    let t = brightness * (1.0 - saturation * (1.0 - fraction));
+    match sector as u8 {
+        0 => [brightness, t, p],
+        1 => [q, brightness, p],
+        2 => [p, brightness, t],
+        3 => [p, q, brightness],
+        4 => [t, p, brightness],
+        _ => [brightness, p, q],
+    }
+}
+
+/// The colors that dvips knows by name, from `color.pro`.
+const DVIPS_NAMES: [(&str, [f64; 4]); 68] = [
+    ("GreenYellow", [0.15, 0.0, 0.69, 0.0]),
+    ("Yellow", [0.0, 0.0, 1.0, 0.0]),
+    ("Goldenrod", [0.0, 0.10, 0.84, 0.0]),
+    ("Dandelion", [0.0, 0.29, 0.84, 0.0]),
+    ("Apricot", [0.0, 0.32, 0.52, 0.0]),
+    ("Peach", [0.0, 0.50, 0.70, 0.0]),
+    ("Melon", [0.0, 0.46, 0.50, 0.0]),
+    ("YellowOrange", [0.0, 0.42, 1.0, 0.0]),
+    ("Orange", [0.0, 0.61, 0.87, 0.0]),
+    ("BurntOrange", [0.0, 0.51, 1.0, 0.0]),
+    ("Bittersweet", [0.0, 0.75, 1.0, 0.24]),
+    ("RedOrange", [0.0, 0.77, 0.87, 0.0]),
+    ("Mahogany", [0.0, 0.85, 0.87, 0.35]),
+    ("Maroon", [0.0, 0.87, 0.68, 0.32]),
+    ("BrickRed", [0.0, 0.89, 0.94, 0.28]),
+    ("Red", [0.0, 1.0, 1.0, 0.0]),
+    ("OrangeRed", [0.0, 1.0, 0.50, 0.0]),
+    ("RubineRed", [0.0, 1.0, 0.13, 0.0]),
+    ("WildStrawberry", [0.0, 0.96, 0.39, 0.0]),
+    ("Salmon", [0.0, 0.53, 0.38, 0.0]),
+    ("CarnationPink", [0.0, 0.63, 0.0, 0.0]),
+    ("Magenta", [0.0, 1.0, 0.0, 0.0]),
+    ("VioletRed", [0.0, 0.81, 0.0, 0.0]),
+    ("Rhodamine", [0.0, 0.82, 0.0, 0.0]),
+    ("Mulberry", [0.34, 0.90, 0.0, 0.02]),
+    ("RedViolet", [0.07, 0.90, 0.0, 0.34]),
+    ("Fuchsia", [0.47, 0.91, 0.0, 0.08]),
+    ("Lavender", [0.0, 0.48, 0.0, 0.0]),
+    ("Thistle", [0.12, 0.59, 0.0, 0.0]),
+    ("Orchid", [0.32, 0.64, 0.0, 0.0]),
+    ("DarkOrchid", [0.40, 0.80, 0.20, 0.0]),
+    ("Purple", [0.45, 0.86, 0.0, 0.0]),
+    ("Plum", [0.50, 1.0, 0.0, 0.0]),
+    ("Violet", [0.79, 0.88, 0.0, 0.0]),
+    ("RoyalPurple", [0.75, 0.90, 0.0, 0.0]),
+    ("BlueViolet", [0.86, 0.91, 0.0, 0.04]),
+    ("Periwinkle", [0.57, 0.55, 0.0, 0.0]),
+    ("CadetBlue", [0.62, 0.57, 0.23, 0.0]),
+    ("CornflowerBlue", [0.65, 0.13, 0.0, 0.0]),
+    ("MidnightBlue", [0.98, 0.13, 0.0, 0.43]),
+    ("NavyBlue", [0.94, 0.54, 0.0, 0.0]),
+    ("RoyalBlue", [1.0, 0.50, 0.0, 0.0]),
+    ("Blue", [1.0, 1.0, 0.0, 0.0]),
+    ("Cerulean", [0.94, 0.11, 0.0, 0.0]),
+    ("Cyan", [1.0, 0.0, 0.0, 0.0]),
+    ("ProcessBlue", [0.96, 0.0, 0.0, 0.0]),
+    ("SkyBlue", [0.62, 0.0, 0.12, 0.0]),
+    ("Turquoise", [0.85, 0.0, 0.20, 0.0]),
+    ("TealBlue", [0.86, 0.0, 0.34, 0.02]),
+    ("Aquamarine", [0.82, 0.0, 0.30, 0.0]),
+    ("BlueGreen", [0.85, 0.0, 0.33, 0.0]),
+    ("Emerald", [1.0, 0.0, 0.50, 0.0]),
+    ("JungleGreen", [0.99, 0.0, 0.52, 0.0]),
+    ("SeaGreen", [0.69, 0.0, 0.50, 0.0]),
+    ("Green", [1.0, 0.0, 1.0, 0.0]),
+    ("ForestGreen", [0.91, 0.0, 0.88, 0.12]),
+    ("PineGreen", [0.92, 0.0, 0.59, 0.25]),
+    ("LimeGreen", [0.50, 0.0, 1.0, 0.0]),
+    ("YellowGreen", [0.44, 0.0, 0.74, 0.0]),
+    ("SpringGreen", [0.26, 0.0, 0.76, 0.0]),
+    ("OliveGreen", [0.64, 0.0, 0.95, 0.40]),
+    ("RawSienna", [0.0, 0.72, 1.0, 0.45]),
+    ("Sepia", [0.0, 0.83, 1.0, 0.70]),
+    ("Brown", [0.0, 0.81, 1.0, 0.60]),
+    ("Tan", [0.14, 0.42, 0.56, 0.0]),
+    ("Gray", [0.0, 0.0, 0.0, 0.50]),
+    ("Black", [0.0, 0.0, 0.0, 1.0]),
+    ("White", [0.0, 0.0, 0.0, 0.0]),
+];
+
+/// The state of the color specials: the color stack and the page background.
+#[derive(Debug, Default)]
+pub struct ColorSpecials {
+    /// The color set by TeX, or `None` if no color has been set.
+    pub current: Option<Color>,
+    stack: Vec<Option<Color>>,
+    /// The color of the page, set with a `background` special.
+    pub background: Option<Color>,
+}
+
+impl ColorSpecials {
+    /// The current color, which is black unless TeX set one.
+    pub fn color(&self) -> Color {
+        self.current.unwrap_or(Color::BLACK)
+    }
+
+    /// Interpret `command` if it is a color special and return whether it was.
+    ///
+    /// `color push <spec>` and `color pop` change the color for a group, `color <spec>` (or
+    /// `color set <spec>`) replaces the whole stack and `background <spec>` sets the color of
+    /// the page. Unknown colors are treated as black.
+    pub fn special(&mut self, command: &str) -> bool {
+        let command = command.trim();
+        if command == "color pop" {
+            self.current = self.stack.pop().flatten();
+        } else if let Some(spec) = command.strip_prefix("color push ") {
+            self.stack.push(self.current);
+            self.current = Some(Color::parse(spec).unwrap_or(Color::BLACK));
+        } else if let Some(spec) = command.strip_prefix("background ") {
+            self.background = Some(Color::parse(spec).unwrap_or(Color::BLACK));
+        } else if let Some(spec) = command.strip_prefix("color ") {
+            let spec = spec.strip_prefix("set ").unwrap_or(spec);
+            self.stack.clear();
+            self.current = Some(Color::parse(spec).unwrap_or(Color::BLACK));
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_parsed_in_their_model() {
+        for (spec, color) in [
+            ("gray 0.5", Color::Gray(0.5)),
+            ("rgb 1 0 0.25", Color::Rgb([1.0, 0.0, 0.25])),
+            ("cmyk 0 1 1 0", Color::Cmyk([0.0, 1.0, 1.0, 0.0])),
+            ("  rgb   0 .5 1 ", Color::Rgb([0.0, 0.5, 1.0])),
+            // Components outside of [0, 1] are clamped.
+            ("rgb 2 -1 0.5", Color::Rgb([1.0, 0.0, 0.5])),
+            // HSB colors become RGB, with the hue going around the color wheel.
+            ("hsb 0 1 1", Color::Rgb([1.0, 0.0, 0.0])),
+            ("hsb 0.5 1 1", Color::Rgb([0.0, 1.0, 1.0])),
+            ("hsb 1 1 1", Color::Rgb([1.0, 0.0, 0.0])),
+            ("hsb 0 0 0.5", Color::Rgb([0.5, 0.5, 0.5])),
+        ] {
+            assert_eq!(Color::parse(spec), Some(color), "{spec}");
+        }
+    }
+
+    #[test]
+    fn dvips_names_are_cmyk_colors() {
+        assert_eq!(DVIPS_NAMES.len(), 68);
+        for (name, cmyk, hex) in [
+            ("Red", [0.0, 1.0, 1.0, 0.0], "#ff0000"),
+            ("Black", [0.0, 0.0, 0.0, 1.0], "#000000"),
+            ("White", [0.0, 0.0, 0.0, 0.0], "#ffffff"),
+            ("Gray", [0.0, 0.0, 0.0, 0.5], "#808080"),
+            ("ForestGreen", [0.91, 0.0, 0.88, 0.12], "#00e000"),
+            ("GreenYellow", [0.15, 0.0, 0.69, 0.0], "#d9ff4f"),
+            ("Sepia", [0.0, 0.83, 1.0, 0.70], "#4d0000"),
+        ] {
+            let color = Color::parse(name);
+            assert_eq!(color, Some(Color::Cmyk(cmyk)), "{name}");
+            assert_eq!(color.unwrap().to_hex(), hex, "{name}");
+        }
+    }
+
+    #[test]
+    fn malformed_colors_are_not_parsed() {
+        for spec in [
+            "",
+            "gray",
+            "rgb 1 0",
+            "rgb 1 0 0 0",
+            "rgb 1 0 x",
+            "cmyk 0 1 1",
+            "hsb 0 1",
+            "red",
+            "Red 1",
+            "NoSuchColor",
+        ] {
+            assert_eq!(Color::parse(spec), None, "{spec}");
+        }
+    }
+
+    #[test]
+    fn pushed_colors_are_restored_by_pop() {
+        let mut colors = ColorSpecials::default();
+        assert_eq!(colors.current, None);
+        assert_eq!(colors.color(), Color::BLACK);
+
+        assert!(colors.special("color push rgb 1 0 0"));
+        assert!(colors.special("color push Blue"));
+        assert_eq!(colors.color(), Color::Cmyk([1.0, 1.0, 0.0, 0.0]));
+        assert!(colors.special("color pop"));
+        assert_eq!(colors.color(), Color::Rgb([1.0, 0.0, 0.0]));
+        assert!(colors.special("color pop"));
+        assert_eq!(colors.current, None);
+    }
+
+    #[test]
+    fn popping_an_empty_stack_leaves_no_color() {
+        let mut colors = ColorSpecials::default();
+        assert!(colors.special("color pop"));
+        assert_eq!(colors.current, None);
+        assert!(colors.special("color gray 0.5"));
+        assert!(colors.special("color pop"));
+        assert_eq!(colors.current, None);
+    }
+
+    #[test]
+    fn set_colors_replace_the_stack() {
+        let mut colors = ColorSpecials::default();
+        for special in [
+            "color push gray 0.1",
+            "color push gray 0.2",
+            "color set gray 0.3",
+        ] {
+            assert!(colors.special(special));
+        }
+        assert_eq!(colors.current, Some(Color::Gray(0.3)));
+        assert!(colors.special("color pop"));
+        assert_eq!(colors.current, None);
+
+        assert!(colors.special(" color rgb 0 1 0 "));
+        assert_eq!(colors.current, Some(Color::Rgb([0.0, 1.0, 0.0])));
+        // Unknown colors are black.
+        assert!(colors.special("color push NoSuchColor"));
+        assert_eq!(colors.current, Some(Color::BLACK));
+    }
+
+    #[test]
+    fn backgrounds_do_not_change_the_text_color() {
+        let mut colors = ColorSpecials::default();
+        assert!(colors.special("background Yellow"));
+        assert_eq!(colors.background, Some(Color::Cmyk([0.0, 0.0, 1.0, 0.0])));
+        assert_eq!(colors.current, None);
+        assert!(colors.special("background gray 1"));
+        assert_eq!(colors.background, Some(Color::Gray(1.0)));
+
+        assert!(!colors.special("colorful"));
+        assert!(!colors.special("papersize=10pt,10pt"));
+    }
+}
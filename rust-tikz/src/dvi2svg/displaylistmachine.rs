@@ -0,0 +1,584 @@
+use super::color::{Color, ColorSpecials};
+use super::encodings::unicode;
+use super::pgf::{Canvas, Paint, PgfInterpreter, Shading};
+use super::raw_svg::{self, IDENTITY, Matrix, PathSegment, RawSvgBuffer, Style, Tag};
+use crate::display_list::{BoundingBox, DisplayItem, DisplayList};
+use crate::dvi::{Glyph, Machine, Rule, Special};
+use crate::logging::{LogCategory, Logger};
+use std::collections::HashMap;
+
+/// An implementation of `Machine` that collects everything drawn into a [`DisplayList`]. The
+/// SVG drawn by TikZ with `dvisvgm:raw` specials is turned into paths, with the transforms of
+/// the enclosing groups applied, and so are the paths drawn by the rust-tikz pgf driver.
+/// Shadings are not part of the display list, so they are filled with their average color and
+/// reported to the logger.
+pub struct DisplayListMachine {
+    items: Vec<DisplayItem>,
+    colors: ColorSpecials,
+    paperwidth: Option<f64>,
+    paperheight: Option<f64>,
+
+    raw_svg: RawSvgBuffer,
+    /// The elements opened by raw specials that have not been closed yet.
+    open_elements: Vec<OpenElement>,
+    /// The segments of each `<clipPath>`, by id.
+    clip_paths: HashMap<String, Vec<PathSegment>>,
+    /// The transformation from the coordinates of whatever is drawn next to those of the
+    /// display list.
+    matrix: Matrix,
+
+    pgf: PgfInterpreter,
+    /// The transformation before each [`Canvas::begin_transform`] that has not been ended
+    /// yet. This is kept apart from `open_elements`, so that the raw SVG of a picture cannot
+    /// close a transform of the pgf driver or the other way around.
+    transforms: Vec<Matrix>,
+    /// The number of clip paths begun by the pgf driver in the current scope.
+    clips: usize,
+    /// The value of `clips` before each scope that has not been ended yet.
+    scopes: Vec<usize>,
+
+    /// Where the parts of the picture that are drawn differently are reported.
+    logger: Logger,
+    /// Whether a shading has been reported, so that only the first one is.
+    reported_shading: bool,
+}
+
+/// An element opened by a raw special.
+enum OpenElement {
+    /// A `<g>` with the style that applies inside it, the transformation outside of it and
+    /// whether it began a clip path.
+    Group {
+        style: Style,
+        outer: Matrix,
+        clipped: bool,
+    },
+    /// A `<clipPath>`. Shapes inside it are added to the clip path rather than drawn.
+    ClipPath(String),
+    /// An element whose contents are not drawn, like `<defs>`.
+    Hidden,
+    /// An element that has no effect on drawing, like the `<svg>` TikZ wraps a picture in.
+    Transparent,
+}
+
+impl DisplayListMachine {
+    pub fn new() -> DisplayListMachine {
+        DisplayListMachine {
+            items: Vec::new(),
+            colors: ColorSpecials::default(),
+            paperwidth: None,
+            paperheight: None,
+            raw_svg: RawSvgBuffer::default(),
+            open_elements: Vec::new(),
+            clip_paths: HashMap::new(),
+            matrix: IDENTITY,
+            pgf: PgfInterpreter::default(),
+            transforms: Vec::new(),
+            clips: 0,
+            scopes: Vec::new(),
+            logger: Logger::default(),
+            reported_shading: false,
+        }
+    }
+
+    /// Report the parts of TikZ pictures that cannot be drawn to `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.pgf.set_logger(logger.clone());
+        self.logger = logger;
+    }
+
+    pub fn into_display_list(mut self) -> DisplayList {
+        let bbox = BoundingBox {
+            x: -72.0,
+            y: -72.0,
+            width: self.paperwidth.unwrap_or(100.0),
+            height: self.paperheight.unwrap_or(100.0),
+        };
+        // The page background is drawn first, as a rule that covers the whole page.
+        if let Some(background) = self.colors.background {
+            let rule = DisplayItem::Rule {
+                x: bbox.x,
+                y: bbox.y,
+                width: bbox.width,
+                height: bbox.height,
+                color: background.to_rgb(),
+            };
+            self.items.insert(0, rule);
+        }
+        DisplayList {
+            bbox,
+            items: self.items,
+        }
+    }
+
+    /// The current color as RGB components.
+    fn rgb(&self) -> [f64; 3] {
+        self.colors.color().to_rgb()
+    }
+
+    /// The style of the innermost group, and the current transformation.
+    fn group(&self) -> (Style, Matrix) {
+        let style = self
+            .open_elements
+            .iter()
+            .rev()
+            .find_map(|e| match e {
+                OpenElement::Group { style, .. } => Some(*style),
+                _ => None,
+            })
+            .unwrap_or_default();
+        (style, self.matrix)
+    }
+}
+
+impl Machine for DisplayListMachine {
+    fn glyph(&mut self, glyph: &Glyph) {
+        self.put_raw();
+        let (_, matrix) = self.group();
+        let (x, y) = raw_svg::transform_point(matrix, glyph.h, glyph.v);
+        // Scaling is the only part of the transformation a glyph can carry.
+        let [a, b, c, d, _, _] = matrix;
+        let scale = (a * d - b * c).abs().sqrt();
+        self.items.push(DisplayItem::Glyph {
+            font: glyph.font.name.clone(),
+            code: glyph.code,
+            unicode: unicode(glyph),
+            x,
+            y,
+            size: glyph.font.size * scale,
+            color: self.rgb(),
+        });
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        self.put_raw();
+        let (_, matrix) = self.group();
+        let (x, y) = (rule.h, rule.v - rule.height);
+        if matrix == IDENTITY {
+            self.items.push(DisplayItem::Rule {
+                x,
+                y,
+                width: rule.width,
+                height: rule.height,
+                color: self.rgb(),
+            });
+            return;
+        }
+        // A transformed rule may no longer be an upright rectangle.
+        let segments = [
+            PathSegment::MoveTo(x, y),
+            PathSegment::LineTo(x + rule.width, y),
+            PathSegment::LineTo(x + rule.width, y + rule.height),
+            PathSegment::LineTo(x, y + rule.height),
+            PathSegment::Close,
+        ];
+        self.items.push(DisplayItem::Path {
+            segments: segments.map(|s| s.transform(matrix)).to_vec(),
+            fill: Some(self.rgb()),
+            stroke: None,
+            stroke_width: 0.0,
+            even_odd: false,
+        });
+    }
+    fn end_page(&mut self) {
+        self.put_raw();
+    }
+    fn special(&mut self, special: &Special) {
+        if self.special_raw(special)
+            || self.special_pgf(special)
+            || self.colors.special(special.text)
+        {
+            return;
+        }
+        self.special_papersize(special.text);
+    }
+}
+
+//Specials
+impl DisplayListMachine {
+    fn special_papersize(&mut self, command: &str) -> bool {
+        let pattern = "papersize=";
+        if let Some(sizes) = command.strip_prefix(pattern) {
+            let mut sizes = sizes.split(',').map(raw_svg::parse_length);
+            if let (Some(Some(width)), Some(Some(height))) = (sizes.next(), sizes.next()) {
+                self.paperwidth = Some(width);
+                self.paperheight = Some(height);
+            }
+        }
+        false
+    }
+
+    fn special_pgf(&mut self, special: &Special) -> bool {
+        let color = self.colors.color();
+        let mut pgf = std::mem::take(&mut self.pgf);
+        let handled = pgf.special(special, color, self);
+        self.pgf = pgf;
+        handled
+    }
+
+    fn special_raw(&mut self, special: &Special) -> bool {
+        if self.raw_svg.special(special) {
+            return true;
+        }
+        self.put_raw();
+        false
+    }
+
+    /// Add the buffered raw SVG to the display list.
+    fn put_raw(&mut self) {
+        if let Some(raw) = self.raw_svg.take() {
+            self.draw_raw(&raw);
+        }
+    }
+
+    /// Add the SVG fragment `raw` to the display list.
+    fn draw_raw(&mut self, raw: &str) {
+        for tag in raw_svg::parse_tags(raw) {
+            match tag {
+                Tag::Open {
+                    name,
+                    attributes,
+                    self_closing,
+                } => self.open_element(&name, &attributes, self_closing),
+                Tag::Close { .. } => self.close_element(),
+            }
+        }
+    }
+
+    fn open_element(&mut self, name: &str, attributes: &HashMap<String, String>, closed: bool) {
+        let element = match name {
+            "g" | "a" if !self.is_hidden() => {
+                let (mut style, outer) = self.group();
+                style.apply(attributes, &self.colors.color().to_hex());
+                let matrix = self.transform(outer, attributes);
+                let clipped = self.begin_clip_path(attributes, matrix);
+                self.matrix = matrix;
+                OpenElement::Group {
+                    style,
+                    outer,
+                    clipped,
+                }
+            }
+            "svg" => OpenElement::Transparent,
+            "clipPath" if !self.is_hidden() => {
+                let id = attributes.get("id").cloned().unwrap_or_default();
+                self.clip_paths.insert(id.clone(), Vec::new());
+                OpenElement::ClipPath(id)
+            }
+            _ => {
+                self.put_shape(name, attributes);
+                OpenElement::Hidden
+            }
+        };
+        self.open_elements.push(element);
+        if closed {
+            self.close_element();
+        }
+    }
+
+    fn close_element(&mut self) {
+        if let Some(OpenElement::Group { outer, clipped, .. }) = self.open_elements.pop() {
+            self.matrix = outer;
+            if clipped {
+                self.items.push(DisplayItem::EndClip);
+            }
+        }
+    }
+
+    /// Whether the elements that are open stop anything from being drawn.
+    fn is_hidden(&self) -> bool {
+        self.open_elements
+            .iter()
+            .any(|e| matches!(e, OpenElement::Hidden | OpenElement::ClipPath(_)))
+    }
+
+    /// Begin the clip path the `clip-path` attribute refers to, if any, in the coordinates
+    /// given by `matrix`. Returns whether a clip path was begun.
+    fn begin_clip_path(&mut self, attributes: &HashMap<String, String>, matrix: Matrix) -> bool {
+        let Some(clip_path) = attributes
+            .get("clip-path")
+            .and_then(|url| url.trim().strip_prefix("url(#"))
+            .and_then(|url| url.strip_suffix(')'))
+            .and_then(|id| self.clip_paths.get(id))
+        else {
+            return false;
+        };
+        self.items.push(DisplayItem::BeginClip {
+            segments: clip_path.iter().map(|s| s.transform(matrix)).collect(),
+            even_odd: false,
+        });
+        true
+    }
+
+    /// `matrix` followed by the `transform` attribute of an element.
+    fn transform(&self, matrix: Matrix, attributes: &HashMap<String, String>) -> Matrix {
+        match attributes.get("transform") {
+            Some(transform) => raw_svg::multiply(raw_svg::parse_transform(transform), matrix),
+            None => matrix,
+        }
+    }
+
+    /// Add a shape element to the display list, or to the clip path that is being defined.
+    /// Elements that are not shapes are ignored.
+    fn put_shape(&mut self, name: &str, attributes: &HashMap<String, String>) {
+        let Some(segments) = raw_svg::shape_segments(name, attributes) else {
+            return;
+        };
+        match self.open_elements.last() {
+            Some(OpenElement::ClipPath(id)) => {
+                // The segments are transformed to the coordinates of the display list when the
+                // clip path is used, so only the transform of the shape is applied here.
+                let matrix = self.transform(IDENTITY, attributes);
+                if let Some(clip_path) = self.clip_paths.get_mut(id) {
+                    clip_path.extend(segments.into_iter().map(|s| s.transform(matrix)));
+                }
+                return;
+            }
+            _ if self.is_hidden() => return,
+            _ => {}
+        }
+
+        let (mut style, matrix) = self.group();
+        style.apply(attributes, &self.colors.color().to_hex());
+        let matrix = self.transform(matrix, attributes);
+        let clipped = self.begin_clip_path(attributes, matrix);
+        let [a, b, c, d, _, _] = matrix;
+        self.items.push(DisplayItem::Path {
+            segments: segments.into_iter().map(|s| s.transform(matrix)).collect(),
+            fill: style.fill,
+            stroke: style.stroke,
+            stroke_width: style.stroke_width * (a * d - b * c).abs().sqrt(),
+            even_odd: style.even_odd,
+        });
+        if clipped {
+            self.items.push(DisplayItem::EndClip);
+        }
+    }
+}
+
+impl Canvas for DisplayListMachine {
+    fn begin_picture(&mut self) {
+        self.begin_scope();
+    }
+
+    fn end_picture(&mut self) {
+        self.end_scope();
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(self.clips);
+        self.clips = 0;
+    }
+
+    fn end_scope(&mut self) {
+        for _ in 0..self.clips {
+            self.items.push(DisplayItem::EndClip);
+        }
+        self.clips = self.scopes.pop().unwrap_or_default();
+    }
+
+    fn clip(&mut self, path: &[PathSegment], even_odd: bool) {
+        self.items.push(DisplayItem::BeginClip {
+            segments: path.iter().map(|s| s.transform(self.matrix)).collect(),
+            even_odd,
+        });
+        self.clips += 1;
+    }
+
+    fn draw_path(&mut self, path: &[PathSegment], paint: &Paint) {
+        // Pictures drawn in the text of another picture are transformed along with the text.
+        let (_, matrix) = self.group();
+        let [a, b, c, d, _, _] = matrix;
+        self.items.push(DisplayItem::Path {
+            segments: path.iter().map(|s| s.transform(matrix)).collect(),
+            fill: paint.fill.map(Color::to_rgb),
+            stroke: paint.stroke.map(Color::to_rgb),
+            stroke_width: paint.line_width * (a * d - b * c).abs().sqrt(),
+            even_odd: paint.even_odd,
+        });
+    }
+
+    fn begin_transform(&mut self, matrix: Matrix) {
+        self.transforms.push(self.matrix);
+        self.matrix = raw_svg::multiply(matrix, self.matrix);
+    }
+
+    fn end_transform(&mut self) {
+        if let Some(outer) = self.transforms.pop() {
+            self.matrix = outer;
+        }
+    }
+
+    fn shading(&mut self, shading: &Shading) {
+        if !self.reported_shading {
+            self.reported_shading = true;
+            self.logger.log(LogCategory::Warning, || {
+                "Shadings are not part of the display list, so they are filled with their \
+                 average color"
+                    .to_string()
+            });
+        }
+        let [x, y, width, height] = shading.rect;
+        let rectangle = [
+            PathSegment::MoveTo(x, y),
+            PathSegment::LineTo(x + width, y),
+            PathSegment::LineTo(x + width, y + height),
+            PathSegment::LineTo(x, y + height),
+            PathSegment::Close,
+        ];
+        self.draw_path(&rectangle, &Paint::fill(shading.average_color()));
+    }
+
+    fn raw_svg(&mut self, svg: &str) -> bool {
+        self.draw_raw(svg);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dvi2svg::pgf::ShadingKind;
+    use std::sync::{Arc, Mutex};
+
+    /// The segments of a rectangle with its top left corner at `(x, y)`.
+    fn rectangle(x: f64, y: f64, width: f64, height: f64) -> Vec<PathSegment> {
+        vec![
+            PathSegment::MoveTo(x, y),
+            PathSegment::LineTo(x + width, y),
+            PathSegment::LineTo(x + width, y + height),
+            PathSegment::LineTo(x, y + height),
+            PathSegment::Close,
+        ]
+    }
+
+    /// A filled path that is not stroked.
+    fn path(segments: Vec<PathSegment>, fill: [f64; 3], stroke_width: f64) -> DisplayItem {
+        DisplayItem::Path {
+            segments,
+            fill: Some(fill),
+            stroke: None,
+            stroke_width,
+            even_odd: false,
+        }
+    }
+
+    #[test]
+    fn clip_paths_of_the_pgf_driver_end_with_their_scope() {
+        let mut machine = DisplayListMachine::new();
+        let black = Color::Gray(0.0);
+        machine.begin_picture();
+        machine.begin_scope();
+        machine.begin_transform([2.0, 0.0, 0.0, 2.0, 0.0, 0.0]);
+        machine.clip(&rectangle(0.0, 0.0, 1.0, 1.0), true);
+        machine.end_transform();
+        machine.clip(&rectangle(0.0, 0.0, 5.0, 5.0), false);
+        machine.draw_path(&rectangle(0.0, 0.0, 3.0, 3.0), &Paint::fill(black));
+        machine.end_scope();
+        machine.clip(&rectangle(1.0, 1.0, 1.0, 1.0), false);
+        machine.end_picture();
+        assert_eq!(
+            machine.items,
+            [
+                DisplayItem::BeginClip {
+                    segments: rectangle(0.0, 0.0, 2.0, 2.0),
+                    even_odd: true,
+                },
+                DisplayItem::BeginClip {
+                    segments: rectangle(0.0, 0.0, 5.0, 5.0),
+                    even_odd: false,
+                },
+                path(rectangle(0.0, 0.0, 3.0, 3.0), [0.0, 0.0, 0.0], 0.0),
+                DisplayItem::EndClip,
+                DisplayItem::EndClip,
+                DisplayItem::BeginClip {
+                    segments: rectangle(1.0, 1.0, 1.0, 1.0),
+                    even_odd: false,
+                },
+                DisplayItem::EndClip,
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_clip_paths_apply_to_the_elements_that_use_them() {
+        let mut machine = DisplayListMachine::new();
+        machine.draw_raw(
+            "<clipPath id=\"c\"><rect width=\"2\" height=\"1\" transform=\"translate(1 0)\"/>\
+             </clipPath>\
+             <g clip-path=\"url(#c)\" transform=\"scale(2)\"><rect width=\"4\" height=\"4\"/></g>\
+             <rect width=\"1\" height=\"1\" clip-path=\"url(#c)\"/>",
+        );
+        assert_eq!(
+            machine.items,
+            [
+                DisplayItem::BeginClip {
+                    segments: rectangle(2.0, 0.0, 4.0, 2.0),
+                    even_odd: false,
+                },
+                path(rectangle(0.0, 0.0, 8.0, 8.0), [0.0, 0.0, 0.0], 2.0),
+                DisplayItem::EndClip,
+                DisplayItem::BeginClip {
+                    segments: rectangle(1.0, 0.0, 2.0, 1.0),
+                    even_odd: false,
+                },
+                path(rectangle(0.0, 0.0, 1.0, 1.0), [0.0, 0.0, 0.0], 1.0),
+                DisplayItem::EndClip,
+            ]
+        );
+    }
+
+    #[test]
+    fn transforms_of_the_pgf_driver_are_kept_apart_from_raw_groups() {
+        let mut machine = DisplayListMachine::new();
+        machine.begin_transform([2.0, 0.0, 0.0, 2.0, 10.0, 0.0]);
+        machine.draw_raw("<g fill=\"#f00\"><rect width=\"1\" height=\"1\"/>");
+        // Ending the transform does not close the group, which is still open.
+        machine.end_transform();
+        machine.draw_raw("<rect width=\"1\" height=\"1\"/></g><rect width=\"1\" height=\"1\"/>");
+        assert_eq!(
+            machine.items,
+            [
+                path(rectangle(10.0, 0.0, 2.0, 2.0), [1.0, 0.0, 0.0], 2.0),
+                path(rectangle(0.0, 0.0, 1.0, 1.0), [1.0, 0.0, 0.0], 1.0),
+                path(rectangle(0.0, 0.0, 1.0, 1.0), [0.0, 0.0, 0.0], 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadings_are_filled_with_their_average_color_and_reported_once() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let mut machine = DisplayListMachine::new();
+        machine.set_logger(Logger::new({
+            let warnings = warnings.clone();
+            move |category, message| {
+                warnings
+                    .lock()
+                    .unwrap()
+                    .push(format!("{category}: {message}"))
+            }
+        }));
+        let shading = Shading {
+            kind: ShadingKind::Axial {
+                from: (0.0, 0.0),
+                to: (10.0, 0.0),
+            },
+            stops: vec![
+                (0.0, Color::Rgb([1.0, 0.0, 0.0])),
+                (10.0, Color::Rgb([0.0, 0.0, 1.0])),
+            ],
+            rect: [0.0, 0.0, 10.0, 5.0],
+        };
+        machine.shading(&shading);
+        machine.shading(&shading);
+        let fill = path(rectangle(0.0, 0.0, 10.0, 5.0), [0.5, 0.0, 0.5], 0.0);
+        assert_eq!(machine.items, [fill.clone(), fill]);
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            [
+                "warning: Shadings are not part of the display list, so they are filled with \
+                 their average color"
+            ]
+        );
+    }
+}
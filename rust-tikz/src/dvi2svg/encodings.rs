@@ -0,0 +1,295 @@
+//! The encodings of TeX fonts. DVI files only give the code of a glyph in its font, and what
+//! that code stands for depends on the font: code 11 is `ﬀ` in `cmr10` but `α` in `cmmi10`.
+//! The tables map every code to the Unicode character it stands for, so that text can be
+//! drawn with other fonts, copied and read out by screen readers.
+//!
+//! Each table is a string with one character per code, written in rows of 16 codes. A `\0`
+//! marks a code that has no glyph.
+
+use crate::dvi::Glyph;
+
+/// The encoding of a TeX font.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// The text fonts of Computer Modern, e.g. `cmr10`.
+    Ot1,
+    /// The typewriter fonts of Computer Modern, e.g. `cmtt10`, which have ASCII where the
+    /// other text fonts have ligatures and accents.
+    Ot1Typewriter,
+    /// Math italic, e.g. `cmmi10`. Euler Roman, e.g. `eurm10`, has the letters and Greek of
+    /// math italic in the same places.
+    Oml,
+    /// Math symbols, e.g. `cmsy10`. Euler Script, e.g. `eusm10`, has the calligraphic capitals
+    /// of the math symbols in the same places.
+    Oms,
+    /// Math extension, e.g. `cmex10`, with delimiters and large operators.
+    Omx,
+    /// The first AMS symbol font, `msam10`.
+    MsA,
+    /// The second AMS symbol font, `msbm10`, with blackboard bold letters.
+    MsB,
+    /// The Euler Fraktur fonts, e.g. `eufm10`.
+    Fraktur,
+    /// The European Computer Modern and Latin Modern fonts, e.g. `ecrm1000`.
+    T1,
+}
+
+impl Encoding {
+    /// The encoding of the TeX font `name`. Fonts that are not known are assumed to be OT1.
+    pub fn of_font(name: &str) -> Encoding {
+        let starts_with = |prefixes: &[&str]| prefixes.iter().any(|p| name.starts_with(p));
+        if starts_with(&["cmmi", "eur"]) {
+            Encoding::Oml
+        } else if starts_with(&["cmsy", "cmbsy", "eus"]) {
+            Encoding::Oms
+        } else if starts_with(&["cmex"]) {
+            Encoding::Omx
+        } else if starts_with(&["msam"]) {
+            Encoding::MsA
+        } else if starts_with(&["msbm"]) {
+            Encoding::MsB
+        } else if starts_with(&["euf"]) {
+            Encoding::Fraktur
+        } else if starts_with(&["ec"]) {
+            Encoding::T1
+        } else if starts_with(&["cmtt", "cmsltt", "cmitt"]) {
+            Encoding::Ot1Typewriter
+        } else {
+            Encoding::Ot1
+        }
+    }
+
+    /// The Unicode character that `code` stands for, or `None` if the encoding has no glyph
+    /// at `code`.
+    pub fn to_unicode(self, code: u32) -> Option<char> {
+        let table = match self {
+            Encoding::Ot1 => OT1,
+            Encoding::Ot1Typewriter => OT1_TYPEWRITER,
+            Encoding::Oml => OML,
+            Encoding::Oms => OMS,
+            Encoding::Omx => OMX,
+            Encoding::MsA => MSAM,
+            Encoding::MsB => MSBM,
+            Encoding::Fraktur => return fraktur(code),
+            Encoding::T1 => T1,
+        };
+        table.chars().nth(code as usize).filter(|&c| c != '\0')
+    }
+}
+
+/// The Unicode character that `glyph` stands for. Codes without a known character become a
+/// space, so that the rest of the text still lines up.
+pub fn unicode(glyph: &Glyph) -> char {
+    Encoding::of_font(&glyph.font.name)
+        .to_unicode(glyph.code)
+        .unwrap_or(' ')
+}
+
+/// The Euler Fraktur letters. Most capitals are in the Mathematical Alphanumeric Symbols
+/// block, but the ones that were encoded earlier are in Letterlike Symbols.
+fn fraktur(code: u32) -> Option<char> {
+    let c = char::from_u32(code)?;
+    match c {
+        'C' => Some('ℭ'),
+        'H' => Some('ℌ'),
+        'I' => Some('ℑ'),
+        'R' => Some('ℜ'),
+        'Z' => Some('ℨ'),
+        'A'..='Z' => char::from_u32(0x1D504 + code - 'A' as u32),
+        'a'..='z' => char::from_u32(0x1D51E + code - 'a' as u32),
+        '!'..='~' => Some(c),
+        _ => None,
+    }
+}
+
+const OT1: &str = concat!(
+    "ΓΔΘΛΞΠΣΥΦΨΩﬀﬁﬂﬃﬄ",
+    "ıȷ`´ˇ˘¯˚¸ßæœøÆŒØ",
+    // Code 32 is the stroke of `Ł`, which only makes sense on top of an `L`.
+    " !”#$%&’()*+,-./",
+    "0123456789:;¡=¿?",
+    "@ABCDEFGHIJKLMNO",
+    "PQRSTUVWXYZ[“]ˆ˙",
+    "‘abcdefghijklmno",
+    "pqrstuvwxyz–—˝˜¨",
+);
+
+const OT1_TYPEWRITER: &str = concat!(
+    "ΓΔΘΛΞΠΣΥΦΨΩ↑↓'¡¿",
+    "ıȷ`´ˇ˘¯˚¸ßæœøÆŒØ",
+    "␣!\"#$%&’()*+,-./",
+    "0123456789:;<=>?",
+    "@ABCDEFGHIJKLMNO",
+    "PQRSTUVWXYZ[\\]^_",
+    "‘abcdefghijklmno",
+    "pqrstuvwxyz{|}~¨",
+);
+
+const OML: &str = concat!(
+    "ΓΔΘΛΞΠΣΥΦΨΩαβγδϵ",
+    "ζηθικλμνξπρστυϕχ",
+    // Codes 44 and 45 are the hooks of `\hookrightarrow` and `\hookleftarrow`.
+    "ψωεϑϖϱςφ↼↽⇀⇁⸦⸧▷◁",
+    "0123456789.,</>⋆",
+    "∂ABCDEFGHIJKLMNO",
+    "PQRSTUVWXYZ♭♮♯⌣⌢",
+    "ℓabcdefghijklmno",
+    "pqrstuvwxyzıȷ℘\u{20D7}⁀",
+);
+
+const OMS: &str = concat!(
+    "−⋅×∗÷⋄±∓⊕⊖⊗⊘⊙◯∘∙",
+    "≍≡⊆⊇≤≥⪯⪰∼≈⊂⊃≪≫≺≻",
+    "←→↑↓↔↗↘≃⇐⇒⇑⇓⇔↖↙∝",
+    // Code 54 is the slash of `\not` and code 55 the bar of `\mapsto`.
+    "′∞∈∋△▽\u{338}|∀∃¬∅ℜℑ⊤⊥",
+    "ℵ𝒜ℬ𝒞𝒟ℰℱ𝒢ℋℐ𝒥𝒦ℒℳ𝒩𝒪",
+    "𝒫𝒬ℛ𝒮𝒯𝒰𝒱𝒲𝒳𝒴𝒵∪∩⊎∧∨",
+    "⊢⊣⌊⌋⌈⌉{}⟨⟩|‖↕⇕\\≀",
+    "√⨿∇∫⊔⊓⊑⊒§†‡¶♣♢♡♠",
+);
+
+// Delimiters come in several sizes and are built from pieces when they are larger still. The
+// pieces map to the Miscellaneous Technical block where it has them.
+const OMX: &str = concat!(
+    "()[]⌊⌋⌈⌉{}⟨⟩|‖/\\",
+    "()()[]⌊⌋⌈⌉{}⟨⟩/\\",
+    "()[]⌊⌋⌈⌉{}⟨⟩/\\/\\",
+    "⎛⎞⎡⎤⎣⎦⎢⎥⎧⎫⎩⎭⎨⎬⎪⏐",
+    "⎝⎠⎜⎟⟨⟩⨆⨆∮∮⨀⨀⨁⨁⨂⨂",
+    "∑∏∫⋃⋂⨄⋀⋁∑∏∫⋃⋂⨄⋀⋁",
+    "∐∐ˆˆˆ˜˜˜[]⌊⌋⌈⌉{}",
+    "√√√√√⏐⏐‖↑↓⏞⏞⏟⏟⇑⇓",
+);
+
+const MSAM: &str = concat!(
+    "⊡⊞⊠□■·◊⧫↻↺⇌⇋⊟⊩⊪⊨",
+    "↠↞⇇⇉⇈⇊↾⇂↿⇃↣↢⇆⇄↰↱",
+    "⇝↭↫↬≗≿≳⪆⊸∴∵≑≜≾≲⪅",
+    "⪕⪖⋞⋟≼≦⩽≶‵\0≓≒≽≧⩾≷",
+    "⊏⊐⊳⊲⊵⊴★≬▼▶◀⇢⇠△▲▽",
+    "≖⋚⋛⪋⪌¥⇛⇚✓⊻⊼⩞∠∡∢∝",
+    "⌣⌢⋐⋑⋓⋒⋏⋎⋋⋌⫅⫆≏≎⋘⋙",
+    "⌜⌝®Ⓢ⋔∔∽⋍⌞⌟✠∁⊺⊚⊛⊝",
+);
+
+const MSBM: &str = concat!(
+    "⪇⪈≰≱≮≯⊀⊁≨≩≰≱≨≩≰≱",
+    "⪵⪶⋨⋩⋦⋧⋠⋡⪹⪺⪉⪊≁≇╱╲",
+    "⊊⊋⊈⊉⫋⫌⫋⫌⊊⊋⊈⊉∦∤∤∦",
+    "⊬⊮⊭⊯⋭⋬⋪⋫↚↛⇍⇏⇎↮⋇∅",
+    "∄𝔸𝔹ℂ𝔻𝔼𝔽𝔾ℍ𝕀𝕁𝕂𝕃𝕄ℕ𝕆",
+    "ℙℚℝ𝕊𝕋𝕌𝕍𝕎𝕏𝕐ℤˆˆ˜˜\0",
+    "Ⅎ⅁\0\0\0≂ℶℷℸ⋖⋗⋉⋊∣∥∖",
+    "∼≈≊⪸⪷↶↷ϝϰ𝕜ℏℏ∍\0\0\0",
+);
+
+const T1: &str = concat!(
+    "`´ˆ˜¨˝˚ˇ˘¯˙¸˛‚‹›",
+    // Code 23 is the compound word mark and code 24 the zero that follows `%` in `‰`.
+    "“”„«»–—\u{200C}\u{200B}ıȷﬀﬁﬂﬃﬄ",
+    "␣!\"#$%&’()*+,-./",
+    "0123456789:;<=>?",
+    "@ABCDEFGHIJKLMNO",
+    "PQRSTUVWXYZ[\\]^_",
+    "‘abcdefghijklmno",
+    "pqrstuvwxyz{|}~-",
+    "ĂĄĆČĎĚĘĞĹĽŁŃŇŊŐŔ",
+    "ŘŚŠŞŤŢŰŮŸŹŽŻĲİđ§",
+    "ăąćčďěęğĺľłńňŋőŕ",
+    "řśšşťţűůÿźžżĳ¡¿£",
+    "ÀÁÂÃÄÅÆÇÈÉÊËÌÍÎÏ",
+    "ÐÑÒÓÔÕÖŒØÙÚÛÜÝÞß",
+    "àáâãäåæçèéêëìíîï",
+    "ðñòóôõöœøùúûüýþß",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings_are_found_from_the_font_name() {
+        for (font, encoding) in [
+            ("cmr10", Encoding::Ot1),
+            ("cmbx12", Encoding::Ot1),
+            ("cmti10", Encoding::Ot1),
+            ("cmmi10", Encoding::Oml),
+            ("cmmib10", Encoding::Oml),
+            ("cmsy10", Encoding::Oms),
+            ("cmbsy10", Encoding::Oms),
+            ("cmex10", Encoding::Omx),
+            ("cmtt10", Encoding::Ot1Typewriter),
+            ("cmsltt10", Encoding::Ot1Typewriter),
+            ("cmitt10", Encoding::Ot1Typewriter),
+            ("ecrm1000", Encoding::T1),
+            ("ecbx1200", Encoding::T1),
+            ("ectt1000", Encoding::T1),
+            ("msam10", Encoding::MsA),
+            ("msbm10", Encoding::MsB),
+            ("eufm10", Encoding::Fraktur),
+            ("eufb10", Encoding::Fraktur),
+            ("eurm10", Encoding::Oml),
+            ("eurb10", Encoding::Oml),
+            ("eusm10", Encoding::Oms),
+            ("eusb10", Encoding::Oms),
+            ("unknown", Encoding::Ot1),
+        ] {
+            assert_eq!(Encoding::of_font(font), encoding, "{font}");
+        }
+    }
+
+    #[test]
+    fn tables_have_a_character_for_every_code() {
+        for (table, length) in [
+            (OT1, 128),
+            (OT1_TYPEWRITER, 128),
+            (OML, 128),
+            (OMS, 128),
+            (OMX, 128),
+            (MSAM, 128),
+            (MSBM, 128),
+            (T1, 256),
+        ] {
+            assert_eq!(table.chars().count(), length, "{table}");
+        }
+    }
+
+    #[test]
+    fn codes_are_mapped_to_unicode() {
+        for (encoding, code, character) in [
+            (Encoding::Ot1, 11, Some('ﬀ')),
+            (Encoding::Ot1, 34, Some('”')),
+            (Encoding::Ot1, 65, Some('A')),
+            (Encoding::Ot1, 123, Some('–')),
+            (Encoding::Ot1Typewriter, 34, Some('"')),
+            (Encoding::Ot1Typewriter, 123, Some('{')),
+            (Encoding::Oml, 11, Some('α')),
+            (Encoding::Oml, 64, Some('∂')),
+            (Encoding::Oml, 96, Some('ℓ')),
+            (Encoding::Oms, 0, Some('−')),
+            (Encoding::Oms, 50, Some('∈')),
+            (Encoding::Oms, 65, Some('𝒜')),
+            (Encoding::Omx, 80, Some('∑')),
+            (Encoding::Omx, 112, Some('√')),
+            (Encoding::MsA, 3, Some('□')),
+            (Encoding::MsA, 57, None),
+            (Encoding::MsB, 82, Some('ℝ')),
+            (Encoding::MsB, 127, None),
+            (Encoding::Fraktur, 'g' as u32, Some('𝔤')),
+            (Encoding::Fraktur, 'A' as u32, Some('𝔄')),
+            (Encoding::Fraktur, 'R' as u32, Some('ℜ')),
+            (Encoding::Fraktur, '1' as u32, Some('1')),
+            (Encoding::Fraktur, 0, None),
+            (Encoding::T1, 34, Some('"')),
+            (Encoding::T1, 138, Some('Ł')),
+            (Encoding::T1, 233, Some('é')),
+            (Encoding::T1, 255, Some('ß')),
+            // Codes past the end of a table have no glyph.
+            (Encoding::Ot1, 128, None),
+            (Encoding::T1, 256, None),
+        ] {
+            assert_eq!(encoding.to_unicode(code), character, "{encoding:?} {code}");
+        }
+    }
+}
@@ -0,0 +1,812 @@
+use super::color::{Color, ColorSpecials};
+use super::pgf::{Canvas, LineCap, LineJoin, Paint, PgfInterpreter, Shading};
+use super::raw_svg::{self, Matrix, PathSegment, RawSvgBuffer, Style, Tag};
+use super::utils::num;
+use crate::dvi::{Glyph, Machine, Rule, Special};
+use crate::logging::{LogCategory, Logger};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// An implementation of `Machine` that generates an Encapsulated PostScript file. The SVG
+/// drawn by TikZ with `dvisvgm:raw` specials and the specials of the rust-tikz pgf driver are
+/// translated into PostScript path operators.
+///
+/// Fonts are not embedded, since the TeX files bundled with the crate have no Type 1 fonts.
+/// Glyphs are drawn with the Type 1 versions of the TeX fonts (e.g., `CMR10` for `cmr10`), which
+/// use the same encoding as TeX and are available wherever TeX is installed. Shadings need
+/// LanguageLevel 3, so they are filled with their average color and reported to the logger.
+pub struct EpsMachine {
+    /// The page content, in SVG coordinates (i.e., the y axis points down).
+    content: String,
+    colors: ColorSpecials,
+    paperwidth: Option<f64>,
+    paperheight: Option<f64>,
+
+    /// The glyphs that are waiting to be drawn with a single `show`.
+    run: Option<GlyphRun>,
+    /// The PostScript names of the fonts that are used, in the order they are defined.
+    fonts: Vec<String>,
+
+    raw_svg: RawSvgBuffer,
+    /// The elements opened by raw specials that have not been closed yet.
+    open_elements: Vec<OpenElement>,
+    /// Path operators for each `<clipPath>`, by id.
+    clip_paths: HashMap<String, String>,
+    pgf: PgfInterpreter,
+    /// Where the parts of the picture that are drawn differently are reported.
+    logger: Logger,
+    /// Whether a shading has been reported, so that only the first one is.
+    reported_shading: bool,
+}
+
+/// Consecutive glyphs on the same baseline, in the same font and color.
+struct GlyphRun {
+    font: usize,
+    size: f64,
+    color: Color,
+    h: f64,
+    v: f64,
+    /// Where the next glyph has to be for it to be part of the run.
+    next_h: f64,
+    codes: Vec<u8>,
+}
+
+/// An element opened by a raw special.
+enum OpenElement {
+    /// A `<g>`, which saved the graphics state with `gsave` and set the style inside it.
+    Group(Style),
+    /// A `<clipPath>`. Paths inside it are collected rather than drawn.
+    ClipPath(String),
+    /// An element whose contents are not drawn, like `<defs>` or `<marker>`.
+    Hidden,
+    /// An element that has no effect on drawing, like the `<svg>` TikZ wraps a picture in.
+    Transparent,
+}
+
+impl EpsMachine {
+    pub fn new() -> EpsMachine {
+        EpsMachine {
+            content: "".to_string(),
+            colors: ColorSpecials::default(),
+            paperwidth: None,
+            paperheight: None,
+            run: None,
+            fonts: Vec::new(),
+            raw_svg: RawSvgBuffer::default(),
+            open_elements: Vec::new(),
+            clip_paths: HashMap::new(),
+            pgf: PgfInterpreter::default(),
+            logger: Logger::default(),
+            reported_shading: false,
+        }
+    }
+
+    /// Report the parts of TikZ pictures that cannot be drawn to `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.pgf.set_logger(logger.clone());
+        self.logger = logger;
+    }
+
+    /// Assemble the EPS file. The bounding box is the size of the page, which TeX gives with a
+    /// `papersize` special. Without one, the bounding box is not known and this fails.
+    pub fn into_eps(self) -> Result<Vec<u8>, String> {
+        let (Some(width), Some(height)) = (self.paperwidth, self.paperheight) else {
+            return Err(
+                "The DVI file has no `papersize` special, so the bounding box of the EPS file \
+                 is not known"
+                    .to_string(),
+            );
+        };
+
+        let mut eps = String::from("%!PS-Adobe-3.0 EPSF-3.0\n");
+        writeln!(
+            eps,
+            "%%BoundingBox: 0 0 {} {}",
+            width.ceil() as i64,
+            height.ceil() as i64
+        )
+        .unwrap();
+        writeln!(
+            eps,
+            "%%HiResBoundingBox: 0 0 {} {}",
+            num(width),
+            num(height)
+        )
+        .unwrap();
+        eps.push_str("%%Creator: rust-tikz\n%%LanguageLevel: 2\n");
+        for (i, font) in self.fonts.iter().enumerate() {
+            let prefix = if i == 0 {
+                "%%DocumentNeededResources:"
+            } else {
+                "%%+"
+            };
+            writeln!(eps, "{prefix} font {font}").unwrap();
+        }
+        eps.push_str("%%Pages: 1\n%%EndComments\n%%BeginSetup\n");
+        for (i, font) in self.fonts.iter().enumerate() {
+            writeln!(
+                eps,
+                "%%IncludeResource: font {font}\n/F{i} /{font} findfont def"
+            )
+            .unwrap();
+        }
+        eps.push_str("%%EndSetup\n%%Page: 1 1\ngsave\n");
+
+        // Like the SVG output, the origin is one inch from the top left corner. The y axis
+        // is flipped so that the content can be written in SVG coordinates.
+        writeln!(eps, "72 {} translate 1 -1 scale", num(height - 72.0)).unwrap();
+        if let Some(background) = self.colors.background {
+            writeln!(
+                eps,
+                "{} -72 -72 {} {} rectfill",
+                background.postscript_operator(),
+                num(width),
+                num(height)
+            )
+            .unwrap();
+        }
+        eps.push_str(&self.content);
+        for element in &self.open_elements {
+            if let OpenElement::Group(_) = element {
+                eps.push_str("grestore\n");
+            }
+        }
+        eps.push_str("grestore\nshowpage\n%%EOF\n");
+        Ok(eps.into_bytes())
+    }
+
+    /// Get the index of the font definition for the TeX font `tex_name`.
+    fn font(&mut self, tex_name: &str) -> usize {
+        let name = tex_name.to_uppercase();
+        if let Some(index) = self.fonts.iter().position(|f| *f == name) {
+            return index;
+        }
+        self.fonts.push(name);
+        self.fonts.len() - 1
+    }
+
+    /// Draw the glyphs of the current run.
+    fn put_run(&mut self) {
+        let Some(run) = self.run.take() else {
+            return;
+        };
+        let size = num(run.size);
+        // The font matrix flips the y axis back so that glyphs are upright.
+        writeln!(
+            self.content,
+            "F{} [{size} 0 0 -{size} 0 0] makefont setfont {} {} {} moveto ({}) show",
+            run.font,
+            run.color.postscript_operator(),
+            num(run.h),
+            num(run.v),
+            escape(&run.codes)
+        )
+        .unwrap();
+    }
+}
+
+impl Machine for EpsMachine {
+    fn glyph(&mut self, glyph: &Glyph) {
+        self.put_raw();
+        let font = self.font(&glyph.font.name);
+        let size = glyph.font.size;
+        let Ok(code) = u8::try_from(glyph.code) else {
+            return;
+        };
+
+        if let Some(run) = &mut self.run
+            && run.font == font
+            && run.size == size
+            && run.color == self.colors.color()
+            && run.v == glyph.v
+            && (run.next_h - glyph.h).abs() < 0.01
+        {
+            run.codes.push(code);
+            run.next_h = glyph.h + glyph.width;
+            return;
+        }
+        self.put_run();
+        self.run = Some(GlyphRun {
+            font,
+            size,
+            color: self.colors.color(),
+            h: glyph.h,
+            v: glyph.v,
+            next_h: glyph.h + glyph.width,
+            codes: vec![code],
+        });
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        self.put_raw();
+        self.put_run();
+        writeln!(
+            self.content,
+            "{} {} {} {} {} rectfill",
+            self.colors.color().postscript_operator(),
+            num(rule.h),
+            num(rule.v - rule.height),
+            num(rule.width),
+            num(rule.height)
+        )
+        .unwrap();
+    }
+    fn end_page(&mut self) {
+        self.put_raw();
+        self.put_run();
+    }
+    fn special(&mut self, special: &Special) {
+        if self.special_raw(special)
+            || self.special_pgf(special)
+            || self.colors.special(special.text)
+        {
+            return;
+        }
+        self.special_papersize(special.text);
+    }
+}
+
+//Specials
+impl EpsMachine {
+    fn special_papersize(&mut self, command: &str) -> bool {
+        let pattern = "papersize=";
+        if let Some(sizes) = command.strip_prefix(pattern) {
+            let mut sizes = sizes.split(',').map(raw_svg::parse_length);
+            if let (Some(Some(width)), Some(Some(height))) = (sizes.next(), sizes.next()) {
+                self.paperwidth = Some(width);
+                self.paperheight = Some(height);
+            }
+        }
+        false
+    }
+
+    fn special_pgf(&mut self, special: &Special) -> bool {
+        // The glyphs before the special have to be drawn first, since it may change the
+        // graphics state.
+        self.put_run();
+        let color = self.colors.color();
+        let mut pgf = std::mem::take(&mut self.pgf);
+        let handled = pgf.special(special, color, self);
+        self.pgf = pgf;
+        handled
+    }
+
+    fn special_raw(&mut self, special: &Special) -> bool {
+        if self.raw_svg.special(special) {
+            return true;
+        }
+        self.put_raw();
+        false
+    }
+
+    /// Draw the buffered raw SVG.
+    fn put_raw(&mut self) {
+        if let Some(raw) = self.raw_svg.take() {
+            self.draw_raw(&raw);
+        }
+    }
+
+    /// Draw the SVG fragment `raw`.
+    fn draw_raw(&mut self, raw: &str) {
+        // The glyphs before the raw SVG have to be drawn first, since it may change the
+        // graphics state.
+        self.put_run();
+        for tag in raw_svg::parse_tags(raw) {
+            match tag {
+                Tag::Open {
+                    name,
+                    attributes,
+                    self_closing,
+                } => self.open_element(&name, &attributes, self_closing),
+                Tag::Close { .. } => self.close_element(),
+            }
+        }
+    }
+
+    fn open_element(&mut self, name: &str, attributes: &HashMap<String, String>, closed: bool) {
+        let element = match name {
+            "g" | "a" if !self.is_hidden() => {
+                self.content.push_str("gsave\n");
+                self.set_graphics_state(attributes);
+                OpenElement::Group(self.style(attributes))
+            }
+            "svg" => OpenElement::Transparent,
+            "clipPath" => {
+                let id = attributes.get("id").cloned().unwrap_or_default();
+                self.clip_paths.insert(id.clone(), String::new());
+                OpenElement::ClipPath(id)
+            }
+            "path" | "rect" | "circle" | "ellipse" | "line" | "polyline" | "polygon" => {
+                self.put_shape(name, attributes);
+                OpenElement::Transparent
+            }
+            _ => OpenElement::Hidden,
+        };
+        self.open_elements.push(element);
+        if closed {
+            self.close_element();
+        }
+    }
+
+    /// Whether the elements that are open stop anything from being drawn.
+    fn is_hidden(&self) -> bool {
+        self.open_elements
+            .iter()
+            .any(|e| matches!(e, OpenElement::Hidden | OpenElement::ClipPath(_)))
+    }
+
+    fn close_element(&mut self) {
+        if let Some(OpenElement::Group(_)) = self.open_elements.pop() {
+            self.content.push_str("grestore\n");
+        }
+    }
+
+    /// Draw a shape element, or add it to the clip path that is being defined.
+    fn put_shape(&mut self, name: &str, attributes: &HashMap<String, String>) {
+        let Some(path) = raw_svg::shape_segments(name, attributes) else {
+            return;
+        };
+        match self.open_elements.last() {
+            Some(OpenElement::ClipPath(id)) => {
+                // A clip path is used where the graphics state is that of the clipped element,
+                // so the transform of the shape is applied to its points instead.
+                let matrix = attributes
+                    .get("transform")
+                    .map_or(raw_svg::IDENTITY, |t| raw_svg::parse_transform(t));
+                let path = path
+                    .into_iter()
+                    .map(|s| s.transform(matrix))
+                    .collect::<Vec<_>>();
+                if let Some(clip_path) = self.clip_paths.get_mut(id) {
+                    clip_path.push_str(&path_operators(&path));
+                }
+                return;
+            }
+            _ if self.is_hidden() => return,
+            _ => {}
+        }
+
+        let path = path_operators(&path);
+        let style = self.style(attributes);
+        self.content.push_str("gsave\n");
+        self.set_graphics_state(attributes);
+        self.paint(
+            &path,
+            style.fill.map(Color::Rgb),
+            style.stroke.map(Color::Rgb),
+            style.even_odd,
+        );
+        self.content.push_str("grestore\n");
+    }
+
+    /// Fill and stroke the path with the operators `path`.
+    fn paint(&mut self, path: &str, fill: Option<Color>, stroke: Option<Color>, even_odd: bool) {
+        self.content.push_str("newpath\n");
+        let fill_operator = if even_odd { "eofill" } else { "fill" };
+        let set_color = Color::postscript_operator;
+        match (fill, stroke) {
+            (Some(fill_color), Some(stroke_color)) => writeln!(
+                self.content,
+                "{path}gsave {} {fill_operator} grestore {} stroke",
+                set_color(fill_color),
+                set_color(stroke_color)
+            ),
+            (Some(fill_color), None) => {
+                writeln!(
+                    self.content,
+                    "{path}{} {fill_operator}",
+                    set_color(fill_color)
+                )
+            }
+            (None, Some(stroke_color)) => {
+                writeln!(self.content, "{path}{} stroke", set_color(stroke_color))
+            }
+            (None, None) => Ok(()),
+        }
+        .unwrap();
+    }
+
+    /// The style inherited from the enclosing groups, updated with `attributes`.
+    fn style(&self, attributes: &HashMap<String, String>) -> Style {
+        let mut style = self
+            .open_elements
+            .iter()
+            .rev()
+            .find_map(|e| match e {
+                OpenElement::Group(style) => Some(*style),
+                _ => None,
+            })
+            .unwrap_or_default();
+        style.apply(attributes, &self.colors.color().to_hex());
+        style
+    }
+
+    /// Write the operators for the attributes that are part of the PostScript graphics state.
+    /// PostScript has no transparency, so opacity is ignored.
+    fn set_graphics_state(&mut self, attributes: &HashMap<String, String>) {
+        if let Some(transform) = attributes.get("transform") {
+            let matrix = raw_svg::parse_transform(transform);
+            let matrix = matrix.map(num).join(" ");
+            writeln!(self.content, "[{matrix}] concat").unwrap();
+        }
+        if let Some(clip_path) = attributes
+            .get("clip-path")
+            .and_then(|url| url.trim().strip_prefix("url(#"))
+            .and_then(|url| url.strip_suffix(')'))
+            .and_then(|id| self.clip_paths.get(id))
+        {
+            writeln!(self.content, "newpath\n{clip_path}clip newpath").unwrap();
+        }
+        if let Some(width) = attributes
+            .get("stroke-width")
+            .and_then(|w| raw_svg::parse_length(w))
+        {
+            writeln!(self.content, "{} setlinewidth", num(width)).unwrap();
+        }
+        if let Some(cap) = attributes.get("stroke-linecap") {
+            let cap = match cap.as_str() {
+                "round" => 1,
+                "square" => 2,
+                _ => 0,
+            };
+            writeln!(self.content, "{cap} setlinecap").unwrap();
+        }
+        if let Some(join) = attributes.get("stroke-linejoin") {
+            let join = match join.as_str() {
+                "round" => 1,
+                "bevel" => 2,
+                _ => 0,
+            };
+            writeln!(self.content, "{join} setlinejoin").unwrap();
+        }
+        if let Some(limit) = attributes
+            .get("stroke-miterlimit")
+            .and_then(|m| raw_svg::parse_length(m))
+        {
+            writeln!(self.content, "{} setmiterlimit", num(limit)).unwrap();
+        }
+        if let Some(dashes) = attributes.get("stroke-dasharray") {
+            let dashes = raw_svg::parse_number_list(dashes);
+            let offset = attributes
+                .get("stroke-dashoffset")
+                .and_then(|o| raw_svg::parse_length(o))
+                .unwrap_or(0.0);
+            let dashes = dashes.into_iter().map(num).collect::<Vec<_>>().join(" ");
+            writeln!(self.content, "[{dashes}] {} setdash", num(offset)).unwrap();
+        }
+    }
+}
+
+impl Canvas for EpsMachine {
+    fn begin_picture(&mut self) {
+        self.content.push_str("gsave\n");
+    }
+
+    fn end_picture(&mut self) {
+        self.put_run();
+        self.content.push_str("grestore\n");
+    }
+
+    fn begin_scope(&mut self) {
+        self.content.push_str("gsave\n");
+    }
+
+    fn end_scope(&mut self) {
+        self.put_run();
+        self.content.push_str("grestore\n");
+    }
+
+    fn clip(&mut self, path: &[PathSegment], even_odd: bool) {
+        let clip = if even_odd { "eoclip" } else { "clip" };
+        writeln!(
+            self.content,
+            "newpath\n{}{clip} newpath",
+            path_operators(path)
+        )
+        .unwrap();
+    }
+
+    fn draw_path(&mut self, path: &[PathSegment], paint: &Paint) {
+        // PostScript has no transparency, so the opacities are ignored.
+        self.content.push_str("gsave\n");
+        if paint.stroke.is_some() {
+            let cap = match paint.line_cap {
+                LineCap::Butt => 0,
+                LineCap::Round => 1,
+                LineCap::Square => 2,
+            };
+            let join = match paint.line_join {
+                LineJoin::Miter => 0,
+                LineJoin::Round => 1,
+                LineJoin::Bevel => 2,
+            };
+            let dashes = paint.dash_array.iter().map(|&d| num(d));
+            writeln!(
+                self.content,
+                "{} setlinewidth {cap} setlinecap {join} setlinejoin {} setmiterlimit [{}] {} setdash",
+                num(paint.line_width),
+                num(paint.miter_limit),
+                dashes.collect::<Vec<_>>().join(" "),
+                num(paint.dash_offset)
+            )
+            .unwrap();
+        }
+        self.paint(
+            &path_operators(path),
+            paint.fill,
+            paint.stroke,
+            paint.even_odd,
+        );
+        self.content.push_str("grestore\n");
+    }
+
+    fn begin_transform(&mut self, matrix: Matrix) {
+        let matrix = matrix.map(num).join(" ");
+        writeln!(self.content, "gsave [{matrix}] concat").unwrap();
+    }
+
+    fn end_transform(&mut self) {
+        self.put_run();
+        self.content.push_str("grestore\n");
+    }
+
+    fn shading(&mut self, shading: &Shading) {
+        if !self.reported_shading {
+            self.reported_shading = true;
+            self.logger.log(LogCategory::Warning, || {
+                "Shadings need PostScript LanguageLevel 3, so they are filled with their \
+                 average color in the EPS file"
+                    .to_string()
+            });
+        }
+        let [x, y, width, height] = shading.rect.map(num);
+        writeln!(
+            self.content,
+            "{} {x} {y} {width} {height} rectfill",
+            shading.average_color().postscript_operator()
+        )
+        .unwrap();
+    }
+
+    fn raw_svg(&mut self, svg: &str) -> bool {
+        self.draw_raw(svg);
+        true
+    }
+}
+
+/// The path construction operators for `segments`.
+fn path_operators(segments: &[PathSegment]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        match *segment {
+            PathSegment::MoveTo(x, y) => writeln!(path, "{} {} moveto", num(x), num(y)),
+            PathSegment::LineTo(x, y) => writeln!(path, "{} {} lineto", num(x), num(y)),
+            PathSegment::CurveTo(x1, y1, x2, y2, x, y) => writeln!(
+                path,
+                "{} {} {} {} {} {} curveto",
+                num(x1),
+                num(y1),
+                num(x2),
+                num(y2),
+                num(x),
+                num(y)
+            ),
+            PathSegment::Close => writeln!(path, "closepath"),
+        }
+        .unwrap();
+    }
+    path
+}
+
+/// Write character codes as a PostScript string, without the parentheses.
+fn escape(codes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &c in codes {
+        match c {
+            b'(' | b')' | b'\\' => write!(escaped, "\\{}", c as char),
+            0x20..=0x7E => write!(escaped, "{}", c as char),
+            _ => write!(escaped, "\\{c:03o}"),
+        }
+        .unwrap();
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dvi::Font;
+    use crate::dvi2svg::pgf::ShadingKind;
+    use std::sync::{Arc, Mutex};
+
+    fn font(name: &str) -> Font {
+        Font {
+            name: name.to_string(),
+            size: 10.0,
+            design_size: 10.0,
+        }
+    }
+
+    /// The character `code` of `font` at `(h, v)`.
+    fn glyph(font: &Font, code: u32, h: f64, v: f64, width: f64) -> Glyph<'_> {
+        Glyph {
+            code,
+            font,
+            h,
+            v,
+            width,
+            height: 6.8,
+            depth: 0.0,
+        }
+    }
+
+    fn special(text: &str) -> Special<'_> {
+        Special {
+            h: 0.0,
+            v: 0.0,
+            text,
+        }
+    }
+
+    #[test]
+    fn the_bounding_box_is_the_paper_size() {
+        let (cmr10, cmmi10) = (font("cmr10"), font("cmmi10"));
+        let mut machine = EpsMachine::new();
+        machine.special(&special("papersize=200.25pt,100pt"));
+        machine.glyph(&glyph(&cmr10, 65, 0.0, 0.0, 7.5));
+        machine.glyph(&glyph(&cmmi10, 65, 10.0, 0.0, 7.5));
+        machine.glyph(&glyph(&cmr10, 66, 20.0, 0.0, 7.5));
+        let eps = String::from_utf8(machine.into_eps().unwrap()).unwrap();
+        assert!(
+            eps.starts_with(
+                "%!PS-Adobe-3.0 EPSF-3.0\n\
+                 %%BoundingBox: 0 0 201 100\n\
+                 %%HiResBoundingBox: 0 0 200.25 100\n\
+                 %%Creator: rust-tikz\n\
+                 %%LanguageLevel: 2\n\
+                 %%DocumentNeededResources: font CMR10\n\
+                 %%+ font CMMI10\n\
+                 %%Pages: 1\n\
+                 %%EndComments\n\
+                 %%BeginSetup\n\
+                 %%IncludeResource: font CMR10\n/F0 /CMR10 findfont def\n\
+                 %%IncludeResource: font CMMI10\n/F1 /CMMI10 findfont def\n\
+                 %%EndSetup\n\
+                 %%Page: 1 1\n\
+                 gsave\n\
+                 72 28 translate 1 -1 scale\n"
+            ),
+            "{eps}"
+        );
+        assert!(eps.ends_with("grestore\nshowpage\n%%EOF\n"), "{eps}");
+    }
+
+    #[test]
+    fn a_missing_paper_size_is_an_error() {
+        let mut machine = EpsMachine::new();
+        machine.glyph(&glyph(&font("cmr10"), 65, 0.0, 0.0, 7.5));
+        assert!(machine.into_eps().unwrap_err().contains("papersize"));
+    }
+
+    #[test]
+    fn adjacent_glyphs_are_shown_together() {
+        let cmr10 = font("cmr10");
+        let mut machine = EpsMachine::new();
+        machine.glyph(&glyph(&cmr10, 65, 10.0, 20.0, 7.5));
+        machine.glyph(&glyph(&cmr10, 66, 17.5, 20.0, 7.0));
+        // A space between words starts a new run, and so does a new line.
+        machine.glyph(&glyph(&cmr10, 67, 30.0, 20.0, 7.0));
+        machine.glyph(&glyph(&cmr10, 68, 10.0, 40.0, 7.0));
+        // So do another font, size and color.
+        machine.glyph(&glyph(&font("cmbx10"), 69, 17.0, 40.0, 7.0));
+        let cmr12 = Font {
+            size: 12.0,
+            ..font("cmr10")
+        };
+        machine.glyph(&glyph(&cmr12, 70, 24.0, 40.0, 7.0));
+        machine.special(&special("color push rgb 1 0 0"));
+        machine.glyph(&glyph(&cmr12, 71, 31.0, 40.0, 7.0));
+        machine.end_page();
+        assert_eq!(
+            machine.content,
+            "F0 [10 0 0 -10 0 0] makefont setfont 0 setgray 10 20 moveto (AB) show\n\
+             F0 [10 0 0 -10 0 0] makefont setfont 0 setgray 30 20 moveto (C) show\n\
+             F0 [10 0 0 -10 0 0] makefont setfont 0 setgray 10 40 moveto (D) show\n\
+             F1 [10 0 0 -10 0 0] makefont setfont 0 setgray 17 40 moveto (E) show\n\
+             F0 [12 0 0 -12 0 0] makefont setfont 0 setgray 24 40 moveto (F) show\n\
+             F0 [12 0 0 -12 0 0] makefont setfont 1 0 0 setrgbcolor 31 40 moveto (G) show\n"
+        );
+    }
+
+    #[test]
+    fn glyphs_are_shown_before_anything_else_is_drawn() {
+        let cmr10 = font("cmr10");
+        let mut machine = EpsMachine::new();
+        machine.glyph(&glyph(&cmr10, 65, 10.0, 20.0, 7.5));
+        machine.rule(&Rule {
+            h: 17.5,
+            v: 20.0,
+            width: 1.0,
+            height: 2.0,
+        });
+        machine.glyph(&glyph(&cmr10, 66, 18.5, 20.0, 7.0));
+        machine.end_page();
+        assert_eq!(
+            machine.content,
+            "F0 [10 0 0 -10 0 0] makefont setfont 0 setgray 10 20 moveto (A) show\n\
+             0 setgray 17.5 18 1 2 rectfill\n\
+             F0 [10 0 0 -10 0 0] makefont setfont 0 setgray 18.5 20 moveto (B) show\n"
+        );
+    }
+
+    #[test]
+    fn the_shapes_of_a_clip_path_are_transformed() {
+        let mut machine = EpsMachine::new();
+        machine.draw_raw(
+            "<clipPath id=\"c\"><rect width=\"10\" height=\"5\" \
+             transform=\"translate(5 5)\"/></clipPath>\
+             <g clip-path=\"url(#c)\"><line x1=\"0\" y1=\"0\" x2=\"20\" y2=\"20\" \
+             stroke=\"#000\"/></g>",
+        );
+        assert!(
+            machine.content.starts_with(
+                "gsave\nnewpath\n5 5 moveto\n15 5 lineto\n15 10 lineto\n5 10 lineto\n\
+                 closepath\nclip newpath\n"
+            ),
+            "{}",
+            machine.content
+        );
+    }
+
+    #[test]
+    fn shadings_are_filled_with_their_average_color_and_reported_once() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let mut machine = EpsMachine::new();
+        machine.set_logger(Logger::new({
+            let warnings = warnings.clone();
+            move |category, message| {
+                warnings
+                    .lock()
+                    .unwrap()
+                    .push(format!("{category}: {message}"))
+            }
+        }));
+        let shading = Shading {
+            kind: ShadingKind::Axial {
+                from: (0.0, 0.0),
+                to: (10.0, 0.0),
+            },
+            stops: vec![
+                (0.0, Color::Rgb([1.0, 0.0, 0.0])),
+                (10.0, Color::Rgb([0.0, 0.0, 1.0])),
+            ],
+            rect: [0.0, 0.0, 10.0, 5.0],
+        };
+        machine.shading(&shading);
+        machine.shading(&shading);
+        assert_eq!(
+            machine.content,
+            "0.5 0 0.5 setrgbcolor 0 0 10 5 rectfill\n\
+             0.5 0 0.5 setrgbcolor 0 0 10 5 rectfill\n"
+        );
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            [
+                "warning: Shadings need PostScript LanguageLevel 3, so they are filled with their \
+                 average color in the EPS file"
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(escape(b"abc 123"), "abc 123");
+        assert_eq!(escape(b"f(x)"), "f\\(x\\)");
+        assert_eq!(escape(b"a\\b"), "a\\\\b");
+        // Control characters and bytes from 0x80 on are written in octal.
+        assert_eq!(
+            escape(&[0, 0x0A, 0x7F, 0x80, 0xFF]),
+            "\\000\\012\\177\\200\\377"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use super::color::ColorSpecials;
+use super::encodings::unicode;
+use super::ids;
+use super::links::{LinkSpecial, MarkupLinks};
+use super::pgf::PgfInterpreter;
+use super::raw_svg::RawSvgBuffer;
+use super::svg_canvas::SvgCanvas;
+use super::utils::escape;
+use crate::dvi::{Glyph, Machine, Rule, Special};
+use crate::logging::Logger;
+
+/// An implementation of `Machine` that generates an HTML fragment, in the style of
+/// `dvi2html`. Text outside of pictures is written as absolutely positioned `<span>`s so that
+/// it can be selected and styled with CSS, and each TikZ picture becomes an inline `<svg>`.
+///
+/// The fragment is a `<div class="rust-tikz">` with the size of the page. Text that TeX did
+/// not give a color inherits the `color` of the surrounding page.
+#[derive(Debug)]
+pub struct HtmlMachine {
+    content: String,
+    colors: ColorSpecials,
+    paperwidth: Option<f64>,
+    paperheight: Option<f64>,
+
+    raw_svg: RawSvgBuffer,
+    pgf: PgfInterpreter,
+    links: MarkupLinks,
+    canvas: SvgCanvas,
+    /// The prefix of the ids in the output, or `None` to derive one from the content.
+    id_prefix: Option<String>,
+}
+
+impl HtmlMachine {
+    pub fn new() -> HtmlMachine {
+        HtmlMachine {
+            content: "".to_string(),
+            colors: ColorSpecials::default(),
+            paperwidth: None,
+            paperheight: None,
+            raw_svg: RawSvgBuffer::default(),
+            pgf: PgfInterpreter::default(),
+            links: MarkupLinks::default(),
+            canvas: SvgCanvas::new(),
+            id_prefix: None,
+        }
+    }
+
+    /// Report the parts of TikZ pictures that cannot be drawn to `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.pgf.set_logger(logger);
+    }
+
+    /// Prefix every id in the output with `prefix`, so that several fragments can be embedded
+    /// in the same page. By default the prefix is derived from a hash of the content.
+    pub fn set_id_prefix(&mut self, prefix: String) {
+        self.id_prefix = Some(prefix);
+    }
+
+    /// Assemble the fragment. Its size is that of the page, which TeX gives with a `papersize`
+    /// special. Without one, the size is not known and this fails.
+    pub fn get_content(&self) -> Result<String, String> {
+        let (Some(width), Some(height)) = (self.paperwidth, self.paperheight) else {
+            return Err(
+                "The DVI file has no `papersize` special, so the size of the HTML fragment is \
+                 not known"
+                    .to_string(),
+            );
+        };
+        let background = match self.colors.background {
+            Some(color) => format!(" background-color: {};", color.to_hex()),
+            None => "".to_string(),
+        };
+        let prefix = match &self.id_prefix {
+            Some(prefix) => prefix.clone(),
+            None => ids::content_prefix(&self.content),
+        };
+        Ok(format!(
+            r#"<div class="rust-tikz" style="position: relative; width: {width}pt; height: {height}pt;{background}">{}
+</div>"#,
+            ids::prefix_ids(&self.content, &prefix)
+        ))
+    }
+
+    /// The color set by TeX, or `currentColor` if there is none so that the color of the
+    /// surrounding page is used.
+    fn css_color(&self) -> String {
+        match self.colors.current {
+            Some(color) => color.to_hex(),
+            None => "currentColor".to_string(),
+        }
+    }
+
+    /// Whether glyphs and rules are drawn inside a picture, as SVG rather than HTML.
+    fn in_svg(&self) -> bool {
+        self.canvas.in_picture()
+    }
+
+    /// The `<svg>` tag that opens a picture on a page of `width` by `height`. Each picture
+    /// covers the whole page and uses the same coordinates as the DVI file.
+    fn picture_tag(width: f64, height: f64) -> String {
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}pt" height="{height}pt" viewBox="-72 -72 {width} {height}" style="position: absolute; left: 0; top: 0; overflow: visible;">"#
+        )
+    }
+}
+
+impl Machine for HtmlMachine {
+    fn glyph(&mut self, glyph: &Glyph) {
+        self.put_svg();
+        let text = escape(unicode(glyph));
+        let font_name = &glyph.font.name;
+        let fontsize = glyph.font.size;
+
+        if self.in_svg() {
+            let fill = self.css_color();
+            self.content.push_str(&format!(
+                r#"
+<text x="{}" y="{}" font-family="{font_name}" font-size="{fontsize}" fill="{fill}">{text}</text>"#,
+                glyph.h, glyph.v
+            ));
+            return;
+        }
+
+        // Positions are measured from TeX's reference point, which is an inch from the top
+        // left corner of the page. `line-height: 0` puts the baseline at `top`.
+        let color = match self.colors.current {
+            Some(color) => format!(" color: {};", color.to_hex()),
+            None => "".to_string(),
+        };
+        self.content.push_str(&format!(
+            r#"
+<span style="position: absolute; left: {}pt; top: {}pt; line-height: 0; white-space: pre; font-family: {font_name}, {}; font-size: {fontsize}pt;{}{color}">{text}</span>"#,
+            glyph.h + 72.0,
+            glyph.v + 72.0,
+            fallback_family(font_name),
+            font_style(font_name),
+        ));
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        self.put_svg();
+        let left = rule.h;
+        let top = rule.v - rule.height;
+        let (width, height) = (rule.width, rule.height);
+
+        if self.in_svg() {
+            let fill = self.css_color();
+            self.content.push_str(&format!(
+                r#"
+<rect x="{left}" y="{top}" width="{width}" height="{height}" fill="{fill}" stroke="none" />"#
+            ));
+            return;
+        }
+        let color = self.css_color();
+        self.content.push_str(&format!(
+            r#"
+<span style="position: absolute; left: {}pt; top: {}pt; width: {width}pt; height: {height}pt; background-color: {color};"></span>"#,
+            left + 72.0,
+            top + 72.0
+        ));
+    }
+    fn end_page(&mut self) {
+        self.put_svg();
+        self.links.finish(&mut self.content);
+    }
+    fn special(&mut self, special: &Special) {
+        if self.special_svg(special)
+            || self.special_pgf(special)
+            || self.colors.special(special.text)
+            || self.special_link(special.text)
+        {
+            return;
+        }
+        self.special_papersize(special.text);
+    }
+}
+
+//Specials
+impl HtmlMachine {
+    fn special_link(&mut self, command: &str) -> bool {
+        let Some(link) = LinkSpecial::parse(command) else {
+            return false;
+        };
+        self.links.special(link, &mut self.content);
+        true
+    }
+
+    fn special_papersize(&mut self, command: &str) -> bool {
+        let pattern = "papersize=";
+        if let Some(sizes) = command.strip_prefix(pattern) {
+            let mut sizes = sizes.split(',').map(super::raw_svg::parse_length);
+            if let (Some(Some(width)), Some(Some(height))) = (sizes.next(), sizes.next()) {
+                self.paperwidth = Some(width);
+                self.paperheight = Some(height);
+                self.canvas
+                    .set_picture_tag(HtmlMachine::picture_tag(width, height));
+            }
+        }
+        false
+    }
+
+    fn special_pgf(&mut self, special: &Special) -> bool {
+        if !self
+            .pgf
+            .special(special, self.colors.color(), &mut self.canvas)
+        {
+            return false;
+        }
+        self.content.push_str(&self.canvas.take());
+        true
+    }
+
+    fn special_svg(&mut self, special: &Special) -> bool {
+        if self.raw_svg.special(special) {
+            return true;
+        }
+        self.put_svg();
+        false
+    }
+
+    /// Write out the buffered raw SVG.
+    fn put_svg(&mut self) {
+        if let Some(svg) = self.raw_svg.take() {
+            self.content.push('\n');
+            self.content.push_str(&svg);
+        }
+    }
+}
+
+/// The generic CSS font family used when the TeX font `font_name` is not installed.
+fn fallback_family(font_name: &str) -> &'static str {
+    if font_name.starts_with("cmtt") {
+        "monospace"
+    } else {
+        "serif"
+    }
+}
+
+/// Declarations that make the fallback font look like the TeX font `font_name`.
+fn font_style(font_name: &str) -> &'static str {
+    if font_name.starts_with("cmb") {
+        " font-weight: bold;"
+    } else if ["cmmi", "cmti", "cmsl"]
+        .iter()
+        .any(|p| font_name.starts_with(p))
+    {
+        " font-style: italic;"
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dvi::Font;
+
+    fn cmr10() -> Font {
+        Font {
+            name: "cmr10".to_string(),
+            size: 10.0,
+            design_size: 10.0,
+        }
+    }
+
+    /// The character `code` of `font` at `(h, v)`.
+    fn glyph(font: &Font, code: u32, h: f64, v: f64) -> Glyph<'_> {
+        Glyph {
+            code,
+            font,
+            h,
+            v,
+            width: 7.5,
+            height: 6.8,
+            depth: 0.0,
+        }
+    }
+
+    fn special(text: &str, h: f64, v: f64) -> Special<'_> {
+        Special { h, v, text }
+    }
+
+    #[test]
+    fn text_is_written_as_spans_positioned_on_the_page() {
+        let font = cmr10();
+        let mut machine = HtmlMachine::new();
+        machine.special(&special("papersize=100pt,50pt", 0.0, 0.0));
+        machine.glyph(&glyph(&font, 65, 10.0, 20.0));
+        machine.special(&special("color push rgb 1 0 0", 17.5, 20.0));
+        machine.glyph(&glyph(&font, b'&' as u32, 17.5, 20.0));
+        machine.rule(&Rule {
+            h: 25.0,
+            v: 20.0,
+            width: 5.0,
+            height: 2.0,
+        });
+        machine.end_page();
+        assert_eq!(
+            machine.get_content().unwrap(),
+            r#"<div class="rust-tikz" style="position: relative; width: 100pt; height: 50pt;">
+<span style="position: absolute; left: 82pt; top: 92pt; line-height: 0; white-space: pre; font-family: cmr10, serif; font-size: 10pt;">A</span>
+<span style="position: absolute; left: 89.5pt; top: 92pt; line-height: 0; white-space: pre; font-family: cmr10, serif; font-size: 10pt; color: #ff0000;">&amp;</span>
+<span style="position: absolute; left: 97pt; top: 90pt; width: 5pt; height: 2pt; background-color: #ff0000;"></span>
+</div>"#
+        );
+    }
+
+    #[test]
+    fn pictures_are_inline_svg_with_prefixed_ids() {
+        let font = cmr10();
+        let mut machine = HtmlMachine::new();
+        machine.set_id_prefix("doc-".to_string());
+        machine.special(&special("papersize=100pt,50pt", 0.0, 0.0));
+        machine.special(&special(
+            "rusttikz:begin-picture;clip;rect 0 0 2 1;discard;moveto 0 0;lineto 5 5;stroke;",
+            10.0,
+            20.0,
+        ));
+        machine.glyph(&glyph(&font, 65, 12.0, 18.0));
+        machine.special(&special("rusttikz:end-picture;", 10.0, 20.0));
+        machine.end_page();
+        let html = machine.get_content().unwrap();
+        assert!(
+            html.contains(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="100pt" height="50pt" viewBox="-72 -72 100 50" style="position: absolute; left: 0; top: 0; overflow: visible;">"#
+            ),
+            "{html}"
+        );
+        assert!(html.contains(r#"<clipPath id="doc-pgf-clip-1">"#), "{html}");
+        assert!(
+            html.contains(r#"clip-path="url(#doc-pgf-clip-1)""#),
+            "{html}"
+        );
+        assert!(!html.contains(r#""pgf-clip-1""#), "{html}");
+        // Text inside a picture is part of the SVG rather than a span.
+        assert!(
+            html.contains(
+                r#"<text x="12" y="18" font-family="cmr10" font-size="10" fill="currentColor">A</text>"#
+            ),
+            "{html}"
+        );
+        assert!(!html.contains("<span"), "{html}");
+        assert!(html.ends_with("</svg>\n</div>"), "{html}");
+    }
+
+    #[test]
+    fn a_missing_paper_size_is_an_error() {
+        let mut machine = HtmlMachine::new();
+        machine.glyph(&glyph(&cmr10(), 65, 0.0, 0.0));
+        assert!(machine.get_content().unwrap_err().contains("papersize"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Ids in SVG and HTML output. Every document defines ids for its clip paths, gradients,
+//! markers and anchors, and since they are numbered the same way in every document, two
+//! documents that are embedded in the same page would refer to each other's definitions.
+//! Prefixing the ids with something unique to the document keeps them apart.
+
+use std::collections::HashSet;
+
+/// A prefix for the ids in `markup` that is derived from its content, so that different
+/// documents get different prefixes but the same document always gets the same one.
+pub fn content_prefix(markup: &str) -> String {
+    // FNV-1a, since the hash of `DefaultHasher` may change between Rust versions.
+    let hash = markup
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("rt{:08x}-", (hash >> 32) as u32 ^ hash as u32)
+}
+
+/// Add `prefix` to every id defined in `markup` and to every `url(#...)` and `href="#..."`
+/// reference to one of them. References to ids that are not defined in `markup`, e.g. to an
+/// anchor in the surrounding page, are left alone.
+///
+/// `prefix` is inserted as it is, so it should only contain characters that are valid in an
+/// XML name.
+pub fn prefix_ids(markup: &str, prefix: &str) -> String {
+    let ids = attribute_values(markup, r#"id=""#, '"')
+        .chain(attribute_values(markup, "id='", '\''))
+        .collect::<HashSet<_>>();
+    if ids.is_empty() {
+        return markup.to_string();
+    }
+    let mut output = markup.to_string();
+    for (opener, closer) in [
+        (r#"id=""#, '"'),
+        ("id='", '\''),
+        ("url(#", ')'),
+        (r##"href="#"##, '"'),
+        ("href='#", '\''),
+    ] {
+        output = insert_prefix(&output, opener, closer, &ids, prefix);
+    }
+    output
+}
+
+/// The values that start after each `opener` in `markup` and end before the next `closer`.
+fn attribute_values<'a>(
+    markup: &'a str,
+    opener: &'a str,
+    closer: char,
+) -> impl Iterator<Item = &'a str> + 'a {
+    openings(markup, opener).filter_map(move |start| {
+        let value = &markup[start..];
+        value.find(closer).map(|end| &value[..end])
+    })
+}
+
+/// Insert `prefix` after each `opener` in `markup` that is followed by one of `ids` and then
+/// `closer`.
+fn insert_prefix(
+    markup: &str,
+    opener: &str,
+    closer: char,
+    ids: &HashSet<&str>,
+    prefix: &str,
+) -> String {
+    let mut output = String::with_capacity(markup.len());
+    let mut copied = 0;
+    for start in openings(markup, opener) {
+        let value = &markup[start..];
+        if value
+            .find(closer)
+            .is_some_and(|end| ids.contains(&value[..end]))
+        {
+            output.push_str(&markup[copied..start]);
+            output.push_str(prefix);
+            copied = start;
+        }
+    }
+    output.push_str(&markup[copied..]);
+    output
+}
+
+/// The positions right after each `opener` in `markup` that is not the end of a longer
+/// attribute name, e.g. the `id="` of `data-id="`.
+fn openings<'a>(markup: &'a str, opener: &'a str) -> impl Iterator<Item = usize> + 'a {
+    markup
+        .match_indices(opener)
+        .filter(|&(index, _)| {
+            !markup[..index].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map(move |(index, _)| index + opener.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_and_references_are_prefixed() {
+        let markup = concat!(
+            r#"<clipPath id="clip1"><path d="M0 0"/></clipPath>"#,
+            r#"<linearGradient id='grad1'/>"#,
+            r##"<g clip-path="url(#clip1)" fill="url(#grad1)">"##,
+            r##"<use href="#clip1"/><use xlink:href="#grad1"/><a href='#clip1'>x</a></g>"##,
+        );
+        assert_eq!(
+            prefix_ids(markup, "p-"),
+            concat!(
+                r#"<clipPath id="p-clip1"><path d="M0 0"/></clipPath>"#,
+                r#"<linearGradient id='p-grad1'/>"#,
+                r##"<g clip-path="url(#p-clip1)" fill="url(#p-grad1)">"##,
+                r##"<use href="#p-clip1"/><use xlink:href="#p-grad1"/><a href='#p-clip1'>x</a></g>"##,
+            )
+        );
+    }
+
+    #[test]
+    fn references_outside_of_the_markup_are_left_alone() {
+        let markup = concat!(
+            r#"<a id="top"></a>"#,
+            r##"<a href="#section.2">x</a><a href="https://example.com/#top">y</a>"##,
+            r#"<a href="top.html">z</a><g data-id="top" fill="url(#other)"/>"#,
+        );
+        assert_eq!(
+            prefix_ids(markup, "p-"),
+            concat!(
+                r#"<a id="p-top"></a>"#,
+                r##"<a href="#section.2">x</a><a href="https://example.com/#top">y</a>"##,
+                r#"<a href="top.html">z</a><g data-id="top" fill="url(#other)"/>"#,
+            )
+        );
+        // Without any ids, nothing is changed.
+        let markup = r##"<a href="#top">x</a>"##;
+        assert_eq!(prefix_ids(markup, "p-"), markup);
+    }
+
+    #[test]
+    fn content_prefixes_depend_only_on_the_content() {
+        let prefix = content_prefix("<path d=\"M0 0\"/>");
+        assert_eq!(prefix, content_prefix("<path d=\"M0 0\"/>"));
+        assert_ne!(prefix, content_prefix("<path d=\"M0 1\"/>"));
+        // The prefix does not change between builds, since it is part of the output.
+        assert_eq!(content_prefix(""), "rt4fd0bfc1-");
+        assert!(
+            prefix.starts_with("rt")
+                && prefix.ends_with('-')
+                && prefix.len() == 11
+                && prefix[2..10].chars().all(|c| c.is_ascii_hexdigit())
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Hyperlink specials. `hyperref` writes different specials depending on its driver:
+//!
+//! - `hypertex` writes HTML tags: `html:<a href="...">`, `html:<a name="...">` and `html:</a>`.
+//! - `dvipdfmx` writes annotations: `pdf:bann << ... >>`, `pdf:eann` and `pdf:dest (name) ...`.
+//! - `dvips` writes pdfmark PostScript, which only gives the target once the link ends:
+//!   `ps:SDict begin H.S end` starts a link and `ps:SDict begin [... /ANN pdfmark end` ends it.
+
+use super::utils::escape_attribute;
+
+/// Where a link points to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// A URI, e.g. `https://example.com`.
+    Uri(String),
+    /// A named destination in the same document.
+    Destination(String),
+}
+
+/// A hyperlink special.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkSpecial {
+    /// The start of a link. The target is `None` if it is only given by [`LinkSpecial::End`].
+    Begin(Option<Target>),
+    /// The end of the innermost link or anchor.
+    End(Option<Target>),
+    /// The start of an anchor with the given name, which contains everything up to the next
+    /// [`LinkSpecial::End`].
+    BeginAnchor(String),
+    /// A named destination at the position of the special.
+    Destination(String),
+}
+
+impl LinkSpecial {
+    /// Parse `special` if it is a hyperlink special.
+    pub fn parse(special: &str) -> Option<LinkSpecial> {
+        if let Some(tag) = special.strip_prefix("html:") {
+            return parse_html(tag.trim());
+        }
+        if let Some(command) = special.strip_prefix("pdf:") {
+            let command = command.trim_start();
+            if let Some(annotation) = command.strip_prefix("bann") {
+                return Some(LinkSpecial::Begin(parse_target(annotation)));
+            }
+            if command.starts_with("eann") {
+                return Some(LinkSpecial::End(None));
+            }
+            if let Some(destination) = command.strip_prefix("dest") {
+                return pdf_string(destination).map(LinkSpecial::Destination);
+            }
+            return None;
+        }
+        let code = special.strip_prefix("ps:SDict begin")?;
+        if code.trim_start().starts_with("H.S") {
+            Some(LinkSpecial::Begin(None))
+        } else if code.contains("/ANN pdfmark") {
+            Some(LinkSpecial::End(parse_target(code)))
+        } else if code.contains("/DEST pdfmark") {
+            let (_, name) = code.split_once("/Dest")?;
+            pdf_string(name).map(LinkSpecial::Destination)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse an HTML `<a>` tag written by the `hypertex` driver.
+fn parse_html(tag: &str) -> Option<LinkSpecial> {
+    if tag.eq_ignore_ascii_case("</a>") {
+        return Some(LinkSpecial::End(None));
+    }
+    let attributes = tag.strip_prefix("<a ")?.strip_suffix('>')?;
+    let (name, value) = attributes.split_once('=')?;
+    let value = value.trim().trim_matches('"').to_string();
+    match name.trim() {
+        "href" => Some(LinkSpecial::Begin(Some(match value.strip_prefix('#') {
+            Some(name) => Target::Destination(name.to_string()),
+            None => Target::Uri(value),
+        }))),
+        "name" => Some(LinkSpecial::BeginAnchor(value)),
+        _ => None,
+    }
+}
+
+/// Find the target of a link in a PDF annotation or pdfmark, e.g. `/URI (https://...)` or
+/// `/Dest (name)`.
+fn parse_target(annotation: &str) -> Option<Target> {
+    // The last `/URI` is the key; an earlier one may be the action type, `/S /URI`.
+    if let Some((_, uri)) = annotation.rsplit_once("/URI") {
+        return pdf_string(uri).map(Target::Uri);
+    }
+    let (_, destination) = annotation
+        .split_once("/Dest")
+        .or_else(|| annotation.split_once("/D"))?;
+    pdf_string(destination).map(Target::Destination)
+}
+
+/// Read the PDF literal string at the start of `input`, e.g. `(name)`, or a PDF name, e.g.
+/// `/name`.
+fn pdf_string(input: &str) -> Option<String> {
+    let input = input.trim_start();
+    if let Some(name) = input.strip_prefix('/') {
+        let end = name
+            .find(|c: char| c.is_whitespace() || "/<>[]()".contains(c))
+            .unwrap_or(name.len());
+        return Some(name[..end].to_string());
+    }
+    let mut chars = input.strip_prefix('(')?.chars();
+    let mut string = String::new();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => string.extend(chars.next()),
+            '(' => {
+                depth += 1;
+                string.push(c);
+            }
+            ')' if depth == 0 => return Some(string),
+            ')' => {
+                depth -= 1;
+                string.push(c);
+            }
+            _ => string.push(c),
+        }
+    }
+    None
+}
+
+/// Wraps links and anchors in `<a>` elements, for the SVG and HTML backends. Since the target
+/// of a link may only be known once it ends, the opening tag is inserted into the markup when
+/// the link ends.
+#[derive(Debug, Default)]
+pub struct MarkupLinks {
+    /// The position in the markup where each open link or anchor starts, and its opening tag
+    /// if it is already known.
+    open: Vec<(usize, Option<String>)>,
+}
+
+impl MarkupLinks {
+    /// Handle `special` by adding to `markup`, which has to hold everything drawn so far.
+    pub fn special(&mut self, special: LinkSpecial, markup: &mut String) {
+        match special {
+            LinkSpecial::Begin(target) => self.open.push((markup.len(), target.map(link_tag))),
+            LinkSpecial::BeginAnchor(name) => self.open.push((
+                markup.len(),
+                Some(format!(r#"<a id="{}">"#, escape_attribute(&name))),
+            )),
+            LinkSpecial::End(target) => {
+                let Some((start, tag)) = self.open.pop() else {
+                    return;
+                };
+                if let Some(tag) = tag.or(target.map(link_tag)) {
+                    markup.insert_str(start, &tag);
+                    markup.push_str("</a>");
+                }
+            }
+            LinkSpecial::Destination(name) => {
+                markup.push_str(&format!(r#"<a id="{}"></a>"#, escape_attribute(&name)));
+            }
+        }
+    }
+
+    /// Close the links that are still open at the end of the page.
+    pub fn finish(&mut self, markup: &mut String) {
+        while !self.open.is_empty() {
+            self.special(LinkSpecial::End(None), markup);
+        }
+    }
+}
+
+/// The opening tag of a link to `target`.
+fn link_tag(target: Target) -> String {
+    let href = match target {
+        Target::Uri(uri) => uri,
+        Target::Destination(name) => format!("#{name}"),
+    };
+    format!(r#"<a href="{}">"#, escape_attribute(&href))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(uri: &str) -> Option<Target> {
+        Some(Target::Uri(uri.to_string()))
+    }
+
+    fn destination(name: &str) -> Option<Target> {
+        Some(Target::Destination(name.to_string()))
+    }
+
+    #[test]
+    fn hypertex_specials_are_html_tags() {
+        for (special, link) in [
+            (
+                r#"html:<a href="https://example.com">"#,
+                LinkSpecial::Begin(uri("https://example.com")),
+            ),
+            (
+                r##"html:<a href="#section.1">"##,
+                LinkSpecial::Begin(destination("section.1")),
+            ),
+            (
+                r#"html:<a name="section.1">"#,
+                LinkSpecial::BeginAnchor("section.1".to_string()),
+            ),
+            ("html:</a>", LinkSpecial::End(None)),
+            ("html: </A> ", LinkSpecial::End(None)),
+        ] {
+            assert_eq!(LinkSpecial::parse(special), Some(link), "{special}");
+        }
+        assert_eq!(LinkSpecial::parse("html:<img src=\"a.png\">"), None);
+        assert_eq!(LinkSpecial::parse("html:<a class=\"x\">"), None);
+    }
+
+    #[test]
+    fn dvipdfmx_specials_are_annotations() {
+        for (special, link) in [
+            (
+                "pdf:bann<</Type/Annot/Subtype/Link/Border[0 0 0]/A<</S/URI/URI(https://example.com/a\\)b)>>>>",
+                LinkSpecial::Begin(uri("https://example.com/a)b")),
+            ),
+            (
+                "pdf:bann<</Type/Annot/Subtype/Link/Border[0 0 1]/H/I/C[1 0 0]/A<</S/GoTo/D(section.1)>>>>",
+                LinkSpecial::Begin(destination("section.1")),
+            ),
+            ("pdf:eann", LinkSpecial::End(None)),
+            (
+                "pdf:dest (f(x)) [@thispage /XYZ @xpos @ypos null]",
+                LinkSpecial::Destination("f(x)".to_string()),
+            ),
+            (
+                "pdf: dest /page.1 [@thispage /XYZ @xpos @ypos null]",
+                LinkSpecial::Destination("page.1".to_string()),
+            ),
+        ] {
+            assert_eq!(LinkSpecial::parse(special), Some(link), "{special}");
+        }
+        assert_eq!(LinkSpecial::parse("pdf:docinfo<</Title(x)>>"), None);
+        assert_eq!(LinkSpecial::parse("pdf:dest (unterminated"), None);
+    }
+
+    #[test]
+    fn dvips_specials_give_the_target_at_the_end() {
+        for (special, link) in [
+            ("ps:SDict begin H.S end", LinkSpecial::Begin(None)),
+            (
+                "ps:SDict begin [/H /I/Border [0 0 1]/BS<</S/S/W 1>>/Color [0 1 1]/Action << /Subtype /URI /URI (https://example.com) >>/Subtype /Link H.B /ANN pdfmark end",
+                LinkSpecial::End(uri("https://example.com")),
+            ),
+            (
+                "ps:SDict begin [/H /I/Border [0 0 1]/BS<</S/S/W 1>>/Color [1 0 0]/Dest (section.1) cvn/Subtype /Link H.B /ANN pdfmark end",
+                LinkSpecial::End(destination("section.1")),
+            ),
+            (
+                "ps:SDict begin [/View [/XYZ H.V]/Dest (section.1) cvn /DEST pdfmark end",
+                LinkSpecial::Destination("section.1".to_string()),
+            ),
+        ] {
+            assert_eq!(LinkSpecial::parse(special), Some(link), "{special}");
+        }
+        assert_eq!(LinkSpecial::parse("ps:SDict begin H.R end"), None);
+        assert_eq!(LinkSpecial::parse("ps: 0 0 moveto"), None);
+        assert_eq!(LinkSpecial::parse("color push Red"), None);
+    }
+
+    /// Feed `specials` to a `MarkupLinks`, with `None` standing for the text `x`, and return the
+    /// markup.
+    fn markup(specials: Vec<Option<LinkSpecial>>) -> String {
+        let mut links = MarkupLinks::default();
+        let mut markup = String::new();
+        for special in specials {
+            match special {
+                Some(special) => links.special(special, &mut markup),
+                None => markup.push('x'),
+            }
+        }
+        links.finish(&mut markup);
+        markup
+    }
+
+    #[test]
+    fn links_wrap_what_is_drawn_between_their_specials() {
+        assert_eq!(
+            markup(vec![
+                None,
+                Some(LinkSpecial::Begin(uri("https://example.com/?a=1&b=\"2\""))),
+                None,
+                Some(LinkSpecial::End(None)),
+                None,
+            ]),
+            r#"x<a href="https://example.com/?a=1&amp;b=&quot;2&quot;">x</a>x"#
+        );
+        // dvips only gives the target at the end.
+        assert_eq!(
+            markup(vec![
+                Some(LinkSpecial::Begin(None)),
+                None,
+                Some(LinkSpecial::End(destination("eq:1"))),
+            ]),
+            r##"<a href="#eq:1">x</a>"##
+        );
+        // Links without a target are left out, and so are ends without a start.
+        assert_eq!(
+            markup(vec![
+                Some(LinkSpecial::Begin(None)),
+                None,
+                Some(LinkSpecial::End(None)),
+                Some(LinkSpecial::End(uri("https://example.com"))),
+            ]),
+            "x"
+        );
+        assert_eq!(
+            markup(vec![
+                Some(LinkSpecial::Destination("top".to_string())),
+                None
+            ]),
+            r#"<a id="top"></a>x"#
+        );
+    }
+
+    #[test]
+    fn nested_links_end_innermost_first() {
+        assert_eq!(
+            markup(vec![
+                Some(LinkSpecial::BeginAnchor("section.1".to_string())),
+                None,
+                Some(LinkSpecial::Begin(uri("https://example.com"))),
+                None,
+                Some(LinkSpecial::End(None)),
+                None,
+                Some(LinkSpecial::End(None)),
+            ]),
+            r#"<a id="section.1">x<a href="https://example.com">x</a>x</a>"#
+        );
+    }
+
+    #[test]
+    fn links_left_open_are_closed_at_the_end_of_the_page() {
+        assert_eq!(
+            markup(vec![
+                Some(LinkSpecial::Begin(destination("a"))),
+                None,
+                Some(LinkSpecial::Begin(uri("https://example.com"))),
+                None,
+                // A dvips link whose end is on the next page has no target yet.
+                Some(LinkSpecial::Begin(None)),
+                None,
+            ]),
+            r##"<a href="#a">x<a href="https://example.com">xx</a></a>"##
+        );
+    }
+}
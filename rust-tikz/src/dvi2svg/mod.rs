@@ -1,23 +1,147 @@
-use dvi2html::tfm;
-use machine::{Executor, Machine};
-use utils::parse_dvi;
+use crate::display_list::DisplayList;
+use crate::dvi::{Interpreter, Machine};
+use crate::font_files::FontFiles;
+use crate::logging::Logger;
 
+pub(crate) mod accessibility;
+pub(crate) mod color;
+pub(crate) mod displaylistmachine;
+pub(crate) mod encodings;
+pub(crate) mod epsmachine;
+pub(crate) mod htmlmachine;
+pub(crate) mod ids;
+pub(crate) mod links;
+pub(crate) mod pgf;
+pub(crate) mod svg_canvas;
+pub(crate) mod svg_optimize;
 pub(crate) mod svgmachine;
-pub(crate) mod machine;
+pub(crate) mod theme;
+#[cfg(feature = "pdf")]
+pub(crate) mod pdfmachine;
+#[cfg(feature = "pdf")]
+pub(crate) mod pdf_writer;
+pub(crate) mod raw_svg;
 pub(crate) mod utils;
 
-pub fn dvi2svg(input: &[u8]) -> Result<String, String> {
-    let font_helper = tfm::FontDataHelper::init().unwrap();
+/// Run the DVI file `input` through `machine`, reading virtual fonts from `font_files` and
+/// sending the interpreter's diagnostics to `logger`.
+fn interpret(
+    input: &[u8],
+    machine: &mut impl Machine,
+    font_files: &FontFiles,
+    logger: &Logger,
+) -> Result<(), String> {
+    let mut interpreter = Interpreter::new().map_err(|e| e.to_string())?;
+    interpreter.set_font_files(font_files.clone());
+    interpreter.set_logger(logger.clone());
+    interpreter.run(input, machine).map_err(|e| e.to_string())
+}
+
+/// Options for [`dvi2svg`]. The default writes a readable SVG whose ids start with a prefix
+/// derived from the content.
+#[derive(Debug, Clone, Default)]
+pub struct SvgOptions {
+    /// The prefix of the ids in the SVG. If `None`, the prefix is derived from the content.
+    pub id_prefix: Option<String>,
+    /// If `Some`, the SVG is made smaller at the cost of readability.
+    pub optimize: Option<svg_optimize::SvgOptimization>,
+    /// If `Some`, the SVG is marked as an image with a title for assistive technology.
+    pub accessibility: Option<accessibility::SvgAccessibility>,
+    /// If `Some`, colors are written so that they can follow the page, e.g. as `currentColor`.
+    pub theme: Option<theme::SvgTheme>,
+}
+
+/// Convert DVI data into an SVG, as set out by `options`.
+///
+/// Virtual fonts are read from `font_files`, which are usually [`FontFiles::bundled`] or the
+/// files of the [`crate::WasmRunner`] that wrote the DVI file. The same goes for the other
+/// conversions.
+pub fn dvi2svg(
+    input: &[u8],
+    options: SvgOptions,
+    font_files: &FontFiles,
+    logger: &Logger,
+) -> Result<String, String> {
     let mut machine = svgmachine::SVGMachine::new();
-    let instructions = parse_dvi(input);
-    let special_handlers: Vec<machine::SpecialHandler> = vec![
-        Box::new(svgmachine::special_html_svg),
-        Box::new(svgmachine::special_html_color),
-        Box::new(svgmachine::special_html_papersize),
-    ];
-    for ins in instructions.iter() {
-        let _ = machine.execute(ins, &font_helper, &special_handlers);
+    machine.set_logger(logger.clone());
+    if let Some(prefix) = options.id_prefix {
+        machine.set_id_prefix(prefix);
+    }
+    if let Some(optimization) = options.optimize {
+        machine.set_optimization(optimization);
+    }
+    if let Some(accessibility) = options.accessibility {
+        machine.set_accessibility(accessibility);
     }
+    if let Some(theme) = options.theme {
+        machine.set_theme(theme);
+    }
+    interpret(input, &mut machine, font_files, logger)?;
 
     Ok(machine.get_content())
-}
\ No newline at end of file
+}
+
+/// Convert DVI data into an HTML fragment with selectable text and an inline SVG for each
+/// TikZ picture. The ids in the fragment start with `id_prefix`, or with a prefix derived from
+/// the content if it is `None`.
+///
+/// The fragment has the size of the page, so this fails if the DVI file does not give it with
+/// a `papersize` special.
+pub fn dvi2html_fragment(
+    input: &[u8],
+    id_prefix: Option<&str>,
+    font_files: &FontFiles,
+    logger: &Logger,
+) -> Result<String, String> {
+    let mut machine = htmlmachine::HtmlMachine::new();
+    machine.set_logger(logger.clone());
+    if let Some(prefix) = id_prefix {
+        machine.set_id_prefix(prefix.to_string());
+    }
+    interpret(input, &mut machine, font_files, logger)?;
+
+    machine.get_content()
+}
+
+/// Convert DVI data into an Encapsulated PostScript file. The TeX fonts are referenced by name
+/// rather than embedded.
+///
+/// The bounding box is the size of the page, so this fails if the DVI file does not give it
+/// with a `papersize` special.
+pub fn dvi2eps(input: &[u8], font_files: &FontFiles, logger: &Logger) -> Result<Vec<u8>, String> {
+    let mut machine = epsmachine::EpsMachine::new();
+    machine.set_logger(logger.clone());
+    interpret(input, &mut machine, font_files, logger)?;
+
+    machine.into_eps()
+}
+
+/// Convert DVI data into a PDF.
+///
+/// The TeX file archive has no Type1 or OpenType versions of TeX's fonts, so glyphs are drawn
+/// with the bundled DejaVu fonts that stand in for them, as in PNG output. The text keeps TeX's
+/// character codes and widths, so each glyph is placed where TeX put it, but its shape is that
+/// of the stand-in and can look too wide for its spacing. Characters the stand-in has no glyph
+/// for are drawn as a blank box and reported to `logger` as warnings. The stand-ins are
+/// embedded in the PDF; TeX's own fonts are not.
+#[cfg(feature = "pdf")]
+pub fn dvi2pdf(input: &[u8], font_files: &FontFiles, logger: &Logger) -> Result<Vec<u8>, String> {
+    let mut machine = pdfmachine::PdfMachine::new();
+    machine.set_logger(logger.clone());
+    interpret(input, &mut machine, font_files, logger)?;
+
+    Ok(machine.into_pdf())
+}
+
+/// Convert DVI data into a [`DisplayList`] of the glyphs, rules and paths it draws.
+pub fn dvi2display_list(
+    input: &[u8],
+    font_files: &FontFiles,
+    logger: &Logger,
+) -> Result<DisplayList, String> {
+    let mut machine = displaylistmachine::DisplayListMachine::new();
+    machine.set_logger(logger.clone());
+    interpret(input, &mut machine, font_files, logger)?;
+
+    Ok(machine.into_display_list())
+}
@@ -0,0 +1,207 @@
+//! A minimal writer for the PDF file format. Objects are written in the order they are added
+//! and the cross-reference table is written at the end.
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+/// Builds a PDF file. Object ids are handed out by [`PdfWriter::reserve`] so that objects can
+/// refer to objects that are written later.
+pub struct PdfWriter {
+    buffer: Vec<u8>,
+    /// The byte offset of each object, indexed by object id - 1.
+    offsets: Vec<Option<usize>>,
+}
+
+impl PdfWriter {
+    pub fn new() -> PdfWriter {
+        PdfWriter {
+            // The comment with high-bit bytes marks the file as binary.
+            buffer: b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Reserve an id for an object that will be written later.
+    pub fn reserve(&mut self) -> usize {
+        self.offsets.push(None);
+        self.offsets.len()
+    }
+
+    /// Write the object `id` with the given body (e.g., a dictionary).
+    pub fn object(&mut self, id: usize, body: &str) {
+        self.offsets[id - 1] = Some(self.buffer.len());
+        self.buffer
+            .extend_from_slice(format!("{id} 0 obj\n{body}\nendobj\n").as_bytes());
+    }
+
+    /// Write the object `id` as a stream compressed with `FlateDecode`. `dictionary` holds any
+    /// entries besides `/Length` and `/Filter`, without the surrounding `<<` and `>>`.
+    pub fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        // Writing to a `Vec` cannot fail.
+        encoder.write_all(data).unwrap();
+        let data = encoder.finish().unwrap();
+
+        self.offsets[id - 1] = Some(self.buffer.len());
+        self.buffer.extend_from_slice(
+            format!(
+                "{id} 0 obj\n<< {dictionary}{}/Length {} /Filter /FlateDecode >>\nstream\n",
+                if dictionary.is_empty() { "" } else { " " },
+                data.len()
+            )
+            .as_bytes(),
+        );
+        self.buffer.extend_from_slice(&data);
+        self.buffer.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    /// Write the cross-reference table and trailer and return the finished file.
+    pub fn finish(mut self, catalog_id: usize) -> Vec<u8> {
+        let xref_offset = self.buffer.len();
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            // Objects that were reserved but never written are marked as free.
+            match offset {
+                Some(offset) => writeln!(xref, "{offset:010} 00000 n "),
+                None => writeln!(xref, "0000000000 00000 f "),
+            }
+            .unwrap();
+        }
+        write!(
+            xref,
+            "trailer\n<< /Size {} /Root {catalog_id} 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+            self.offsets.len() + 1
+        )
+        .unwrap();
+        self.buffer.extend_from_slice(xref.as_bytes());
+        self.buffer
+    }
+}
+
+/// Escape a string for use as a PDF name (e.g., `/DejaVuSerif`).
+pub fn name(value: &str) -> String {
+    let mut escaped = String::from("/");
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.+".contains(&b) {
+            escaped.push(b as char);
+        } else {
+            write!(escaped, "#{b:02X}").unwrap();
+        }
+    }
+    escaped
+}
+
+/// Format `value` as a PDF literal string.
+pub fn string(value: &str) -> String {
+    let mut escaped = String::from("(");
+    for c in value.chars() {
+        if matches!(c, '(' | ')' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push(')');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read as _;
+
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+
+    /// The lines of the cross-reference table of `pdf`, after its header.
+    fn xref_entries(pdf: &str) -> Vec<&str> {
+        let (_, xref) = pdf.split_once("\nxref\n").unwrap();
+        let (xref, _) = xref.split_once("trailer\n").unwrap();
+        xref.lines().skip(1).collect()
+    }
+
+    #[test]
+    fn every_xref_offset_points_at_its_object() {
+        let mut writer = PdfWriter::new();
+        let catalog = writer.reserve();
+        let pages = writer.reserve();
+        let unused = writer.reserve();
+        let contents = writer.reserve();
+        // Objects are written in a different order than their ids were reserved.
+        writer.stream(contents, "", b"0 0 m 1 1 l S");
+        writer.object(pages, "<< /Type /Pages /Kids [] /Count 0 >>");
+        writer.object(catalog, &format!("<< /Type /Catalog /Pages {pages} 0 R >>"));
+        let pdf = writer.finish(catalog);
+        let text = String::from_utf8_lossy(&pdf);
+
+        let entries = xref_entries(&text);
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0], "0000000000 65535 f ");
+        assert_eq!(entries[unused], "0000000000 00000 f ");
+        for id in [catalog, pages, contents] {
+            let (offset, rest) = entries[id].split_once(' ').unwrap();
+            assert_eq!(rest, "00000 n ");
+            let offset = offset.parse::<usize>().unwrap();
+            assert!(
+                pdf[offset..].starts_with(format!("{id} 0 obj\n").as_bytes()),
+                "the offset of object {id} points at {:?}",
+                String::from_utf8_lossy(&pdf[offset..offset + 10])
+            );
+        }
+    }
+
+    #[test]
+    fn the_trailer_points_at_the_xref_table_and_the_catalog() {
+        let mut writer = PdfWriter::new();
+        writer.reserve();
+        let catalog = writer.reserve();
+        writer.object(catalog, "<< /Type /Catalog >>");
+        let pdf = writer.finish(catalog);
+        let text = String::from_utf8_lossy(&pdf);
+
+        assert!(text.starts_with("%PDF-1.7\n"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("trailer\n<< /Size 3 /Root 2 0 R >>\n"));
+        let (_, start) = text.rsplit_once("startxref\n").unwrap();
+        let offset = start.trim_end_matches("%%EOF\n").trim();
+        let offset = offset.parse::<usize>().unwrap();
+        assert!(pdf[offset..].starts_with(b"xref\n0 3\n"));
+    }
+
+    #[test]
+    fn streams_are_compressed_with_their_length() {
+        let mut writer = PdfWriter::new();
+        let id = writer.reserve();
+        writer.stream(id, "/Length1 4", b"data");
+        let pdf = writer.finish(id);
+
+        let header = format!("{id} 0 obj\n<< /Length1 4 /Length ");
+        let start = pdf
+            .windows(header.len())
+            .position(|w| w == header.as_bytes())
+            .unwrap();
+        let rest = &pdf[start + header.len()..];
+        let length_end = rest.iter().position(|&b| b == b' ').unwrap();
+        let length = std::str::from_utf8(&rest[..length_end])
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        let marker = b" /Filter /FlateDecode >>\nstream\n";
+        assert!(rest[length_end..].starts_with(marker));
+        let data = &rest[length_end + marker.len()..][..length];
+
+        let mut decoded = Vec::new();
+        ZlibDecoder::new(data).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, b"data");
+        assert!(rest[length_end + marker.len() + length..].starts_with(b"\nendstream\n"));
+    }
+
+    #[test]
+    fn names_and_strings_are_escaped() {
+        assert_eq!(name("DejaVuSerif-Bold"), "/DejaVuSerif-Bold");
+        assert_eq!(name("a b#(c)"), "/a#20b#23#28c#29");
+        assert_eq!(string("f(x) = \\y"), "(f\\(x\\) = \\\\y)");
+    }
+}
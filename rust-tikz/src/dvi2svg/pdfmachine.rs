@@ -0,0 +1,1281 @@
+use super::color::{Color, ColorSpecials};
+use super::encodings::unicode;
+use super::links::{LinkSpecial, Target};
+use super::pdf_writer::{PdfWriter, name, string};
+use super::pgf::{Canvas, LineCap, LineJoin, Paint, PgfInterpreter, Shading, ShadingKind};
+use super::raw_svg::{self, IDENTITY, Matrix, PathSegment, RawSvgBuffer, Style, Tag};
+use super::utils::num;
+use crate::dvi::{Glyph, Machine, Rule, Special};
+use crate::fonts::{self, BundledFont};
+use crate::logging::{LogCategory, Logger};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use ttf_parser::{Face, GlyphId};
+
+/// An implementation of `Machine` that generates a PDF as output. Glyphs are drawn with the
+/// bundled fonts that stand in for TeX's fonts (see [`crate::dvi2pdf`]), and the SVG drawn by
+/// TikZ with `dvisvgm:raw`
+/// specials is translated into PDF path operators. The specials of the rust-tikz pgf driver
+/// are drawn with PDF operators, and shadings become PDF shadings. Hyperlinks become link
+/// annotations over the glyphs and rules they contain.
+pub struct PdfMachine {
+    /// The page content stream, in SVG coordinates (i.e., the y axis points down).
+    content: String,
+    colors: ColorSpecials,
+    paperwidth: Option<f64>,
+    paperheight: Option<f64>,
+
+    raw_svg: RawSvgBuffer,
+    /// The elements opened by raw specials that have not been closed yet.
+    open_elements: Vec<OpenElement>,
+    /// Path operators for each `<clipPath>`, by id.
+    clip_paths: HashMap<String, String>,
+    /// The `(fill, stroke)` opacities of each graphics state dictionary that is used.
+    opacities: Vec<(f64, f64)>,
+    /// The shadings that are drawn, in the order they appear in the shading resources.
+    shadings: Vec<Shading>,
+    /// A font for each TeX font that is used, in the order they appear in the font resources.
+    pdf_fonts: Vec<PdfFont>,
+    /// The run of glyphs that was written last, which the next glyph may be added to.
+    text_run: Option<TextRun>,
+    pgf: PgfInterpreter,
+    /// Where glyphs that the bundled fonts cannot draw are reported.
+    logger: Logger,
+    /// The transformations of the text in pgf pictures, innermost last.
+    transforms: Vec<Matrix>,
+
+    /// The links that have started but not ended yet, innermost last.
+    open_links: Vec<OpenLink>,
+    /// The area and target of each link annotation, in DVI coordinates.
+    annotations: Vec<([f64; 4], Target)>,
+    /// The named destinations and their positions, in DVI coordinates.
+    destinations: Vec<(String, f64, f64)>,
+}
+
+/// A link that is being drawn.
+struct OpenLink {
+    /// The target of the link, if it is already known.
+    target: Option<Target>,
+    /// The area covered by the glyphs and rules of the link so far, as `[x0, y0, x1, y1]`.
+    area: Option<[f64; 4]>,
+}
+
+/// A TeX font, drawn with the bundled font that stands in for it. The character codes of the
+/// PDF font are those of the TeX font, and their widths are TeX's, so that a line of text can
+/// be written as one run and still end up where TeX put each glyph.
+struct PdfFont {
+    /// The name of the TeX font, e.g. `cmr10`.
+    tex_name: String,
+    font: &'static BundledFont,
+    face: Face<'static>,
+    /// The characters that have been drawn, by their code in the TeX font.
+    characters: BTreeMap<u16, Character>,
+}
+
+/// A character of a [`PdfFont`].
+struct Character {
+    /// The glyph of the bundled font that is drawn.
+    glyph: GlyphId,
+    /// The character the code stands for.
+    unicode: char,
+    /// The width from the TeX font's metrics, in thousandths of the font size.
+    width: f64,
+}
+
+/// The glyphs written last, with a single text object of the content stream.
+struct TextRun {
+    font: usize,
+    size: f64,
+    v: f64,
+    /// The operator that sets the color of the glyphs.
+    color: String,
+    /// Where the next glyph goes if TeX adds no space in between.
+    h: f64,
+    /// The length of the content stream after the run, so that it is only continued if nothing
+    /// else has been written since.
+    end: usize,
+}
+
+/// The end of the text object of a [`TextRun`].
+const TEXT_RUN_END: &str = "] TJ ET\n";
+
+/// An element opened by a raw special.
+enum OpenElement {
+    /// A `<g>`, which saved the graphics state with `q` and set the style inside it.
+    Group(Style),
+    /// A `<clipPath>`. Paths inside it are collected rather than drawn.
+    ClipPath(String),
+    /// An element whose contents are not drawn, like `<defs>` or `<marker>`.
+    Hidden,
+    /// An element that has no effect on drawing, like the `<svg>` TikZ wraps a picture in.
+    Transparent,
+}
+
+impl PdfMachine {
+    pub fn new() -> PdfMachine {
+        PdfMachine {
+            content: "".to_string(),
+            colors: ColorSpecials::default(),
+            paperwidth: None,
+            paperheight: None,
+            raw_svg: RawSvgBuffer::default(),
+            open_elements: Vec::new(),
+            clip_paths: HashMap::new(),
+            opacities: Vec::new(),
+            shadings: Vec::new(),
+            pdf_fonts: Vec::new(),
+            text_run: None,
+            pgf: PgfInterpreter::default(),
+            logger: Logger::default(),
+            transforms: Vec::new(),
+            open_links: Vec::new(),
+            annotations: Vec::new(),
+            destinations: Vec::new(),
+        }
+    }
+
+    /// Report the parts of TikZ pictures and the glyphs that cannot be drawn to `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.pgf.set_logger(logger.clone());
+        self.logger = logger;
+    }
+
+    /// Assemble the PDF file. Everything is drawn on a single page.
+    pub fn into_pdf(self) -> Vec<u8> {
+        let width = self.paperwidth.unwrap_or(100.0);
+        let height = self.paperheight.unwrap_or(100.0);
+
+        let mut writer = PdfWriter::new();
+        let catalog = writer.reserve();
+        let pages = writer.reserve();
+        let page = writer.reserve();
+        let contents = writer.reserve();
+
+        // TeX fonts that are drawn with the same bundled font share its file.
+        let mut font_files: Vec<(&BundledFont, usize)> = Vec::new();
+        let mut font_resources = String::new();
+        for (i, font) in self.pdf_fonts.iter().enumerate() {
+            let font_file = match font_files.iter().find(|(f, _)| std::ptr::eq(*f, font.font)) {
+                Some(&(_, id)) => id,
+                None => {
+                    let id = writer.reserve();
+                    writer.stream(
+                        id,
+                        &format!("/Length1 {}", font.font.data.len()),
+                        font.font.data,
+                    );
+                    font_files.push((font.font, id));
+                    id
+                }
+            };
+            let id = write_font(&mut writer, font, font_file);
+            write!(font_resources, "/F{i} {id} 0 R ").unwrap();
+        }
+        let mut opacity_resources = String::new();
+        for (i, (fill, stroke)) in self.opacities.iter().enumerate() {
+            write!(
+                opacity_resources,
+                "/GS{i} << /ca {} /CA {} >> ",
+                num(*fill),
+                num(*stroke)
+            )
+            .unwrap();
+        }
+        let mut shading_resources = String::new();
+        for (i, shading) in self.shadings.iter().enumerate() {
+            let id = writer.reserve();
+            writer.object(id, &shading_dictionary(shading));
+            write!(shading_resources, "/Sh{i} {id} 0 R ").unwrap();
+        }
+
+        // Convert from DVI coordinates to PDF coordinates, see the content stream below.
+        let to_pdf = |x: f64, y: f64| (num(x + 72.0), num(height - 72.0 - y));
+        let mut annotations = String::new();
+        for ([x0, y0, x1, y1], target) in &self.annotations {
+            let action = match target {
+                Target::Uri(uri) => format!("/A << /S /URI /URI {} >>", string(uri)),
+                Target::Destination(destination) => format!("/Dest {}", name(destination)),
+            };
+            let ((left, bottom), (right, top)) = (to_pdf(*x0, *y1), to_pdf(*x1, *y0));
+            let id = writer.reserve();
+            writer.object(
+                id,
+                &format!(
+                    "<< /Type /Annot /Subtype /Link /Rect [{left} {bottom} {right} {top}] \
+                    /Border [0 0 0] {action} >>"
+                ),
+            );
+            write!(annotations, "{id} 0 R ").unwrap();
+        }
+        let mut destinations = String::new();
+        for (destination, x, y) in &self.destinations {
+            let (x, y) = to_pdf(*x, *y);
+            write!(
+                destinations,
+                "{} [{page} 0 R /XYZ {x} {y} null] ",
+                name(destination)
+            )
+            .unwrap();
+        }
+
+        writer.object(
+            catalog,
+            &format!("<< /Type /Catalog /Pages {pages} 0 R /Dests << {destinations}>> >>"),
+        );
+        writer.object(
+            pages,
+            &format!("<< /Type /Pages /Kids [{page} 0 R] /Count 1 >>"),
+        );
+        writer.object(
+            page,
+            &format!(
+                "<< /Type /Page /Parent {pages} 0 R /MediaBox [0 0 {} {}] \
+                /Resources << /Font << {font_resources}>> /ExtGState << {opacity_resources}>> \
+                /Shading << {shading_resources}>> >> \
+                /Annots [{annotations}] /Contents {contents} 0 R >>",
+                num(width),
+                num(height)
+            ),
+        );
+
+        // Like the SVG output, the origin is one inch from the top left corner. The y axis
+        // is flipped so that the content can be written in SVG coordinates.
+        let mut content = format!("1 0 0 -1 72 {} cm\n", num(height - 72.0));
+        if let Some(background) = self.colors.background {
+            writeln!(
+                content,
+                "{} -72 -72 {} {} re f",
+                background.pdf_operator(false),
+                num(width),
+                num(height)
+            )
+            .unwrap();
+        }
+        content.push_str(&self.content);
+        for element in &self.open_elements {
+            if let OpenElement::Group(_) = element {
+                content.push_str("Q\n");
+            }
+        }
+        writer.stream(contents, "", content.as_bytes());
+
+        writer.finish(catalog)
+    }
+
+    /// Get the index of the PDF font for the TeX font `tex_name`.
+    fn pdf_font(&mut self, tex_name: &str) -> usize {
+        if let Some(index) = self.pdf_fonts.iter().position(|f| f.tex_name == tex_name) {
+            return index;
+        }
+        let font = fonts::substitute(tex_name);
+        self.pdf_fonts.push(PdfFont {
+            tex_name: tex_name.to_string(),
+            font,
+            face: Face::parse(font.data, 0).expect("Bundled fonts are valid"),
+            characters: BTreeMap::new(),
+        });
+        self.pdf_fonts.len() - 1
+    }
+
+    /// Add the character `code` of `glyph` to the PDF font `index`, the first time it is drawn.
+    /// Characters that the bundled font has no glyph for are drawn as its `.notdef` glyph, a
+    /// blank box, and reported.
+    fn add_character(&mut self, index: usize, code: u16, glyph: &Glyph) {
+        let pdf_font = &mut self.pdf_fonts[index];
+        if pdf_font.characters.contains_key(&code) {
+            return;
+        }
+        let character = unicode(glyph);
+        let glyph_id = pdf_font.face.glyph_index(character);
+        if glyph_id.is_none() {
+            self.logger.log(LogCategory::Warning, || {
+                format!(
+                    "The bundled font {} has no glyph for `{character}` (code {code} of {}), so \
+                     a blank box is drawn in its place",
+                    pdf_font.font.name, pdf_font.tex_name
+                )
+            });
+        }
+        pdf_font.characters.insert(
+            code,
+            Character {
+                glyph: glyph_id.unwrap_or(GlyphId(0)),
+                unicode: character,
+                width: glyph.width * 1000.0 / glyph.font.size,
+            },
+        );
+    }
+
+    /// Write the character `code` of the PDF font `index`, adding it to the text run that was
+    /// written last if it is on the same line in the same font and color.
+    fn put_character(&mut self, index: usize, code: u16, glyph: &Glyph) {
+        let size = glyph.font.size;
+        let color = self.colors.color().pdf_operator(false);
+        match &self.text_run {
+            Some(run)
+                if run.end == self.content.len()
+                    && run.font == index
+                    && run.size == size
+                    && run.v == glyph.v
+                    && run.color == color =>
+            {
+                // The glyphs of the run advance by their TeX widths, so only the space TeX
+                // adds in between, e.g. for kerning or between words, has to be written.
+                let space = -(glyph.h - run.h) * 1000.0 / size;
+                self.content.truncate(run.end - TEXT_RUN_END.len());
+                if num(space) != "0" {
+                    write!(self.content, " {} ", num(space)).unwrap();
+                }
+            }
+            // The text matrix flips the y axis back so that glyphs are upright.
+            _ => write!(
+                self.content,
+                "BT /F{index} {} Tf {color} 1 0 0 -1 {} {} Tm [",
+                num(size),
+                num(glyph.h),
+                num(glyph.v)
+            )
+            .unwrap(),
+        }
+        write!(self.content, "<{code:04X}>{TEXT_RUN_END}").unwrap();
+        self.text_run = Some(TextRun {
+            font: index,
+            size,
+            v: glyph.v,
+            color,
+            h: glyph.h + glyph.width,
+            end: self.content.len(),
+        });
+    }
+}
+
+impl Machine for PdfMachine {
+    fn glyph(&mut self, glyph: &Glyph) {
+        self.put_raw();
+        self.extend_links(
+            glyph.h,
+            glyph.v - glyph.height,
+            glyph.h + glyph.width,
+            glyph.v + glyph.depth,
+        );
+        // TFM fonts have at most 256 characters, so larger codes only come from fonts that
+        // cannot be drawn anyway.
+        let Ok(code) = u16::try_from(glyph.code) else {
+            self.logger.log(LogCategory::Warning, || {
+                format!(
+                    "Cannot draw code {} of {}, so it is left out of the PDF",
+                    glyph.code, glyph.font.name
+                )
+            });
+            return;
+        };
+        let index = self.pdf_font(&glyph.font.name);
+        self.add_character(index, code, glyph);
+        self.put_character(index, code, glyph);
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        self.put_raw();
+        self.extend_links(rule.h, rule.v - rule.height, rule.h + rule.width, rule.v);
+        writeln!(
+            self.content,
+            "{} {} {} {} {} re f",
+            self.colors.color().pdf_operator(false),
+            num(rule.h),
+            num(rule.v - rule.height),
+            num(rule.width),
+            num(rule.height)
+        )
+        .unwrap();
+    }
+    fn end_page(&mut self) {
+        self.put_raw();
+    }
+    fn special(&mut self, special: &Special) {
+        if self.special_raw(special)
+            || self.special_pgf(special)
+            || self.colors.special(special.text)
+            || self.special_link(special)
+        {
+            return;
+        }
+        self.special_papersize(special.text);
+    }
+}
+
+//Specials
+impl PdfMachine {
+    fn special_link(&mut self, special: &Special) -> bool {
+        let Some(link) = LinkSpecial::parse(special.text) else {
+            return false;
+        };
+        match link {
+            LinkSpecial::Begin(target) => self.open_links.push(OpenLink { target, area: None }),
+            LinkSpecial::BeginAnchor(name) => {
+                self.destinations.push((name, special.h, special.v));
+                // The anchor ends like a link, but has nothing to link to.
+                self.open_links.push(OpenLink {
+                    target: None,
+                    area: None,
+                });
+            }
+            LinkSpecial::End(target) => {
+                if let Some(link) = self.open_links.pop()
+                    && let (Some(target), Some(area)) = (link.target.or(target), link.area)
+                {
+                    self.annotations.push((area, target));
+                }
+            }
+            LinkSpecial::Destination(name) => {
+                self.destinations.push((name, special.h, special.v));
+            }
+        }
+        true
+    }
+
+    /// Add the rectangle from `(x0, y0)` to `(x1, y1)` to the area of the open links.
+    fn extend_links(&mut self, x0: f64, y0: f64, x1: f64, y1: f64) {
+        if self.open_links.is_empty() {
+            return;
+        }
+        let matrix = self.transforms.last().copied().unwrap_or(IDENTITY);
+        let corners = [(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+            .map(|(x, y)| raw_svg::transform_point(matrix, x, y));
+        for link in &mut self.open_links {
+            let mut area = link
+                .area
+                .unwrap_or([f64::MAX, f64::MAX, f64::MIN, f64::MIN]);
+            for (x, y) in corners {
+                area = [
+                    area[0].min(x),
+                    area[1].min(y),
+                    area[2].max(x),
+                    area[3].max(y),
+                ];
+            }
+            link.area = Some(area);
+        }
+    }
+
+    fn special_papersize(&mut self, command: &str) -> bool {
+        let pattern = "papersize=";
+        if let Some(sizes) = command.strip_prefix(pattern) {
+            let mut sizes = sizes.split(',').map(raw_svg::parse_length);
+            if let (Some(Some(width)), Some(Some(height))) = (sizes.next(), sizes.next()) {
+                self.paperwidth = Some(width);
+                self.paperheight = Some(height);
+            }
+        }
+        false
+    }
+
+    fn special_pgf(&mut self, special: &Special) -> bool {
+        let color = self.colors.color();
+        let mut pgf = std::mem::take(&mut self.pgf);
+        let handled = pgf.special(special, color, self);
+        self.pgf = pgf;
+        handled
+    }
+
+    fn special_raw(&mut self, special: &Special) -> bool {
+        if self.raw_svg.special(special) {
+            return true;
+        }
+        self.put_raw();
+        false
+    }
+
+    /// Draw the buffered raw SVG.
+    fn put_raw(&mut self) {
+        if let Some(raw) = self.raw_svg.take() {
+            self.draw_raw(&raw);
+        }
+    }
+
+    /// Draw the SVG fragment `raw`.
+    fn draw_raw(&mut self, raw: &str) {
+        for tag in raw_svg::parse_tags(raw) {
+            match tag {
+                Tag::Open {
+                    name,
+                    attributes,
+                    self_closing,
+                } => self.open_element(&name, &attributes, self_closing),
+                Tag::Close { .. } => self.close_element(),
+            }
+        }
+    }
+
+    fn open_element(&mut self, name: &str, attributes: &HashMap<String, String>, closed: bool) {
+        let element = match name {
+            "g" | "a" if !self.is_hidden() => {
+                self.content.push_str("q\n");
+                self.set_graphics_state(attributes);
+                OpenElement::Group(self.style(attributes))
+            }
+            "svg" => OpenElement::Transparent,
+            "clipPath" => {
+                let id = attributes.get("id").cloned().unwrap_or_default();
+                self.clip_paths.insert(id.clone(), String::new());
+                OpenElement::ClipPath(id)
+            }
+            "path" | "rect" | "circle" | "ellipse" | "line" | "polyline" | "polygon" => {
+                self.put_shape(name, attributes);
+                OpenElement::Transparent
+            }
+            _ => OpenElement::Hidden,
+        };
+        self.open_elements.push(element);
+        if closed {
+            self.close_element();
+        }
+    }
+
+    /// Whether the elements that are open stop anything from being drawn.
+    fn is_hidden(&self) -> bool {
+        self.open_elements
+            .iter()
+            .any(|e| matches!(e, OpenElement::Hidden | OpenElement::ClipPath(_)))
+    }
+
+    fn close_element(&mut self) {
+        if let Some(OpenElement::Group(_)) = self.open_elements.pop() {
+            self.content.push_str("Q\n");
+        }
+    }
+
+    /// Draw a shape element, or add it to the clip path that is being defined.
+    fn put_shape(&mut self, name: &str, attributes: &HashMap<String, String>) {
+        let Some(path) = raw_svg::shape_segments(name, attributes) else {
+            return;
+        };
+        let path = path_operators(&path);
+        match self.open_elements.last() {
+            Some(OpenElement::ClipPath(id)) => {
+                // TODO: transforms on the paths of a clip path are ignored
+                if let Some(clip_path) = self.clip_paths.get_mut(id) {
+                    clip_path.push_str(&path);
+                }
+                return;
+            }
+            _ if self.is_hidden() => return,
+            _ => {}
+        }
+
+        let style = self.style(attributes);
+        self.content.push_str("q\n");
+        self.set_graphics_state(attributes);
+        if let Some([r, g, b]) = style.fill {
+            writeln!(self.content, "{} {} {} rg", num(r), num(g), num(b)).unwrap();
+        }
+        if let Some([r, g, b]) = style.stroke {
+            writeln!(self.content, "{} {} {} RG", num(r), num(g), num(b)).unwrap();
+        }
+        self.content.push_str(&path);
+        self.content.push_str(paint_operator(
+            style.fill.is_some(),
+            style.stroke.is_some(),
+            style.even_odd,
+        ));
+        self.content.push_str("\nQ\n");
+    }
+
+    /// The style inherited from the enclosing groups, updated with `attributes`.
+    fn style(&self, attributes: &HashMap<String, String>) -> Style {
+        let mut style = self
+            .open_elements
+            .iter()
+            .rev()
+            .find_map(|e| match e {
+                OpenElement::Group(style) => Some(*style),
+                _ => None,
+            })
+            .unwrap_or_default();
+        style.apply(attributes, &self.colors.color().to_hex());
+        style
+    }
+
+    /// Write the operators for the attributes that are part of the PDF graphics state.
+    fn set_graphics_state(&mut self, attributes: &HashMap<String, String>) {
+        if let Some(transform) = attributes.get("transform") {
+            let matrix = raw_svg::parse_transform(transform);
+            let matrix = matrix.map(num).join(" ");
+            writeln!(self.content, "{matrix} cm").unwrap();
+        }
+        if let Some(clip_path) = attributes
+            .get("clip-path")
+            .and_then(|url| url.trim().strip_prefix("url(#"))
+            .and_then(|url| url.strip_suffix(')'))
+            .and_then(|id| self.clip_paths.get(id))
+        {
+            writeln!(self.content, "{clip_path}W n").unwrap();
+        }
+        if let Some(width) = attributes
+            .get("stroke-width")
+            .and_then(|w| raw_svg::parse_length(w))
+        {
+            writeln!(self.content, "{} w", num(width)).unwrap();
+        }
+        if let Some(cap) = attributes.get("stroke-linecap") {
+            let cap = match cap.as_str() {
+                "round" => 1,
+                "square" => 2,
+                _ => 0,
+            };
+            writeln!(self.content, "{cap} J").unwrap();
+        }
+        if let Some(join) = attributes.get("stroke-linejoin") {
+            let join = match join.as_str() {
+                "round" => 1,
+                "bevel" => 2,
+                _ => 0,
+            };
+            writeln!(self.content, "{join} j").unwrap();
+        }
+        if let Some(limit) = attributes
+            .get("stroke-miterlimit")
+            .and_then(|m| raw_svg::parse_length(m))
+        {
+            writeln!(self.content, "{} M", num(limit)).unwrap();
+        }
+        if let Some(dashes) = attributes.get("stroke-dasharray") {
+            let dashes = raw_svg::parse_number_list(dashes);
+            let offset = attributes
+                .get("stroke-dashoffset")
+                .and_then(|o| raw_svg::parse_length(o))
+                .unwrap_or(0.0);
+            let dashes = dashes.into_iter().map(num).collect::<Vec<_>>().join(" ");
+            writeln!(self.content, "[{dashes}] {} d", num(offset)).unwrap();
+        }
+
+        let opacity = |key: &str| attributes.get(key).and_then(|o| raw_svg::parse_length(o));
+        let (fill, stroke) = match (
+            opacity("opacity"),
+            opacity("fill-opacity"),
+            opacity("stroke-opacity"),
+        ) {
+            (None, None, None) => return,
+            (all, fill, stroke) => {
+                let all = all.unwrap_or(1.0);
+                (all * fill.unwrap_or(1.0), all * stroke.unwrap_or(1.0))
+            }
+        };
+        self.set_opacity(fill, stroke);
+    }
+
+    /// Set the fill and stroke opacity with a graphics state dictionary.
+    fn set_opacity(&mut self, fill: f64, stroke: f64) {
+        let index = match self.opacities.iter().position(|&o| o == (fill, stroke)) {
+            Some(index) => index,
+            None => {
+                self.opacities.push((fill, stroke));
+                self.opacities.len() - 1
+            }
+        };
+        writeln!(self.content, "/GS{index} gs").unwrap();
+    }
+}
+
+impl Canvas for PdfMachine {
+    fn begin_picture(&mut self) {
+        self.content.push_str("q\n");
+    }
+
+    fn end_picture(&mut self) {
+        self.content.push_str("Q\n");
+    }
+
+    fn begin_scope(&mut self) {
+        self.content.push_str("q\n");
+    }
+
+    fn end_scope(&mut self) {
+        self.content.push_str("Q\n");
+    }
+
+    fn clip(&mut self, path: &[PathSegment], even_odd: bool) {
+        self.content.push_str(&path_operators(path));
+        self.content
+            .push_str(if even_odd { "W* n\n" } else { "W n\n" });
+    }
+
+    fn draw_path(&mut self, path: &[PathSegment], paint: &Paint) {
+        self.content.push_str("q\n");
+        if paint.fill_opacity < 1.0 || paint.stroke_opacity < 1.0 {
+            self.set_opacity(paint.fill_opacity, paint.stroke_opacity);
+        }
+        if let Some(fill) = paint.fill {
+            writeln!(self.content, "{}", fill.pdf_operator(false)).unwrap();
+        }
+        if let Some(stroke) = paint.stroke {
+            let cap = match paint.line_cap {
+                LineCap::Butt => 0,
+                LineCap::Round => 1,
+                LineCap::Square => 2,
+            };
+            let join = match paint.line_join {
+                LineJoin::Miter => 0,
+                LineJoin::Round => 1,
+                LineJoin::Bevel => 2,
+            };
+            let dashes = paint.dash_array.iter().map(|&d| num(d));
+            writeln!(
+                self.content,
+                "{} {} w {cap} J {join} j {} M [{}] {} d",
+                stroke.pdf_operator(true),
+                num(paint.line_width),
+                num(paint.miter_limit),
+                dashes.collect::<Vec<_>>().join(" "),
+                num(paint.dash_offset)
+            )
+            .unwrap();
+        }
+        self.content.push_str(&path_operators(path));
+        self.content.push_str(paint_operator(
+            paint.fill.is_some(),
+            paint.stroke.is_some(),
+            paint.even_odd,
+        ));
+        self.content.push_str("\nQ\n");
+    }
+
+    fn begin_transform(&mut self, matrix: Matrix) {
+        let outer = self.transforms.last().copied().unwrap_or(IDENTITY);
+        self.transforms.push(raw_svg::multiply(matrix, outer));
+        let matrix = matrix.map(num).join(" ");
+        writeln!(self.content, "q {matrix} cm").unwrap();
+    }
+
+    fn end_transform(&mut self) {
+        self.transforms.pop();
+        self.content.push_str("Q\n");
+    }
+
+    fn shading(&mut self, shading: &Shading) {
+        let [x, y, width, height] = shading.rect;
+        writeln!(
+            self.content,
+            "q {} {} {} {} re W n /Sh{} sh Q",
+            num(x),
+            num(y),
+            num(width),
+            num(height),
+            self.shadings.len()
+        )
+        .unwrap();
+        self.shadings.push(shading.clone());
+    }
+
+    fn raw_svg(&mut self, svg: &str) -> bool {
+        self.draw_raw(svg);
+        true
+    }
+}
+
+/// The operator that paints the current path.
+fn paint_operator(fill: bool, stroke: bool, even_odd: bool) -> &'static str {
+    match (fill, stroke, even_odd) {
+        (true, true, false) => "B",
+        (true, true, true) => "B*",
+        (true, false, false) => "f",
+        (true, false, true) => "f*",
+        (false, true, _) => "S",
+        (false, false, _) => "n",
+    }
+}
+
+/// The shading dictionary for `shading`. Its coordinates are in the coordinates of the content
+/// stream.
+fn shading_dictionary(shading: &Shading) -> String {
+    let first = shading.stops.first().map_or(0.0, |s| s.0);
+    let last = shading.stops.last().map_or(1.0, |s| s.0);
+    let (shading_type, coords) = match shading.kind {
+        ShadingKind::Axial { from, to } => (2, [from.0, from.1, to.0, to.1].map(num).join(" ")),
+        ShadingKind::Radial { inner, outer } => (
+            3,
+            [inner.0, inner.1, first, outer.0, outer.1, last]
+                .map(num)
+                .join(" "),
+        ),
+    };
+    format!(
+        "<< /ShadingType {shading_type} /ColorSpace /DeviceRGB /Coords [{coords}] \
+        /Function {} /Extend [true true] >>",
+        stops_function(&shading.stops)
+    )
+}
+
+/// A function from 0 to 1 that interpolates between the colors of `stops`, whose positions are
+/// scaled to fit.
+fn stops_function(stops: &[(f64, Color)]) -> String {
+    let interpolate = |from: Color, to: Color| {
+        format!(
+            "<< /FunctionType 2 /Domain [0 1] /C0 [{}] /C1 [{}] /N 1 >>",
+            from.to_rgb().map(num).join(" "),
+            to.to_rgb().map(num).join(" ")
+        )
+    };
+    match stops {
+        [] => interpolate(Color::BLACK, Color::BLACK),
+        [(_, color)] => interpolate(*color, *color),
+        [(_, from), (_, to)] => interpolate(*from, *to),
+        [(first, _), .., (last, _)] => {
+            // Stitch together a function for each pair of neighbouring stops.
+            let length = if last > first { last - first } else { 1.0 };
+            let functions = stops.windows(2).map(|w| interpolate(w[0].1, w[1].1));
+            let bounds = stops[1..stops.len() - 1]
+                .iter()
+                .map(|&(position, _)| num((position - first) / length));
+            let encode = vec!["0 1"; stops.len() - 1];
+            format!(
+                "<< /FunctionType 3 /Domain [0 1] /Functions [{}] /Bounds [{}] /Encode [{}] >>",
+                functions.collect::<Vec<_>>().join(" "),
+                bounds.collect::<Vec<_>>().join(" "),
+                encode.join(" ")
+            )
+        }
+    }
+}
+
+/// The path construction operators for `segments`.
+fn path_operators(segments: &[PathSegment]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        match *segment {
+            PathSegment::MoveTo(x, y) => writeln!(path, "{} {} m", num(x), num(y)),
+            PathSegment::LineTo(x, y) => writeln!(path, "{} {} l", num(x), num(y)),
+            PathSegment::CurveTo(x1, y1, x2, y2, x, y) => writeln!(
+                path,
+                "{} {} {} {} {} {} c",
+                num(x1),
+                num(y1),
+                num(x2),
+                num(y2),
+                num(x),
+                num(y)
+            ),
+            PathSegment::Close => writeln!(path, "h"),
+        }
+        .unwrap();
+    }
+    path
+}
+
+/// Write `font` as a composite font whose character codes are those of the TeX font, drawn
+/// with the glyphs of the bundled font in `font_file`, and return the id of the font dictionary.
+fn write_font(writer: &mut PdfWriter, font: &PdfFont, font_file: usize) -> usize {
+    let type0 = writer.reserve();
+    let cid_font = writer.reserve();
+    let descriptor = writer.reserve();
+    let cid_to_gid = writer.reserve();
+    let to_unicode = writer.reserve();
+
+    let face = &font.face;
+    let base_font = name(font.font.name);
+    let mut widths = String::new();
+    for (code, character) in &font.characters {
+        write!(widths, "{code} [{}] ", num(character.width)).unwrap();
+    }
+
+    writer.object(
+        type0,
+        &format!(
+            "<< /Type /Font /Subtype /Type0 /BaseFont {base_font} /Encoding /Identity-H \
+            /DescendantFonts [{cid_font} 0 R] /ToUnicode {to_unicode} 0 R >>"
+        ),
+    );
+    writer.object(
+        cid_font,
+        &format!(
+            "<< /Type /Font /Subtype /CIDFontType2 /BaseFont {base_font} \
+            /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+            /FontDescriptor {descriptor} 0 R /CIDToGIDMap {cid_to_gid} 0 R /DW 0 /W [{widths}] >>"
+        ),
+    );
+
+    // PDF glyph metrics are in thousandths of the font size.
+    let scale = 1000.0 / face.units_per_em() as f64;
+    let bbox = face.global_bounding_box();
+    let italic_angle = face.italic_angle() as f64;
+    // Symbolic, plus fixed pitch or serif, plus italic.
+    let mut flags = 4;
+    flags |= if std::ptr::eq(font.font, &fonts::MONO) {
+        1
+    } else {
+        2
+    };
+    if italic_angle != 0.0 {
+        flags |= 64;
+    }
+    writer.object(
+        descriptor,
+        &format!(
+            "<< /Type /FontDescriptor /FontName {base_font} /Flags {flags} \
+            /FontBBox [{} {} {} {}] /ItalicAngle {} /Ascent {} /Descent {} /CapHeight {} \
+            /StemV 80 /FontFile2 {font_file} 0 R >>",
+            num(bbox.x_min as f64 * scale),
+            num(bbox.y_min as f64 * scale),
+            num(bbox.x_max as f64 * scale),
+            num(bbox.y_max as f64 * scale),
+            num(italic_angle),
+            num(face.ascender() as f64 * scale),
+            num(face.descender() as f64 * scale),
+            num(face.capital_height().unwrap_or(face.ascender()) as f64 * scale),
+        ),
+    );
+
+    // The glyph id of each character code, as big-endian 16-bit numbers. Codes that are not
+    // drawn map to `.notdef`.
+    let codes = font
+        .characters
+        .last_key_value()
+        .map_or(0, |(&code, _)| code as usize + 1);
+    let mut map = vec![0; 2 * codes];
+    for (&code, character) in &font.characters {
+        let code = code as usize;
+        map[2 * code..2 * code + 2].copy_from_slice(&character.glyph.0.to_be_bytes());
+    }
+    writer.stream(cid_to_gid, "", &map);
+
+    // Map the codes back to characters so that text can be copied out of the PDF.
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+        /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+        /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+        1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+    let characters = font.characters.iter().collect::<Vec<_>>();
+    // A `bfchar` block can hold at most 100 entries.
+    for chunk in characters.chunks(100) {
+        writeln!(cmap, "{} beginbfchar", chunk.len()).unwrap();
+        for (code, character) in chunk {
+            let utf16 = character
+                .unicode
+                .encode_utf16(&mut [0; 2])
+                .iter()
+                .map(|unit| format!("{unit:04X}"))
+                .collect::<String>();
+            writeln!(cmap, "<{code:04X}> <{utf16}>").unwrap();
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    writer.stream(to_unicode, "", cmap.as_bytes());
+
+    type0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read as _;
+    use std::sync::{Arc, Mutex};
+
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+    use crate::dvi::Font;
+
+    const RED: Color = Color::Rgb([1.0, 0.0, 0.0]);
+    const BLUE: Color = Color::Rgb([0.0, 0.0, 1.0]);
+
+    /// The body of the object `id` in `pdf`, up to `endobj`.
+    fn object(pdf: &[u8], id: usize) -> &[u8] {
+        let header = format!("\n{id} 0 obj\n");
+        let start = pdf
+            .windows(header.len())
+            .position(|w| w == header.as_bytes())
+            .unwrap_or_else(|| panic!("object {id} is missing"))
+            + header.len();
+        let end = pdf[start..]
+            .windows(7)
+            .position(|w| w == b"\nendobj")
+            .unwrap();
+        &pdf[start..start + end]
+    }
+
+    /// The decompressed data of the stream object `id` in `pdf`.
+    fn stream(pdf: &[u8], id: usize) -> Vec<u8> {
+        let object = object(pdf, id);
+        let start = object.windows(7).position(|w| w == b"stream\n").unwrap() + 7;
+        let mut data = Vec::new();
+        ZlibDecoder::new(&object[start..])
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn paint_operators() {
+        assert_eq!(paint_operator(true, true, false), "B");
+        assert_eq!(paint_operator(true, true, true), "B*");
+        assert_eq!(paint_operator(true, false, false), "f");
+        assert_eq!(paint_operator(true, false, true), "f*");
+        assert_eq!(paint_operator(false, true, false), "S");
+        assert_eq!(paint_operator(false, true, true), "S");
+        assert_eq!(paint_operator(false, false, false), "n");
+    }
+
+    #[test]
+    fn a_single_stop_is_a_constant_function() {
+        assert_eq!(
+            stops_function(&[(5.0, RED)]),
+            "<< /FunctionType 2 /Domain [0 1] /C0 [1 0 0] /C1 [1 0 0] /N 1 >>"
+        );
+        assert_eq!(
+            stops_function(&[]),
+            "<< /FunctionType 2 /Domain [0 1] /C0 [0 0 0] /C1 [0 0 0] /N 1 >>"
+        );
+    }
+
+    #[test]
+    fn two_stops_are_interpolated_directly() {
+        assert_eq!(
+            stops_function(&[(0.0, RED), (10.0, BLUE)]),
+            "<< /FunctionType 2 /Domain [0 1] /C0 [1 0 0] /C1 [0 0 1] /N 1 >>"
+        );
+    }
+
+    #[test]
+    fn more_stops_are_stitched_together_at_their_positions() {
+        let green = Color::Rgb([0.0, 1.0, 0.0]);
+        let interpolate = |from: &str, to: &str| {
+            format!("<< /FunctionType 2 /Domain [0 1] /C0 [{from}] /C1 [{to}] /N 1 >>")
+        };
+        // The stops are scaled from 10..50 to 0..1.
+        assert_eq!(
+            stops_function(&[(10.0, RED), (20.0, green), (50.0, BLUE)]),
+            format!(
+                "<< /FunctionType 3 /Domain [0 1] /Functions [{} {}] /Bounds [0.25] \
+                 /Encode [0 1 0 1] >>",
+                interpolate("1 0 0", "0 1 0"),
+                interpolate("0 1 0", "0 0 1")
+            )
+        );
+        assert_eq!(
+            stops_function(&[(0.0, RED), (1.0, green), (3.0, RED), (4.0, BLUE)]),
+            format!(
+                "<< /FunctionType 3 /Domain [0 1] /Functions [{} {} {}] /Bounds [0.25 0.75] \
+                 /Encode [0 1 0 1 0 1] >>",
+                interpolate("1 0 0", "0 1 0"),
+                interpolate("0 1 0", "1 0 0"),
+                interpolate("1 0 0", "0 0 1")
+            )
+        );
+    }
+
+    #[test]
+    fn shadings_are_axial_or_radial() {
+        let axial = Shading {
+            kind: ShadingKind::Axial {
+                from: (1.0, 2.0),
+                to: (3.0, 4.0),
+            },
+            stops: vec![(0.0, RED), (2.0, BLUE)],
+            rect: [0.0, 0.0, 10.0, 10.0],
+        };
+        assert_eq!(
+            shading_dictionary(&axial),
+            format!(
+                "<< /ShadingType 2 /ColorSpace /DeviceRGB /Coords [1 2 3 4] /Function {} \
+                 /Extend [true true] >>",
+                stops_function(&axial.stops)
+            )
+        );
+
+        // The radii of the circles are the positions of the first and last stops.
+        let radial = Shading {
+            kind: ShadingKind::Radial {
+                inner: (1.0, 2.0),
+                outer: (3.0, 4.0),
+            },
+            stops: vec![(0.5, RED), (6.0, BLUE)],
+            rect: [0.0, 0.0, 10.0, 10.0],
+        };
+        assert!(
+            shading_dictionary(&radial)
+                .starts_with("<< /ShadingType 3 /ColorSpace /DeviceRGB /Coords [1 2 0.5 3 4 6] ")
+        );
+    }
+
+    fn font(name: &str) -> Font {
+        Font {
+            name: name.to_string(),
+            size: 10.0,
+            design_size: 10.0,
+        }
+    }
+
+    /// The character `code` of `font` at `(h, v)`.
+    fn glyph(font: &Font, code: u32, h: f64, v: f64, width: f64) -> Glyph<'_> {
+        Glyph {
+            code,
+            font,
+            h,
+            v,
+            width,
+            height: 6.8,
+            depth: 0.0,
+        }
+    }
+
+    #[test]
+    fn fonts_have_the_codes_and_widths_of_the_tex_font() {
+        let cmr10 = font("cmr10");
+        let mut machine = PdfMachine::new();
+        machine.glyph(&glyph(&cmr10, 65, 0.0, 0.0, 7.5));
+        machine.glyph(&glyph(&cmr10, 0, 0.0, 0.0, 6.25));
+        let pdf_font = &machine.pdf_fonts[0];
+        let gamma = pdf_font.face.glyph_index('Γ').unwrap();
+        let a = pdf_font.face.glyph_index('A').unwrap();
+
+        let mut writer = PdfWriter::new();
+        let font_file = writer.reserve();
+        let id = write_font(&mut writer, pdf_font, font_file);
+        let pdf = writer.finish(id);
+
+        let type0 = String::from_utf8_lossy(object(&pdf, id)).into_owned();
+        assert!(type0.contains("/Subtype /Type0 /BaseFont /DejaVuSerif /Encoding /Identity-H"));
+        // The font's objects come right after the font dictionary, in the order they were
+        // reserved.
+        let cid_font = String::from_utf8_lossy(object(&pdf, id + 1)).into_owned();
+        assert!(
+            cid_font.contains("/DW 0 /W [0 [625] 65 [750] ]"),
+            "{cid_font}"
+        );
+        let descriptor = String::from_utf8_lossy(object(&pdf, id + 2)).into_owned();
+        assert!(descriptor.contains(&format!("/FontFile2 {font_file} 0 R")));
+
+        let cid_to_gid = stream(&pdf, id + 3);
+        assert_eq!(cid_to_gid.len(), 2 * 66);
+        assert_eq!(cid_to_gid[..2], gamma.0.to_be_bytes());
+        assert_eq!(cid_to_gid[130..], a.0.to_be_bytes());
+        assert!(cid_to_gid[2..130].iter().all(|&b| b == 0));
+
+        let to_unicode = String::from_utf8(stream(&pdf, id + 4)).unwrap();
+        assert!(to_unicode.contains("2 beginbfchar\n<0000> <0393>\n<0041> <0041>\n"));
+    }
+
+    #[test]
+    fn characters_outside_of_the_basic_multilingual_plane_are_mapped_to_surrogate_pairs() {
+        let mut machine = PdfMachine::new();
+        let index = machine.pdf_font("eufm10");
+        machine.pdf_fonts[index].characters.insert(
+            65,
+            Character {
+                glyph: GlyphId(0),
+                unicode: '𝔄',
+                width: 0.0,
+            },
+        );
+
+        let mut writer = PdfWriter::new();
+        let font_file = writer.reserve();
+        let id = write_font(&mut writer, &machine.pdf_fonts[index], font_file);
+        let pdf = writer.finish(id);
+        let to_unicode = String::from_utf8(stream(&pdf, id + 4)).unwrap();
+        assert!(to_unicode.contains("<0041> <D835DD04>\n"));
+    }
+
+    #[test]
+    fn tex_fonts_with_the_same_stand_in_share_its_file() {
+        let (cmr10, cmr7, cmmi10) = (font("cmr10"), font("cmr7"), font("cmmi10"));
+        let mut machine = PdfMachine::new();
+        machine.glyph(&glyph(&cmr10, 65, 0.0, 0.0, 7.5));
+        machine.glyph(&glyph(&cmr7, 65, 0.0, 0.0, 7.5));
+        machine.glyph(&glyph(&cmmi10, 65, 0.0, 0.0, 7.5));
+        let pdf = String::from_utf8_lossy(&machine.into_pdf()).into_owned();
+        assert_eq!(pdf.matches("/Subtype /Type0").count(), 3);
+        assert_eq!(pdf.matches("/Length1 ").count(), 2);
+    }
+
+    #[test]
+    fn glyphs_on_a_line_are_written_as_one_run() {
+        let cmr10 = font("cmr10");
+        let mut machine = PdfMachine::new();
+        machine.glyph(&glyph(&cmr10, 65, 10.0, 20.0, 7.5));
+        machine.glyph(&glyph(&cmr10, 66, 17.5, 20.0, 7.0));
+        // A space of 5pt, which is half the font size.
+        machine.glyph(&glyph(&cmr10, 67, 29.5, 20.0, 7.0));
+        // The next line, and a glyph after a rule.
+        machine.glyph(&glyph(&cmr10, 65, 10.0, 40.0, 7.5));
+        machine.rule(&Rule {
+            h: 17.5,
+            v: 40.0,
+            width: 1.0,
+            height: 1.0,
+        });
+        machine.glyph(&glyph(&cmr10, 66, 18.5, 40.0, 7.0));
+        assert_eq!(
+            machine.content,
+            "BT /F0 10 Tf 0 g 1 0 0 -1 10 20 Tm [<0041><0042> -500 <0043>] TJ ET\n\
+             BT /F0 10 Tf 0 g 1 0 0 -1 10 40 Tm [<0041>] TJ ET\n\
+             0 g 17.5 39 1 1 re f\n\
+             BT /F0 10 Tf 0 g 1 0 0 -1 18.5 40 Tm [<0042>] TJ ET\n"
+        );
+    }
+
+    #[test]
+    fn glyphs_the_stand_in_does_not_have_are_reported_once() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let mut machine = PdfMachine::new();
+        machine.set_logger(Logger::new({
+            let warnings = warnings.clone();
+            move |category, message| {
+                warnings
+                    .lock()
+                    .unwrap()
+                    .push(format!("{category}: {message}"))
+            }
+        }));
+        let eufm10 = font("eufm10");
+        for h in [0.0, 10.0] {
+            machine.glyph(&glyph(&eufm10, 65, h, 0.0, 7.5));
+        }
+        machine.glyph(&glyph(&eufm10, 0x10000, 20.0, 0.0, 7.5));
+
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            [
+                "warning: The bundled font DejaVuSerif has no glyph for `𝔄` (code 65 of eufm10), \
+                 so a blank box is drawn in its place",
+                "warning: Cannot draw code 65536 of eufm10, so it is left out of the PDF",
+            ]
+        );
+        // The glyph is still drawn, as `.notdef`.
+        assert_eq!(machine.pdf_fonts[0].characters[&65].glyph, GlyphId(0));
+    }
+
+    #[test]
+    fn links_become_annotations_over_their_glyphs() {
+        let cmr10 = font("cmr10");
+        let special = |text| Special {
+            h: 10.0,
+            v: 20.0,
+            text,
+        };
+        let mut machine = PdfMachine::new();
+        machine.special(&special("papersize=200pt,100pt"));
+        machine.special(&special("html:<a href=\"https://example.com\">"));
+        machine.glyph(&glyph(&cmr10, 65, 10.0, 20.0, 7.5));
+        machine.special(&special("html:</a>"));
+        machine.special(&special("html:<a name=\"target\">"));
+        machine.special(&special("html:</a>"));
+        let pdf = String::from_utf8_lossy(&machine.into_pdf()).into_owned();
+
+        assert!(pdf.contains("/MediaBox [0 0 200 100]"), "{pdf}");
+        // The origin is one inch from the top left corner and the y axis points up.
+        assert!(
+            pdf.contains(
+                "<< /Type /Annot /Subtype /Link /Rect [82 8 89.5 14.8] /Border [0 0 0] \
+                 /A << /S /URI /URI (https://example.com) >> >>"
+            ),
+            "{pdf}"
+        );
+        // Anchors are destinations rather than links.
+        assert_eq!(pdf.matches("/Type /Annot").count(), 1);
+        assert!(
+            pdf.contains("/Dests << /target [3 0 R /XYZ 82 8 null] >>"),
+            "{pdf}"
+        );
+    }
+}
@@ -0,0 +1,928 @@
+//! An interpreter for the specials written by `pgfsys-rusttikz.def`, the pgf driver that
+//! rust-tikz loads. The driver writes every drawing operation of a TikZ picture as a
+//! `rusttikz:` special with a command and its arguments, e.g.
+//!
+//! ```text
+//! rusttikz:moveto 0 0;lineto 10 10;stroke;
+//! ```
+//!
+//! [`PgfInterpreter`] keeps track of the path and the graphics state and reports what is drawn
+//! to a [`Canvas`]. Coordinates passed to a [`Canvas`] are DVI coordinates (TeX points from
+//! TeX's reference point with the y axis pointing down), with all transformations of the
+//! picture already applied, so every backend can draw them the same way.
+//!
+//! Patterns, fadings, transparency groups, blend modes and invisible paths are not replaced by
+//! the driver, so pgf writes them with the driver the format was built with. Their commands
+//! arrive as SVG markup in between the other commands, and are passed on to
+//! [`Canvas::raw_svg`] in order, just like `dvisvgm:raw` specials.
+
+use std::collections::HashSet;
+
+use super::color::Color;
+use super::raw_svg::{self, Matrix, PathSegment};
+use crate::logging::{LogCategory, Logger};
+
+/// The prefix of the specials written by the driver.
+const PREFIX: &str = "rusttikz:";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+/// How a path is painted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paint {
+    /// The fill color, or `None` if the path is not filled.
+    pub fill: Option<Color>,
+    /// The stroke color, or `None` if the path is not stroked.
+    pub stroke: Option<Color>,
+    pub even_odd: bool,
+    pub line_width: f64,
+    /// The lengths of the dashes and gaps. The line is solid if this is empty.
+    pub dash_array: Vec<f64>,
+    pub dash_offset: f64,
+    pub line_cap: LineCap,
+    pub line_join: LineJoin,
+    pub miter_limit: f64,
+    pub fill_opacity: f64,
+    pub stroke_opacity: f64,
+}
+
+impl Paint {
+    /// Fill with `color`, without stroking.
+    pub fn fill(color: Color) -> Paint {
+        Paint {
+            fill: Some(color),
+            stroke: None,
+            even_odd: false,
+            line_width: 0.0,
+            dash_array: Vec::new(),
+            dash_offset: 0.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 10.0,
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+        }
+    }
+}
+
+/// The geometry of a shading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadingKind {
+    /// The color changes along the line from `from` to `to`, which correspond to the positions
+    /// of the first and last stops.
+    Axial { from: (f64, f64), to: (f64, f64) },
+    /// The color changes from a circle around `inner` to a circle around `outer`, whose radii
+    /// are the positions of the first and last stops.
+    Radial {
+        inner: (f64, f64),
+        outer: (f64, f64),
+    },
+}
+
+/// A smooth transition between colors, which fills a rectangle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shading {
+    pub kind: ShadingKind,
+    /// The colors and their positions in points, in increasing order.
+    pub stops: Vec<(f64, Color)>,
+    /// The rectangle that is filled, as `[x, y, width, height]` with `(x, y)` the top left
+    /// corner.
+    pub rect: [f64; 4],
+}
+
+impl Shading {
+    /// The color halfway through the shading, for backends that cannot draw shadings.
+    pub fn average_color(&self) -> Color {
+        let (Some((_, first)), Some((_, last))) = (self.stops.first(), self.stops.last()) else {
+            return Color::BLACK;
+        };
+        let (first, last) = (first.to_rgb(), last.to_rgb());
+        Color::Rgb([0, 1, 2].map(|i| (first[i] + last[i]) / 2.0))
+    }
+}
+
+/// A backend that draws TikZ pictures. Every method does nothing by default.
+pub trait Canvas {
+    fn begin_picture(&mut self) {}
+    fn end_picture(&mut self) {}
+    /// Save the graphics state, which includes the clip path.
+    fn begin_scope(&mut self) {}
+    /// Restore the graphics state saved by the matching [`Canvas::begin_scope`].
+    fn end_scope(&mut self) {}
+    /// Clip everything drawn until the end of the current scope to `path`.
+    fn clip(&mut self, _path: &[PathSegment], _even_odd: bool) {}
+    fn draw_path(&mut self, _path: &[PathSegment], _paint: &Paint) {}
+    /// Glyphs, rules and shadings until the matching [`Canvas::end_transform`] have to be
+    /// transformed by `matrix`. This is how text is placed in a picture.
+    fn begin_transform(&mut self, _matrix: Matrix) {}
+    fn end_transform(&mut self) {}
+    fn shading(&mut self, _shading: &Shading) {}
+    /// Draw the SVG markup of a command that the driver does not replace, with the
+    /// placeholders of dvisvgm filled in. Returns whether the markup was drawn, so that the
+    /// commands a backend leaves out can be reported.
+    fn raw_svg(&mut self, _svg: &str) -> bool {
+        false
+    }
+}
+
+/// The graphics state of a picture.
+#[derive(Debug, Clone)]
+struct State {
+    /// The transformation from picture coordinates to DVI coordinates.
+    matrix: Matrix,
+    /// The colors set by pgf. `None` means the color of the surrounding text.
+    fill: Option<Color>,
+    stroke: Option<Color>,
+    even_odd: bool,
+    line_width: f64,
+    dash_array: Vec<f64>,
+    dash_offset: f64,
+    line_cap: LineCap,
+    line_join: LineJoin,
+    miter_limit: f64,
+    fill_opacity: f64,
+    stroke_opacity: f64,
+}
+
+impl State {
+    fn new(matrix: Matrix) -> State {
+        // The PDF defaults, which pgf assumes.
+        State {
+            matrix,
+            fill: None,
+            stroke: None,
+            even_odd: false,
+            line_width: 1.0,
+            dash_array: Vec::new(),
+            dash_offset: 0.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 10.0,
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+        }
+    }
+}
+
+/// A picture that is being drawn. Pictures can be nested, e.g. in the text of a node.
+#[derive(Debug)]
+struct Picture {
+    state: State,
+    saved_states: Vec<State>,
+    /// The path being constructed, in DVI coordinates.
+    path: Vec<PathSegment>,
+    /// Whether the next path is also used to clip.
+    clip_next: bool,
+}
+
+/// Interprets the specials written by the rust-tikz pgf driver.
+#[derive(Debug, Default)]
+pub struct PgfInterpreter {
+    pictures: Vec<Picture>,
+    /// The number of text boxes that are open.
+    hbox_depth: usize,
+    /// Where commands that cannot be drawn are reported.
+    logger: Logger,
+    /// The names of the commands that have been reported, so that each is reported once.
+    reported: HashSet<String>,
+}
+
+impl PgfInterpreter {
+    /// Report the commands that cannot be drawn to `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = logger;
+    }
+
+    /// Interpret `special` if it was written by the driver and return whether it was.
+    /// `text_color` is the current color of the text, which is used until pgf sets a color.
+    pub fn special<C: Canvas + ?Sized>(
+        &mut self,
+        special: &crate::dvi::Special,
+        text_color: Color,
+        canvas: &mut C,
+    ) -> bool {
+        let Some(commands) = special.text.strip_prefix(PREFIX) else {
+            return false;
+        };
+        // The color specification of a shading is separated by semicolons too, so a shading
+        // is the only command in its special.
+        if let Some(arguments) = commands.trim_start().strip_prefix("shading ") {
+            let arguments = arguments.split_whitespace().collect::<Vec<_>>();
+            if let Some(shading) = parse_shading(&arguments, special.h, special.v) {
+                canvas.shading(&shading);
+            }
+            return true;
+        }
+        let mut rest = commands;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+            if rest.starts_with('<') {
+                let end = markup_end(rest);
+                self.markup(&rest[..end], special, canvas);
+                rest = &rest[end..];
+                continue;
+            }
+            let (command, next) = rest.split_once(';').unwrap_or((rest, ""));
+            let mut words = command.split_whitespace();
+            if let Some(name) = words.next() {
+                let arguments = words.collect::<Vec<_>>();
+                self.command(name, &arguments, special, text_color, canvas);
+            }
+            rest = next;
+        }
+        true
+    }
+
+    /// Pass the SVG markup of a command that the driver does not replace on to the canvas, or
+    /// report it if the canvas cannot draw it.
+    fn markup<C: Canvas + ?Sized>(
+        &mut self,
+        markup: &str,
+        special: &crate::dvi::Special,
+        canvas: &mut C,
+    ) {
+        let svg = raw_svg::fill_placeholders(markup, special.h, special.v);
+        if canvas.raw_svg(&svg) {
+            return;
+        }
+        let mut words = markup.split_whitespace();
+        if let Some(name) = words.next() {
+            self.unsupported(name, &words.collect::<Vec<_>>());
+        }
+    }
+
+    fn command<C: Canvas + ?Sized>(
+        &mut self,
+        name: &str,
+        arguments: &[&str],
+        special: &crate::dvi::Special,
+        text_color: Color,
+        canvas: &mut C,
+    ) {
+        match name {
+            "begin-picture" => {
+                // The picture is drawn with the y axis pointing up from its origin.
+                let matrix = [1.0, 0.0, 0.0, -1.0, special.h, special.v];
+                self.pictures.push(Picture {
+                    state: State::new(matrix),
+                    saved_states: Vec::new(),
+                    path: Vec::new(),
+                    clip_next: false,
+                });
+                canvas.begin_picture();
+                return;
+            }
+            "end-picture" => {
+                if let Some(picture) = self.pictures.pop() {
+                    for _ in picture.saved_states {
+                        canvas.end_scope();
+                    }
+                    canvas.end_picture();
+                }
+                return;
+            }
+            "begin-hbox" => {
+                let Some(picture) = self.pictures.last() else {
+                    return;
+                };
+                // Text is typeset at the position of the box, with the y axis pointing down,
+                // and has to be moved into the coordinates of the picture.
+                let to_picture = [1.0, 0.0, 0.0, -1.0, -special.h, special.v];
+                canvas.begin_transform(raw_svg::multiply(to_picture, picture.state.matrix));
+                self.hbox_depth += 1;
+                return;
+            }
+            "end-hbox" => {
+                if self.hbox_depth > 0 {
+                    self.hbox_depth -= 1;
+                    canvas.end_transform();
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let Some(picture) = self.pictures.last_mut() else {
+            return;
+        };
+        let numbers = arguments
+            .iter()
+            .map(|a| a.parse::<f64>().unwrap_or(0.0))
+            .collect::<Vec<_>>();
+        let matrix = picture.state.matrix;
+        let point = |x: f64, y: f64| raw_svg::transform_point(matrix, x, y);
+        match (name, numbers.as_slice()) {
+            ("moveto", &[x, y]) => {
+                let (x, y) = point(x, y);
+                picture.path.push(PathSegment::MoveTo(x, y));
+            }
+            ("lineto", &[x, y]) => {
+                let (x, y) = point(x, y);
+                picture.path.push(PathSegment::LineTo(x, y));
+            }
+            ("curveto", &[x1, y1, x2, y2, x, y]) => {
+                let ((x1, y1), (x2, y2), (x, y)) = (point(x1, y1), point(x2, y2), point(x, y));
+                picture
+                    .path
+                    .push(PathSegment::CurveTo(x1, y1, x2, y2, x, y));
+            }
+            ("rect", &[x, y, width, height]) => {
+                let corners = [
+                    (x, y),
+                    (x + width, y),
+                    (x + width, y + height),
+                    (x, y + height),
+                ];
+                for (i, (x, y)) in corners.into_iter().enumerate() {
+                    let (x, y) = point(x, y);
+                    picture.path.push(match i {
+                        0 => PathSegment::MoveTo(x, y),
+                        _ => PathSegment::LineTo(x, y),
+                    });
+                }
+                picture.path.push(PathSegment::Close);
+            }
+            ("closepath", _) => picture.path.push(PathSegment::Close),
+            ("fill" | "stroke" | "fill-stroke" | "discard", _) => {
+                let path = std::mem::take(&mut picture.path);
+                let state = &picture.state;
+                let fill = matches!(name, "fill" | "fill-stroke");
+                let stroke = matches!(name, "stroke" | "fill-stroke");
+                if fill || stroke {
+                    // Lengths are scaled by the transformation, which is exact as long as it
+                    // does not stretch one direction more than the other.
+                    let [a, b, c, d, _, _] = state.matrix;
+                    let scale = (a * d - b * c).abs().sqrt();
+                    let paint = Paint {
+                        fill: fill.then_some(state.fill.unwrap_or(text_color)),
+                        stroke: stroke.then_some(state.stroke.unwrap_or(text_color)),
+                        even_odd: state.even_odd,
+                        line_width: state.line_width * scale,
+                        dash_array: state.dash_array.iter().map(|d| d * scale).collect(),
+                        dash_offset: state.dash_offset * scale,
+                        line_cap: state.line_cap,
+                        line_join: state.line_join,
+                        miter_limit: state.miter_limit,
+                        fill_opacity: state.fill_opacity,
+                        stroke_opacity: state.stroke_opacity,
+                    };
+                    canvas.draw_path(&path, &paint);
+                }
+                if std::mem::take(&mut picture.clip_next) {
+                    canvas.clip(&path, picture.state.even_odd);
+                }
+            }
+            ("clip", _) => picture.clip_next = true,
+            ("eo-rule", _) => picture.state.even_odd = arguments.first() == Some(&"on"),
+            ("begin-scope", _) => {
+                picture.saved_states.push(picture.state.clone());
+                canvas.begin_scope();
+            }
+            ("end-scope", _) => {
+                if let Some(state) = picture.saved_states.pop() {
+                    picture.state = state;
+                    canvas.end_scope();
+                }
+            }
+            ("transform", &[a, b, c, d, e, f]) => {
+                picture.state.matrix = raw_svg::multiply([a, b, c, d, e, f], picture.state.matrix);
+            }
+            ("line-width", &[width]) => picture.state.line_width = width,
+            ("dash", &[offset, ..]) => {
+                picture.state.dash_offset = offset;
+                picture.state.dash_array = numbers[1..].to_vec();
+            }
+            ("cap", _) => {
+                picture.state.line_cap = match arguments.first() {
+                    Some(&"round") => LineCap::Round,
+                    Some(&"rect") => LineCap::Square,
+                    _ => LineCap::Butt,
+                }
+            }
+            ("join", _) => {
+                picture.state.line_join = match arguments.first() {
+                    Some(&"round") => LineJoin::Round,
+                    Some(&"bevel") => LineJoin::Bevel,
+                    _ => LineJoin::Miter,
+                }
+            }
+            ("miter-limit", &[limit]) => picture.state.miter_limit = limit,
+            ("fill-opacity", &[opacity]) => picture.state.fill_opacity = opacity,
+            ("stroke-opacity", &[opacity]) => picture.state.stroke_opacity = opacity,
+            ("fill-color", _) => picture.state.fill = Color::parse(&arguments.join(" ")),
+            ("stroke-color", _) => picture.state.stroke = Color::parse(&arguments.join(" ")),
+            _ => self.unsupported(name, arguments),
+        }
+    }
+
+    /// Report a command that cannot be drawn, unless a command of the same name has already
+    /// been reported.
+    fn unsupported(&mut self, name: &str, arguments: &[&str]) {
+        if !self.reported.insert(name.to_string()) {
+            return;
+        }
+        self.logger.log(LogCategory::Warning, || {
+            let command = [name].iter().chain(arguments).copied().collect::<Vec<_>>();
+            format!(
+                "Cannot draw the pgf driver command `{}`, so it is left out of the picture",
+                command.join(" ")
+            )
+        });
+    }
+}
+
+/// The length of the SVG markup at the start of `commands`: the tags up to the next command,
+/// along with the whitespace and `{?nl}` placeholders after them.
+fn markup_end(commands: &str) -> usize {
+    let mut end = 0;
+    while let Some(close) = commands[end..].find('>') {
+        let mut gap = commands[end + close + 1..].trim_start();
+        while let Some(rest) = gap.strip_prefix("{?nl}") {
+            gap = rest.trim_start();
+        }
+        end = commands.len() - gap.len();
+        if !gap.starts_with('<') {
+            break;
+        }
+    }
+    if end == 0 { commands.len() } else { end }
+}
+
+/// Parse the arguments of a `shading` special at `(h, v)`, which is the lower left corner of
+/// the shading.
+fn parse_shading(arguments: &[&str], h: f64, v: f64) -> Option<Shading> {
+    let (kind, rest) = arguments.split_first()?;
+    let number = |i: usize| rest.get(i).and_then(|n| n.parse::<f64>().ok());
+    let (kind, rect, spec) = match *kind {
+        "horizontal" => {
+            let (width, height) = (number(0)?, number(1)?);
+            let stops = parse_stops(&rest[2..].join(" "));
+            let (first, last) = (stops.first()?.0, stops.last()?.0);
+            let kind = ShadingKind::Axial {
+                from: (h + first, v),
+                to: (h + last, v),
+            };
+            (kind, [h, v - height, width, height], stops)
+        }
+        "vertical" => {
+            let (width, height) = (number(0)?, number(1)?);
+            let stops = parse_stops(&rest[2..].join(" "));
+            let (first, last) = (stops.first()?.0, stops.last()?.0);
+            let kind = ShadingKind::Axial {
+                from: (h, v - first),
+                to: (h, v - last),
+            };
+            (kind, [h, v - height, width, height], stops)
+        }
+        "radial" => {
+            let (radius, x, y) = (number(0)?, number(1)?, number(2)?);
+            let stops = parse_stops(&rest[3..].join(" "));
+            let center = (h + radius, v - radius);
+            let kind = ShadingKind::Radial {
+                inner: (center.0 + x, center.1 - y),
+                outer: center,
+            };
+            (
+                kind,
+                [h, v - 2.0 * radius, 2.0 * radius, 2.0 * radius],
+                stops,
+            )
+        }
+        _ => return None,
+    };
+    Some(Shading {
+        kind,
+        stops: spec,
+        rect,
+    })
+}
+
+/// Parse a pgf color specification like `rgb(0bp)=(1,0,0); rgb(25bp)=(0,0,1)` into stops.
+fn parse_stops(spec: &str) -> Vec<(f64, Color)> {
+    let mut stops = Vec::new();
+    for stop in spec.split(';') {
+        let Some((position, color)) = stop.split_once('=') else {
+            continue;
+        };
+        let Some((model, position)) = position.trim().split_once('(') else {
+            continue;
+        };
+        let position = position.trim_end_matches(')');
+        let components = color
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .replace(',', " ");
+        if let (Some(position), Some(color)) = (
+            parse_dimension(position),
+            Color::parse(&format!("{} {components}", model.trim())),
+        ) {
+            stops.push((position, color));
+        }
+    }
+    stops
+}
+
+/// Parse a TeX dimension in `pt` or `bp` into points.
+fn parse_dimension(dimension: &str) -> Option<f64> {
+    let dimension = dimension.trim();
+    if let Some(bp) = dimension.strip_suffix("bp") {
+        return bp.trim().parse::<f64>().ok().map(|bp| bp * 72.27 / 72.0);
+    }
+    dimension.trim_end_matches("pt").trim().parse::<f64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::dvi::Special;
+
+    /// Records what the interpreter draws.
+    #[derive(Default)]
+    struct Recorder {
+        /// Whether SVG markup is drawn, as it is by the SVG backend.
+        draws_raw_svg: bool,
+        events: Vec<String>,
+        paths: Vec<(Vec<PathSegment>, Paint)>,
+        clips: Vec<Vec<PathSegment>>,
+        shadings: Vec<Shading>,
+    }
+
+    impl Canvas for Recorder {
+        fn begin_picture(&mut self) {
+            self.events.push("begin-picture".to_string());
+        }
+        fn end_picture(&mut self) {
+            self.events.push("end-picture".to_string());
+        }
+        fn begin_scope(&mut self) {
+            self.events.push("begin-scope".to_string());
+        }
+        fn end_scope(&mut self) {
+            self.events.push("end-scope".to_string());
+        }
+        fn clip(&mut self, path: &[PathSegment], _even_odd: bool) {
+            self.clips.push(path.to_vec());
+        }
+        fn draw_path(&mut self, path: &[PathSegment], paint: &Paint) {
+            self.events.push("path".to_string());
+            self.paths.push((path.to_vec(), paint.clone()));
+        }
+        fn shading(&mut self, shading: &Shading) {
+            self.shadings.push(shading.clone());
+        }
+        fn raw_svg(&mut self, svg: &str) -> bool {
+            if self.draws_raw_svg {
+                self.events.push(svg.to_string());
+            }
+            self.draws_raw_svg
+        }
+    }
+
+    const TEXT_COLOR: Color = Color::Gray(0.25);
+
+    /// Interpret `specials`, each at `(10, 20)`, and return what was drawn.
+    fn draw(interpreter: &mut PgfInterpreter, specials: &[&str]) -> Recorder {
+        draw_on(interpreter, Recorder::default(), specials)
+    }
+
+    /// Interpret `specials`, each at `(10, 20)`, and return what was drawn on `recorder`.
+    fn draw_on(
+        interpreter: &mut PgfInterpreter,
+        mut recorder: Recorder,
+        specials: &[&str],
+    ) -> Recorder {
+        for text in specials {
+            let special = Special {
+                h: 10.0,
+                v: 20.0,
+                text,
+            };
+            interpreter.special(&special, TEXT_COLOR, &mut recorder);
+        }
+        recorder
+    }
+
+    #[test]
+    fn paths_are_drawn_in_dvi_coordinates() {
+        let recorder = draw(
+            &mut PgfInterpreter::default(),
+            &[
+                "rusttikz:begin-picture;",
+                "rusttikz:moveto 0 0;lineto 5 5;curveto 1 2 3 4 5 6;closepath;stroke;",
+            ],
+        );
+        let [(path, paint)] = recorder.paths.as_slice() else {
+            panic!("expected one path, got {:?}", recorder.paths);
+        };
+        // The picture starts at the special, with the y axis pointing up.
+        assert_eq!(
+            path,
+            &[
+                PathSegment::MoveTo(10.0, 20.0),
+                PathSegment::LineTo(15.0, 15.0),
+                PathSegment::CurveTo(11.0, 18.0, 13.0, 16.0, 15.0, 14.0),
+                PathSegment::Close,
+            ]
+        );
+        assert_eq!(paint.stroke, Some(TEXT_COLOR));
+        assert_eq!(paint.fill, None);
+        assert_eq!(paint.line_width, 1.0);
+    }
+
+    #[test]
+    fn scopes_restore_the_graphics_state() {
+        let recorder = draw(
+            &mut PgfInterpreter::default(),
+            &[
+                "rusttikz:begin-picture;",
+                "rusttikz:begin-scope;line-width 2;stroke-color rgb 1 0 0;dash 1 3 4;",
+                "rusttikz:cap round;join bevel;fill-opacity 0.5;moveto 0 0;fill-stroke;",
+                "rusttikz:end-scope;moveto 0 0;fill;",
+            ],
+        );
+        let [(_, scoped), (_, restored)] = recorder.paths.as_slice() else {
+            panic!("expected two paths, got {:?}", recorder.paths);
+        };
+        assert_eq!(scoped.stroke, Some(Color::Rgb([1.0, 0.0, 0.0])));
+        assert_eq!(scoped.fill, Some(TEXT_COLOR));
+        assert_eq!(scoped.line_width, 2.0);
+        assert_eq!(
+            (scoped.dash_offset, scoped.dash_array.as_slice()),
+            (1.0, &[3.0, 4.0][..])
+        );
+        assert_eq!(
+            (scoped.line_cap, scoped.line_join),
+            (LineCap::Round, LineJoin::Bevel)
+        );
+        assert_eq!(scoped.fill_opacity, 0.5);
+
+        assert_eq!(restored.stroke, None);
+        assert_eq!(restored.line_width, 1.0);
+        assert!(restored.dash_array.is_empty());
+        assert_eq!(restored.fill_opacity, 1.0);
+    }
+
+    #[test]
+    fn lengths_are_scaled_by_the_transformation() {
+        let recorder = draw(
+            &mut PgfInterpreter::default(),
+            &[
+                "rusttikz:begin-picture;transform 2 0 0 2 1 1;line-width 3;dash 0 1 2;moveto 1 1;stroke;",
+            ],
+        );
+        let (path, paint) = &recorder.paths[0];
+        assert_eq!(path, &[PathSegment::MoveTo(13.0, 17.0)]);
+        assert_eq!(paint.line_width, 6.0);
+        assert_eq!(paint.dash_array, [2.0, 4.0]);
+    }
+
+    #[test]
+    fn clipping_paths_are_not_drawn_unless_they_are_painted() {
+        let recorder = draw(
+            &mut PgfInterpreter::default(),
+            &["rusttikz:begin-picture;clip;rect 0 0 2 1;discard;moveto 0 0;stroke;"],
+        );
+        assert_eq!(
+            recorder.clips,
+            [vec![
+                PathSegment::MoveTo(10.0, 20.0),
+                PathSegment::LineTo(12.0, 20.0),
+                PathSegment::LineTo(12.0, 19.0),
+                PathSegment::LineTo(10.0, 19.0),
+                PathSegment::Close,
+            ]]
+        );
+        // Only the next path clips.
+        assert_eq!(recorder.paths.len(), 1);
+        assert_eq!(recorder.clips.len(), 1);
+    }
+
+    #[test]
+    fn pictures_close_the_scopes_that_are_left_open() {
+        let recorder = draw(
+            &mut PgfInterpreter::default(),
+            &[
+                "rusttikz:begin-picture;begin-scope;begin-scope;end-scope;",
+                "rusttikz:end-picture;",
+            ],
+        );
+        assert_eq!(
+            recorder.events,
+            [
+                "begin-picture",
+                "begin-scope",
+                "begin-scope",
+                "end-scope",
+                "end-scope",
+                "end-picture"
+            ]
+        );
+    }
+
+    #[test]
+    fn other_specials_and_commands_outside_of_pictures_are_ignored() {
+        let mut interpreter = PgfInterpreter::default();
+        let mut recorder = Recorder::default();
+        let special = Special {
+            h: 0.0,
+            v: 0.0,
+            text: "color push gray 0",
+        };
+        assert!(!interpreter.special(&special, TEXT_COLOR, &mut recorder));
+        let recorder = draw(&mut interpreter, &["rusttikz:moveto 0 0;stroke;"]);
+        assert!(recorder.paths.is_empty());
+    }
+
+    #[test]
+    fn shadings_are_drawn_at_their_lower_left_corner() {
+        let recorder = draw(
+            &mut PgfInterpreter::default(),
+            &["rusttikz:shading horizontal 50 10 rgb(0pt)=(1,0,0); rgb(50pt)=(0,0,1);"],
+        );
+        assert_eq!(
+            recorder.shadings,
+            [Shading {
+                kind: ShadingKind::Axial {
+                    from: (10.0, 20.0),
+                    to: (60.0, 20.0),
+                },
+                stops: vec![
+                    (0.0, Color::Rgb([1.0, 0.0, 0.0])),
+                    (50.0, Color::Rgb([0.0, 0.0, 1.0])),
+                ],
+                rect: [10.0, 10.0, 50.0, 10.0],
+            }]
+        );
+    }
+
+    #[test]
+    fn unsupported_commands_are_reported_once() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = PgfInterpreter::default();
+        interpreter.set_logger(Logger::new({
+            let warnings = warnings.clone();
+            move |category, message| {
+                warnings
+                    .lock()
+                    .unwrap()
+                    .push(format!("{category}: {message}"))
+            }
+        }));
+        let recorder = draw(
+            &mut interpreter,
+            &[
+                "rusttikz:begin-picture;",
+                "rusttikz:<g opacity=\"0.5\">",
+                "rusttikz:<g opacity=\"0.2\">",
+                "rusttikz:moveto 1;",
+            ],
+        );
+        assert!(recorder.paths.is_empty());
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(
+            *warnings,
+            [
+                "warning: Cannot draw the pgf driver command `<g opacity=\"0.5\">`, so it is left \
+                 out of the picture",
+                "warning: Cannot draw the pgf driver command `moveto 1`, so it is left out of \
+                 the picture",
+            ]
+        );
+    }
+
+    #[test]
+    fn markup_is_passed_on_in_between_the_commands() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = PgfInterpreter::default();
+        interpreter.set_logger(Logger::new({
+            let warnings = warnings.clone();
+            move |_, message| warnings.lock().unwrap().push(message.to_string())
+        }));
+        let recorder = Recorder {
+            draws_raw_svg: true,
+            ..Default::default()
+        };
+        let recorder = draw_on(
+            &mut interpreter,
+            recorder,
+            &[
+                "rusttikz:begin-picture;",
+                "rusttikz:<g mask=\"url(#pgfmask1)\">{?nl}moveto 0 0;lineto 1 1;stroke;</g>{?nl}",
+                "rusttikz:<path style=\"fill:red;stroke:none\" d=\"M{?x} {?y}\"/>{?nl} <use/>",
+            ],
+        );
+        assert_eq!(
+            recorder.events,
+            [
+                "begin-picture",
+                "<g mask=\"url(#pgfmask1)\">\n",
+                "path",
+                "</g>\n",
+                "<path style=\"fill:red;stroke:none\" d=\"M10 20\"/>\n <use/>",
+            ]
+        );
+        assert!(warnings.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn markup_ends_before_the_next_command() {
+        assert_eq!(markup_end("<g>moveto 0 0;"), 3);
+        assert_eq!(markup_end("<g> {?nl} <g/>{?nl}stroke;"), 19);
+        assert_eq!(markup_end("<g><path d=\"M0 0\"/></g>"), 23);
+        // A tag that is not closed takes up the rest of the special.
+        assert_eq!(markup_end("<g opacity=\"0.5\""), 16);
+    }
+
+    #[test]
+    fn shadings_are_parsed_from_their_arguments() {
+        let spec = "gray(0pt)=(0); gray(10pt)=(1)";
+        let arguments = |kind: &str| -> Vec<String> {
+            format!("{kind} {spec}")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect()
+        };
+        let parse = |kind: &str, h, v| {
+            let arguments = arguments(kind);
+            let arguments = arguments.iter().map(String::as_str).collect::<Vec<_>>();
+            parse_shading(&arguments, h, v)
+        };
+
+        let vertical = parse("vertical 4 10", 0.0, 100.0).unwrap();
+        assert_eq!(
+            vertical.kind,
+            ShadingKind::Axial {
+                from: (0.0, 100.0),
+                to: (0.0, 90.0),
+            }
+        );
+        assert_eq!(vertical.rect, [0.0, 90.0, 4.0, 10.0]);
+
+        // The circles are centered in a square whose side is twice the last stop.
+        let radial = parse("radial 10 2 3", 0.0, 100.0).unwrap();
+        assert_eq!(
+            radial.kind,
+            ShadingKind::Radial {
+                inner: (12.0, 87.0),
+                outer: (10.0, 90.0),
+            }
+        );
+        assert_eq!(radial.rect, [0.0, 80.0, 20.0, 20.0]);
+
+        assert_eq!(parse("conic 10 10", 0.0, 0.0), None);
+        assert_eq!(parse("horizontal 10", 0.0, 0.0), None);
+        assert_eq!(parse_shading(&["horizontal", "10", "10"], 0.0, 0.0), None);
+    }
+
+    #[test]
+    fn stops_are_parsed_from_pgf_color_specifications() {
+        assert_eq!(
+            parse_stops("rgb(0bp)=(1,0,0); rgb(25bp)=(0,0,1)"),
+            [
+                (0.0, Color::Rgb([1.0, 0.0, 0.0])),
+                (25.0 * 72.27 / 72.0, Color::Rgb([0.0, 0.0, 1.0])),
+            ]
+        );
+        assert_eq!(
+            parse_stops("cmyk(1pt)=(0,1,1,0); gray(2pt)=(0.5)"),
+            [
+                (1.0, Color::Cmyk([0.0, 1.0, 1.0, 0.0])),
+                (2.0, Color::Gray(0.5)),
+            ]
+        );
+        // Stops with the wrong number of components or no position are left out.
+        assert_eq!(
+            parse_stops("rgb(0pt)=(1,0); gray=(1); gray(xpt)=(1); gray(3pt)=(0)"),
+            [(3.0, Color::Gray(0.0))]
+        );
+        assert!(parse_stops("").is_empty());
+    }
+
+    #[test]
+    fn dimensions_are_parsed_in_points() {
+        assert_eq!(parse_dimension("10pt"), Some(10.0));
+        assert_eq!(parse_dimension(" 2.5 pt "), Some(2.5));
+        assert_eq!(parse_dimension("72bp"), Some(72.27));
+        assert_eq!(parse_dimension("-3"), Some(-3.0));
+        assert_eq!(parse_dimension("1cm"), None);
+        assert_eq!(parse_dimension(""), None);
+    }
+}
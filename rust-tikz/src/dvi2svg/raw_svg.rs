@@ -0,0 +1,1003 @@
+//! A small parser for the SVG fragments that TikZ emits with `dvisvgm:raw` specials. The
+//! fragments are not well-formed on their own (a `<g>` may be opened in one special and closed
+//! many specials later), so they are parsed into a flat list of tags rather than a tree.
+
+use std::collections::HashMap;
+
+use super::utils::svg_num;
+use crate::dvi::Special;
+
+/// The SVG of consecutive `dvisvgm:raw` specials. A group is often opened in one special and
+/// filled in by the next ones, so the fragments are only written out together, once something
+/// other than a raw special comes.
+///
+/// The placeholders of each fragment are filled in as it is buffered (see
+/// [`fill_placeholders`]), since the position of a fragment is lost once the next one comes.
+#[derive(Debug, Default)]
+pub struct RawSvgBuffer {
+    svg: String,
+}
+
+impl RawSvgBuffer {
+    /// Buffer the SVG of `special` if it is a `dvisvgm:raw` special, and return whether it was.
+    pub fn special(&mut self, special: &Special) -> bool {
+        match special.text.strip_prefix("dvisvgm:raw ") {
+            Some(svg) => {
+                self.svg
+                    .push_str(&fill_placeholders(svg, special.h, special.v));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take the buffered SVG, or `None` if there is none.
+    pub fn take(&mut self) -> Option<String> {
+        if self.svg.is_empty() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.svg))
+    }
+}
+
+/// Fill in the placeholders of dvisvgm in the SVG fragment of a special at `(h, v)`: `{?x}`
+/// and `{?y}` are the position of the special and `{?nl}` is a newline.
+pub fn fill_placeholders(svg: &str, h: f64, v: f64) -> String {
+    svg.replace("{?x}", &svg_num(h))
+        .replace("{?y}", &svg_num(v))
+        .replace("{?nl}", "\n")
+}
+
+/// A single tag from a raw SVG fragment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    /// An opening tag like `<g ...>`, or a self-closing tag like `<path ... />`.
+    Open {
+        name: String,
+        attributes: HashMap<String, String>,
+        self_closing: bool,
+    },
+    /// A closing tag like `</g>`.
+    Close { name: String },
+}
+
+/// Parse the tags in `fragment`. Text between tags and comments are skipped.
+pub fn parse_tags(fragment: &str) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    let mut rest = fragment;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.split_once("-->").map_or("", |(_, after)| after);
+            continue;
+        }
+        let Some(end) = find_tag_end(rest) else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+
+        if let Some(close) = tag.strip_prefix('/') {
+            let name = close.split_whitespace().next().unwrap_or_default();
+            tags.push(Tag::Close {
+                name: name.to_string(),
+            });
+            continue;
+        }
+        let (tag, self_closing) = match tag.trim_end().strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(tag.len());
+        tags.push(Tag::Open {
+            name: tag[..name_end].to_string(),
+            attributes: parse_attributes(&tag[name_end..]),
+            self_closing,
+        });
+    }
+    tags
+}
+
+/// Find the `>` that ends a tag, skipping over any quoted attribute values.
+fn find_tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse `name="value"` pairs. Attributes without a value (e.g., `<svg beginpicture>`) are
+/// given an empty value. Declarations in a `style` attribute are treated like attributes.
+fn parse_attributes(mut input: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    loop {
+        input = input.trim_start();
+        if input.is_empty() {
+            break;
+        }
+        let name_end = input
+            .find(|c: char| c == '=' || c.is_ascii_whitespace())
+            .unwrap_or(input.len());
+        let name = &input[..name_end];
+        input = input[name_end..].trim_start();
+
+        let mut value = "";
+        if let Some(after_eq) = input.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            match after_eq.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let after_quote = &after_eq[1..];
+                    let end = after_quote.find(q).unwrap_or(after_quote.len());
+                    value = &after_quote[..end];
+                    input = after_quote.get(end + 1..).unwrap_or_default();
+                }
+                _ => {
+                    let end = after_eq
+                        .find(|c: char| c.is_ascii_whitespace())
+                        .unwrap_or(after_eq.len());
+                    value = &after_eq[..end];
+                    input = &after_eq[end..];
+                }
+            }
+        }
+
+        if name == "style" {
+            for declaration in value.split(';') {
+                if let Some((key, value)) = declaration.split_once(':') {
+                    attributes.insert(key.trim().to_string(), value.trim().to_string());
+                }
+            }
+        } else if !name.is_empty() {
+            attributes.insert(name.to_string(), value.to_string());
+        }
+    }
+    attributes
+}
+
+/// A segment of a path with absolute coordinates. Every SVG path command is reduced to one
+/// of these.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum PathSegment {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    Close,
+}
+
+/// Parse SVG path data (the `d` attribute of a `<path>`). Parsing stops at the first error,
+/// keeping the segments that were parsed so far.
+pub fn parse_path_data(data: &str) -> Vec<PathSegment> {
+    let mut segments = Vec::new();
+    let mut numbers = NumberReader { input: data };
+
+    let (mut x, mut y) = (0.0, 0.0);
+    let (mut start_x, mut start_y) = (0.0, 0.0);
+    // The second control point of the previous segment if it was a cubic curve, which `S`
+    // reflects, and the control point of the previous segment if it was a quadratic curve,
+    // which `T` reflects.
+    let mut last_cubic: Option<(f64, f64)> = None;
+    let mut last_quadratic = None;
+    let mut command = None;
+
+    loop {
+        match numbers.next_command() {
+            Some(c) => command = Some(c),
+            None if numbers.is_empty() => break,
+            None => {}
+        }
+        let Some(c) = command else {
+            break;
+        };
+        let relative = c.is_ascii_lowercase();
+        let (dx, dy) = if relative { (x, y) } else { (0.0, 0.0) };
+
+        let (mut cubic, mut quadratic) = (None, None);
+        match c.to_ascii_uppercase() {
+            'M' => {
+                let Some([px, py]) = numbers.take() else {
+                    break;
+                };
+                (x, y) = (px + dx, py + dy);
+                (start_x, start_y) = (x, y);
+                segments.push(PathSegment::MoveTo(x, y));
+                // Coordinates after the first pair of a moveto are implicit linetos.
+                command = Some(if relative { 'l' } else { 'L' });
+            }
+            'L' => {
+                let Some([px, py]) = numbers.take() else {
+                    break;
+                };
+                (x, y) = (px + dx, py + dy);
+                segments.push(PathSegment::LineTo(x, y));
+            }
+            'H' => {
+                let Some([px]) = numbers.take() else {
+                    break;
+                };
+                x = px + dx;
+                segments.push(PathSegment::LineTo(x, y));
+            }
+            'V' => {
+                let Some([py]) = numbers.take() else {
+                    break;
+                };
+                y = py + dy;
+                segments.push(PathSegment::LineTo(x, y));
+            }
+            'C' => {
+                let Some([x1, y1, x2, y2, px, py]) = numbers.take() else {
+                    break;
+                };
+                let (x2, y2) = (x2 + dx, y2 + dy);
+                segments.push(PathSegment::CurveTo(
+                    x1 + dx,
+                    y1 + dy,
+                    x2,
+                    y2,
+                    px + dx,
+                    py + dy,
+                ));
+                (x, y) = (px + dx, py + dy);
+                cubic = Some((x2, y2));
+            }
+            'S' => {
+                let Some([x2, y2, px, py]) = numbers.take() else {
+                    break;
+                };
+                let (x1, y1) = reflect(last_cubic, x, y);
+                let (x2, y2) = (x2 + dx, y2 + dy);
+                segments.push(PathSegment::CurveTo(x1, y1, x2, y2, px + dx, py + dy));
+                (x, y) = (px + dx, py + dy);
+                cubic = Some((x2, y2));
+            }
+            'Q' | 'T' => {
+                let (qx, qy, px, py) = if c.eq_ignore_ascii_case(&'Q') {
+                    let Some([qx, qy, px, py]) = numbers.take() else {
+                        break;
+                    };
+                    (qx + dx, qy + dy, px + dx, py + dy)
+                } else {
+                    let Some([px, py]) = numbers.take() else {
+                        break;
+                    };
+                    let (qx, qy) = reflect(last_quadratic, x, y);
+                    (qx, qy, px + dx, py + dy)
+                };
+                // Raise the quadratic curve to a cubic one.
+                segments.push(PathSegment::CurveTo(
+                    x + 2.0 / 3.0 * (qx - x),
+                    y + 2.0 / 3.0 * (qy - y),
+                    px + 2.0 / 3.0 * (qx - px),
+                    py + 2.0 / 3.0 * (qy - py),
+                    px,
+                    py,
+                ));
+                (x, y) = (px, py);
+                quadratic = Some((qx, qy));
+            }
+            'A' => {
+                let (Some([rx, ry, angle]), Some(large_arc), Some(sweep), Some([px, py])) = (
+                    numbers.take(),
+                    numbers.next_flag(),
+                    numbers.next_flag(),
+                    numbers.take(),
+                ) else {
+                    break;
+                };
+                let end = (px + dx, py + dy);
+                segments.extend(arc((x, y), (rx, ry), angle, large_arc, sweep, end));
+                (x, y) = end;
+            }
+            'Z' => {
+                segments.push(PathSegment::Close);
+                (x, y) = (start_x, start_y);
+                command = None;
+            }
+            _ => break,
+        }
+        (last_cubic, last_quadratic) = (cubic, quadratic);
+    }
+    segments
+}
+
+/// Reflect the previous control point about the current point. Without a previous control
+/// point, the current point is used.
+fn reflect(control: Option<(f64, f64)>, x: f64, y: f64) -> (f64, f64) {
+    match control {
+        Some((cx, cy)) => (2.0 * x - cx, 2.0 * y - cy),
+        None => (x, y),
+    }
+}
+
+/// The Bézier curves of an elliptical arc from `from` to `to`, given the way an SVG path does:
+/// the radii, the rotation of the ellipse in degrees, and the flags that choose between the
+/// four arcs through the two points. See the "Elliptical arc implementation notes" of the SVG
+/// specification.
+fn arc(
+    from: (f64, f64),
+    radii: (f64, f64),
+    angle: f64,
+    large_arc: bool,
+    sweep: bool,
+    to: (f64, f64),
+) -> Vec<PathSegment> {
+    use std::f64::consts::{FRAC_PI_2, TAU};
+
+    if from == to {
+        return Vec::new();
+    }
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if rx == 0.0 || ry == 0.0 {
+        return vec![PathSegment::LineTo(to.0, to.1)];
+    }
+    let (sin, cos) = angle.to_radians().sin_cos();
+
+    // The midpoint of the chord in the coordinates of the unrotated ellipse.
+    let (hx, hy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let (x1, y1) = (cos * hx + sin * hy, -sin * hx + cos * hy);
+    // Radii that are too small for the ellipse to reach both points are scaled up.
+    let lambda = (x1 / rx).powi(2) + (y1 / ry).powi(2);
+    if lambda > 1.0 {
+        (rx, ry) = (rx * lambda.sqrt(), ry * lambda.sqrt());
+    }
+    let numerator = (rx * ry).powi(2) - (rx * y1).powi(2) - (ry * x1).powi(2);
+    let denominator = (rx * y1).powi(2) + (ry * x1).powi(2);
+    let mut coefficient = (numerator / denominator).max(0.0).sqrt();
+    if large_arc == sweep {
+        coefficient = -coefficient;
+    }
+    let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+    let cx = cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0;
+    let cy = sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0;
+
+    // The angles of the endpoints on the unit circle the ellipse is stretched from.
+    let start = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+    let end = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+    let mut sweep_angle = end - start;
+    if sweep && sweep_angle < 0.0 {
+        sweep_angle += TAU;
+    } else if !sweep && sweep_angle > 0.0 {
+        sweep_angle -= TAU;
+    }
+
+    // Each curve spans at most a quarter of the ellipse.
+    let count = (sweep_angle.abs() / FRAC_PI_2 - 1e-9).ceil().max(1.0);
+    let step = sweep_angle / count;
+    let k = 4.0 / 3.0 * (step / 4.0).tan();
+    let point = |t: f64| {
+        let (sin_t, cos_t) = t.sin_cos();
+        (
+            cx + rx * cos_t * cos - ry * sin_t * sin,
+            cy + rx * cos_t * sin + ry * sin_t * cos,
+        )
+    };
+    let tangent = |t: f64| {
+        let (sin_t, cos_t) = t.sin_cos();
+        (
+            -rx * sin_t * cos - ry * cos_t * sin,
+            -rx * sin_t * sin + ry * cos_t * cos,
+        )
+    };
+    let count = count as usize;
+    (0..count)
+        .map(|i| {
+            let (t1, t2) = (start + i as f64 * step, start + (i + 1) as f64 * step);
+            let ((ax, ay), (adx, ady)) = (point(t1), tangent(t1));
+            let (bdx, bdy) = tangent(t2);
+            // The arc ends exactly at `to`, whatever the rounding of the angles.
+            let (bx, by) = if i + 1 == count { to } else { point(t2) };
+            PathSegment::CurveTo(
+                ax + k * adx,
+                ay + k * ady,
+                bx - k * bdx,
+                by - k * bdy,
+                bx,
+                by,
+            )
+        })
+        .collect()
+}
+
+/// Reads the commands and numbers of SVG path data or a list of numbers.
+struct NumberReader<'a> {
+    input: &'a str,
+}
+
+impl NumberReader<'_> {
+    fn skip_separators(&mut self) {
+        self.input = self
+            .input
+            .trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+    }
+
+    fn is_empty(&mut self) -> bool {
+        self.skip_separators();
+        self.input.is_empty()
+    }
+
+    /// Read a command letter if the next token is one.
+    fn next_command(&mut self) -> Option<char> {
+        self.skip_separators();
+        let c = self.input.chars().next()?;
+        if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            self.input = &self.input[1..];
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    fn next_number(&mut self) -> Option<f64> {
+        self.skip_separators();
+        let bytes = self.input.as_bytes();
+        let mut end = 0;
+        if matches!(bytes.first(), Some(b'+' | b'-')) {
+            end += 1;
+        }
+        let mut seen_dot = false;
+        while let Some(&b) = bytes.get(end) {
+            match b {
+                b'0'..=b'9' => {}
+                b'.' if !seen_dot => seen_dot = true,
+                b'e' | b'E' => {
+                    end += 1;
+                    if matches!(bytes.get(end), Some(b'+' | b'-')) {
+                        end += 1;
+                    }
+                    while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+                        end += 1;
+                    }
+                    break;
+                }
+                _ => break,
+            }
+            end += 1;
+        }
+        let number = self.input[..end].parse().ok()?;
+        self.input = &self.input[end..];
+        Some(number)
+    }
+
+    /// Read an arc flag, which may be written without a separator before the next number.
+    fn next_flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let flag = match self.input.as_bytes().first()? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.input = &self.input[1..];
+        Some(flag)
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[f64; N]> {
+        let mut numbers = [0.0; N];
+        for number in numbers.iter_mut() {
+            *number = self.next_number()?;
+        }
+        Some(numbers)
+    }
+}
+
+/// Parse a list of numbers separated by whitespace or commas, e.g. the `points` of a
+/// `<polygon>` or a `stroke-dasharray`.
+pub fn parse_number_list(input: &str) -> Vec<f64> {
+    let mut numbers = NumberReader { input };
+    std::iter::from_fn(|| numbers.next_number()).collect()
+}
+
+/// Parse a length, ignoring any unit. SVG lengths written by TikZ are in points.
+pub fn parse_length(input: &str) -> Option<f64> {
+    NumberReader { input }.next_number()
+}
+
+impl PathSegment {
+    /// Apply `matrix` to the points of the segment.
+    pub fn transform(self, matrix: Matrix) -> PathSegment {
+        let point = |x, y| transform_point(matrix, x, y);
+        match self {
+            PathSegment::MoveTo(x, y) => {
+                let (x, y) = point(x, y);
+                PathSegment::MoveTo(x, y)
+            }
+            PathSegment::LineTo(x, y) => {
+                let (x, y) = point(x, y);
+                PathSegment::LineTo(x, y)
+            }
+            PathSegment::CurveTo(x1, y1, x2, y2, x, y) => {
+                let ((x1, y1), (x2, y2), (x, y)) = (point(x1, y1), point(x2, y2), point(x, y));
+                PathSegment::CurveTo(x1, y1, x2, y2, x, y)
+            }
+            PathSegment::Close => PathSegment::Close,
+        }
+    }
+}
+
+/// The segments of a shape element like `<path>` or `<circle>`, or `None` if the element is
+/// not a shape.
+pub fn shape_segments(
+    name: &str,
+    attributes: &HashMap<String, String>,
+) -> Option<Vec<PathSegment>> {
+    let length = |key: &str| {
+        attributes
+            .get(key)
+            .and_then(|v| parse_length(v))
+            .unwrap_or(0.0)
+    };
+    let segments = match name {
+        "path" => parse_path_data(attributes.get("d")?),
+        "rect" => {
+            let (x, y) = (length("x"), length("y"));
+            let (width, height) = (length("width"), length("height"));
+            vec![
+                PathSegment::MoveTo(x, y),
+                PathSegment::LineTo(x + width, y),
+                PathSegment::LineTo(x + width, y + height),
+                PathSegment::LineTo(x, y + height),
+                PathSegment::Close,
+            ]
+        }
+        "circle" => ellipse(length("cx"), length("cy"), length("r"), length("r")),
+        "ellipse" => ellipse(length("cx"), length("cy"), length("rx"), length("ry")),
+        "line" => vec![
+            PathSegment::MoveTo(length("x1"), length("y1")),
+            PathSegment::LineTo(length("x2"), length("y2")),
+        ],
+        "polyline" | "polygon" => {
+            let points = parse_number_list(attributes.get("points")?);
+            let mut segments = points
+                .chunks_exact(2)
+                .enumerate()
+                .map(|(i, p)| match i {
+                    0 => PathSegment::MoveTo(p[0], p[1]),
+                    _ => PathSegment::LineTo(p[0], p[1]),
+                })
+                .collect::<Vec<_>>();
+            if name == "polygon" {
+                segments.push(PathSegment::Close);
+            }
+            segments
+        }
+        _ => return None,
+    };
+    Some(segments)
+}
+
+/// The constant used to approximate a quarter circle with a cubic Bézier curve.
+const KAPPA: f64 = 0.552_284_749_8;
+
+/// An ellipse made of four Bézier curves.
+fn ellipse(cx: f64, cy: f64, rx: f64, ry: f64) -> Vec<PathSegment> {
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    vec![
+        PathSegment::MoveTo(cx + rx, cy),
+        PathSegment::CurveTo(cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry),
+        PathSegment::CurveTo(cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy),
+        PathSegment::CurveTo(cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry),
+        PathSegment::CurveTo(cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy),
+        PathSegment::Close,
+    ]
+}
+
+/// How an element is painted. Groups pass their style on to the elements inside them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub fill: Option<[f64; 3]>,
+    pub stroke: Option<[f64; 3]>,
+    pub stroke_width: f64,
+    pub even_odd: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        // The SVG defaults: filled in black and not stroked.
+        Style {
+            fill: Some([0.0, 0.0, 0.0]),
+            stroke: None,
+            stroke_width: 1.0,
+            even_odd: false,
+        }
+    }
+}
+
+impl Style {
+    /// Update the style with the attributes of an element. `current_color` is used for
+    /// `currentColor`.
+    pub fn apply(&mut self, attributes: &HashMap<String, String>, current_color: &str) {
+        let paint = |value: &str, inherited| match value.trim() {
+            "none" => None,
+            "currentColor" => parse_color(current_color),
+            // Gradients and patterns (`url(#...)`) are not supported.
+            value => parse_color(value).or(inherited),
+        };
+        if let Some(fill) = attributes.get("fill") {
+            self.fill = paint(fill, self.fill);
+        }
+        if let Some(stroke) = attributes.get("stroke") {
+            self.stroke = paint(stroke, self.stroke);
+        }
+        if let Some(width) = attributes.get("stroke-width").and_then(|w| parse_length(w)) {
+            self.stroke_width = width;
+        }
+        if let Some(rule) = attributes.get("fill-rule") {
+            self.even_odd = rule == "evenodd";
+        }
+    }
+}
+
+/// An affine transformation `[a b c d e f]`, as used by both SVG and PDF.
+pub type Matrix = [f64; 6];
+
+pub const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Compute the transformation that applies `second` after `first`.
+pub fn multiply(first: Matrix, second: Matrix) -> Matrix {
+    let [a1, b1, c1, d1, e1, f1] = first;
+    let [a2, b2, c2, d2, e2, f2] = second;
+    [
+        a1 * a2 + b1 * c2,
+        a1 * b2 + b1 * d2,
+        c1 * a2 + d1 * c2,
+        c1 * b2 + d1 * d2,
+        e1 * a2 + f1 * c2 + e2,
+        e1 * b2 + f1 * d2 + f2,
+    ]
+}
+
+/// Apply `matrix` to the point `(x, y)`.
+pub fn transform_point(matrix: Matrix, x: f64, y: f64) -> (f64, f64) {
+    let [a, b, c, d, e, f] = matrix;
+    (a * x + c * y + e, b * x + d * y + f)
+}
+
+/// Parse an SVG `transform` attribute into a single matrix. Unknown transformations are
+/// ignored.
+pub fn parse_transform(input: &str) -> Matrix {
+    let mut matrix = IDENTITY;
+    let mut rest = input;
+    while let Some((name, after_name)) = rest.split_once('(') {
+        let Some((arguments, after_arguments)) = after_name.split_once(')') else {
+            break;
+        };
+        rest = after_arguments;
+        let arguments = parse_number_list(arguments);
+        let name = name.trim_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        let transform = match (name, arguments.as_slice()) {
+            ("matrix", &[a, b, c, d, e, f]) => [a, b, c, d, e, f],
+            ("translate", &[x]) => [1.0, 0.0, 0.0, 1.0, x, 0.0],
+            ("translate", &[x, y]) => [1.0, 0.0, 0.0, 1.0, x, y],
+            ("scale", &[s]) => [s, 0.0, 0.0, s, 0.0, 0.0],
+            ("scale", &[sx, sy]) => [sx, 0.0, 0.0, sy, 0.0, 0.0],
+            ("rotate", &[angle, ref center @ ..]) => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let rotation = [cos, sin, -sin, cos, 0.0, 0.0];
+                match center {
+                    &[cx, cy] => multiply(
+                        multiply([1.0, 0.0, 0.0, 1.0, -cx, -cy], rotation),
+                        [1.0, 0.0, 0.0, 1.0, cx, cy],
+                    ),
+                    _ => rotation,
+                }
+            }
+            ("skewX", &[angle]) => [1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0],
+            ("skewY", &[angle]) => [1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0],
+            _ => continue,
+        };
+        // Transformations in the list are applied right to left.
+        matrix = multiply(transform, matrix);
+    }
+    matrix
+}
+
+/// Parse an SVG color into RGB components between 0 and 1. `none` and unknown colors
+/// return `None`.
+pub fn parse_color(input: &str) -> Option<[f64; 3]> {
+    let input = input.trim();
+    if let Some(hex) = input.strip_prefix('#') {
+        let digits = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(f64::from))
+            .collect::<Option<Vec<_>>>()?;
+        return match digits[..] {
+            [r, g, b] => Some([r / 15.0, g / 15.0, b / 15.0]),
+            [r1, r2, g1, g2, b1, b2] => Some([
+                (r1 * 16.0 + r2) / 255.0,
+                (g1 * 16.0 + g2) / 255.0,
+                (b1 * 16.0 + b2) / 255.0,
+            ]),
+            _ => None,
+        };
+    }
+    if let Some(components) = input
+        .strip_prefix("rgb(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let components = components
+            .split(',')
+            .map(|c| match c.trim().strip_suffix('%') {
+                Some(percent) => percent.parse::<f64>().ok().map(|p| p / 100.0),
+                None => c.trim().parse::<f64>().ok().map(|v| v / 255.0),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        return match components[..] {
+            [r, g, b] => Some([r, g, b]),
+            _ => None,
+        };
+    }
+    match input {
+        "black" => Some([0.0, 0.0, 0.0]),
+        "white" => Some([1.0, 1.0, 1.0]),
+        "red" => Some([1.0, 0.0, 0.0]),
+        "green" => Some([0.0, 128.0 / 255.0, 0.0]),
+        "blue" => Some([0.0, 0.0, 1.0]),
+        "gray" | "grey" => Some([128.0 / 255.0; 3]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn special(text: &str) -> Special<'_> {
+        Special {
+            h: 1.0,
+            v: 2.0,
+            text,
+        }
+    }
+
+    #[test]
+    fn consecutive_raw_specials_are_buffered_together() {
+        let mut buffer = RawSvgBuffer::default();
+        assert_eq!(buffer.take(), None);
+        assert!(buffer.special(&special("dvisvgm:raw <g fill=\"red\">")));
+        assert!(buffer.special(&special("dvisvgm:raw <path d=\"M{?x} {?y}\"/></g>")));
+        assert!(!buffer.special(&special("color push gray 0")));
+        assert!(!buffer.special(&special("dvisvgm:rawdef <defs/>")));
+        assert_eq!(
+            buffer.take().as_deref(),
+            Some("<g fill=\"red\"><path d=\"M1 2\"/></g>")
+        );
+        assert_eq!(buffer.take(), None);
+    }
+
+    /// Whether the numbers of `a` and `b` are equal up to rounding.
+    fn segments_are_close(a: &[PathSegment], b: &[PathSegment]) -> bool {
+        let numbers = |segment: &PathSegment| match *segment {
+            PathSegment::MoveTo(x, y) => vec![0.0, x, y],
+            PathSegment::LineTo(x, y) => vec![1.0, x, y],
+            PathSegment::CurveTo(x1, y1, x2, y2, x, y) => vec![2.0, x1, y1, x2, y2, x, y],
+            PathSegment::Close => vec![3.0],
+        };
+        a.len() == b.len()
+            && a.iter().zip(b).all(|(a, b)| {
+                let (a, b) = (numbers(a), numbers(b));
+                a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-9)
+            })
+    }
+
+    #[test]
+    fn path_commands_are_reduced_to_segments() {
+        use PathSegment::{Close, CurveTo as C, LineTo as L, MoveTo as M};
+        let k = 10.0 * KAPPA;
+        let cases: &[(&str, &[PathSegment])] = &[
+            // Coordinates after a moveto are implicit linetos.
+            ("M1 2 3 4", &[M(1.0, 2.0), L(3.0, 4.0)]),
+            ("m1 2 3 4", &[M(1.0, 2.0), L(4.0, 6.0)]),
+            ("M1 2L3 4 5 6", &[M(1.0, 2.0), L(3.0, 4.0), L(5.0, 6.0)]),
+            ("M1 2l3 4 5 6", &[M(1.0, 2.0), L(4.0, 6.0), L(9.0, 12.0)]),
+            ("M1 2H5V7", &[M(1.0, 2.0), L(5.0, 2.0), L(5.0, 7.0)]),
+            ("M1 2h5v7", &[M(1.0, 2.0), L(6.0, 2.0), L(6.0, 9.0)]),
+            ("M1 2H3 4", &[M(1.0, 2.0), L(3.0, 2.0), L(4.0, 2.0)]),
+            (
+                "M0 0C1 2 3 4 5 6 7 8 9 10 11 12",
+                &[
+                    M(0.0, 0.0),
+                    C(1.0, 2.0, 3.0, 4.0, 5.0, 6.0),
+                    C(7.0, 8.0, 9.0, 10.0, 11.0, 12.0),
+                ],
+            ),
+            (
+                "M1 1c1 2 3 4 5 6",
+                &[M(1.0, 1.0), C(2.0, 3.0, 4.0, 5.0, 6.0, 7.0)],
+            ),
+            // A smooth curve reflects the second control point of a previous cubic curve.
+            (
+                "M0 0C1 2 3 4 5 6S9 10 11 12",
+                &[
+                    M(0.0, 0.0),
+                    C(1.0, 2.0, 3.0, 4.0, 5.0, 6.0),
+                    C(7.0, 8.0, 9.0, 10.0, 11.0, 12.0),
+                ],
+            ),
+            (
+                "M0 0C1 2 3 4 5 6s4 4 6 6",
+                &[
+                    M(0.0, 0.0),
+                    C(1.0, 2.0, 3.0, 4.0, 5.0, 6.0),
+                    C(7.0, 8.0, 9.0, 10.0, 11.0, 12.0),
+                ],
+            ),
+            // Otherwise its first control point is the current point.
+            (
+                "M1 2S3 4 5 6",
+                &[M(1.0, 2.0), C(1.0, 2.0, 3.0, 4.0, 5.0, 6.0)],
+            ),
+            (
+                "M0 0Q3 3 6 0S9 3 12 0",
+                &[
+                    M(0.0, 0.0),
+                    C(2.0, 2.0, 4.0, 2.0, 6.0, 0.0),
+                    C(6.0, 0.0, 9.0, 3.0, 12.0, 0.0),
+                ],
+            ),
+            // Quadratic curves are raised to cubic ones.
+            (
+                "M0 0Q3 3 6 0",
+                &[M(0.0, 0.0), C(2.0, 2.0, 4.0, 2.0, 6.0, 0.0)],
+            ),
+            (
+                "M1 1q3 3 6 0",
+                &[M(1.0, 1.0), C(3.0, 3.0, 5.0, 3.0, 7.0, 1.0)],
+            ),
+            // A smooth quadratic curve reflects the control point of a previous quadratic curve.
+            (
+                "M0 0Q3 3 6 0T12 0",
+                &[
+                    M(0.0, 0.0),
+                    C(2.0, 2.0, 4.0, 2.0, 6.0, 0.0),
+                    C(8.0, -2.0, 10.0, -2.0, 12.0, 0.0),
+                ],
+            ),
+            (
+                "M0 0Q3 3 6 0t6 0",
+                &[
+                    M(0.0, 0.0),
+                    C(2.0, 2.0, 4.0, 2.0, 6.0, 0.0),
+                    C(8.0, -2.0, 10.0, -2.0, 12.0, 0.0),
+                ],
+            ),
+            (
+                "M0 0C1 2 3 4 6 0T12 0",
+                &[
+                    M(0.0, 0.0),
+                    C(1.0, 2.0, 3.0, 4.0, 6.0, 0.0),
+                    C(6.0, 0.0, 8.0, 0.0, 12.0, 0.0),
+                ],
+            ),
+            // A closepath moves back to the start of the subpath.
+            (
+                "M1 2l3 4zl1 1",
+                &[M(1.0, 2.0), L(4.0, 6.0), Close, L(2.0, 3.0)],
+            ),
+            // A half circle is drawn with two curves, and the flags need no separators.
+            (
+                "M0 0A10 10 0 0 1 20 0",
+                &[
+                    M(0.0, 0.0),
+                    C(0.0, -k, 10.0 - k, -10.0, 10.0, -10.0),
+                    C(10.0 + k, -10.0, 20.0, -k, 20.0, 0.0),
+                ],
+            ),
+            (
+                "M0 0a10 10 0 0120 0",
+                &[
+                    M(0.0, 0.0),
+                    C(0.0, -k, 10.0 - k, -10.0, 10.0, -10.0),
+                    C(10.0 + k, -10.0, 20.0, -k, 20.0, 0.0),
+                ],
+            ),
+            (
+                "M0 0A10 10 0 0 0 20 0",
+                &[
+                    M(0.0, 0.0),
+                    C(0.0, k, 10.0 - k, 10.0, 10.0, 10.0),
+                    C(10.0 + k, 10.0, 20.0, k, 20.0, 0.0),
+                ],
+            ),
+            // Radii that are too small are scaled up.
+            (
+                "M0 0A1 1 0 0 1 20 0",
+                &[
+                    M(0.0, 0.0),
+                    C(0.0, -k, 10.0 - k, -10.0, 10.0, -10.0),
+                    C(10.0 + k, -10.0, 20.0, -k, 20.0, 0.0),
+                ],
+            ),
+            // An arc with a zero radius is a line, and one that ends where it starts is left out.
+            ("M0 0A0 10 0 0 1 5 5", &[M(0.0, 0.0), L(5.0, 5.0)]),
+            ("M1 1A5 5 0 0 1 1 1", &[M(1.0, 1.0)]),
+            // Parsing stops at the first error.
+            ("M1 2L3", &[M(1.0, 2.0)]),
+            ("M1 2X3 4", &[M(1.0, 2.0)]),
+        ];
+        for &(data, expected) in cases {
+            let segments = parse_path_data(data);
+            assert!(
+                segments_are_close(&segments, expected),
+                "{data}: {segments:?} is not {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn large_arcs_go_the_long_way_around() {
+        // Three quarters of the circle around (0, 10), from (0, 0) through (-10, 10) and (0, 20).
+        let segments = parse_path_data("M0 0A10 10 0 1 0 10 10");
+        let ends = segments
+            .iter()
+            .map(|segment| match *segment {
+                PathSegment::MoveTo(x, y) | PathSegment::CurveTo(_, _, _, _, x, y) => (x, y),
+                _ => panic!("{segments:?}"),
+            })
+            .collect::<Vec<_>>();
+        let expected = [(0.0, 0.0), (-10.0, 10.0), (0.0, 20.0), (10.0, 10.0)];
+        assert_eq!(ends.len(), expected.len(), "{segments:?}");
+        for ((x, y), (ex, ey)) in ends.into_iter().zip(expected) {
+            assert!(
+                (x - ex).abs() < 1e-9 && (y - ey).abs() < 1e-9,
+                "{segments:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn transforms_are_parsed_into_a_matrix() {
+        let cases: &[(&str, Matrix)] = &[
+            ("", IDENTITY),
+            ("translate(10)", [1.0, 0.0, 0.0, 1.0, 10.0, 0.0]),
+            ("translate(10, 20)", [1.0, 0.0, 0.0, 1.0, 10.0, 20.0]),
+            ("scale(2)", [2.0, 0.0, 0.0, 2.0, 0.0, 0.0]),
+            ("scale(2 3)", [2.0, 0.0, 0.0, 3.0, 0.0, 0.0]),
+            ("rotate(90)", [0.0, 1.0, -1.0, 0.0, 0.0, 0.0]),
+            // A rotation around (10, 0) moves the origin to (10, -10).
+            ("rotate(90 10 0)", [0.0, 1.0, -1.0, 0.0, 10.0, -10.0]),
+            ("skewX(45)", [1.0, 0.0, 1.0, 1.0, 0.0, 0.0]),
+            ("skewY(45)", [1.0, 1.0, 0.0, 1.0, 0.0, 0.0]),
+            ("matrix(1 2 3 4 5 6)", [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+            // The transformations in a list are applied from right to left.
+            ("translate(10 0) scale(2)", [2.0, 0.0, 0.0, 2.0, 10.0, 0.0]),
+            ("scale(2),translate(10 0)", [2.0, 0.0, 0.0, 2.0, 20.0, 0.0]),
+            // Unknown transformations are ignored.
+            (
+                "perspective(1) translate(1 2)",
+                [1.0, 0.0, 0.0, 1.0, 1.0, 2.0],
+            ),
+        ];
+        for &(transform, expected) in cases {
+            let matrix = parse_transform(transform);
+            assert!(
+                matrix
+                    .iter()
+                    .zip(expected)
+                    .all(|(a, b)| (a - b).abs() < 1e-9),
+                "{transform}: {matrix:?} is not {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_with_the_position_of_the_special() {
+        assert_eq!(
+            fill_placeholders(
+                "<g transform=\"translate({?x},{?y})\">{?nl}</g>",
+                1.5,
+                -0.25
+            ),
+            "<g transform=\"translate(1.5,-0.25)\">\n</g>"
+        );
+        assert_eq!(fill_placeholders("<g/>", 1.0, 2.0), "<g/>");
+    }
+}
@@ -0,0 +1,384 @@
+use super::pgf::{Canvas, LineCap, LineJoin, Paint, Shading, ShadingKind};
+use super::raw_svg::PathSegment;
+use super::theme::SvgTheme;
+use super::utils::svg_num;
+
+/// A [`Canvas`] that draws TikZ pictures as SVG markup, for the SVG and HTML backends. The
+/// markup is collected until it is taken with [`SvgCanvas::take`], so that the machine can put
+/// it in between its glyphs and rules.
+#[derive(Debug, Default)]
+pub struct SvgCanvas {
+    output: String,
+    /// The tag that opens a picture, or `None` if pictures are drawn straight into the
+    /// surrounding SVG.
+    picture_tag: Option<String>,
+    /// The number of `<g>` elements opened by clipping in each open scope. Pictures count as
+    /// scopes too.
+    clip_groups: Vec<usize>,
+    /// The number of clip paths and gradients defined so far, used to give them unique ids.
+    next_id: usize,
+    /// How colors are written.
+    theme: SvgTheme,
+}
+
+impl SvgCanvas {
+    pub fn new() -> SvgCanvas {
+        SvgCanvas::default()
+    }
+
+    /// Wrap each picture that begins from now on in `tag` (e.g. an `<svg>` element) and a
+    /// closing `</svg>`.
+    pub fn set_picture_tag(&mut self, tag: String) {
+        self.picture_tag = Some(tag);
+    }
+
+    /// Write colors as `theme` says.
+    pub fn set_theme(&mut self, theme: SvgTheme) {
+        self.theme = theme;
+    }
+
+    /// Take the markup drawn so far.
+    pub fn take(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Whether a picture is being drawn.
+    pub fn in_picture(&self) -> bool {
+        !self.clip_groups.is_empty()
+    }
+
+    fn id(&mut self, kind: &str) -> String {
+        self.next_id += 1;
+        format!("pgf-{kind}-{}", self.next_id)
+    }
+
+    fn close_clip_groups(&mut self) {
+        for _ in 0..self.clip_groups.pop().unwrap_or(0) {
+            self.output.push_str("</g>");
+        }
+    }
+}
+
+impl Canvas for SvgCanvas {
+    fn begin_picture(&mut self) {
+        if let Some(tag) = &self.picture_tag {
+            self.output.push('\n');
+            self.output.push_str(tag);
+        }
+        self.clip_groups.push(0);
+    }
+
+    fn end_picture(&mut self) {
+        self.close_clip_groups();
+        if self.picture_tag.is_some() {
+            self.output.push_str("\n</svg>");
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.output.push_str("\n<g>");
+        self.clip_groups.push(0);
+    }
+
+    fn end_scope(&mut self) {
+        self.close_clip_groups();
+        self.output.push_str("</g>");
+    }
+
+    fn clip(&mut self, path: &[PathSegment], even_odd: bool) {
+        let id = self.id("clip");
+        let rule = if even_odd { "evenodd" } else { "nonzero" };
+        self.output.push_str(&format!(
+            r#"
+<clipPath id="{id}"><path d="{}" clip-rule="{rule}" /></clipPath><g clip-path="url(#{id})">"#,
+            path_data(path)
+        ));
+        if let Some(groups) = self.clip_groups.last_mut() {
+            *groups += 1;
+        }
+    }
+
+    fn draw_path(&mut self, path: &[PathSegment], paint: &Paint) {
+        let mut attributes = match paint.fill {
+            Some(fill) => {
+                let rule = if paint.even_odd { "evenodd" } else { "nonzero" };
+                format!(r#" fill="{}" fill-rule="{rule}""#, self.theme.paint(fill))
+            }
+            None => r#" fill="none""#.to_string(),
+        };
+        if paint.fill.is_some() && paint.fill_opacity < 1.0 {
+            attributes.push_str(&format!(
+                r#" fill-opacity="{}""#,
+                svg_num(paint.fill_opacity)
+            ));
+        }
+        if let Some(stroke) = paint.stroke {
+            attributes.push_str(&format!(
+                r#" stroke="{}" stroke-width="{}""#,
+                self.theme.paint(stroke),
+                svg_num(paint.line_width)
+            ));
+            if !paint.dash_array.is_empty() {
+                let dashes = paint.dash_array.iter().copied().map(svg_num);
+                attributes.push_str(&format!(
+                    r#" stroke-dasharray="{}" stroke-dashoffset="{}""#,
+                    dashes.collect::<Vec<_>>().join(" "),
+                    svg_num(paint.dash_offset)
+                ));
+            }
+            match paint.line_cap {
+                LineCap::Butt => {}
+                LineCap::Round => attributes.push_str(r#" stroke-linecap="round""#),
+                LineCap::Square => attributes.push_str(r#" stroke-linecap="square""#),
+            }
+            match paint.line_join {
+                LineJoin::Miter => attributes.push_str(&format!(
+                    r#" stroke-miterlimit="{}""#,
+                    svg_num(paint.miter_limit)
+                )),
+                LineJoin::Round => attributes.push_str(r#" stroke-linejoin="round""#),
+                LineJoin::Bevel => attributes.push_str(r#" stroke-linejoin="bevel""#),
+            }
+            if paint.stroke_opacity < 1.0 {
+                attributes.push_str(&format!(
+                    r#" stroke-opacity="{}""#,
+                    svg_num(paint.stroke_opacity)
+                ));
+            }
+        }
+        self.output.push_str(&format!(
+            r#"
+<path d="{}"{attributes} />"#,
+            path_data(path)
+        ));
+    }
+
+    fn begin_transform(&mut self, matrix: [f64; 6]) {
+        let [a, b, c, d, e, f] = matrix.map(svg_num);
+        self.output.push_str(&format!(
+            r#"<g transform="matrix({a} {b} {c} {d} {e} {f})">"#
+        ));
+    }
+
+    fn end_transform(&mut self) {
+        self.output.push_str("</g>");
+    }
+
+    fn raw_svg(&mut self, svg: &str) -> bool {
+        self.output.push_str(svg);
+        true
+    }
+
+    fn shading(&mut self, shading: &Shading) {
+        let (Some(&(first, _)), Some(&(last, _))) = (shading.stops.first(), shading.stops.last())
+        else {
+            return;
+        };
+        let id = self.id("shading");
+        let length = if last > first { last - first } else { 1.0 };
+        let stops = shading
+            .stops
+            .iter()
+            .map(|&(position, color)| {
+                format!(
+                    r#"<stop offset="{}" stop-color="{}" />"#,
+                    svg_num((position - first) / length),
+                    self.theme.paint(color)
+                )
+            })
+            .collect::<String>();
+        let gradient = match shading.kind {
+            ShadingKind::Axial { from, to } => {
+                let [x1, y1, x2, y2] = [from.0, from.1, to.0, to.1].map(svg_num);
+                format!(
+                    r#"<linearGradient id="{id}" gradientUnits="userSpaceOnUse" x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}">{stops}</linearGradient>"#
+                )
+            }
+            ShadingKind::Radial { inner, outer } => {
+                let [cx, cy, r, fx, fy, fr] =
+                    [outer.0, outer.1, last, inner.0, inner.1, first].map(svg_num);
+                format!(
+                    r#"<radialGradient id="{id}" gradientUnits="userSpaceOnUse" cx="{cx}" cy="{cy}" r="{r}" fx="{fx}" fy="{fy}" fr="{fr}">{stops}</radialGradient>"#
+                )
+            }
+        };
+        let [x, y, width, height] = shading.rect.map(svg_num);
+        self.output.push_str(&format!(
+            r#"
+<defs>{gradient}</defs><rect x="{x}" y="{y}" width="{width}" height="{height}" fill="url(#{id})" />"#
+        ));
+    }
+}
+
+/// The SVG path data of `path`.
+fn path_data(path: &[PathSegment]) -> String {
+    let commands = path.iter().map(|segment| match *segment {
+        PathSegment::MoveTo(x, y) => format!("M{} {}", svg_num(x), svg_num(y)),
+        PathSegment::LineTo(x, y) => format!("L{} {}", svg_num(x), svg_num(y)),
+        PathSegment::CurveTo(x1, y1, x2, y2, x, y) => {
+            let [x1, y1, x2, y2, x, y] = [x1, y1, x2, y2, x, y].map(svg_num);
+            format!("C{x1} {y1} {x2} {y2} {x} {y}")
+        }
+        PathSegment::Close => "Z".to_string(),
+    });
+    commands.collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dvi::Special;
+    use crate::dvi2svg::color::Color;
+    use crate::dvi2svg::pgf::PgfInterpreter;
+
+    const RED: Color = Color::Rgb([1.0, 0.0, 0.0]);
+    const BLACK: Color = Color::Gray(0.0);
+
+    #[test]
+    fn clips_open_groups_that_are_closed_with_their_scope() {
+        let mut canvas = SvgCanvas::new();
+        canvas.set_picture_tag("<svg>".to_string());
+        canvas.begin_picture();
+        assert!(canvas.in_picture());
+        canvas.clip(
+            &[
+                PathSegment::MoveTo(0.0, 0.0),
+                PathSegment::LineTo(1.0, 0.0),
+                PathSegment::LineTo(1.0, 1.0),
+                PathSegment::Close,
+            ],
+            true,
+        );
+        canvas.begin_scope();
+        canvas.clip(
+            &[
+                PathSegment::MoveTo(0.0, 0.0),
+                PathSegment::CurveTo(0.5, 0.0, 1.0, 0.25, 1.0, 1.0 / 3.0),
+            ],
+            false,
+        );
+        canvas.end_scope();
+        canvas.end_picture();
+        assert!(!canvas.in_picture());
+        assert_eq!(
+            canvas.take(),
+            r#"
+<svg>
+<clipPath id="pgf-clip-1"><path d="M0 0 L1 0 L1 1 Z" clip-rule="evenodd" /></clipPath><g clip-path="url(#pgf-clip-1)">
+<g>
+<clipPath id="pgf-clip-2"><path d="M0 0 C0.5 0 1 0.25 1 0.333333" clip-rule="nonzero" /></clipPath><g clip-path="url(#pgf-clip-2)"></g></g></g>
+</svg>"#
+        );
+        assert_eq!(canvas.take(), "");
+    }
+
+    #[test]
+    fn paths_have_the_opacities_and_line_style_of_the_paint() {
+        let mut canvas = SvgCanvas::new();
+        let path = [PathSegment::MoveTo(0.0, 0.0), PathSegment::LineTo(1.0, 1.0)];
+        canvas.draw_path(
+            &path,
+            &Paint {
+                stroke: Some(BLACK),
+                line_width: 0.4,
+                dash_array: vec![1.0, 2.0],
+                dash_offset: 0.5,
+                line_cap: LineCap::Round,
+                fill_opacity: 0.5,
+                stroke_opacity: 0.25,
+                ..Paint::fill(RED)
+            },
+        );
+        canvas.draw_path(
+            &path,
+            &Paint {
+                fill: None,
+                stroke: Some(RED),
+                even_odd: true,
+                line_width: 1.0,
+                line_join: LineJoin::Bevel,
+                // The opacity of a fill that is not drawn is left out.
+                fill_opacity: 0.5,
+                ..Paint::fill(RED)
+            },
+        );
+        canvas.draw_path(
+            &path,
+            &Paint {
+                even_odd: true,
+                ..Paint::fill(BLACK)
+            },
+        );
+        assert_eq!(
+            canvas.take(),
+            r##"
+<path d="M0 0 L1 1" fill="#ff0000" fill-rule="nonzero" fill-opacity="0.5" stroke="#000000" stroke-width="0.4" stroke-dasharray="1 2" stroke-dashoffset="0.5" stroke-linecap="round" stroke-miterlimit="10" stroke-opacity="0.25" />
+<path d="M0 0 L1 1" fill="none" stroke="#ff0000" stroke-width="1" stroke-linejoin="bevel" />
+<path d="M0 0 L1 1" fill="#000000" fill-rule="evenodd" />"##
+        );
+    }
+
+    #[test]
+    fn opacity_groups_are_passed_on_around_the_paths_inside_them() {
+        let mut canvas = SvgCanvas::new();
+        let mut interpreter = PgfInterpreter::default();
+        for text in [
+            "rusttikz:begin-picture;",
+            "rusttikz:<g opacity=\"0.5\">{?nl}moveto 0 0;lineto 1 1;stroke;</g>",
+            "rusttikz:end-picture;",
+        ] {
+            let special = Special {
+                h: 10.0,
+                v: 20.0,
+                text,
+            };
+            assert!(interpreter.special(&special, BLACK, &mut canvas));
+        }
+        assert_eq!(
+            canvas.take(),
+            r##"<g opacity="0.5">
+<path d="M10 20 L11 19" fill="none" stroke="#000000" stroke-width="1" stroke-miterlimit="10" /></g>"##
+        );
+    }
+
+    #[test]
+    fn shadings_fill_their_rectangle_with_a_gradient() {
+        let mut canvas = SvgCanvas::new();
+        canvas.shading(&Shading {
+            kind: ShadingKind::Axial {
+                from: (0.0, 0.0),
+                to: (10.0, 0.0),
+            },
+            stops: vec![
+                (0.0, RED),
+                (5.0, Color::Rgb([0.0, 1.0, 0.0])),
+                (10.0, Color::Rgb([0.0, 0.0, 1.0])),
+            ],
+            rect: [0.0, 0.0, 10.0, 5.0],
+        });
+        // A shading without stops draws nothing.
+        canvas.shading(&Shading {
+            kind: ShadingKind::Axial {
+                from: (0.0, 0.0),
+                to: (10.0, 0.0),
+            },
+            stops: Vec::new(),
+            rect: [0.0, 0.0, 10.0, 5.0],
+        });
+        // The stops of a radial shading are the radii of the circles.
+        canvas.shading(&Shading {
+            kind: ShadingKind::Radial {
+                inner: (12.0, 87.0),
+                outer: (10.0, 90.0),
+            },
+            stops: vec![(2.0, BLACK), (10.0, Color::Gray(1.0))],
+            rect: [0.0, 80.0, 20.0, 20.0],
+        });
+        assert_eq!(
+            canvas.take(),
+            r##"
+<defs><linearGradient id="pgf-shading-1" gradientUnits="userSpaceOnUse" x1="0" y1="0" x2="10" y2="0"><stop offset="0" stop-color="#ff0000" /><stop offset="0.5" stop-color="#00ff00" /><stop offset="1" stop-color="#0000ff" /></linearGradient></defs><rect x="0" y="0" width="10" height="5" fill="url(#pgf-shading-1)" />
+<defs><radialGradient id="pgf-shading-2" gradientUnits="userSpaceOnUse" cx="10" cy="90" r="10" fx="12" fy="87" fr="2"><stop offset="0" stop-color="#000000" /><stop offset="1" stop-color="#ffffff" /></radialGradient></defs><rect x="0" y="80" width="20" height="20" fill="url(#pgf-shading-2)" />"##
+        );
+    }
+}
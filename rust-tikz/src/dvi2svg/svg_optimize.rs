@@ -0,0 +1,362 @@
+//! Making SVG output smaller. TeX sets every glyph separately, so without optimization the SVG
+//! has a `<text>` element with the same font and color attributes for every character.
+
+use super::utils::svg_num;
+
+/// How to optimize SVG output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvgOptimization {
+    /// The number of decimal places that coordinates and lengths are rounded to.
+    pub precision: usize,
+}
+
+impl Default for SvgOptimization {
+    fn default() -> Self {
+        // A hundredth of a point is far below what can be seen at any reasonable zoom.
+        SvgOptimization { precision: 2 }
+    }
+}
+
+/// The attributes that glyphs must share to be written in the same `<text>` element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextStyle {
+    pub font_family: String,
+    pub font_size: f64,
+    pub fill: String,
+}
+
+/// Merges glyphs on the same baseline into one `<text>` element with a list of x coordinates,
+/// and puts consecutive `<text>` elements with the same style into a `<g>` element that holds
+/// the style attributes. Spaces are added between words, so that the text can be searched
+/// and copied.
+#[derive(Debug, Default)]
+pub struct TextRuns {
+    /// The style of the open `<g>` element.
+    group: Option<TextStyle>,
+    /// The glyphs that are not written yet.
+    run: Option<Run>,
+}
+
+/// Glyphs on one baseline.
+#[derive(Debug)]
+struct Run {
+    baseline: f64,
+    xs: Vec<f64>,
+    characters: String,
+    /// Where the last glyph ends.
+    right: f64,
+}
+
+impl TextRuns {
+    /// Add a glyph at `(x, y)` that is `width` wide and written as `text`, a single (possibly
+    /// escaped) character. Glyphs are written to `markup` once a glyph with a different style
+    /// or baseline is added, or [`TextRuns::finish`] is called.
+    pub fn glyph(
+        &mut self,
+        style: TextStyle,
+        x: f64,
+        y: f64,
+        width: f64,
+        text: &str,
+        markup: &mut String,
+    ) {
+        let font_size = style.font_size;
+        if self.group.as_ref() != Some(&style) {
+            self.finish(markup);
+            markup.push_str(&format!(
+                r#"<g font-family="{}" font-size="{}" fill="{}">"#,
+                style.font_family,
+                svg_num(style.font_size),
+                style.fill
+            ));
+            self.group = Some(style);
+        } else if self
+            .run
+            .as_ref()
+            .is_some_and(|run| run.baseline != y)
+        {
+            self.write_run(markup);
+        }
+        let run = self.run.get_or_insert_with(|| Run {
+            baseline: y,
+            xs: Vec::new(),
+            characters: String::new(),
+            right: x,
+        });
+        // Kerns are much smaller than a fifth of an em, and interword spaces are larger.
+        if x - run.right > font_size / 5.0 {
+            run.xs.push(run.right);
+            run.characters.push(' ');
+        }
+        run.xs.push(x);
+        run.characters.push_str(text);
+        run.right = x + width;
+    }
+
+    /// Write the remaining glyphs to `markup`. This has to be called before anything else is
+    /// written to `markup`.
+    pub fn finish(&mut self, markup: &mut String) {
+        self.write_run(markup);
+        if self.group.take().is_some() {
+            markup.push_str("</g>");
+        }
+    }
+
+    fn write_run(&mut self, markup: &mut String) {
+        let Some(run) = self.run.take() else {
+            return;
+        };
+        let xs = run.xs.into_iter().map(svg_num).collect::<Vec<_>>();
+        markup.push_str(&format!(
+            r#"<text x="{}" y="{}">{}</text>"#,
+            xs.join(" "),
+            svg_num(run.baseline),
+            run.characters
+        ));
+    }
+}
+
+/// The attributes whose numbers are rounded by [`minify`]. Only the translations of
+/// `transform` attributes are rounded, see [`round_transform`].
+const GEOMETRY_ATTRIBUTES: [&str; 22] = [
+    "x",
+    "y",
+    "width",
+    "height",
+    "d",
+    "points",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "fx",
+    "fy",
+    "fr",
+    "font-size",
+    "stroke-width",
+    "stroke-dasharray",
+    "stroke-dashoffset",
+];
+
+/// Remove the whitespace that only lays out the markup and round the numbers in geometry
+/// attributes to `precision` decimal places.
+pub fn minify(svg: &str, precision: usize) -> String {
+    let mut output = String::with_capacity(svg.len());
+    let mut rest = svg;
+    while let Some(start) = rest.find('<') {
+        push_text(&mut output, &rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        push_tag(&mut output, &rest[start..=start + end], precision);
+        rest = &rest[start + end + 1..];
+    }
+    push_text(&mut output, rest);
+    output
+}
+
+/// Add the text between two tags to `output`. Runs of whitespace with a line break are
+/// layout, so they are removed next to tags and collapsed to a space elsewhere.
+fn push_text(output: &mut String, text: &str) {
+    let mut words = text.split('\n').peekable();
+    let first = words.next().unwrap_or("");
+    if words.peek().is_none() {
+        output.push_str(first);
+        return;
+    }
+    let mut collapsed = first.trim_end().to_string();
+    for word in words.map(str::trim).filter(|word| !word.is_empty()) {
+        if !collapsed.is_empty() {
+            collapsed.push(' ');
+        }
+        collapsed.push_str(word);
+    }
+    output.push_str(&collapsed);
+}
+
+/// Add a tag to `output` with the whitespace between its attributes collapsed and the numbers
+/// in its geometry attributes rounded.
+fn push_tag(output: &mut String, tag: &str, precision: usize) {
+    // Comments, CDATA sections and processing instructions are left alone.
+    if tag.starts_with("<!") || tag.starts_with("<?") {
+        output.push_str(tag);
+        return;
+    }
+    let mut rest = tag;
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim_end();
+        let name_start = name.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let Some(offset) = rest[equals + 1..].find(['"', '\'']) else {
+            break;
+        };
+        let quote_start = equals + 1 + offset;
+        let quote = if rest[quote_start..].starts_with('"') {
+            '"'
+        } else {
+            '\''
+        };
+        let Some(quote_end) = rest[quote_start + 1..].find(quote) else {
+            break;
+        };
+        let value = &rest[quote_start + 1..quote_start + 1 + quote_end];
+        let attribute = &name[name_start..];
+        push_collapsed(output, &rest[..name_start]);
+        output.push_str(attribute);
+        output.push('=');
+        output.push(quote);
+        if attribute == "transform" {
+            output.push_str(&round_transform(value, precision));
+        } else if GEOMETRY_ATTRIBUTES.contains(&attribute) {
+            output.push_str(&round_numbers(value, precision));
+        } else {
+            output.push_str(value);
+        }
+        output.push(quote);
+        rest = &rest[quote_start + quote_end + 2..];
+    }
+    push_collapsed(output, rest.trim_end_matches(['>', '/']).trim_end());
+    if rest.trim_end_matches('>').trim_end().ends_with('/') {
+        output.push('/');
+    }
+    output.push('>');
+}
+
+/// Add `text` to `output` with every run of whitespace collapsed to a single space.
+fn push_collapsed(output: &mut String, text: &str) {
+    let mut words = text.split_whitespace();
+    if let Some(first) = words.next() {
+        if text.starts_with(char::is_whitespace) {
+            output.push(' ');
+        }
+        output.push_str(first);
+        for word in words {
+            output.push(' ');
+            output.push_str(word);
+        }
+        if text.ends_with(char::is_whitespace) {
+            output.push(' ');
+        }
+    } else if !text.is_empty() {
+        output.push(' ');
+    }
+}
+
+/// Round every number in `value` to `precision` decimal places.
+fn round_numbers(value: &str, precision: usize) -> String {
+    round_numbers_after(value, 0, precision)
+}
+
+/// Round the translations in the `transform` attribute `value` to `precision` decimal places.
+/// The other numbers of a transformation scale and rotate everything inside the element, so
+/// rounding them would distort it; e.g. the 0.7071 of a rotation by 45 degrees would become 1.
+fn round_transform(value: &str, precision: usize) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(open) = rest.find('(') {
+        let Some(close) = rest[open..].find(')').map(|close| open + close) else {
+            break;
+        };
+        let name = rest[..open]
+            .trim_start_matches([' ', ',', '\n', '\t'])
+            .trim_end();
+        let arguments = &rest[open + 1..close];
+        output.push_str(&rest[..=open]);
+        match name {
+            "translate" => output.push_str(&round_numbers(arguments, precision)),
+            // The translation is the last two of `matrix(a b c d e f)`.
+            "matrix" => output.push_str(&round_numbers_after(arguments, 4, precision)),
+            _ => output.push_str(arguments),
+        }
+        output.push(')');
+        rest = &rest[close + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Round every number in `value` except for the first `skip` to `precision` decimal places.
+fn round_numbers_after(value: &str, skip: usize, precision: usize) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut count = 0;
+    while let Some(mut start) = rest.find(|c: char| c.is_ascii_digit() || c == '.') {
+        // A minus sign belongs to the number, so that e.g. -0.001 is not rounded to -0.
+        if rest[..start].ends_with('-') {
+            start -= 1;
+        }
+        output.push_str(&rest[..start]);
+        let number = &rest[start..];
+        let sign = usize::from(number.starts_with('-'));
+        let mut seen_point = false;
+        let end = number[sign..]
+            .find(|c: char| {
+                if c == '.' && !seen_point {
+                    seen_point = true;
+                    false
+                } else {
+                    !c.is_ascii_digit()
+                }
+            })
+            .map_or(number.len(), |end| sign + end);
+        match number[..end].parse::<f64>() {
+            Ok(parsed) if seen_point && count >= skip => output.push_str(&round(parsed, precision)),
+            _ => output.push_str(&number[..end]),
+        }
+        count += 1;
+        rest = &number[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Format `value` with at most `precision` decimal places and no trailing zeros.
+fn round(value: f64, precision: usize) -> String {
+    let formatted = format!("{value:.precision$}");
+    let formatted = match formatted.contains('.') {
+        true => formatted.trim_end_matches('0').trim_end_matches('.'),
+        false => &formatted,
+    };
+    match formatted {
+        "" | "-0" => "0".to_string(),
+        _ => formatted.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_rounded_with_their_sign() {
+        assert_eq!(
+            round_numbers("M-1.23456 -0.004L3.999-2.5", 2),
+            "M-1.23 0L4-2.5"
+        );
+        assert_eq!(round_numbers("-0.5 .25", 0), "0 0");
+    }
+
+    #[test]
+    fn integers_are_left_as_they_are() {
+        assert_eq!(round_numbers("M10 -20L300 4", 1), "M10 -20L300 4");
+        assert_eq!(round_numbers("1.", 1), "1");
+    }
+
+    #[test]
+    fn only_the_translation_of_a_transform_is_rounded() {
+        let rotated = r#"<g transform="matrix(0.707107 0.707107 -0.707107 0.707107 10 -20)">"#;
+        assert_eq!(minify(rotated, 0), rotated);
+        assert_eq!(
+            round_transform("matrix(0.5 0 0 2 12.3456 -7.891)", 1),
+            "matrix(0.5 0 0 2 12.3 -7.9)"
+        );
+        assert_eq!(
+            round_transform("translate(1.234,5.678) rotate(33.333) scale(0.125)", 1),
+            "translate(1.2,5.7) rotate(33.333) scale(0.125)"
+        );
+    }
+}
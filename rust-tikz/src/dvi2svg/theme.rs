@@ -0,0 +1,136 @@
+//! Colors in SVG output that follow the theme of the page the SVG is embedded in. TeX only
+//! knows absolute colors, so a diagram drawn in black disappears on a dark background unless
+//! its colors are replaced or left to CSS.
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::color::Color;
+
+/// The attributes that hold colors in SVG output.
+const COLOR_ATTRIBUTES: [&str; 3] = ["fill", "stroke", "stop-color"];
+
+/// How colors are written in SVG output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SvgTheme {
+    /// Write black, the color TeX uses unless told otherwise, as `currentColor`, so that it
+    /// follows the CSS `color` of the surrounding page.
+    pub current_color: bool,
+    /// Add a stylesheet that lets every other color be overridden by a CSS custom property
+    /// named after it, e.g. `--tikz-color-ff0000` for red. Colors whose property is not set
+    /// stay as they are.
+    pub css_variables: bool,
+    /// Colors to replace, from a hex color like `#ff0000` to any color that SVG accepts in an
+    /// attribute, e.g. `#ffffff` or `currentColor`. This takes precedence over the other
+    /// options.
+    pub remap: HashMap<String, String>,
+}
+
+impl SvgTheme {
+    /// The value of a color attribute for `color`.
+    pub(crate) fn paint(&self, color: Color) -> String {
+        let hex = color.to_hex();
+        if let Some(replacement) = self.remap.get(&hex) {
+            replacement.clone()
+        } else if self.current_color && hex == "#000000" {
+            "currentColor".to_string()
+        } else {
+            hex
+        }
+    }
+
+    /// The CSS rules that let the colors in `markup` be overridden by custom properties, or an
+    /// empty string if [`SvgTheme::css_variables`] is off. Rules in a stylesheet take precedence
+    /// over attributes, and unlike attributes they may use `var()`.
+    pub(crate) fn stylesheet(&self, markup: &str) -> String {
+        if !self.css_variables {
+            return "".to_string();
+        }
+        let mut used = BTreeSet::new();
+        for attribute in COLOR_ATTRIBUTES {
+            let opening = format!(r##" {attribute}="#"##);
+            for (index, _) in markup.match_indices(&opening) {
+                let start = index + opening.len();
+                let Some(digits) = markup.get(start..start + 6) else {
+                    continue;
+                };
+                if digits.chars().all(|c| c.is_ascii_hexdigit())
+                    && markup[start + 6..].starts_with('"')
+                {
+                    used.insert((attribute, digits));
+                }
+            }
+        }
+        used.into_iter()
+            .map(|(attribute, digits)| {
+                format!(
+                    r##"[{attribute}="#{digits}"] {{ {attribute}: var(--tikz-color-{digits}, #{digits}); }}"##
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_written_as_hex_by_default() {
+        let theme = SvgTheme::default();
+        assert_eq!(theme.paint(Color::BLACK), "#000000");
+        assert_eq!(theme.paint(Color::Rgb([1.0, 0.0, 0.0])), "#ff0000");
+        assert_eq!(theme.stylesheet(r##"<rect fill="#ff0000"/>"##), "");
+    }
+
+    #[test]
+    fn remapped_colors_take_precedence_over_current_color() {
+        let theme = SvgTheme {
+            current_color: true,
+            remap: HashMap::from([
+                ("#ff0000".to_string(), "#ffaaaa".to_string()),
+                ("#000000".to_string(), "#eeeeee".to_string()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(theme.paint(Color::Rgb([1.0, 0.0, 0.0])), "#ffaaaa");
+        assert_eq!(theme.paint(Color::BLACK), "#eeeeee");
+        // Colors are remapped by their hex value, whatever model they were given in.
+        assert_eq!(theme.paint(Color::Cmyk([0.0, 1.0, 1.0, 0.0])), "#ffaaaa");
+        assert_eq!(theme.paint(Color::Gray(1.0)), "#ffffff");
+
+        let theme = SvgTheme {
+            current_color: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            theme.paint(Color::Cmyk([0.0, 0.0, 0.0, 1.0])),
+            "currentColor"
+        );
+        assert_eq!(theme.paint(Color::Gray(0.5)), "#808080");
+    }
+
+    #[test]
+    fn stylesheets_only_cover_hex_color_attributes() {
+        let theme = SvgTheme {
+            css_variables: true,
+            ..Default::default()
+        };
+        let markup = concat!(
+            r##"<rect fill="#ff0000" stroke="#00FF00"/><stop stop-color="#0000ff"/>"##,
+            r##"<rect fill="#ff0000"/><rect fill="currentColor" stroke="#fff"/>"##,
+            r##"<rect fill="#ff00000"/><rect fill="#gg0000"/><rect data-fill="#123456"/>"##,
+            r##"<rect fill="#abcdef"##,
+        );
+        // The rules are sorted, so that the output does not depend on the order of the markup.
+        assert_eq!(
+            theme.stylesheet(markup),
+            [
+                r##"[fill="#ff0000"] { fill: var(--tikz-color-ff0000, #ff0000); }"##,
+                r##"[stop-color="#0000ff"] { stop-color: var(--tikz-color-0000ff, #0000ff); }"##,
+                r##"[stroke="#00FF00"] { stroke: var(--tikz-color-00FF00, #00FF00); }"##,
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -1,41 +1,43 @@
-use dvi::Instruction;
-
-pub fn tex_color_to_hex(color: &str) -> String {
-    match color {
-        "gray 0" => "black".to_string(),
-        "gray 1" => "white".to_string(),
-        _ if color.starts_with("rgb ") => format!(
-            "#{}",
-            color
-                .split_whitespace()
-                .filter(|&i| i != "rgb")
-                .map(|v| format!("{:0>2x}", (v.parse::<f64>().unwrap() * 255.0) as u8)) //TODO? unwrap?
-                .collect::<Vec<String>>()
-                .join("")
-        ),
-        _ if color.starts_with("gray ") => {
-            let v = color.split_ascii_whitespace().collect::<Vec<&str>>();
-            let r = (v.get(1).unwrap().parse::<f64>().unwrap() * 255.0) as u8;
-            format!("#{:0>2x}{:0>2x}{:0>2x}", r, r, r)
-        }
-        _ => "black".to_string(),
+/// Escape a character for use in XML or HTML text.
+pub fn escape(c: char) -> String {
+    match c {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        _ => c.to_string(),
     }
 }
 
-// Copied from https://github.com/derekdreery/dvi-rs/blob/master/tests/lib.rs
-// Adapted to new version of nom
-pub fn parse_dvi(input: &[u8]) -> Vec<Instruction> {
-    let mut input = input;
-    let mut instructions = Vec::new();
-    while !input.is_empty() {
-        let instruction = match Instruction::parse(&input) {
-            Ok((i, inst)) => {
-                input = i;
-                inst
-            }
-            _ => panic!("Parse error"),
-        };
-        instructions.push(instruction);
+/// Escape `value` for use in an attribute, or in XML or HTML text.
+pub fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Format a number for a PDF or PostScript content stream. PDF does not allow exponents, and
+/// four decimal places is plenty for coordinates in points.
+pub fn num(value: f64) -> String {
+    fixed(value, 4)
+}
+
+/// Format a number for SVG output. Six decimal places are far below anything visible, and
+/// rounding to them keeps the last bits of a computation, which may differ between the math
+/// libraries of different platforms, out of the output.
+pub fn svg_num(value: f64) -> String {
+    fixed(value, 6)
+}
+
+/// Format `value` with at most `places` decimal places and without an exponent. Non-finite
+/// numbers are written as `0`, and so is `-0`.
+fn fixed(value: f64, places: usize) -> String {
+    let value = if value.is_finite() { value } else { 0.0 };
+    let formatted = format!("{value:.places$}");
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    match formatted {
+        "-0" | "" => "0".to_string(),
+        _ => formatted.to_string(),
     }
-    instructions
 }
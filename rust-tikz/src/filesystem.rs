@@ -1,16 +1,24 @@
+use std::ops::Deref;
+use std::sync::Arc;
 use std::{cmp::max, cmp::min, collections::HashMap};
 
+use crate::archive::TexArchive;
+use crate::font_files::FontFiles;
+use crate::logging::{LogCategory, Logger};
+
 /// A virtual file system that allows for opening, reading, and
 /// writing files in memory.
 #[derive(Debug, Clone)]
 pub(crate) struct VirtualFileSystem {
     /// The files in the virtual file system.
-    data: HashMap<String, Vec<u8>>,
+    files: LayeredFiles,
     stdin: Vec<u8>,
     stdout: Vec<u8>,
     /// A mapping from file descriptors to file handles. This
     /// keeps track of open files, etc.
     fd_to_file_pointer: Vec<FilePointer>,
+    /// Where warnings about bad file descriptors and traces of opened files go.
+    logger: Logger,
 }
 
 /// How a file is currently being read (in bytes mode, as raw data, or in
@@ -24,16 +32,28 @@ pub(crate) enum ReadMode {
 }
 
 impl VirtualFileSystem {
-    /// Create a new virtual file system initialized with the files in `data`.
-    pub fn new(data: HashMap<String, Vec<u8>>) -> Self {
+    /// Create a new virtual file system backed by the files in `archive`. Files from the archive
+    /// are only decompressed when TeX opens them.
+    pub fn new(archive: Arc<TexArchive>) -> Self {
         Self {
-            data,
+            files: LayeredFiles::new(archive),
             stdin: Vec::new(),
             stdout: Vec::new(),
             fd_to_file_pointer: vec![],
+            logger: Logger::default(),
         }
     }
 
+    /// Send diagnostics to `logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = logger;
+    }
+
+    /// The logger that diagnostics are sent to.
+    pub fn logger(&self) -> &Logger {
+        &self.logger
+    }
+
     /// Get a file descriptor for the specified file. There is no
     /// way for this function to fail. If the file does not exist,
     /// a new file will be created with the corresponding name.
@@ -45,12 +65,15 @@ impl VirtualFileSystem {
             FileType::Stdin => FilePointer::new_stdin(),
             FileType::Stdout => FilePointer::new_stdout(),
             FileType::Named(name) => {
-                let mut is_new_file = false;
                 // Ensure there is some data for the file
-                self.data.entry(name.to_string()).or_insert_with(|| {
-                    is_new_file = true;
-                    Vec::new()
+                let is_new_file = !self.files.exists(name);
+                self.logger.log(LogCategory::Files, || {
+                    let status = if is_new_file { "new" } else { "existing" };
+                    format!("open {name} ({status})")
                 });
+                if is_new_file {
+                    self.files.create(name);
+                }
                 if is_new_file && erstat_if_new {
                     FilePointer::new_named_with_erstat(name)
                 } else {
@@ -72,28 +95,30 @@ impl VirtualFileSystem {
     }
 
     /// Get the bytes contained in `file`.
-    pub fn get_file_contents(&self, file: FileType<&str>) -> Option<&Vec<u8>> {
+    pub fn get_file_contents(&self, file: FileType<&str>) -> Option<FileContents<'_>> {
         match file {
-            FileType::Stdin => Some(&self.stdin),
-            FileType::Stdout => Some(&self.stdout),
-            FileType::Named(name) => self.data.get(name),
+            FileType::Stdin => Some(FileContents::Owned(&self.stdin)),
+            FileType::Stdout => Some(FileContents::Owned(&self.stdout)),
+            FileType::Named(name) => self.files.read(name),
         }
     }
 
-    /// Write data to the file referenced by the file indexed by `fd`. If `fd` is negative, this function
-    /// prints and exist.
+    /// Write data to the file referenced by the file indexed by `fd`. If `fd` is negative, a warning
+    /// is logged and nothing is written.
     ///
     /// Data will be written starting at index `file.position` as stored by the file pointer.
     pub fn write_to_file_by_index(&mut self, fd: i32, data: &[u8]) {
         if fd < 0 {
-            println!("write_to_file_by_index to fd {} but fd is negative", fd);
+            self.logger.log(LogCategory::Warning, || {
+                format!("write_to_file_by_index to fd {} but fd is negative", fd)
+            });
             return;
         }
         if let Some(fp) = self.fd_to_file_pointer.get_mut(fd as usize) {
             let buffer = match fp.file {
                 FileType::Stdin => &mut self.stdin,
                 FileType::Stdout => &mut self.stdout,
-                FileType::Named(ref name) => self.data.get_mut(name).unwrap(),
+                FileType::Named(ref name) => self.files.write(name),
             };
             // Write to `buffer` starting at `fp.position` but take care to add to the length of the buffer if
             // we need to write past the end of the buffer.
@@ -106,10 +131,12 @@ impl VirtualFileSystem {
             );
             fp.byte_seek_position += data.len();
         } else {
-            println!(
-                "write_to_file_by_index to fd {} but there is no corresponding file",
-                fd
-            );
+            self.logger.log(LogCategory::Warning, || {
+                format!(
+                    "write_to_file_by_index to fd {} but there is no corresponding file",
+                    fd
+                )
+            });
         }
     }
 
@@ -122,16 +149,18 @@ impl VirtualFileSystem {
         read_mode: ReadMode,
     ) -> Vec<u8> {
         if fd < 0 {
-            println!("read_from_file_by_index to fd {} but fd is negative", fd);
+            self.logger.log(LogCategory::Warning, || {
+                format!("read_from_file_by_index to fd {} but fd is negative", fd)
+            });
             return Vec::new();
         }
         if let Some(fp) = self.fd_to_file_pointer.get_mut(fd as usize) {
             let buffer = match fp.file {
-                FileType::Stdin => &mut self.stdin,
-                FileType::Stdout => &mut self.stdout,
-                FileType::Named(ref name) => self.data.get_mut(name).unwrap(),
+                FileType::Stdin => FileContents::Owned(&self.stdin),
+                FileType::Stdout => FileContents::Owned(&self.stdout),
+                FileType::Named(ref name) => self.files.read(name).unwrap(),
             };
-            let start = if buffer.len() == 0 {
+            let start = if buffer.is_empty() {
                 0
             } else {
                 min(fp.byte_seek_position, buffer.len() - 1)
@@ -148,10 +177,12 @@ impl VirtualFileSystem {
             }
             data
         } else {
-            println!(
-                "read_from_file_by_index to fd {} but there is no corresponding file",
-                fd
-            );
+            self.logger.log(LogCategory::Warning, || {
+                format!(
+                    "read_from_file_by_index to fd {} but there is no corresponding file",
+                    fd
+                )
+            });
             Vec::new()
         }
     }
@@ -160,13 +191,13 @@ impl VirtualFileSystem {
     pub fn file_pointer_at_eof(&self, fp: &FilePointer) -> bool {
         match &fp.file {
             FileType::Stdin => {
-                self.stdin.len() == 0
+                self.stdin.is_empty()
                     || fp.byte_seek_position >= self.stdin.len()
                     || fp.text_seek_position >= self.stdin.len()
             }
             FileType::Stdout => false,
             FileType::Named(name) => {
-                let buffer = self.data.get(name).unwrap();
+                let buffer = self.files.read(name).unwrap();
                 // XXX: not sure if this is right? Should `ReadMode` be passed into this function?
                 fp.byte_seek_position >= buffer.len() || fp.text_seek_position >= buffer.len()
             }
@@ -181,7 +212,7 @@ impl VirtualFileSystem {
                 FileType::Stdin => self.stdin.get(fp.text_seek_position) == Some(&b'\n'),
                 FileType::Stdout => false,
                 FileType::Named(name) => {
-                    let buffer = self.data.get(name).unwrap();
+                    let buffer = self.files.read(name).unwrap();
                     buffer.get(max(fp.text_seek_position, 0)) == Some(&b'\n')
                 }
             }
@@ -191,17 +222,19 @@ impl VirtualFileSystem {
     /// otherwise do nothing.
     pub fn skip_current_newline_by_index(&mut self, fd: i32) {
         if fd < 0 {
-            println!(
-                "skip_current_newline_by_index to fd {} but fd is negative",
-                fd
-            );
+            self.logger.log(LogCategory::Warning, || {
+                format!(
+                    "skip_current_newline_by_index to fd {} but fd is negative",
+                    fd
+                )
+            });
             return;
         }
         if let Some(fp) = self.fd_to_file_pointer.get_mut(fd as usize) {
             let buffer = match fp.file {
-                FileType::Stdin => &mut self.stdin,
-                FileType::Stdout => &mut self.stdout,
-                FileType::Named(ref name) => self.data.get_mut(name).unwrap(),
+                FileType::Stdin => FileContents::Owned(&self.stdin),
+                FileType::Stdout => FileContents::Owned(&self.stdout),
+                FileType::Named(ref name) => self.files.read(name).unwrap(),
             };
 
             if let Some(&b'\n') = buffer.get(fp.text_seek_position) {
@@ -209,10 +242,12 @@ impl VirtualFileSystem {
                 fp.text_seek_position += 1;
             }
         } else {
-            println!(
-                "skip_current_newline_by_index to fd {} but there is no corresponding file",
-                fd
-            );
+            self.logger.log(LogCategory::Warning, || {
+                format!(
+                    "skip_current_newline_by_index to fd {} but there is no corresponding file",
+                    fd
+                )
+            });
         }
     }
 
@@ -220,14 +255,16 @@ impl VirtualFileSystem {
     /// If the text position is already at the end of the file, return None.
     pub fn read_line_by_index(&mut self, fd: i32) -> Option<Vec<u8>> {
         if fd < 0 {
-            println!("read_line_by_index to fd {} but fd is negative", fd);
+            self.logger.log(LogCategory::Warning, || {
+                format!("read_line_by_index to fd {} but fd is negative", fd)
+            });
             return None;
         }
         if let Some(fp) = self.fd_to_file_pointer.get_mut(fd as usize) {
             let buffer = match fp.file {
-                FileType::Stdin => &mut self.stdin,
-                FileType::Stdout => &mut self.stdout,
-                FileType::Named(ref name) => self.data.get_mut(name).unwrap(),
+                FileType::Stdin => FileContents::Owned(&self.stdin),
+                FileType::Stdout => FileContents::Owned(&self.stdout),
+                FileType::Named(ref name) => self.files.read(name).unwrap(),
             };
             let start = fp.text_seek_position;
             let end = buffer
@@ -243,10 +280,12 @@ impl VirtualFileSystem {
             fp.text_seek_position = end + 1;
             Some(data)
         } else {
-            println!(
-                "read_line_by_index to fd {} but there is no corresponding file",
-                fd
-            );
+            self.logger.log(LogCategory::Warning, || {
+                format!(
+                    "read_line_by_index to fd {} but there is no corresponding file",
+                    fd
+                )
+            });
             None
         }
     }
@@ -261,6 +300,37 @@ impl VirtualFileSystem {
         self.stdin = data.to_vec();
     }
 
+    /// Add the file `name` to the read-only base layer, replacing a file of the same name.
+    /// Unlike files that are written to, it is kept by [`VirtualFileSystem::reset`].
+    pub fn add_file(&mut self, name: &str, contents: Arc<[u8]>) {
+        self.files.overlay.remove(name);
+        self.files.added.insert(name.to_string(), contents);
+    }
+
+    /// Forget every file that was written to, so that the file system only has the files of
+    /// the base layer again. File descriptors opened before the reset no longer resolve.
+    pub fn reset(&mut self) {
+        self.files.overlay.clear();
+        self.fd_to_file_pointer.clear();
+    }
+
+    /// The font files in the file system, for the DVI interpreter. Font files that were
+    /// added or written to the file system take precedence over the bundled ones.
+    pub fn font_files(&self) -> FontFiles {
+        let mut font_files = FontFiles::from_archive(self.files.base.clone());
+        for (name, data) in &self.files.added {
+            if name.ends_with(".tfm") || name.ends_with(".vf") {
+                font_files.insert(name.clone(), data.clone());
+            }
+        }
+        for (name, data) in &self.files.overlay {
+            if name.ends_with(".tfm") || name.ends_with(".vf") {
+                font_files.insert(name.clone(), data.as_slice());
+            }
+        }
+        font_files
+    }
+
     /// Set the contents of a file. This will override the entire file and
     /// reset the position of any pointers into the file.
     pub fn set_file_contents(&mut self, file: FileType<&str>, data: &[u8]) {
@@ -268,13 +338,13 @@ impl VirtualFileSystem {
             FileType::Stdin => self.stdin = data.to_vec(),
             FileType::Stdout => self.stdout = data.to_vec(),
             FileType::Named(name) => {
-                self.data.insert(name.to_string(), data.to_vec());
+                *self.files.write(name) = data.to_vec();
                 for fp in &mut self.fd_to_file_pointer {
-                    if let FileType::Named(ref n) = fp.file {
-                        if n == name {
-                            fp.byte_seek_position = 0;
-                            fp.text_seek_position = 0;
-                        }
+                    if let FileType::Named(ref n) = fp.file
+                        && n == name
+                    {
+                        fp.byte_seek_position = 0;
+                        fp.text_seek_position = 0;
                     }
                 }
             }
@@ -282,6 +352,85 @@ impl VirtualFileSystem {
     }
 }
 
+/// The named files of a [`VirtualFileSystem`], stored in two layers. The base layer is
+/// read-only: it is an archive that is shared between every file system, along with the files
+/// that were added to this file system. Files that are created or written to live in the
+/// overlay, which belongs to a single file system. Writing to a file from the base layer first
+/// copies it into the overlay.
+#[derive(Debug, Clone)]
+struct LayeredFiles {
+    base: Arc<TexArchive>,
+    /// Files added to the base layer, which take precedence over the archive.
+    added: HashMap<String, Arc<[u8]>>,
+    overlay: HashMap<String, Vec<u8>>,
+}
+
+impl LayeredFiles {
+    fn new(base: Arc<TexArchive>) -> Self {
+        Self {
+            base,
+            added: HashMap::new(),
+            overlay: HashMap::new(),
+        }
+    }
+
+    /// Returns whether `name` exists in either layer.
+    fn exists(&self, name: &str) -> bool {
+        self.overlay.contains_key(name) || self.added.contains_key(name) || self.base.contains(name)
+    }
+
+    /// Get the contents of `name` from the base layer.
+    fn read_base(&self, name: &str) -> Option<Arc<[u8]>> {
+        match self.added.get(name) {
+            Some(data) => Some(data.clone()),
+            None => self.base.get(name),
+        }
+    }
+
+    /// Create an empty file called `name` in the overlay.
+    fn create(&mut self, name: &str) {
+        self.overlay.insert(name.to_string(), Vec::new());
+    }
+
+    /// Get the contents of `name`, preferring the overlay to the base layer.
+    fn read(&self, name: &str) -> Option<FileContents<'_>> {
+        match self.overlay.get(name) {
+            Some(data) => Some(FileContents::Owned(data)),
+            None => self.read_base(name).map(FileContents::Shared),
+        }
+    }
+
+    /// Get a writable buffer for `name`. If the file only exists in the base layer, it is
+    /// copied into the overlay first. If it doesn't exist at all, it is created.
+    fn write(&mut self, name: &str) -> &mut Vec<u8> {
+        if !self.overlay.contains_key(name) {
+            let data = self.read_base(name).map(|data| data.to_vec());
+            self.overlay
+                .insert(name.to_string(), data.unwrap_or_default());
+        }
+        self.overlay.get_mut(name).unwrap()
+    }
+}
+
+/// The contents of a file. Files from the base layer of the file system are shared
+/// between every file system; other files are borrowed from the file system that owns them.
+#[derive(Debug, Clone)]
+pub(crate) enum FileContents<'a> {
+    Owned(&'a [u8]),
+    Shared(Arc<[u8]>),
+}
+
+impl Deref for FileContents<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileContents::Owned(data) => data,
+            FileContents::Shared(data) => data,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum FileType<T> {
     Stdin,
@@ -346,3 +495,71 @@ impl FilePointer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_system() -> VirtualFileSystem {
+        VirtualFileSystem::new(Arc::new(TexArchive::from_files(&[("base.tex", b"base")])))
+    }
+
+    fn contents(vfs: &VirtualFileSystem, name: &str) -> Option<Vec<u8>> {
+        vfs.get_file_contents(FileType::Named(name))
+            .map(|contents| contents.to_vec())
+    }
+
+    #[test]
+    fn reset_forgets_written_files_but_keeps_the_base_layer() {
+        let mut vfs = file_system();
+        vfs.add_file("input.tex", b"added"[..].into());
+        for name in ["base.tex", "input.tex", "input.log"] {
+            vfs.set_file_contents(FileType::Named(name), b"written");
+            assert_eq!(
+                contents(&vfs, name).as_deref(),
+                Some(&b"written"[..]),
+                "{name}"
+            );
+        }
+        let fd = vfs.get_file_descriptor(FileType::Named("input.log"), false) as i32;
+        assert!(vfs.get_file_pointer_by_index(fd).is_some());
+
+        vfs.reset();
+        assert!(vfs.get_file_pointer_by_index(fd).is_none());
+        assert_eq!(contents(&vfs, "base.tex").as_deref(), Some(&b"base"[..]));
+        assert_eq!(contents(&vfs, "input.tex").as_deref(), Some(&b"added"[..]));
+        assert_eq!(contents(&vfs, "input.log"), None);
+    }
+
+    #[test]
+    fn writes_are_not_seen_by_other_file_systems() {
+        let archive = Arc::new(TexArchive::from_files(&[("base.tex", b"base")]));
+        let mut first = VirtualFileSystem::new(archive.clone());
+        let second = VirtualFileSystem::new(archive.clone());
+        first.add_file("input.tex", b"added"[..].into());
+        let copy = first.clone();
+        first.set_file_contents(FileType::Named("base.tex"), b"written");
+        first.set_file_contents(FileType::Named("input.tex"), b"written");
+
+        assert_eq!(contents(&second, "base.tex").as_deref(), Some(&b"base"[..]));
+        assert_eq!(contents(&second, "input.tex"), None);
+        assert_eq!(contents(&copy, "base.tex").as_deref(), Some(&b"base"[..]));
+        assert_eq!(contents(&copy, "input.tex").as_deref(), Some(&b"added"[..]));
+        assert_eq!(archive.get("base.tex").as_deref(), Some(&b"base"[..]));
+    }
+
+    #[test]
+    fn added_files_replace_written_ones() {
+        let mut vfs = file_system();
+        vfs.set_file_contents(FileType::Named("input.tex"), b"written");
+        vfs.add_file("input.tex", b"added"[..].into());
+        assert_eq!(contents(&vfs, "input.tex").as_deref(), Some(&b"added"[..]));
+        // Files in the archive can be replaced too.
+        vfs.add_file("base.tex", b"replaced"[..].into());
+        vfs.reset();
+        assert_eq!(
+            contents(&vfs, "base.tex").as_deref(),
+            Some(&b"replaced"[..])
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! The font files that the DVI interpreter reads: the `.tfm` files with the metrics of fonts
+//! and the `.vf` files of virtual fonts. They come from the same place as the files TeX reads:
+//! the bundled archive, and the files a [`crate::WasmRunner`] has added to its file system.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::archive::TexArchive;
+
+/// A read-only collection of font files, looked up by file name, e.g. `ecrm1000.vf`. Cloning
+/// a `FontFiles` is cheap since the bundled archive is shared.
+#[derive(Debug, Clone, Default)]
+pub struct FontFiles {
+    archive: Option<Arc<TexArchive>>,
+    /// Files that take precedence over the archive.
+    files: HashMap<String, Arc<[u8]>>,
+}
+
+impl FontFiles {
+    /// No files at all.
+    pub fn new() -> FontFiles {
+        FontFiles::default()
+    }
+
+    /// The font files bundled with the crate.
+    pub fn bundled() -> FontFiles {
+        FontFiles::from_archive(TexArchive::bundled())
+    }
+
+    pub(crate) fn from_archive(archive: Arc<TexArchive>) -> FontFiles {
+        FontFiles {
+            archive: Some(archive),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Add the file `name`, replacing a file of the same name.
+    pub fn insert(&mut self, name: impl Into<String>, contents: impl Into<Arc<[u8]>>) {
+        self.files.insert(name.into(), contents.into());
+    }
+
+    /// The contents of the file `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<Arc<[u8]>> {
+        match self.files.get(name) {
+            Some(contents) => Some(contents.clone()),
+            None => self.archive.as_ref()?.get(name),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Fonts that are bundled with the crate. TeX only gives us the names of its Computer Modern
+//! fonts, so backends that need real glyphs (PNG and PDF) substitute these fonts instead.
+
+/// A bundled TrueType font.
+pub(crate) struct BundledFont {
+    /// The PostScript name of the font.
+    pub(crate) name: &'static str,
+    pub(crate) data: &'static [u8],
+}
+
+pub(crate) static SERIF: BundledFont = BundledFont {
+    name: "DejaVuSerif",
+    data: include_bytes!("./assets/fonts/DejaVuSerif.ttf"),
+};
+
+pub(crate) static SERIF_ITALIC: BundledFont = BundledFont {
+    name: "DejaVuSerif-Italic",
+    data: include_bytes!("./assets/fonts/DejaVuSerif-Italic.ttf"),
+};
+
+pub(crate) static SERIF_BOLD: BundledFont = BundledFont {
+    name: "DejaVuSerif-Bold",
+    data: include_bytes!("./assets/fonts/DejaVuSerif-Bold.ttf"),
+};
+
+pub(crate) static MONO: BundledFont = BundledFont {
+    name: "DejaVuSansMono",
+    data: include_bytes!("./assets/fonts/DejaVuSansMono.ttf"),
+};
+
+/// All of the bundled fonts.
+#[cfg_attr(not(feature = "png"), allow(dead_code))]
+pub(crate) static FONTS: [&BundledFont; 4] = [&SERIF, &SERIF_ITALIC, &SERIF_BOLD, &MONO];
+
+/// Pick the bundled font that stands in for the TeX font `tex_name` (e.g., `cmr10`). This is
+/// used for both PNG and PDF output.
+#[cfg_attr(not(any(feature = "png", feature = "pdf")), allow(dead_code))]
+pub(crate) fn substitute(tex_name: &str) -> &'static BundledFont {
+    if tex_name.starts_with("cmtt") {
+        &MONO
+    } else if tex_name.starts_with("cmb") {
+        &SERIF_BOLD
+    } else if ["cmmi", "cmti", "cmsl"]
+        .iter()
+        .any(|p| tex_name.starts_with(p))
+    {
+        &SERIF_ITALIC
+    } else {
+        &SERIF
+    }
+}
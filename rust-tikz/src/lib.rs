@@ -1,12 +1,35 @@
 use anyhow::{Error, Result};
-use dvi2html::tfm;
-use dvi2svg::dvi2svg;
 
+mod archive;
+#[cfg(feature = "async")]
+mod async_render;
+pub mod display_list;
+pub mod dvi;
 mod filesystem;
+mod font_files;
+mod logging;
 mod texjax_imports;
 use filesystem::*;
 mod dvi2svg;
+#[cfg(feature = "png")]
+mod dvi2png;
+#[cfg(any(feature = "png", feature = "pdf"))]
+mod fonts;
+mod renderer;
+mod tfm;
+mod vf;
 mod wasm_runner;
+#[cfg(feature = "png")]
+pub use dvi2png::{dvi2png, parse_background, svg2png};
+#[cfg(feature = "pdf")]
+pub use dvi2svg::dvi2pdf;
+pub use dvi2svg::accessibility::{AltText, SvgAccessibility};
+pub use dvi2svg::svg_optimize::SvgOptimization;
+pub use dvi2svg::theme::SvgTheme;
+pub use dvi2svg::{dvi2display_list, dvi2eps, dvi2html_fragment};
+pub use font_files::FontFiles;
+pub use logging::{LogCategory, LogSink, Logger};
+pub use renderer::*;
 pub use wasm_runner::*;
 
 /// Convert `input` into an SVG string. This function creates a new [`WasmRunner`]
@@ -15,8 +38,7 @@ pub use wasm_runner::*;
 pub fn text2svg_simple(input: &str) -> Result<String> {
     let mut wasm_runner = WasmRunner::new()?;
     let svg_result = tex2svg(&mut wasm_runner, input);
-    if svg_result.is_err() {
-        let error = svg_result.unwrap_err();
+    if let Err(error) = &svg_result {
         //println!("Error: {}", error);
         // Show the messages file
         let messages = wasm_runner.get_messages()?;
@@ -0,0 +1,118 @@
+//! Diagnostics from TeX and the DVI conversion. Nothing is printed by the library itself;
+//! instead, messages are passed to the sink of a [`Logger`], which drops them by default.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// What a diagnostic message is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogCategory {
+    /// Problems that make the output wrong, e.g. a font without metrics, whose glyphs are
+    /// spaced with the metrics of another font. Errors are always passed to the sink.
+    Error,
+    /// Problems that were worked around, e.g. a character without metrics. Warnings are always
+    /// passed to the sink.
+    Warning,
+    /// Files opened by TeX.
+    Files,
+    /// Specials in the DVI file, with their position.
+    Specials,
+    /// Fonts loaded while interpreting the DVI file.
+    Fonts,
+}
+
+impl LogCategory {
+    /// The categories that can be switched on with [`Logger::enable`].
+    pub const TRACES: [LogCategory; 3] = [
+        LogCategory::Files,
+        LogCategory::Specials,
+        LogCategory::Fonts,
+    ];
+
+    /// The name of the category, e.g. `files`.
+    pub fn name(self) -> &'static str {
+        match self {
+            LogCategory::Error => "error",
+            LogCategory::Warning => "warning",
+            LogCategory::Files => "files",
+            LogCategory::Specials => "specials",
+            LogCategory::Fonts => "fonts",
+        }
+    }
+
+    /// Look up a category by its [`LogCategory::name`].
+    pub fn parse(name: &str) -> Option<LogCategory> {
+        [LogCategory::Error, LogCategory::Warning]
+            .into_iter()
+            .chain(LogCategory::TRACES)
+            .find(|category| category.name() == name)
+    }
+}
+
+impl fmt::Display for LogCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The function that receives the messages of a [`Logger`].
+pub type LogSink = dyn Fn(LogCategory, &str) + Send + Sync;
+
+/// Where diagnostics go. A `Logger` passes errors, warnings and the trace categories that have
+/// been switched on to its sink. Cloning a `Logger` is cheap and the clones share the same sink.
+///
+/// The default `Logger` has no sink, so it drops everything without formatting it.
+#[derive(Clone, Default)]
+pub struct Logger {
+    sink: Option<Arc<LogSink>>,
+    traces: Vec<LogCategory>,
+}
+
+impl Logger {
+    /// Create a `Logger` that passes errors and warnings to `sink`. Switch on other categories with
+    /// [`Logger::enable`].
+    pub fn new(sink: impl Fn(LogCategory, &str) + Send + Sync + 'static) -> Logger {
+        Logger {
+            sink: Some(Arc::new(sink)),
+            traces: Vec::new(),
+        }
+    }
+
+    /// Create a `Logger` that writes to stderr, e.g. `[fonts] cmr10 at 10pt`.
+    pub fn stderr() -> Logger {
+        Logger::new(|category, message| eprintln!("[{category}] {message}"))
+    }
+
+    /// Pass the messages of `category` to the sink too.
+    pub fn enable(mut self, category: LogCategory) -> Logger {
+        if !self.traces.contains(&category) {
+            self.traces.push(category);
+        }
+        self
+    }
+
+    /// Whether messages of `category` are passed to the sink.
+    pub fn is_enabled(&self, category: LogCategory) -> bool {
+        self.sink.is_some()
+            && (matches!(category, LogCategory::Error | LogCategory::Warning)
+                || self.traces.contains(&category))
+    }
+
+    /// Pass a message of `category` to the sink. `message` is only called if the category is
+    /// enabled, so that formatting is skipped otherwise.
+    pub fn log(&self, category: LogCategory, message: impl FnOnce() -> String) {
+        match &self.sink {
+            Some(sink) if self.is_enabled(category) => sink(category, &message()),
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Debug for Logger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Logger")
+            .field("sink", &self.sink.is_some())
+            .field("traces", &self.traces)
+            .finish()
+    }
+}
@@ -1,41 +1,295 @@
+use std::io::{Read, Write};
+
 use anyhow::{Error, Result};
-use dvi2html::tfm;
-use dvi2svg::dvi2svg;
+use rust_tikz::{
+    AltText, Interaction, LogCategory, Logger, RenderFormat, RenderOptions, Renderer,
+    SvgAccessibility, SvgOptimization, SvgTheme,
+};
+
+const USAGE: &str = r#"Usage: rust-tikz [OPTIONS] [INPUT]
+
+Render the TeX document INPUT (or stdin if INPUT is omitted or `-`). The document is assumed
+to start with `\begin{document}` and end with `\end{document}`; `tikz` is already loaded.
+
+Options:
+  -f, --format <FORMAT>    Output format: svg (default), html, png, pdf, eps, json or dvi
+  -o, --output <FILE>      Write the output to FILE instead of stdout
+      --dpi <DPI>          Resolution of PNG output (default: 300)
+      --background <COLOR> Background of PNG output: transparent (default), white, black,
+                           or a hex color like #ffffff
+      --optimize           Make SVG output smaller: merge glyphs into runs of text, round
+                           coordinates and remove whitespace
+      --precision <DIGITS> Decimal places of coordinates in optimized SVG output (default: 2);
+                           implies --optimize
+      --id-prefix <PREFIX> Prefix of the ids in SVG and HTML output (default: derived from
+                           the content)
+      --title <TEXT>       Title of SVG output for screen readers
+      --desc <TEXT>        Description of SVG output for screen readers
+      --aria-label <TEXT>  Label of SVG output for screen readers (default: the title)
+      --alt-text <FROM>    Derive the title of SVG output from the TeX `source` or the
+                           typeset `text` if no title is given
+      --current-color      Write black in SVG output as `currentColor`, so that it follows
+                           the text color of the page
+      --css-variables      Let the colors of SVG output be overridden by CSS custom
+                           properties like `--tikz-color-ff0000`
+      --remap-color <FROM=TO>
+                           Replace the color FROM (a hex color like #ff0000) in SVG output
+                           with TO. May be given more than once
+      --interaction <MODE> What TeX does on errors: batchmode, nonstopmode, scrollmode or
+                           errorstopmode (default)
+      --best-effort        Write whatever TeX produced even if it reported errors, and print
+                           the errors to stderr
+      --trace <CATEGORIES> Print traces of files, specials and/or fonts to stderr, e.g.
+                           `--trace files,fonts`. Errors and warnings are always
+                           printed
+  -h, --help               Print this message
+"#;
+
+/// Command line arguments.
+#[cfg_attr(not(feature = "png"), allow(dead_code))]
+struct Args {
+    input: Option<String>,
+    output: Option<String>,
+    format: String,
+    dpi: f32,
+    background: String,
+    id_prefix: Option<String>,
+    optimize: bool,
+    precision: usize,
+    /// `None` if none of the accessibility options are given.
+    accessibility: Option<SvgAccessibility>,
+    /// `None` if none of the color options are given.
+    theme: Option<SvgTheme>,
+    interaction: Interaction,
+    best_effort: bool,
+    logger: Logger,
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        Args::parse_from(std::env::args().skip(1))
+    }
 
-mod filesystem;
-mod texjax_imports;
-use filesystem::*;
-mod dvi2svg;
-mod wasm_runner;
-pub use wasm_runner::*;
+    /// Parse the arguments `argv`, which do not include the name of the program.
+    fn parse_from(argv: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = Args {
+            input: None,
+            output: None,
+            format: "svg".to_string(),
+            dpi: 300.0,
+            background: "transparent".to_string(),
+            id_prefix: None,
+            optimize: false,
+            precision: SvgOptimization::default().precision,
+            accessibility: None,
+            theme: None,
+            interaction: Interaction::default(),
+            best_effort: false,
+            logger: Logger::stderr(),
+        };
+        let mut argv = argv.into_iter();
+        while let Some(arg) = argv.next() {
+            let mut value = |name: &str| {
+                argv.next()
+                    .ok_or_else(|| Error::msg(format!("Missing value for `{name}`")))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    print!("{USAGE}");
+                    std::process::exit(0);
+                }
+                "-f" | "--format" => args.format = value(&arg)?,
+                "-o" | "--output" => args.output = Some(value(&arg)?),
+                "--dpi" => {
+                    args.dpi = value(&arg)?
+                        .parse()
+                        .ok()
+                        .filter(|dpi: &f32| dpi.is_finite() && *dpi > 0.0)
+                        .ok_or_else(|| Error::msg("`--dpi` must be a positive number"))?
+                }
+                "--background" => args.background = value(&arg)?,
+                "--id-prefix" => args.id_prefix = Some(value(&arg)?),
+                "--optimize" => args.optimize = true,
+                "--title" => args.accessibility().title = Some(value(&arg)?),
+                "--desc" => args.accessibility().description = Some(value(&arg)?),
+                "--aria-label" => args.accessibility().label = Some(value(&arg)?),
+                "--alt-text" => {
+                    args.accessibility().alt_text = match value(&arg)?.as_str() {
+                        "source" => AltText::Source,
+                        "text" => AltText::Text,
+                        _ => return Err(Error::msg("`--alt-text` must be `source` or `text`")),
+                    }
+                }
+                "--current-color" => args.theme().current_color = true,
+                "--css-variables" => args.theme().css_variables = true,
+                "--remap-color" => {
+                    let mapping = value(&arg)?;
+                    let (from, to) = mapping.split_once('=').ok_or_else(|| {
+                        Error::msg("`--remap-color` must be of the form `#000000=#ffffff`")
+                    })?;
+                    args.theme()
+                        .remap
+                        .insert(from.trim().to_lowercase(), to.trim().to_string());
+                }
+                "--interaction" => {
+                    args.interaction = Interaction::parse(&value(&arg)?).ok_or_else(|| {
+                        Error::msg(
+                            "`--interaction` must be `batchmode`, `nonstopmode`, `scrollmode` \
+                             or `errorstopmode`",
+                        )
+                    })?
+                }
+                "--best-effort" => args.best_effort = true,
+                "--trace" => {
+                    for name in value(&arg)?.split(',') {
+                        let category = LogCategory::parse(name.trim()).ok_or_else(|| {
+                            Error::msg(format!("Unknown trace category `{name}`"))
+                        })?;
+                        args.logger = args.logger.enable(category);
+                    }
+                }
+                "--precision" => {
+                    args.precision = value(&arg)?
+                        .parse()
+                        .map_err(|_| Error::msg("`--precision` must be a whole number"))?;
+                    args.optimize = true;
+                }
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(Error::msg(format!("Unknown option `{arg}`\n\n{USAGE}")));
+                }
+                _ => args.input = Some(arg),
+            }
+        }
+        Ok(args)
+    }
+
+    fn accessibility(&mut self) -> &mut SvgAccessibility {
+        self.accessibility
+            .get_or_insert_with(SvgAccessibility::default)
+    }
+
+    fn theme(&mut self) -> &mut SvgTheme {
+        self.theme.get_or_insert_with(SvgTheme::default)
+    }
+
+    fn render_format(&self) -> Result<RenderFormat> {
+        match self.format.as_str() {
+            "svg" => Ok(RenderFormat::Svg),
+            "html" => Ok(RenderFormat::Html),
+            #[cfg(feature = "png")]
+            "png" => Ok(RenderFormat::Png {
+                dpi: self.dpi,
+                background: rust_tikz::parse_background(&self.background).map_err(Error::msg)?,
+            }),
+            #[cfg(feature = "pdf")]
+            "pdf" => Ok(RenderFormat::Pdf),
+            "eps" => Ok(RenderFormat::Eps),
+            #[cfg(feature = "json")]
+            "json" => Ok(RenderFormat::Json),
+            "dvi" => Ok(RenderFormat::Dvi),
+            format => Err(Error::msg(format!("Unsupported output format `{format}`"))),
+        }
+    }
+}
 
 fn main() -> Result<()> {
-    let input = r#"
-        \begin{document}
-%\begin{tikzpicture}[domain=0:4]
-%  \draw[very thin,color=gray] (-0.1,-1.1) grid (3.9,3.9);
-%  \draw[->] (-0.2,0) -- (4.2,0) node[right] {$x$};
-%  \draw[->] (0,-1.2) -- (0,4.2) node[above] {$f(x)$};
-%  \draw[color=red]    plot (\x,\x)             node[right] {$f(x) =x$};
-%  \draw[color=blue]   plot (\x,{sin(\x r)})    node[right] {$f(x) = \sin x$};
-%  \draw[color=orange] plot (\x,{0.05*exp(\x)}) node[right] {$f(x) = \frac{1}{20} \mathrm e^x$};
-%\end{tikzpicture}
-abc$abc\alpha\beta\gamma\omega$
-\end{document}
-        "#;
-
-    let mut wasm_runner = WasmRunner::new()?;
-    let svg_result = tex2svg(&mut wasm_runner, input);
-    if svg_result.is_err() {
-        let error = svg_result.unwrap_err();
-        println!("Error: {}", error);
-        // Show the log file
-        let log_result = wasm_runner.get_messages()?;
-        println!("input.log:\n{}", log_result);
-        return Err(Error::msg(format!("Failed to convert DVI to SVG.",)));
-    }
-
-    println!("\n\nSVG OUTPUT\n\n{}", svg_result.unwrap());
+    let args = Args::parse()?;
+    let options = RenderOptions {
+        format: args.render_format()?,
+        id_prefix: args.id_prefix.clone(),
+        optimize: args.optimize.then_some(SvgOptimization {
+            precision: args.precision,
+        }),
+        accessibility: args.accessibility.clone(),
+        theme: args.theme.clone(),
+        // The date is taken from `SOURCE_DATE_EPOCH`, if it is set.
+        source_date_epoch: None,
+        interaction: args.interaction,
+        best_effort: args.best_effort,
+    };
+
+    let input = match args.input.as_deref() {
+        None | Some("-") => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        Some(path) => std::fs::read_to_string(path)?,
+    };
+
+    let mut renderer = Renderer::with_pool_size(0)?;
+    renderer.set_logger(args.logger.clone());
+    let output = renderer.render(&input, &options)?;
+    for error in &output.errors {
+        eprintln!("{error}\n");
+    }
+
+    match args.output {
+        Some(path) => std::fs::write(path, &output.data)?,
+        None => std::io::stdout().write_all(&output.data)?,
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(argv: &[&str]) -> Result<Args> {
+        Args::parse_from(argv.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn dpi_must_be_a_positive_number() {
+        assert_eq!(parse(&[]).unwrap().dpi, 300.0);
+        assert_eq!(parse(&["--dpi", "150"]).unwrap().dpi, 150.0);
+        assert_eq!(parse(&["--dpi", "72.5"]).unwrap().dpi, 72.5);
+        for dpi in ["high", "0", "-96", "inf", "NaN"] {
+            let error = parse(&["--dpi", dpi]).err().unwrap();
+            assert_eq!(
+                error.to_string(),
+                "`--dpi` must be a positive number",
+                "{dpi}"
+            );
+        }
+        let error = parse(&["--dpi"]).err().unwrap();
+        assert_eq!(error.to_string(), "Missing value for `--dpi`");
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_output_has_the_resolution_and_background_of_the_options() {
+        let args = parse(&["-f", "png", "--dpi", "150", "--background", "#ff000080"]).unwrap();
+        assert_eq!(
+            args.render_format().unwrap(),
+            RenderFormat::Png {
+                dpi: 150.0,
+                background: Some([255, 0, 0, 128]),
+            }
+        );
+        let args = parse(&["--format", "png"]).unwrap();
+        assert_eq!(
+            args.render_format().unwrap(),
+            RenderFormat::Png {
+                dpi: 300.0,
+                background: None,
+            }
+        );
+        let args = parse(&["-f", "png", "--background", "red"]).unwrap();
+        assert_eq!(
+            args.render_format().unwrap_err().to_string(),
+            "Invalid background color `red`"
+        );
+    }
+
+    #[test]
+    fn precision_implies_optimize() {
+        assert!(!parse(&[]).unwrap().optimize);
+        let args = parse(&["--precision", "3"]).unwrap();
+        assert!(args.optimize);
+        assert_eq!(args.precision, 3);
+        let error = parse(&["--precision", "many"]).err().unwrap();
+        assert_eq!(error.to_string(), "`--precision` must be a whole number");
+    }
+}
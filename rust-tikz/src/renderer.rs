@@ -0,0 +1,368 @@
+//! A thread-safe handle for rendering TeX. A [`Renderer`] compiles the TeX engine once and
+//! keeps a pool of [`WasmRunner`]s so that it can be shared between threads.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{Error, Result};
+
+use crate::dvi2svg::{SvgOptions, dvi2svg};
+use crate::{
+    FontFiles, Interaction, Logger, SvgAccessibility, SvgOptimization, SvgTheme, TexEngine,
+    WasmRunner,
+};
+
+/// The number of idle runners a [`Renderer`] keeps around by default.
+const DEFAULT_POOL_SIZE: usize = 4;
+
+/// The format a document is rendered to.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RenderFormat {
+    /// An SVG image, encoded as UTF-8.
+    #[default]
+    Svg,
+    /// An HTML fragment with absolutely positioned text and an inline SVG for each TikZ
+    /// picture, encoded as UTF-8.
+    Html,
+    /// A PNG image rasterized at `dpi` dots per inch. If `background` is `None` the image has a
+    /// transparent background; otherwise it is filled with the given RGBA color.
+    #[cfg(feature = "png")]
+    Png {
+        dpi: f32,
+        background: Option<[u8; 4]>,
+    },
+    /// A single page PDF. Glyphs are placed where TeX put them but drawn with bundled fonts
+    /// that stand in for TeX's fonts, see [`crate::dvi2pdf`].
+    #[cfg(feature = "pdf")]
+    Pdf,
+    /// An Encapsulated PostScript file. The TeX fonts are referenced by name rather than
+    /// embedded.
+    Eps,
+    /// A [`crate::display_list::DisplayList`] serialized as JSON.
+    #[cfg(feature = "json")]
+    Json,
+    /// The DVI file produced by TeX, unconverted. Use this to draw the document with a custom
+    /// [`crate::dvi::Machine`].
+    Dvi,
+}
+
+impl RenderFormat {
+    /// The name of the format, e.g. `SVG`.
+    fn name(&self) -> &'static str {
+        match self {
+            RenderFormat::Svg => "SVG",
+            RenderFormat::Html => "HTML",
+            #[cfg(feature = "png")]
+            RenderFormat::Png { .. } => "PNG",
+            #[cfg(feature = "pdf")]
+            RenderFormat::Pdf => "PDF",
+            RenderFormat::Eps => "EPS",
+            #[cfg(feature = "json")]
+            RenderFormat::Json => "JSON",
+            RenderFormat::Dvi => "DVI",
+        }
+    }
+}
+
+/// Options that control how a single document is rendered.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// The format of [`RenderOutput::data`].
+    pub format: RenderFormat,
+    /// The prefix of the ids in SVG and HTML output, which keeps the ids of documents that are
+    /// embedded in the same page apart. If `None`, the prefix is derived from a hash of the
+    /// document.
+    pub id_prefix: Option<String>,
+    /// If `Some`, SVG output is made smaller by merging glyphs into runs of text, rounding
+    /// coordinates and removing whitespace.
+    pub optimize: Option<SvgOptimization>,
+    /// If `Some`, SVG output is marked as an image with a title, description and label for
+    /// screen readers.
+    pub accessibility: Option<SvgAccessibility>,
+    /// If `Some`, colors in SVG output are written so that they can follow the theme of the
+    /// page, e.g. black as `currentColor`.
+    pub theme: Option<SvgTheme>,
+    /// The time TeX takes as the current time, e.g. for `\today`, in seconds since the Unix
+    /// epoch. If `None`, [`crate::default_source_date_epoch`] is used.
+    pub source_date_epoch: Option<i64>,
+    /// What TeX does when it finds an error.
+    pub interaction: Interaction,
+    /// If `false` (the default), a render fails if TeX reports any errors, even if TeX wrote a
+    /// DVI file. If `true`, whatever TeX produced is converted anyway and the errors are
+    /// returned in [`RenderOutput::errors`]. This works best with [`Interaction::NonStop`],
+    /// since TeX carries on after errors.
+    ///
+    /// Before this option was added, renders with errors succeeded as long as TeX wrote a DVI
+    /// file, and the errors were only in [`RenderOutput::log`]. Set `best_effort` to keep that
+    /// behavior.
+    pub best_effort: bool,
+}
+
+/// The result of rendering a document.
+#[derive(Debug, Clone)]
+pub struct RenderOutput {
+    /// The rendered document, encoded according to [`RenderOptions::format`].
+    pub data: Vec<u8>,
+    /// The output that TeX wrote to stdout.
+    pub messages: String,
+    /// The log file that TeX wrote.
+    pub log: String,
+    /// The errors TeX reported. This is only ever non-empty if [`RenderOptions::best_effort`]
+    /// is set.
+    pub errors: Vec<String>,
+}
+
+impl RenderOutput {
+    /// Interpret the rendered document as text. This fails for binary formats.
+    pub fn into_string(self) -> Result<String> {
+        String::from_utf8(self.data).map_err(|_| Error::msg("Rendered output is not text"))
+    }
+}
+
+/// A handle for rendering TeX that can be shared between threads. The TeX WASM module is
+/// compiled once when the `Renderer` is created and every render borrows a [`WasmRunner`]
+/// from an internal pool, so [`Renderer::render`] may be called concurrently.
+pub struct Renderer {
+    tex_engine: TexEngine,
+    /// Runners that are not currently rendering anything.
+    idle_runners: Mutex<Vec<WasmRunner>>,
+    /// The maximum number of idle runners to keep. Extra runners are dropped
+    /// once they finish rendering.
+    pool_size: usize,
+    /// Where the diagnostics of every render go.
+    logger: Logger,
+    /// Files that are added to every runner when it is spawned, by name.
+    files: Vec<(String, Arc<[u8]>)>,
+}
+
+impl Renderer {
+    /// Create a new `Renderer` that keeps up to 4 idle runners.
+    pub fn new() -> Result<Self> {
+        Self::with_pool_size(DEFAULT_POOL_SIZE)
+    }
+
+    /// Create a new `Renderer` that keeps up to `pool_size` idle runners. More runners than this
+    /// will be created if there are more concurrent renders, but they are not reused.
+    pub fn with_pool_size(pool_size: usize) -> Result<Self> {
+        Ok(Self::with_engine(TexEngine::new()?, pool_size))
+    }
+
+    /// Create a new `Renderer` that spawns its runners from `tex_engine` and keeps up to
+    /// `pool_size` idle runners.
+    pub fn with_engine(tex_engine: TexEngine, pool_size: usize) -> Self {
+        Self {
+            tex_engine,
+            idle_runners: Mutex::new(Vec::new()),
+            pool_size,
+            logger: Logger::default(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Send the diagnostics of TeX and of the DVI conversion to `logger` for every render.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = logger;
+    }
+
+    /// Add the file `name` for TeX to read in every render, e.g. a package, or the `.tfm` and
+    /// `.vf` files of the fonts it uses. See [`WasmRunner::add_file`].
+    pub fn add_file(&mut self, name: impl Into<String>, contents: impl Into<Arc<[u8]>>) {
+        let name = name.into();
+        let contents = contents.into();
+        let idle_runners = self
+            .idle_runners
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        for runner in idle_runners {
+            runner.add_file(&name, contents.clone());
+        }
+        self.files.retain(|(existing, _)| *existing != name);
+        self.files.push((name, contents));
+    }
+
+    /// Render the TeX document `input`. This function can be called from many threads at once.
+    pub fn render(&self, input: &str, options: &RenderOptions) -> Result<RenderOutput> {
+        let mut runner = self.get_runner(options)?;
+        runner.set_input(input.as_bytes());
+        let result = runner.run_to_dvi();
+        self.finish_render(runner, result, input, options)
+    }
+
+    /// Get an idle runner from the pool or spawn a new one if there are none, and set it up
+    /// for a render with `options`.
+    pub(crate) fn get_runner(&self, options: &RenderOptions) -> Result<WasmRunner> {
+        let mut runner = match self.take_runner() {
+            Some(runner) => runner,
+            None => {
+                let mut runner = self.tex_engine.new_runner()?;
+                for (name, contents) in &self.files {
+                    runner.add_file(name, contents.clone());
+                }
+                runner
+            }
+        };
+        runner.set_logger(self.logger.clone());
+        runner.set_interaction(options.interaction);
+        runner.set_source_date_epoch(
+            options
+                .source_date_epoch
+                .unwrap_or_else(crate::default_source_date_epoch),
+        );
+        Ok(runner)
+    }
+
+    /// Convert the DVI `result` of running `runner` on `input` into a [`RenderOutput`] and
+    /// return the runner to the pool.
+    pub(crate) fn finish_render(
+        &self,
+        runner: WasmRunner,
+        result: Result<Vec<u8>>,
+        input: &str,
+        options: &RenderOptions,
+    ) -> Result<RenderOutput> {
+        let messages = runner.get_messages().unwrap_or_default();
+        let log = runner.get_log().unwrap_or_default();
+        let errors = runner.get_errors().unwrap_or_default();
+        let font_files = runner.font_files();
+
+        // Only runners that completed successfully are reused. A runner that failed may
+        // have been interrupted part way through running TeX.
+        if result.is_ok() {
+            self.return_runner(runner);
+        }
+        if !errors.is_empty() && !options.best_effort {
+            return Err(Error::msg(format!(
+                "TeX reported errors.\n{}\n\nMessages:\n{messages}",
+                errors.join("\n\n")
+            )));
+        }
+        match result.and_then(|dvi| convert_dvi(&dvi, input, options, &font_files, &self.logger)) {
+            Ok(data) => Ok(RenderOutput {
+                data,
+                messages,
+                log,
+                errors,
+            }),
+            Err(error) => Err(Error::msg(format!(
+                "Failed to convert DVI to {}.\nError: {error}\n\nMessages:\n{messages}",
+                options.format.name()
+            ))),
+        }
+    }
+
+    fn take_runner(&self) -> Option<WasmRunner> {
+        self.idle_runners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+    }
+
+    fn return_runner(&self, runner: WasmRunner) {
+        let mut idle_runners = self
+            .idle_runners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if idle_runners.len() < self.pool_size {
+            idle_runners.push(runner);
+        }
+    }
+}
+
+/// Convert a DVI file, which TeX produced from `input` with `font_files`, into the format of
+/// `options`.
+fn convert_dvi(
+    dvi: &[u8],
+    input: &str,
+    options: &RenderOptions,
+    font_files: &FontFiles,
+    logger: &Logger,
+) -> Result<Vec<u8>> {
+    let id_prefix = options.id_prefix.as_deref();
+    let data = match options.format {
+        RenderFormat::Svg => {
+            let svg_options = SvgOptions {
+                id_prefix: options.id_prefix.clone(),
+                optimize: options.optimize,
+                accessibility: options
+                    .accessibility
+                    .as_ref()
+                    .map(|accessibility| accessibility.with_source(input)),
+                theme: options.theme.clone(),
+            };
+            dvi2svg(dvi, svg_options, font_files, logger)
+                .map_err(Error::msg)?
+                .into_bytes()
+        }
+        RenderFormat::Html => crate::dvi2html_fragment(dvi, id_prefix, font_files, logger)
+            .map_err(Error::msg)?
+            .into_bytes(),
+        #[cfg(feature = "png")]
+        RenderFormat::Png { dpi, background } => {
+            crate::dvi2png(dvi, dpi, background, font_files, logger).map_err(Error::msg)?
+        }
+        #[cfg(feature = "pdf")]
+        RenderFormat::Pdf => crate::dvi2pdf(dvi, font_files, logger).map_err(Error::msg)?,
+        RenderFormat::Eps => crate::dvi2eps(dvi, font_files, logger).map_err(Error::msg)?,
+        #[cfg(feature = "json")]
+        RenderFormat::Json => {
+            let display_list =
+                crate::dvi2display_list(dvi, font_files, logger).map_err(Error::msg)?;
+            serde_json::to_vec(&display_list)?
+        }
+        RenderFormat::Dvi => dvi.to_vec(),
+    };
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AltText;
+
+    #[test]
+    fn renderer_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Renderer>();
+    }
+
+    /// A document whose alt text is `Clean document <number>.` unless a file written by an
+    /// earlier document is still there. It writes that file itself.
+    fn document(number: usize) -> String {
+        format!(
+            r"\begin{{document}}\IfFileExists{{written.tex}}{{Leaked}}{{Clean}} document {number}.
+\newwrite\written\immediate\openout\written=written.tex
+\immediate\write\written{{{number}}}\immediate\closeout\written
+\end{{document}}
+"
+        )
+    }
+
+    #[test]
+    fn documents_render_concurrently_without_seeing_each_others_files() {
+        let options = RenderOptions {
+            accessibility: Some(SvgAccessibility {
+                alt_text: AltText::Text,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // Fewer runners than threads, so that runners are reused by other threads.
+        let renderer = Renderer::with_pool_size(2).unwrap();
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let (renderer, options) = (&renderer, &options);
+                scope.spawn(move || {
+                    for i in 0..3 {
+                        let number = thread * 3 + i;
+                        let svg = renderer
+                            .render(&document(number), options)
+                            .unwrap()
+                            .into_string()
+                            .unwrap();
+                        let title = format!("<title>Clean document {number}.</title>");
+                        assert!(svg.contains(&title), "{svg}");
+                    }
+                });
+            }
+        });
+    }
+}
@@ -1,350 +1,498 @@
+use std::fmt;
+
 use wasmi::*;
 
 use crate::{FileType, ReadMode, VirtualFileSystem};
 
-/// All the functions that are imported by the TeXJax WebAssembly module.
-/// These are created to mirror `library.js` from the original TeXJax project.
-///
-/// Many of these functions mimic their PASCAL equivalents (as used by the original TeX engine).
-pub(crate) struct TexJaxImports {
-    /// Close a file. Mimics Pascal's `close` function.
-    ///
-    /// Called with a file descriptor.
-    pub(crate) close: Func,
-    /// Mimics Pascal's `eof` function. Returns true if the current file pointer is at the end of the file.
-    ///
-    /// Called with a file descriptor.
-    pub(crate) eof: Func,
-    /// Mimics Pascal's `eoln` function. Returns true if the current file pointer is at the end of the line (ASCII character 10)
-    /// or at the end of the file.
-    ///
-    /// Called with a file descriptor.
-    pub(crate) eoln: Func,
-    pub(crate) erstat: Func,
-    pub(crate) get: Func,
-    /// Return the current day. This value is hard-coded to allow for WASM compilation.
-    pub(crate) get_current_day: Func,
-    /// Return the current minutes. This value is hard-coded to allow for WASM compilation.
-    pub(crate) get_current_minutes: Func,
-    /// Return the current month. This value is hard-coded to allow for WASM compilation.
-    pub(crate) get_current_month: Func,
-    /// Return the current year. This value is hard-coded to allow for WASM compilation.
-    pub(crate) get_current_year: Func,
-    /// Recreation of TeX's `input_ln` function. However, global variables are passed in as arguments.
-    pub(crate) input_ln: Func,
-    pub(crate) print_char: Func,
-    pub(crate) print_integer: Func,
-    pub(crate) print_newline: Func,
-    /// Print a string stored in memory to the file pointed to by the file descriptor.
-    /// The strings are stored in TeX's internal memory format:
-    ///  - `pointer` points to the first byte which is the length of the string.
-    ///  - The string is stored in the next `length` bytes.
-    pub(crate) print_string: Func,
-    /// Open a file for reading. Mimics Pascal's `reset` function.
-    ///
-    /// Called with a reference to a file name. Returns a file descriptor.
-    pub(crate) reset: Func,
-    /// Open a file for writing. Mimics Pascal's `rewrite` function.
-    ///
-    /// Called with a reference to a file name. Returns a file descriptor.
-    pub(crate) rewrite: Func,
-    pub(crate) tex_final_end: Func,
-    pub(crate) put: Func,
+/// The data that the system calls made by TeX have access to.
+#[derive(Debug, Clone)]
+pub(crate) struct HostState {
+    pub(crate) vfs: VirtualFileSystem,
+    /// If set, TeX is paused after this many system calls so that it can be run in slices.
+    pub(crate) slice_len: Option<u32>,
+    /// The number of system calls made since TeX was last paused.
+    calls_in_slice: u32,
+    /// The date and time TeX reads when it starts.
+    pub(crate) date: TexDate,
+}
+
+impl HostState {
+    pub(crate) fn new(vfs: VirtualFileSystem) -> Self {
+        Self {
+            vfs,
+            slice_len: None,
+            calls_in_slice: 0,
+            date: TexDate::from_unix_time(0),
+        }
+    }
+
+    /// Record that a system call was made and return whether the current slice is used up.
+    fn slice_is_used_up(&mut self) -> bool {
+        let Some(slice_len) = self.slice_len else {
+            return false;
+        };
+        self.calls_in_slice += 1;
+        if self.calls_in_slice >= slice_len {
+            self.calls_in_slice = 0;
+            return true;
+        }
+        false
+    }
+}
+
+/// The date and time that TeX typesets with `\today`, `\year`, `\month`, `\day` and `\time`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TexDate {
+    year: i32,
+    month: i32,
+    day: i32,
+    /// Minutes since midnight.
+    minutes: i32,
+}
+
+impl TexDate {
+    /// The date and time in UTC `seconds` after the Unix epoch.
+    pub(crate) fn from_unix_time(seconds: i64) -> TexDate {
+        let days = seconds.div_euclid(86_400);
+        let minutes = (seconds.rem_euclid(86_400) / 60) as i32;
+        // The proleptic Gregorian calendar, counted in 400 year eras starting on 1 March so that
+        // leap days come at the end of a year. See Howard Hinnant's `civil_from_days`.
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        TexDate {
+            year: year as i32,
+            month: month as i32,
+            day: day as i32,
+            minutes,
+        }
+    }
+}
+
+/// Returned as an error from a system call to pause TeX. The call can be resumed with
+/// `result` as the return value of the system call.
+#[derive(Debug)]
+pub(crate) struct Pause {
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    pub(crate) result: Option<i32>,
+}
+
+impl fmt::Display for Pause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TeX was paused")
+    }
+}
+
+impl wasmi::core::HostError for Pause {}
+
+/// Finish a system call that does not return anything, pausing TeX if the current slice
+/// is used up.
+fn finish_call(caller: &mut Caller<HostState>) -> Result<(), Error> {
+    if caller.data_mut().slice_is_used_up() {
+        return Err(Error::host(Pause { result: None }));
+    }
+    Ok(())
+}
+
+/// Finish a system call that returns `result`, pausing TeX if the current slice is used up.
+fn finish_call_with(caller: &mut Caller<HostState>, result: i32) -> Result<i32, Error> {
+    if caller.data_mut().slice_is_used_up() {
+        return Err(Error::host(Pause {
+            result: Some(result),
+        }));
+    }
+    Ok(result)
 }
 
 /// Read a specified number of bytes from the memory at the specified pointer.
 fn read_memory(memory: &Memory, ctx: &impl AsContext, pointer: usize, length: u32) -> Vec<u8> {
     let mut buffer = vec![0u8; length as usize];
-    let mut buf = [0u8; 1];
-    for i in 0..(length as usize) {
-        memory.read(ctx, pointer + i, &mut buf).unwrap();
-        buffer[i as usize] = buf[0];
-    }
+    memory.read(ctx, pointer, &mut buffer).unwrap();
     buffer
 }
 
-impl TexJaxImports {
-    pub(crate) fn new(store: &mut Store<VirtualFileSystem>) -> Self {
-        Self {
-            close: Func::wrap(&mut *store, |_fd: i32| {
-                // We don't need to close files, so this is a no-op.
-            }),
-            eof: Func::wrap(&mut *store, |mut caller: Caller<_>, fd: i32| -> i32 {
-                let vfs: &mut VirtualFileSystem = caller.data_mut();
-                let fp = vfs.get_file_pointer_by_index(fd).unwrap();
-                //println!("[eof] {:?}: {}", fp.file, vfs.file_pointer_at_eof(fp));
-                vfs.file_pointer_at_eof(fp) as i32
-            }),
-            eoln: Func::wrap(&mut *store, |mut caller: Caller<_>, fd: i32| -> i32 {
-                let vfs: &mut VirtualFileSystem = caller.data_mut();
-                let fp = vfs.get_file_pointer_by_index(fd).unwrap();
-                //println!("[eoln] {:?}: {}", fp.file, vfs.file_pointer_at_eoln(fp));
-                vfs.file_pointer_at_eoln(fp) as i32
-            }),
-            erstat: Func::wrap(&mut *store, |mut caller: Caller<_>, fd: i32| -> i32 {
-                let vfs: &mut VirtualFileSystem = caller.data_mut();
-                let fp = vfs.get_file_pointer_by_index(fd).unwrap();
-                //println!("[erstat] {} {} {:?}", fd, fp.erstat, fp.file);
-                fp.erstat
-            }),
-            get: Func::wrap(
-                &mut *store,
-                |mut caller: Caller<_>, fd: i32, pointer: u32, length: u32| {
-                    let mem = caller.get_export("0").unwrap().into_memory().unwrap();
-
-                    let file_contents = {
-                        let vfs: &mut VirtualFileSystem = caller.data_mut();
-                        vfs.read_from_file_by_index(fd, length as usize, ReadMode::Bytes)
-                    };
-                    if file_contents.len() == 0 {
-                        mem.write(&mut caller, pointer as usize, &[0])
-                            .expect("Failed to write to memory");
-                    } else {
-                        mem.write(&mut caller, pointer as usize, &file_contents)
-                            .expect("Failed to write to memory");
-                    }
-
-                    //let fp = {
-                    //    let vfs: &mut VirtualFileSystem = caller.data_mut();
-                    //    vfs.get_file_pointer_by_index(fd).unwrap()
-                    //}
-                    //.clone();
-                    //println!(
-                    //    "[get] {} {} {} contents: {:?} fp at end {:?}",
-                    //    fd, pointer, length, &file_contents, fp
-                    //);
-                },
-            ),
-            get_current_day: Func::wrap(&mut *store, || -> i32 {
-                //println!("[get_current_day]");
-                1
-            }),
-            get_current_minutes: Func::wrap(&mut *store, || -> i32 {
-                //println!("[get_current_minutes]");
-                0
-            }),
-            get_current_month: Func::wrap(&mut *store, || -> i32 {
-                //println!("[get_current_month]");
-                1
-            }),
-            get_current_year: Func::wrap(&mut *store, || -> i32 {
-                //println!("[get_current_year]");
-                1970
-            }),
-            input_ln: Func::wrap(
-                &mut *store,
-                |mut caller: Caller<_>,
-                 fd: i32,
-                 bypass_eoln: i32,
-                 buf_pointer: u32,
-                 first_pointer: u32,
-                 last_pointer: u32,
-                 _max_buf_stack_pointer: u32,
-                 _buf_size: u32|
-                 -> i32 {
-                    //println!(
-                    //    "[input_ln] {} {} {} {} {} {} {}",
-                    //    fd,
-                    //    bypass_eoln,
-                    //    buf_pointer,
-                    //    first_pointer,
-                    //    last_pointer,
-                    //    max_buf_stack_pointer,
-                    //    buf_size,
-                    //);
-                    let mem = caller.get_export("0").unwrap().into_memory().unwrap();
-                    // Get the u32 stored in the `first_pointer` memory location.
-                    let get_first = |caller: &Caller<VirtualFileSystem>| {
-                        let first =
-                            u8_to_u32(&read_memory(&mem, caller, first_pointer as usize, 4));
-                        first
-                    };
-                    // Get the u32 stored in the `first_pointer` memory location.
-                    //let get_last = |caller: &Caller<VirtualFileSystem>| {
-                    //    let last = u8_to_u32(&read_memory(&mem, caller, last_pointer as usize, 4));
-                    //    last
-                    //};
-                    //// Set the u32 stored in the `first_pointer` memory location.
-                    //let set_first = |first: u32, caller: &mut Caller<VirtualFileSystem>| {
-                    //    mem.write(caller, first_pointer as usize, &first.to_ne_bytes())
-                    //        .expect("Failed to write to memory");
-                    //};
-                    // Set the u32 stored in the `last_pointer` memory location.
-                    let set_last = |last: u32, caller: &mut Caller<VirtualFileSystem>| {
-                        mem.write(caller, last_pointer as usize, &last.to_ne_bytes())
-                            .expect("Failed to write to memory");
-                    };
-
-                    // Get the byte at offset first_pointer and last_pointer from the memory
-                    let first = get_first(&caller);
-                    let last = first;
-                    // Default last_pointer to first_pointer in case we need to bail early.
-                    // cf. Matthew 19:30
-                    set_last(last, &mut caller);
-
-                    //{
-                    //    println!(
-                    //        "  [input_ln] first[0] = {}; last[0] = {}",
-                    //        get_first(&caller),
-                    //        get_last(&caller),
-                    //    );
-                    //}
-                    //{
-                    //    let vfs: &mut VirtualFileSystem = caller.data_mut();
-                    //    let fp = vfs.get_file_pointer_by_index(fd).unwrap();
-                    //    println!("  [input_ln] {:?}", fp);
-                    //}
-
-                    let vfs: &mut VirtualFileSystem = caller.data_mut();
-                    if bypass_eoln != false as i32 {
-                        vfs.skip_current_newline_by_index(fd);
-                    }
-
-                    if let Some(mut input_line) = vfs.read_line_by_index(fd) {
-                        // We have successfully read in a line of text.
-                        // The TeX algorithm says all spaces at the end of the line are to be ignored.
-                        while let Some(&b' ') = input_line.last() {
-                            input_line.pop();
-                        }
-                        if input_line.len() > 0 {
-                            let _first = get_first(&caller);
-                            mem.write(
-                                &mut caller,
-                                (buf_pointer + _first as u32) as usize,
-                                &input_line,
-                            )
-                            .expect("Failed to write to memory");
-                            set_last(_first + input_line.len() as u32, &mut caller);
-                        }
-
-                        //{
-                        //    println!(
-                        //        "  [input_ln] input_line (directly read): {:?} {:?}",
-                        //        String::from_utf8_lossy(&input_line),
-                        //        &input_line,
-                        //    );
-                        //    println!(
-                        //        "  [input_ln] first[0] = {}; last[0] = {}",
-                        //        get_first(&caller),
-                        //        get_last(&caller),
-                        //    );
-                        //}
-
-                        true as i32
-                    } else {
-                        // We won't get a line of text if we are at the end of the file.
-                        false as i32
-                    }
-                },
-            ),
-            print_char: Func::wrap(&mut *store, |mut caller: Caller<_>, fd: i32, char: i32| {
-                let vfs: &mut VirtualFileSystem = caller.data_mut();
-                vfs.write_to_file_by_index(fd, &[char as u8]);
-            }),
-            print_integer: Func::wrap(&mut *store, |mut caller: Caller<_>, fd: i32, num: i32| {
-                let vfs: &mut VirtualFileSystem = caller.data_mut();
-                vfs.write_to_file_by_index(fd, num.to_string().as_bytes());
-            }),
-            print_newline: Func::wrap(&mut *store, |mut caller: Caller<_>, fd: i32| {
-                let vfs: &mut VirtualFileSystem = caller.data_mut();
-                //println!(
-                //    "[print_newline] {} {:?}",
-                //    fd,
-                //    vfs.get_file_pointer_by_index(fd).map(|fp| &fp.file)
-                //);
-
-                vfs.write_to_file_by_index(fd, b"\n");
-            }),
-            print_string: Func::wrap(
-                &mut *store,
-                |mut caller: Caller<_>, fd: i32, pointer: i32| {
-                    let mem = caller.get_export("0").unwrap().into_memory().unwrap();
-                    let str_len = *read_memory(&mem, &caller, pointer as usize, 1)
-                        .first()
-                        .unwrap();
-                    let string = String::from_utf8(read_memory(
-                        &mem,
-                        &caller,
-                        pointer as usize + 1,
-                        str_len as u32,
-                    ))
-                    .unwrap();
-
-                    //println!(
-                    //    "[print_string] {} {} {:?} {:?}",
-                    //    fd,
-                    //    pointer,
-                    //    (caller.data() as &VirtualFileSystem)
-                    //        .get_file_pointer_by_index(fd)
-                    //        .unwrap()
-                    //        .file,
-                    //    string
-                    //);
-
-                    // write to the correct file
-                    let vfs: &mut VirtualFileSystem = caller.data_mut();
-                    vfs.write_to_file_by_index(fd, string.as_bytes());
-                },
-            ),
-            put: Func::wrap(
-                &mut *store,
-                |mut caller: Caller<_>, fd: i32, pointer: u32, length: u32| {
-                    let mem = caller.get_export("0").unwrap().into_memory().unwrap();
-                    let mut buffer = vec![0u8; length as usize];
-                    mem.read(&caller, pointer as usize, &mut buffer)
-                        .expect("Failed to read memory before call to `put`");
-
-                    let vfs: &mut VirtualFileSystem = caller.data_mut();
-                    vfs.write_to_file_by_index(fd, &buffer);
-                },
-            ),
-            reset: Func::wrap(
-                &mut *store,
-                |mut caller: Caller<_>, length: u32, pointer: u32| -> i32 {
-                    let mem = caller.get_export("0").unwrap().into_memory().unwrap();
-                    let file_name = read_memory(&mem, &caller, pointer as usize, length);
-                    let file_name = String::from_utf8(file_name).unwrap();
-                    let file_name = clean_filename(&file_name);
-
-                    let file = match file_name {
-                        "TTY:" => FileType::Stdin,
-                        _ => FileType::Named(file_name),
-                    };
-
-                    //println!(
-                    //    "[reset] {length} {pointer} Requesting file '{file_name}' returned descriptor to {file:?}",
-                    //);
-
-                    let vfs: &mut VirtualFileSystem = caller.data_mut();
-                    vfs.get_file_descriptor(file, true) as i32
-                },
-            ),
-            rewrite: Func::wrap(
-                &mut *store,
-                |mut caller: Caller<_>, length: u32, pointer: u32| -> u32 {
-                    let mem = caller.get_export("0").unwrap().into_memory().unwrap();
-                    let file_name = read_memory(&mem, &caller, pointer as usize, length);
-                    let file_name = String::from_utf8(file_name).unwrap();
-                    let file_name = clean_filename(&file_name);
-
-                    let file = match file_name {
-                        "TTY:" => FileType::Stdout,
-                        _ => FileType::Named(file_name),
-                    };
-
-                    //println!(
-                    //    "[rewrite] Requesting file '{file_name}' returned descriptor to {file:?}"
-                    //);
-
-                    let vfs: &mut VirtualFileSystem = caller.data_mut();
-                    vfs.get_file_descriptor(file, false) as u32
-                },
-            ),
-            tex_final_end: Func::wrap(&mut *store, || {
-                // This is a no-op since we have no need to finalize anything.
-            }),
-        }
-    }
+/// Define all of the functions that are imported by the TeXJax WebAssembly module on `linker`.
+/// These are created to mirror `library.js` from the original TeXJax project.
+///
+/// Many of these functions mimic their PASCAL equivalents (as used by the original TeX engine).
+///
+/// The functions are not tied to a particular `Store`, so a single `Linker` can be used to
+/// instantiate the TeX engine many times.
+pub(crate) fn define_imports(linker: &mut Linker<HostState>) -> Result<(), Error> {
+    // Close a file. Mimics Pascal's `close` function.
+    //
+    // Called with a file descriptor.
+    linker.func_wrap("library", "close", |_fd: i32| {
+        // We don't need to close files, so this is a no-op.
+    })?;
+
+    // Mimics Pascal's `eof` function. Returns true if the current file pointer is at the end of the file.
+    //
+    // Called with a file descriptor.
+    linker.func_wrap(
+        "library",
+        "eof",
+        |mut caller: Caller<HostState>, fd: i32| -> i32 {
+            let vfs = &mut caller.data_mut().vfs;
+            let fp = vfs.get_file_pointer_by_index(fd).unwrap();
+            //println!("[eof] {:?}: {}", fp.file, vfs.file_pointer_at_eof(fp));
+            vfs.file_pointer_at_eof(fp) as i32
+        },
+    )?;
+
+    // Mimics Pascal's `eoln` function. Returns true if the current file pointer is at the end of the line (ASCII character 10)
+    // or at the end of the file.
+    //
+    // Called with a file descriptor.
+    linker.func_wrap(
+        "library",
+        "eoln",
+        |mut caller: Caller<HostState>, fd: i32| -> i32 {
+            let vfs = &mut caller.data_mut().vfs;
+            let fp = vfs.get_file_pointer_by_index(fd).unwrap();
+            //println!("[eoln] {:?}: {}", fp.file, vfs.file_pointer_at_eoln(fp));
+            vfs.file_pointer_at_eoln(fp) as i32
+        },
+    )?;
+
+    linker.func_wrap(
+        "library",
+        "erstat",
+        |mut caller: Caller<HostState>, fd: i32| -> i32 {
+            let vfs = &mut caller.data_mut().vfs;
+            let fp = vfs.get_file_pointer_by_index(fd).unwrap();
+            //println!("[erstat] {} {} {:?}", fd, fp.erstat, fp.file);
+            fp.erstat
+        },
+    )?;
+
+    linker.func_wrap(
+        "library",
+        "get",
+        |mut caller: Caller<HostState>, fd: i32, pointer: u32, length: u32| -> Result<(), Error> {
+            let mem = caller.get_export("0").unwrap().into_memory().unwrap();
+
+            let file_contents = {
+                let vfs = &mut caller.data_mut().vfs;
+                vfs.read_from_file_by_index(fd, length as usize, ReadMode::Bytes)
+            };
+            if file_contents.is_empty() {
+                mem.write(&mut caller, pointer as usize, &[0])
+                    .expect("Failed to write to memory");
+            } else {
+                mem.write(&mut caller, pointer as usize, &file_contents)
+                    .expect("Failed to write to memory");
+            }
+
+            //let fp = {
+            //    let vfs = &mut caller.data_mut().vfs;
+            //    vfs.get_file_pointer_by_index(fd).unwrap()
+            //}
+            //.clone();
+            //println!(
+            //    "[get] {} {} {} contents: {:?} fp at end {:?}",
+            //    fd, pointer, length, &file_contents, fp
+            //);
+
+            finish_call(&mut caller)
+        },
+    )?;
+
+    // Return the current day. The date is the runner's `source_date_epoch` rather than the
+    // system clock, so that the output does not depend on when it was rendered.
+    linker.func_wrap(
+        "library",
+        "getCurrentDay",
+        |caller: Caller<HostState>| -> i32 { caller.data().date.day },
+    )?;
+
+    // Return the current minutes since midnight.
+    linker.func_wrap(
+        "library",
+        "getCurrentMinutes",
+        |caller: Caller<HostState>| -> i32 { caller.data().date.minutes },
+    )?;
+
+    // Return the current month.
+    linker.func_wrap(
+        "library",
+        "getCurrentMonth",
+        |caller: Caller<HostState>| -> i32 { caller.data().date.month },
+    )?;
+
+    // Return the current year.
+    linker.func_wrap(
+        "library",
+        "getCurrentYear",
+        |caller: Caller<HostState>| -> i32 { caller.data().date.year },
+    )?;
+
+    // Recreation of TeX's `input_ln` function. However, global variables are passed in as arguments.
+    linker.func_wrap(
+        "library",
+        "inputln",
+        |mut caller: Caller<HostState>,
+         fd: i32,
+         bypass_eoln: i32,
+         buf_pointer: u32,
+         first_pointer: u32,
+         last_pointer: u32,
+         _max_buf_stack_pointer: u32,
+         _buf_size: u32|
+         -> Result<i32, Error> {
+            //println!(
+            //    "[input_ln] {} {} {} {} {} {} {}",
+            //    fd,
+            //    bypass_eoln,
+            //    buf_pointer,
+            //    first_pointer,
+            //    last_pointer,
+            //    max_buf_stack_pointer,
+            //    buf_size,
+            //);
+            let mem = caller.get_export("0").unwrap().into_memory().unwrap();
+            // Get the u32 stored in the `first_pointer` memory location.
+            let get_first = |caller: &Caller<HostState>| {
+                u8_to_u32(&read_memory(&mem, caller, first_pointer as usize, 4))
+            };
+            // Get the u32 stored in the `first_pointer` memory location.
+            //let get_last = |caller: &Caller<HostState>| {
+            //    let last = u8_to_u32(&read_memory(&mem, caller, last_pointer as usize, 4));
+            //    last
+            //};
+            //// Set the u32 stored in the `first_pointer` memory location.
+            //let set_first = |first: u32, caller: &mut Caller<HostState>| {
+            //    mem.write(caller, first_pointer as usize, &first.to_ne_bytes())
+            //        .expect("Failed to write to memory");
+            //};
+            // Set the u32 stored in the `last_pointer` memory location.
+            let set_last = |last: u32, caller: &mut Caller<HostState>| {
+                mem.write(caller, last_pointer as usize, &last.to_ne_bytes())
+                    .expect("Failed to write to memory");
+            };
+
+            // Get the byte at offset first_pointer and last_pointer from the memory
+            let first = get_first(&caller);
+            let last = first;
+            // Default last_pointer to first_pointer in case we need to bail early.
+            // cf. Matthew 19:30
+            set_last(last, &mut caller);
+
+            //{
+            //    println!(
+            //        "  [input_ln] first[0] = {}; last[0] = {}",
+            //        get_first(&caller),
+            //        get_last(&caller),
+            //    );
+            //}
+            //{
+            //    let vfs = &mut caller.data_mut().vfs;
+            //    let fp = vfs.get_file_pointer_by_index(fd).unwrap();
+            //    println!("  [input_ln] {:?}", fp);
+            //}
+
+            let vfs = &mut caller.data_mut().vfs;
+            if bypass_eoln != false as i32 {
+                vfs.skip_current_newline_by_index(fd);
+            }
+
+            let result = if let Some(mut input_line) = vfs.read_line_by_index(fd) {
+                // We have successfully read in a line of text.
+                // The TeX algorithm says all spaces at the end of the line are to be ignored.
+                while let Some(&b' ') = input_line.last() {
+                    input_line.pop();
+                }
+                if !input_line.is_empty() {
+                    let _first = get_first(&caller);
+                    mem.write(&mut caller, (buf_pointer + _first) as usize, &input_line)
+                        .expect("Failed to write to memory");
+                    set_last(_first + input_line.len() as u32, &mut caller);
+                }
+
+                //{
+                //    println!(
+                //        "  [input_ln] input_line (directly read): {:?} {:?}",
+                //        String::from_utf8_lossy(&input_line),
+                //        &input_line,
+                //    );
+                //    println!(
+                //        "  [input_ln] first[0] = {}; last[0] = {}",
+                //        get_first(&caller),
+                //        get_last(&caller),
+                //    );
+                //}
+
+                true as i32
+            } else {
+                // We won't get a line of text if we are at the end of the file.
+                false as i32
+            };
+
+            finish_call_with(&mut caller, result)
+        },
+    )?;
+
+    linker.func_wrap(
+        "library",
+        "printChar",
+        |mut caller: Caller<HostState>, fd: i32, char: i32| -> Result<(), Error> {
+            let vfs = &mut caller.data_mut().vfs;
+            vfs.write_to_file_by_index(fd, &[char as u8]);
+            finish_call(&mut caller)
+        },
+    )?;
+
+    linker.func_wrap(
+        "library",
+        "printInteger",
+        |mut caller: Caller<HostState>, fd: i32, num: i32| -> Result<(), Error> {
+            let vfs = &mut caller.data_mut().vfs;
+            vfs.write_to_file_by_index(fd, num.to_string().as_bytes());
+            finish_call(&mut caller)
+        },
+    )?;
+
+    linker.func_wrap(
+        "library",
+        "printNewline",
+        |mut caller: Caller<HostState>, fd: i32| -> Result<(), Error> {
+            let vfs = &mut caller.data_mut().vfs;
+            //println!(
+            //    "[print_newline] {} {:?}",
+            //    fd,
+            //    vfs.get_file_pointer_by_index(fd).map(|fp| &fp.file)
+            //);
+
+            vfs.write_to_file_by_index(fd, b"\n");
+            finish_call(&mut caller)
+        },
+    )?;
+
+    // Print a string stored in memory to the file pointed to by the file descriptor.
+    // The strings are stored in TeX's internal memory format:
+    //  - `pointer` points to the first byte which is the length of the string.
+    //  - The string is stored in the next `length` bytes.
+    linker.func_wrap(
+        "library",
+        "printString",
+        |mut caller: Caller<HostState>, fd: i32, pointer: i32| -> Result<(), Error> {
+            let mem = caller.get_export("0").unwrap().into_memory().unwrap();
+            let str_len = *read_memory(&mem, &caller, pointer as usize, 1)
+                .first()
+                .unwrap();
+            let string = String::from_utf8(read_memory(
+                &mem,
+                &caller,
+                pointer as usize + 1,
+                str_len as u32,
+            ))
+            .unwrap();
+
+            //println!(
+            //    "[print_string] {} {} {:?} {:?}",
+            //    fd,
+            //    pointer,
+            //    caller.data().vfs
+            //        .get_file_pointer_by_index(fd)
+            //        .unwrap()
+            //        .file,
+            //    string
+            //);
+
+            // write to the correct file
+            let vfs = &mut caller.data_mut().vfs;
+            vfs.write_to_file_by_index(fd, string.as_bytes());
+            finish_call(&mut caller)
+        },
+    )?;
+
+    linker.func_wrap(
+        "library",
+        "put",
+        |mut caller: Caller<HostState>, fd: i32, pointer: u32, length: u32| -> Result<(), Error> {
+            let mem = caller.get_export("0").unwrap().into_memory().unwrap();
+            let mut buffer = vec![0u8; length as usize];
+            mem.read(&caller, pointer as usize, &mut buffer)
+                .expect("Failed to read memory before call to `put`");
+
+            let vfs = &mut caller.data_mut().vfs;
+            vfs.write_to_file_by_index(fd, &buffer);
+            finish_call(&mut caller)
+        },
+    )?;
+
+    // Open a file for reading. Mimics Pascal's `reset` function.
+    //
+    // Called with a reference to a file name. Returns a file descriptor.
+    linker.func_wrap(
+        "library",
+        "reset",
+        |mut caller: Caller<HostState>, length: u32, pointer: u32| -> i32 {
+            let mem = caller.get_export("0").unwrap().into_memory().unwrap();
+            let file_name = read_memory(&mem, &caller, pointer as usize, length);
+            let file_name = String::from_utf8(file_name).unwrap();
+            let file_name = clean_filename(&file_name);
+
+            let file = match file_name {
+                "TTY:" => FileType::Stdin,
+                _ => FileType::Named(file_name),
+            };
+
+            //println!(
+            //    "[reset] {length} {pointer} Requesting file '{file_name}' returned descriptor to {file:?}",
+            //);
+
+            let vfs = &mut caller.data_mut().vfs;
+            vfs.get_file_descriptor(file, true) as i32
+        },
+    )?;
+
+    // Open a file for writing. Mimics Pascal's `rewrite` function.
+    //
+    // Called with a reference to a file name. Returns a file descriptor.
+    linker.func_wrap(
+        "library",
+        "rewrite",
+        |mut caller: Caller<HostState>, length: u32, pointer: u32| -> u32 {
+            let mem = caller.get_export("0").unwrap().into_memory().unwrap();
+            let file_name = read_memory(&mem, &caller, pointer as usize, length);
+            let file_name = String::from_utf8(file_name).unwrap();
+            let file_name = clean_filename(&file_name);
+
+            let file = match file_name {
+                "TTY:" => FileType::Stdout,
+                _ => FileType::Named(file_name),
+            };
+
+            //println!(
+            //    "[rewrite] Requesting file '{file_name}' returned descriptor to {file:?}"
+            //);
+
+            // Like Pascal's `rewrite`, opening a file for writing empties it, so that nothing of
+            // an earlier run is left at its end.
+            let vfs = &mut caller.data_mut().vfs;
+            let fd = vfs.get_file_descriptor(file.clone(), false);
+            vfs.set_file_contents(file, &[]);
+            fd as u32
+        },
+    )?;
+
+    linker.func_wrap("library", "tex_final_end", || {
+        // This is a no-op since we have no need to finalize anything.
+    })?;
+
+    Ok(())
 }
 
 /// Cleans up a file name by
@@ -398,6 +546,46 @@ fn clean_filename(file_name: &str) -> &str {
 /// Convert a slice of 4 bytes into a u32.
 fn u8_to_u32(bytes: &[u8]) -> u32 {
     let mut buf = [0u8; 4];
-    buf.copy_from_slice(&bytes);
+    buf.copy_from_slice(bytes);
     u32::from_ne_bytes(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: i32, day: i32, minutes: i32) -> TexDate {
+        TexDate {
+            year,
+            month,
+            day,
+            minutes,
+        }
+    }
+
+    #[test]
+    fn unix_times_are_converted_to_utc_dates() {
+        assert_eq!(TexDate::from_unix_time(0), date(1970, 1, 1, 0));
+        // 29 February 2024, 12:00 and 23:59:59.
+        assert_eq!(
+            TexDate::from_unix_time(1_709_208_000),
+            date(2024, 2, 29, 720)
+        );
+        assert_eq!(
+            TexDate::from_unix_time(1_709_251_199),
+            date(2024, 2, 29, 1439)
+        );
+        // 29 February 2000, in a year divisible by 400, and the last second of 2024.
+        assert_eq!(TexDate::from_unix_time(951_782_400), date(2000, 2, 29, 0));
+        assert_eq!(
+            TexDate::from_unix_time(1_735_689_599),
+            date(2024, 12, 31, 1439)
+        );
+    }
+
+    #[test]
+    fn times_before_the_epoch_count_back_from_midnight() {
+        assert_eq!(TexDate::from_unix_time(-1), date(1969, 12, 31, 1439));
+        assert_eq!(TexDate::from_unix_time(-86_400), date(1969, 12, 31, 0));
+    }
+}
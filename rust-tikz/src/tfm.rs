@@ -0,0 +1,215 @@
+//! Font metrics. The widths of glyphs come from the `.tfm` file of their font, which is read
+//! from the same files TeX read, so fonts that come with a package have the metrics TeX used.
+//! Fonts without a `.tfm` file fall back to the metrics of the Computer Modern and AMS fonts
+//! that are compiled into `dvi2html`.
+//!
+//! The format is described in the documentation of `TFtype`.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::{Error, Result};
+pub(crate) use dvi2html::tfm::FontDataHelper;
+
+static COMPILED_IN_FONTS: OnceLock<FontDataHelper> = OnceLock::new();
+
+/// The metrics that are compiled into `dvi2html`. They are decompressed the first time they
+/// are needed and then shared by every [`crate::dvi::Interpreter`].
+pub(crate) fn compiled_in_fonts() -> Result<&'static FontDataHelper> {
+    if let Some(helper) = COMPILED_IN_FONTS.get() {
+        return Ok(helper);
+    }
+    let helper = FontDataHelper::init()
+        .map_err(|e| Error::msg(format!("Cannot load font metrics: {e:?}")))?;
+    Ok(COMPILED_IN_FONTS.get_or_init(|| helper))
+}
+
+/// The metrics of the characters of a font, as `fix_word`s relative to its design size.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FontMetrics {
+    pub characters: HashMap<u32, CharacterMetrics>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CharacterMetrics {
+    pub width: i32,
+    pub height: i32,
+    pub depth: i32,
+}
+
+impl FontMetrics {
+    /// Parse a `.tfm` file.
+    pub fn parse(bytes: &[u8]) -> Result<FontMetrics> {
+        let word = |index: usize| -> Result<[u8; 4]> {
+            let start = index * 4;
+            bytes
+                .get(start..start + 4)
+                .map(|word| [word[0], word[1], word[2], word[3]])
+                .ok_or(Error::msg("The file is truncated"))
+        };
+        let half_word = |index: usize| -> Result<usize> {
+            let start = index * 2;
+            bytes
+                .get(start..start + 2)
+                .map(|half_word| usize::from(u16::from_be_bytes([half_word[0], half_word[1]])))
+                .ok_or(Error::msg("The file is truncated"))
+        };
+        // The file starts with the lengths of its sections in words, and the range of codes.
+        let header_length = half_word(1)?;
+        let first_code = half_word(2)?;
+        let last_code = half_word(3)?;
+        let widths = half_word(4)?;
+        let heights = half_word(5)?;
+        let depths = half_word(6)?;
+        let char_info = 6 + header_length;
+        let width_table = char_info + (last_code + 1).saturating_sub(first_code);
+        let height_table = width_table + widths;
+        let depth_table = height_table + heights;
+        let fix_word = |table: usize, length: usize, index: usize| -> Result<i32> {
+            if index >= length {
+                return Err(Error::msg(format!(
+                    "Index {index} is outside of a table of {length} entries"
+                )));
+            }
+            Ok(i32::from_be_bytes(word(table + index)?))
+        };
+
+        let mut characters = HashMap::new();
+        for code in first_code..=last_code {
+            let [width_index, height_and_depth, _, _] = word(char_info + code - first_code)?;
+            // Codes that are not in the font have a width index of zero.
+            if width_index == 0 {
+                continue;
+            }
+            let metrics = CharacterMetrics {
+                width: fix_word(width_table, widths, width_index.into())?,
+                height: fix_word(height_table, heights, (height_and_depth >> 4).into())?,
+                depth: fix_word(depth_table, depths, (height_and_depth & 0xf).into())?,
+            };
+            characters.insert(code as u32, metrics);
+        }
+        Ok(FontMetrics { characters })
+    }
+
+    /// The compiled-in metrics of the font `name`, if there are any.
+    pub fn compiled_in(helper: &FontDataHelper, name: &str) -> Option<FontMetrics> {
+        let data = helper.get(name.to_string())?;
+        let characters = data
+            .characters
+            .iter()
+            .map(|(&code, metrics)| {
+                // `dvi2html` stores the `fix_word`s as `u32`, but they are signed.
+                let metrics = CharacterMetrics {
+                    width: metrics.width as i32,
+                    height: metrics.height as i32,
+                    depth: metrics.depth as i32,
+                };
+                (code, metrics)
+            })
+            .collect();
+        Some(FontMetrics { characters })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `.tfm` file with the codes `first_code..=last_code`, whose `char_info` words give the
+    /// width index and the height and depth indices of each code.
+    fn tfm(
+        first_code: u16,
+        last_code: u16,
+        char_info: &[(u8, u8, u8)],
+        widths: &[i32],
+        heights: &[i32],
+        depths: &[i32],
+    ) -> Vec<u8> {
+        let header = [0u32; 2];
+        let lengths = [
+            0,
+            header.len() as u16,
+            first_code,
+            last_code,
+            widths.len() as u16,
+            heights.len() as u16,
+            depths.len() as u16,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let mut bytes: Vec<u8> = lengths.iter().flat_map(|n| n.to_be_bytes()).collect();
+        bytes.extend(header.iter().flat_map(|word| word.to_be_bytes()));
+        for &(width, height, depth) in char_info {
+            bytes.extend([width, height << 4 | depth, 0, 0]);
+        }
+        for fix_word in widths.iter().chain(heights).chain(depths) {
+            bytes.extend(fix_word.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_the_metrics_of_each_code() {
+        // The code 66 is not in the font.
+        let bytes = tfm(
+            65,
+            67,
+            &[(1, 1, 0), (0, 0, 0), (2, 2, 1)],
+            &[0, 0x8_0000, -0x4_0000],
+            &[0, 0x10_0000, 0x2_0000],
+            &[0, 0x1_0000],
+        );
+        let metrics = FontMetrics::parse(&bytes).unwrap();
+        assert_eq!(metrics.characters.len(), 2);
+        let a = metrics.characters[&65];
+        assert_eq!((a.width, a.height, a.depth), (0x8_0000, 0x10_0000, 0));
+        assert!(!metrics.characters.contains_key(&66));
+        let c = metrics.characters[&67];
+        assert_eq!(
+            (c.width, c.height, c.depth),
+            (-0x4_0000, 0x2_0000, 0x1_0000)
+        );
+    }
+
+    #[test]
+    fn a_font_without_codes_has_no_metrics() {
+        // An empty font has `bc = ec + 1`, but a larger `bc` must not fail either.
+        for (first_code, last_code) in [(1, 0), (10, 2)] {
+            let bytes = tfm(first_code, last_code, &[], &[0], &[0], &[0]);
+            let metrics = FontMetrics::parse(&bytes).unwrap();
+            assert!(metrics.characters.is_empty());
+        }
+    }
+
+    #[test]
+    fn compiled_in_metrics_are_fix_words() {
+        let helper = compiled_in_fonts().unwrap();
+        let metrics = FontMetrics::compiled_in(helper, "cmr10").unwrap();
+        // In cmr10, `a` is 5.00002pt wide, about half of the design size.
+        assert_eq!(metrics.characters[&97].width, 524_290);
+        assert!(FontMetrics::compiled_in(helper, "nofont").is_none());
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = tfm(65, 65, &[(1, 1, 1)], &[0, 1], &[0, 2], &[0, 3]);
+        assert!(FontMetrics::parse(&bytes).is_ok());
+        for length in [0, 3, 12, 30, bytes.len() - 1] {
+            assert!(
+                FontMetrics::parse(&bytes[..length]).is_err(),
+                "{length} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn indices_outside_of_the_tables_are_errors() {
+        for char_info in [(2, 1, 1), (1, 2, 1), (1, 1, 2)] {
+            let bytes = tfm(65, 65, &[char_info], &[0, 1], &[0, 2], &[0, 3]);
+            assert!(FontMetrics::parse(&bytes).is_err(), "{char_info:?}");
+        }
+    }
+}
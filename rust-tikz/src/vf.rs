@@ -0,0 +1,138 @@
+//! Virtual fonts. A virtual font (a `.vf` file) describes each of its characters with a short
+//! DVI program, its packet, that sets characters from other fonts, rules and specials. T1
+//! encoded fonts, for example, build accented letters out of a letter and an accent.
+//!
+//! The format is described in the documentation of `VFtype`. Lengths in a virtual font are
+//! `fix_word`s relative to the size the virtual font is loaded at, like TFM metrics.
+
+use std::collections::HashMap;
+
+use ::dvi::{FontDef, Instruction};
+use anyhow::{Error, Result};
+
+const SHORT_CHAR_MAX: u8 = 241;
+const LONG_CHAR: u8 = 242;
+const FNT_DEF1: u8 = 243;
+const FNT_DEF4: u8 = 246;
+const PRE: u8 = 247;
+const POST: u8 = 248;
+const VF_ID: u8 = 202;
+
+/// The contents of a `.vf` file.
+#[derive(Debug, Clone)]
+pub(crate) struct VirtualFont {
+    /// The fonts the packets use, in the order they are defined.
+    pub fonts: Vec<LocalFont>,
+    pub characters: HashMap<u32, VirtualCharacter>,
+}
+
+/// A font that the packets of a virtual font use.
+#[derive(Debug, Clone)]
+pub(crate) struct LocalFont {
+    /// The number the packets select the font with.
+    pub number: u32,
+    pub name: String,
+    /// The size the font is loaded at, as a `fix_word`.
+    pub scale_factor: i32,
+    /// The design size of the font in units of 2^-20 points.
+    pub design_size: u32,
+}
+
+/// A character of a virtual font.
+#[derive(Debug, Clone)]
+pub(crate) struct VirtualCharacter {
+    /// The width the character advances by, as a `fix_word`.
+    pub width: i32,
+    /// The DVI instructions that draw the character.
+    pub packet: Vec<u8>,
+}
+
+impl VirtualFont {
+    pub fn parse(bytes: &[u8]) -> Result<VirtualFont> {
+        let mut reader = Reader { bytes };
+        if reader.take(2)? != [PRE, VF_ID] {
+            return Err(Error::msg("Not a virtual font"));
+        }
+        let comment_length = reader.unsigned(1)? as usize;
+        // The comment, the checksum and the design size of the virtual font itself.
+        reader.take(comment_length + 8)?;
+
+        let mut font = VirtualFont {
+            fonts: Vec::new(),
+            characters: HashMap::new(),
+        };
+        loop {
+            let opcode = *reader.bytes.first().ok_or_else(truncated)?;
+            match opcode {
+                0..=SHORT_CHAR_MAX => {
+                    reader.take(1)?;
+                    let length = opcode as usize;
+                    let code = reader.unsigned(1)?;
+                    let width = reader.unsigned(3)? as i32;
+                    let packet = reader.take(length)?.to_vec();
+                    font.characters
+                        .insert(code, VirtualCharacter { width, packet });
+                }
+                LONG_CHAR => {
+                    reader.take(1)?;
+                    let length = reader.unsigned(4)? as usize;
+                    let code = reader.unsigned(4)?;
+                    let width = reader.unsigned(4)? as i32;
+                    let packet = reader.take(length)?.to_vec();
+                    font.characters
+                        .insert(code, VirtualCharacter { width, packet });
+                }
+                // Font definitions have the same format as in DVI files.
+                FNT_DEF1..=FNT_DEF4 => {
+                    let (rest, instruction) = Instruction::parse(reader.bytes)
+                        .map_err(|_| Error::msg("Cannot parse a font definition"))?;
+                    reader.bytes = rest;
+                    if let Instruction::FontDef(def) = instruction {
+                        font.fonts.push(LocalFont::new(&def));
+                    }
+                }
+                POST => return Ok(font),
+                _ => return Err(Error::msg(format!("Unexpected command {opcode}"))),
+            }
+        }
+    }
+}
+
+impl LocalFont {
+    fn new(def: &FontDef) -> LocalFont {
+        LocalFont {
+            number: def.number,
+            name: String::from_utf8_lossy(&def.filename).to_string(),
+            scale_factor: def.scale_factor as i32,
+            design_size: def.design_size,
+        }
+    }
+}
+
+fn truncated() -> Error {
+    Error::msg("The file is truncated")
+}
+
+/// A cursor over the bytes of a `.vf` file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if length > self.bytes.len() {
+            return Err(truncated());
+        }
+        let (head, tail) = self.bytes.split_at(length);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    /// Read a big-endian unsigned number of `length` bytes.
+    fn unsigned(&mut self, length: usize) -> Result<u32> {
+        Ok(self
+            .take(length)?
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | u32::from(byte)))
+    }
+}
@@ -1,88 +1,189 @@
 //! Functions to set up a WASM runtime to run the TeX engine as well as compile TeX source to SVG.
 
-use std::collections::HashMap;
-use std::io::Read;
+use std::sync::Arc;
 
-use crate::dvi2svg;
+use crate::dvi2svg::{SvgOptions, dvi2svg};
 use anyhow::Error;
 use anyhow::Result;
-use flate2::read::GzDecoder;
-use tar::Archive;
 use wasmi::*;
 
+use crate::archive::TexArchive;
 use crate::filesystem::*;
+use crate::font_files::FontFiles;
+use crate::logging::Logger;
 use crate::texjax_imports::*;
 
-const TEX_FILE_BYTES: &[u8] = include_bytes!("./assets/tex_files.tar.gz");
 const WASM_BYTES: &[u8] = include_bytes!("./assets/tex.wasm");
 const CORE_BYTES: &[u8] = include_bytes!("./assets/core.dump");
 
+/// What TeX does when it finds an error. These are TeX's `\batchmode`, `\nonstopmode`,
+/// `\scrollmode` and `\errorstopmode`. Nobody is at the terminal to answer TeX, so the run
+/// ends wherever TeX would wait for an answer that cannot be given.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Interaction {
+    /// Like [`Interaction::NonStop`], but TeX prints nothing to the terminal, so
+    /// [`WasmRunner::get_messages`] is empty and errors are only written to the log.
+    Batch,
+    /// TeX carries on after errors. The run ends if TeX needs input, e.g. for a missing file.
+    NonStop,
+    /// TeX carries on after errors, but asks for the name of a file it cannot find. Since
+    /// nobody answers, the run ends there.
+    ScrollStop,
+    /// TeX stops at the first error. Pages that were shipped out before the error are kept.
+    #[default]
+    ErrorStop,
+}
+
+impl Interaction {
+    /// Parse the name TeX gives the mode, e.g. `nonstopmode`.
+    pub fn parse(name: &str) -> Option<Interaction> {
+        match name {
+            "batchmode" => Some(Interaction::Batch),
+            "nonstopmode" => Some(Interaction::NonStop),
+            "scrollmode" => Some(Interaction::ScrollStop),
+            "errorstopmode" => Some(Interaction::ErrorStop),
+            _ => None,
+        }
+    }
+
+    /// The lines TeX reads from the terminal. The first line sets the mode and loads the
+    /// rust-tikz pgf driver before `input.tex`, so that TikZ draws with structured `rusttikz:`
+    /// specials rather than the raw SVG of the driver the format was built with.
+    fn terminal_input(self) -> String {
+        let (mode, answers) = match self {
+            Interaction::Batch => ("batchmode", ""),
+            Interaction::NonStop => ("nonstopmode", ""),
+            // `\end` finishes a document that ends without one.
+            Interaction::ScrollStop => ("scrollmode", "\\end\n"),
+            // `\end` is not an answer to an error, so TeX asks again and `X` makes it quit.
+            Interaction::ErrorStop => ("errorstopmode", "\\end\nX\n"),
+        };
+        format!(
+            "\\{mode}\\makeatletter\\input pgfsys-rusttikz.def \\makeatother\\input input.tex \n{answers}"
+        )
+    }
+}
+
+/// The compiled TeX engine. Validating and translating the TeX WASM is the most expensive part
+/// of creating a [`WasmRunner`], so a `TexEngine` does it once and can then spawn many runners
+/// cheaply with [`TexEngine::new_runner`]. Cloning a `TexEngine` is cheap and the clones share the
+/// same compiled module.
+///
+/// `wasmi` cannot serialize a translated module, so the translation cannot be cached between
+/// processes. If start-up time of the first runner matters, use [`TexEngine::with_config`] with
+/// lazy compilation enabled so that functions are only translated the first time TeX calls them.
+#[derive(Clone)]
+pub struct TexEngine {
+    engine: Engine,
+    module: Arc<Module>,
+    /// A linker with all TeX system calls defined. The TeX memory is defined separately for
+    /// each runner, since it belongs to a `Store`.
+    linker: Linker<HostState>,
+}
+
+impl TexEngine {
+    /// Compile the TeX engine with the default `wasmi` configuration.
+    pub fn new() -> Result<Self> {
+        Self::with_config(&Config::default())
+    }
+
+    /// Compile the TeX engine using the given `wasmi` configuration.
+    pub fn with_config(config: &Config) -> Result<Self> {
+        let engine = Engine::new(config);
+        let module = Module::new(&engine, WASM_BYTES)?;
+
+        // Create a linker and define all imports as coming from our rust library.
+        let mut linker = <Linker<HostState>>::new(&engine);
+        define_imports(&mut linker)?;
+
+        Ok(Self {
+            engine,
+            module: Arc::new(module),
+            linker,
+        })
+    }
+
+    /// Create a new [`WasmRunner`] with pre-loaded TeX core that uses this engine.
+    pub fn new_runner(&self) -> Result<WasmRunner> {
+        WasmRunner::with_engine(self)
+    }
+}
+
+/// The outcome of running one slice of TeX with [`WasmRunner::start_run`] or
+/// [`WasmRunner::resume_run`].
+#[cfg(feature = "async")]
+pub(crate) enum RunSlice {
+    Finished,
+    Paused(PausedRun),
+}
+
+/// A TeX run that has been paused part way through.
+#[cfg(feature = "async")]
+pub(crate) struct PausedRun {
+    invocation: TypedResumableInvocation<()>,
+    /// The return value of the system call that paused TeX.
+    result: Option<i32>,
+}
+
+/// The time that TeX takes as the current time unless told otherwise, in seconds since the Unix
+/// epoch. Following the reproducible builds convention, this is the value of the
+/// `SOURCE_DATE_EPOCH` environment variable, or the epoch itself (1 January 1970) if it is not
+/// set, so that rendering the same document always gives the same output. To typeset the real
+/// date, pass the current time as [`crate::RenderOptions::source_date_epoch`].
+pub fn default_source_date_epoch() -> i64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .unwrap_or(0)
+}
+
 /// Holds the TeX engine and initialized `wasmr` runtime. This object stubs out all
 /// of the system calls that the WASM-compiled TeX engine needs to run.
 pub struct WasmRunner {
-    store: Store<VirtualFileSystem>,
+    store: Store<HostState>,
     instance: Instance,
     /// Whether the TeX engine has run or not.
     has_run: bool,
 }
 
 impl WasmRunner {
-    /// Create a new WasmRunner with pre-loaded TeX core.
+    /// Create a new WasmRunner with pre-loaded TeX core. This compiles the TeX engine from
+    /// scratch; to create many runners, use [`TexEngine::new_runner`] instead.
     pub fn new() -> Result<Self> {
-        // We have an in-memory file structure populated with the files that tex needs to run.
-        // Extract these files to memory.
-        let mut extracted_files = extract_tar_gz_to_memory(TEX_FILE_BYTES)?;
+        TexEngine::new()?.new_runner()
+    }
+
+    fn with_engine(tex_engine: &TexEngine) -> Result<Self> {
+        // We have an in-memory file structure backed by the files that tex needs to run.
+        // Files are only decompressed from the bundled archive when TeX opens them.
+        let mut filesystem = VirtualFileSystem::new(TexArchive::bundled());
         // Add `input.tex` to the in-memory file structure.
         // This is the file that TeX will execute.
-        //extracted_files.insert("input.tex".to_string(), b"\n\\begin{document}\n\\begin{tikzpicture}\n\\draw (0,0) circle (1in);\n\\end{tikzpicture}\n\\color{blue}$x^2$\n\nfoo\\par This is very cool!\\end{document}".to_vec());
-        extracted_files.insert(
-            "input.tex".to_string(),
+        filesystem.add_file(
+            "input.tex",
             "\n\\begin{document}Hello World\\end{document}"
                 .as_bytes()
-                .to_vec(),
+                .into(),
         );
-        let mut filesystem = VirtualFileSystem::new(extracted_files);
-        filesystem.set_stdin(" input.tex \n\\end\n".as_bytes());
-
-        // First step is to create the Wasm execution engine with some config.
-        // In this example we are using the default configuration.
-        let engine = Engine::default();
-        let module = Module::new(&engine, WASM_BYTES)?;
+        filesystem.set_stdin(Interaction::default().terminal_input().as_bytes());
 
         // All Wasm objects operate within the context of a `Store`.
         // Each `Store` has a type parameter to store host-specific data.
-        type HostState = VirtualFileSystem;
-        let mut store = Store::new(&engine, filesystem);
+        let mut host_state = HostState::new(filesystem);
+        host_state.date = TexDate::from_unix_time(default_source_date_epoch());
+        let mut store = Store::new(&tex_engine.engine, host_state);
         // 1100 pages is taken from the tikzjax Javascript code.
         let memory = Memory::new(&mut store, MemoryType::new(1100, Some(1100))?)?;
         memory.write(&mut store, 0, CORE_BYTES)?;
 
-        let imports = TexJaxImports::new(&mut store);
-
-        // Create a linker and define all imports as coming from our rust library.
-        let mut linker = <Linker<HostState>>::new(&engine);
-        linker.define("library", "printInteger", imports.print_integer)?;
-        linker.define("library", "printChar", imports.print_char)?;
-        linker.define("library", "printString", imports.print_string)?;
-        linker.define("library", "printNewline", imports.print_newline)?;
-        linker.define("library", "reset", imports.reset)?;
-        linker.define("library", "inputln", imports.input_ln)?;
-        linker.define("library", "rewrite", imports.rewrite)?;
-        linker.define("library", "get", imports.get)?;
-        linker.define("library", "put", imports.put)?;
-        linker.define("library", "eof", imports.eof)?;
-        linker.define("library", "eoln", imports.eoln)?;
-        linker.define("library", "erstat", imports.erstat)?;
-        linker.define("library", "close", imports.close)?;
-        linker.define("library", "getCurrentMinutes", imports.get_current_minutes)?;
-        linker.define("library", "getCurrentDay", imports.get_current_day)?;
-        linker.define("library", "getCurrentMonth", imports.get_current_month)?;
-        linker.define("library", "getCurrentYear", imports.get_current_year)?;
-        linker.define("library", "tex_final_end", imports.tex_final_end)?;
+        // The system calls are shared, but the memory belongs to this runner's store.
+        let mut linker = tex_engine.linker.clone();
         linker.define("env", "memory", memory)?;
 
         // Execute the exported "main" function.
-        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        let instance = linker
+            .instantiate(&mut store, &tex_engine.module)?
+            .start(&mut store)?;
 
         Ok(Self {
             store,
@@ -95,14 +196,70 @@ impl WasmRunner {
     pub fn set_input(&mut self, input: &[u8]) {
         self.store
             .data_mut()
-            .set_file_contents(FileType::Named("input.tex"), input);
+            .vfs
+            .add_file("input.tex", input.into());
         self.has_run = false;
     }
 
+    /// Set the time that TeX takes as the current time, in seconds since the Unix epoch. This is
+    /// the date `\today` typesets. It defaults to [`default_source_date_epoch`].
+    pub fn set_source_date_epoch(&mut self, seconds: i64) {
+        self.store.data_mut().date = TexDate::from_unix_time(seconds);
+    }
+
+    /// Set what TeX does when it finds an error. It defaults to [`Interaction::ErrorStop`].
+    pub fn set_interaction(&mut self, interaction: Interaction) {
+        self.store
+            .data_mut()
+            .vfs
+            .set_stdin(interaction.terminal_input().as_bytes());
+    }
+
+    /// Add the file `name` for TeX to read, e.g. a package, or the `.tfm` and `.vf` files of the
+    /// fonts it uses. Font files are also used to interpret the DVI file. A file of the same
+    /// name is replaced. Added files are kept for every run, while files that TeX writes are
+    /// forgotten before the next run.
+    pub fn add_file(&mut self, name: &str, contents: impl Into<Arc<[u8]>>) {
+        self.store.data_mut().vfs.add_file(name, contents.into());
+    }
+
+    /// Send diagnostics, e.g. warnings about files TeX could not read or traces of the files it
+    /// opens, to `logger`. The logger is also used when converting the DVI file in
+    /// [`WasmRunner::run`].
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.store.data_mut().vfs.set_logger(logger);
+    }
+
+    /// The logger that diagnostics are sent to.
+    pub fn logger(&self) -> &Logger {
+        self.store.data().vfs.logger()
+    }
+
+    /// The font files in the runner's file system, for interpreting the DVI files it produces
+    /// with [`crate::dvi::Interpreter::set_font_files`].
+    pub fn font_files(&self) -> FontFiles {
+        self.store.data().vfs.font_files()
+    }
+
     /// Run the TeX engine. If all is successful, a string with the output will be returned.
     pub fn run(&mut self) -> Result<String> {
+        let input_dvi = self.run_to_dvi()?;
+        let font_files = self.font_files();
+        let svg = dvi2svg(
+            &input_dvi,
+            SvgOptions::default(),
+            &font_files,
+            self.logger(),
+        )
+        .map_err(|e| Error::msg(format!("Failed to convert DVI to SVG: {e}")))?;
+
+        Ok(svg)
+    }
+
+    /// Run the TeX engine and return the DVI file it produced, without converting it.
+    pub fn run_to_dvi(&mut self) -> Result<Vec<u8>> {
         if !self.has_run {
-            self.has_run = true;
+            self.start();
             // Execute the exported "main" function.
             let main_func = self
                 .instance
@@ -113,14 +270,62 @@ impl WasmRunner {
         let input_dvi = self
             .store
             .data()
+            .vfs
             .get_file_contents(FileType::Named("input.dvi"))
             .ok_or(Error::msg(
                 "Cannot find `input.dvi`. Maybe compilation failed?",
             ))?;
-        let svg = dvi2svg(input_dvi)
-            .map_err(|e| Error::msg(format!("Failed to convert DVI to SVG: {}", e.to_string())))?;
 
-        Ok(svg)
+        Ok(input_dvi.to_vec())
+    }
+
+    /// Prepare for running TeX. The files written by earlier runs, e.g. `input.aux` or the DVI
+    /// file, are forgotten, so that a run only depends on its input and the added files.
+    fn start(&mut self) {
+        self.has_run = true;
+        self.store.data_mut().vfs.reset();
+    }
+
+    /// Start running the TeX engine, pausing after every `slice_len` system calls. Paused runs
+    /// are continued with [`WasmRunner::resume_run`]. Once the run has finished, call
+    /// [`WasmRunner::run`] or [`WasmRunner::run_to_dvi`] to get the output.
+    #[cfg(feature = "async")]
+    pub(crate) fn start_run(&mut self, slice_len: u32) -> Result<RunSlice> {
+        self.start();
+        self.store.data_mut().slice_len = Some(slice_len);
+        let main_func = self
+            .instance
+            .get_typed_func::<(), ()>(&self.store, "main")?;
+        let call = main_func.call_resumable(&mut self.store, ());
+        self.finish_slice(call)
+    }
+
+    /// Continue a run that was started with [`WasmRunner::start_run`].
+    #[cfg(feature = "async")]
+    pub(crate) fn resume_run(&mut self, paused: PausedRun) -> Result<RunSlice> {
+        let inputs: Vec<Val> = paused.result.map(Val::I32).into_iter().collect();
+        let call = paused.invocation.resume(&mut self.store, &inputs);
+        self.finish_slice(call)
+    }
+
+    #[cfg(feature = "async")]
+    fn finish_slice(
+        &mut self,
+        call: Result<TypedResumableCall<()>, wasmi::Error>,
+    ) -> Result<RunSlice> {
+        match call? {
+            TypedResumableCall::Finished(()) => {
+                self.store.data_mut().slice_len = None;
+                Ok(RunSlice::Finished)
+            }
+            TypedResumableCall::Resumable(invocation) => {
+                let result = match invocation.host_error().downcast_ref::<Pause>() {
+                    Some(pause) => pause.result,
+                    None => return Err(Error::msg(invocation.host_error().to_string())),
+                };
+                Ok(RunSlice::Paused(PausedRun { invocation, result }))
+            }
+        }
     }
 
     /// Get the output that TeX wrote to stdout.
@@ -128,7 +333,7 @@ impl WasmRunner {
         if !self.has_run {
             return Err(Error::msg("TeX has not run yet."));
         }
-        let stdout = self.store.data().get_stdout();
+        let stdout = self.store.data().vfs.get_stdout();
         Ok(stdout)
     }
 
@@ -140,38 +345,52 @@ impl WasmRunner {
         let input_log = self
             .store
             .data()
+            .vfs
             .get_file_contents(FileType::Named("input.log"))
             .ok_or(Error::msg(
                 "Cannot find `input.log`. Maybe compilation failed?",
             ))?;
-        let input_log_text = String::from_utf8_lossy(input_log);
+        let input_log_text = String::from_utf8_lossy(&input_log);
         Ok(input_log_text.to_string())
     }
+
+    /// Get the errors that TeX wrote to the log, each with the lines of context TeX gives.
+    pub fn get_errors(&self) -> Result<Vec<String>> {
+        Ok(tex_errors(&self.get_log()?))
+    }
 }
 
-fn extract_tar_gz_to_memory(bytes: &[u8]) -> Result<HashMap<String, Vec<u8>>> {
-    // Create a GzDecoder to decompress the .tar.gz file
-    let gz_decoder = GzDecoder::new(bytes);
-
-    // Create a tar archive from the decompressed data
-    let mut archive = Archive::new(gz_decoder);
-
-    // Extract the tar archive to memory
-    let mut extracted_files = HashMap::new();
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        let mut file_data = Vec::new();
-        entry.read_to_end(&mut file_data)?;
-        let file_name = entry.path()?.to_string_lossy().into_owned();
-        // Trim off a leading "./"
-        let file_name = file_name.trim_start_matches("./");
-        if file_name.is_empty() {
+/// The errors in a TeX log. Every error starts with a line beginning with `! `, followed by
+/// the context TeX shows, which ends with the line in the file where the error happened (e.g.
+/// `l.12 \foo`) and the rest of that line. The help TeX writes after the context is left out.
+fn tex_errors(log: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut lines = log.lines();
+    while let Some(line) = lines.next() {
+        if !line.starts_with("! ") {
             continue;
         }
-        extracted_files.insert(file_name.to_string(), file_data);
+        let mut error = line.to_string();
+        let mut has_context = false;
+        while let Some(line) = lines.next() {
+            // Errors that are not in a file, e.g. in the terminal line, end at an empty line.
+            if (line.is_empty() && has_context) || line.starts_with("? ") {
+                break;
+            }
+            has_context |= line.starts_with('<');
+            error.push('\n');
+            error.push_str(line);
+            if line.starts_with("l.") {
+                if let Some(rest) = lines.next() {
+                    error.push('\n');
+                    error.push_str(rest);
+                }
+                break;
+            }
+        }
+        errors.push(error.trim_end().to_string());
     }
-
-    Ok(extracted_files)
+    errors
 }
 
 /// Convert a TeX string to SVG using the given [`WasmRunner`]. This function can be called
@@ -181,3 +400,163 @@ pub fn tex2svg(wasm_runner: &mut WasmRunner, input_str: &str) -> Result<String>
     let svg = wasm_runner.run()?;
     Ok(svg)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_end_after_the_line_of_the_file() {
+        let log = "\
+(./input.tex
+! Undefined control sequence.
+l.3 \\foo
+         bar
+The control sequence at the end of the top line
+of your error message was never \\def'ed.
+
+)";
+        assert_eq!(
+            tex_errors(log),
+            ["! Undefined control sequence.\nl.3 \\foo\n         bar"]
+        );
+    }
+
+    #[test]
+    fn errors_keep_every_line_of_their_context() {
+        let log = "\
+! Undefined control sequence.
+\\mymacro ->\\bar
+                
+l.5 \\mymacro
+             {}
+? 
+! LaTeX Error: Environment foo undefined.
+
+See the LaTeX manual or LaTeX Companion for explanation.
+Type  H <return>  for immediate help.
+ ...                                              
+                                                  
+l.7 \\begin{foo}
+               
+Your command was ignored.
+";
+        let errors = tex_errors(log);
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert_eq!(
+            errors[0],
+            "! Undefined control sequence.\n\\mymacro ->\\bar\n                \n\
+             l.5 \\mymacro\n             {}"
+        );
+        // The empty line after a LaTeX error comes before the context, so it is kept.
+        assert!(
+            errors[1].starts_with("! LaTeX Error: Environment foo undefined.\n\nSee the LaTeX"),
+            "{}",
+            errors[1]
+        );
+        assert!(errors[1].ends_with("\nl.7 \\begin{foo}"), "{}", errors[1]);
+    }
+
+    #[test]
+    fn errors_outside_of_files_end_at_an_empty_line_or_a_prompt() {
+        let log = "\
+! Emergency stop.
+<*> \\input input.tex 
+                     
+*** (job aborted, no legal \\end found)
+
+! Missing $ inserted.
+<inserted text> 
+                $
+? X
+";
+        assert_eq!(
+            tex_errors(log),
+            [
+                "! Emergency stop.\n<*> \\input input.tex \n                     \n\
+                 *** (job aborted, no legal \\end found)",
+                "! Missing $ inserted.\n<inserted text> \n                $",
+            ]
+        );
+    }
+
+    #[test]
+    fn logs_without_errors_have_no_errors() {
+        let log = "This is TeX\n(./input.tex [1])\nOutput written on input.dvi (1 page).\n";
+        assert!(tex_errors(log).is_empty());
+    }
+
+    #[test]
+    fn tex_reads_the_date_of_the_source_date_epoch() -> Result<()> {
+        let mut runner = WasmRunner::new()?;
+        // 29 February 2024, 23:59:59 UTC.
+        runner.set_source_date_epoch(1_709_251_199);
+
+        // A module that calls the system calls TeX reads the date with, in the runner's store.
+        let module = wat::parse_str(
+            r#"(module
+                (import "library" "getCurrentYear" (func $year (result i32)))
+                (import "library" "getCurrentMonth" (func $month (result i32)))
+                (import "library" "getCurrentDay" (func $day (result i32)))
+                (import "library" "getCurrentMinutes" (func $minutes (result i32)))
+                (func (export "year") (result i32) call $year)
+                (func (export "month") (result i32) call $month)
+                (func (export "day") (result i32) call $day)
+                (func (export "time") (result i32) call $minutes))"#,
+        )?;
+        let store = &mut runner.store;
+        let module = Module::new(store.engine(), &module[..])?;
+        let mut linker = <Linker<HostState>>::new(store.engine());
+        define_imports(&mut linker)?;
+        let instance = linker
+            .instantiate(&mut *store, &module)?
+            .start(&mut *store)?;
+        let mut read = |name: &str| -> Result<i32> {
+            let function = instance.get_typed_func::<(), i32>(&*store, name)?;
+            Ok(function.call(&mut *store, ())?)
+        };
+
+        assert_eq!(
+            [read("year")?, read("month")?, read("day")?, read("time")?],
+            [2024, 2, 29, 1439]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn interactions_are_parsed_from_the_names_of_the_modes() {
+        for interaction in [
+            Interaction::Batch,
+            Interaction::NonStop,
+            Interaction::ScrollStop,
+            Interaction::ErrorStop,
+        ] {
+            let input = interaction.terminal_input();
+            let name = input[1..].split('\\').next().unwrap();
+            assert_eq!(Interaction::parse(name), Some(interaction), "{input}");
+        }
+        assert_eq!(Interaction::parse("nonstop"), None);
+    }
+
+    #[test]
+    fn terminal_input_answers_tex_for_the_interaction() {
+        let first_line =
+            "\\makeatletter\\input pgfsys-rusttikz.def \\makeatother\\input input.tex \n";
+        assert_eq!(
+            Interaction::Batch.terminal_input(),
+            format!("\\batchmode{first_line}")
+        );
+        assert_eq!(
+            Interaction::NonStop.terminal_input(),
+            format!("\\nonstopmode{first_line}")
+        );
+        assert_eq!(
+            Interaction::ScrollStop.terminal_input(),
+            format!("\\scrollmode{first_line}\\end\n")
+        );
+        assert_eq!(
+            Interaction::ErrorStop.terminal_input(),
+            format!("\\errorstopmode{first_line}\\end\nX\n")
+        );
+    }
+}
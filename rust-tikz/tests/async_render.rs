@@ -0,0 +1,49 @@
+//! `Renderer::render_async` has to hand control back to the executor while TeX runs. The
+//! future is polled by hand, so the test does not depend on an async runtime.
+#![cfg(feature = "async")]
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use rust_tikz::{RenderOptions, Renderer};
+
+/// A waker that does nothing, since the future is polled in a loop anyway.
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Poll `future` until it is ready, and return its output and how often it was pending.
+fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    let mut pending = 0;
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return (output, pending),
+            Poll::Pending => pending += 1,
+        }
+    }
+}
+
+#[test]
+fn rendering_yields_to_the_executor() {
+    let renderer = Renderer::new().unwrap();
+    let options = RenderOptions::default();
+    let input = r#"\begin{document}
+\begin{tikzpicture}
+\foreach \i in {1,...,50} { \draw (0,0) circle (\i mm); }
+\end{tikzpicture}
+\end{document}
+"#;
+    let (output, pending) = block_on(renderer.render_async(input, &options));
+    let output = output.unwrap();
+    assert!(pending >= 1, "the render never yielded");
+    // The result is the same as without yielding.
+    let blocking = renderer.render(input, &options).unwrap();
+    assert_eq!(output.data, blocking.data);
+}
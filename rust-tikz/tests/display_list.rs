@@ -0,0 +1,102 @@
+//! The display list of a rendered page, read back from its JSON output.
+#![cfg(feature = "json")]
+
+use rust_tikz::display_list::{DisplayItem, DisplayList, PathSegment};
+use rust_tikz::{RenderFormat, RenderOptions, Renderer};
+
+const DOCUMENT: &str = r#"\begin{document}
+\noindent AB\rule{10pt}{5pt}
+
+\begin{tikzpicture}
+\draw (0,0) -- (1,0);
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn render_display_list(input: &str) -> DisplayList {
+    let options = RenderOptions {
+        format: RenderFormat::Json,
+        ..Default::default()
+    };
+    let output = Renderer::new().unwrap().render(input, &options).unwrap();
+    serde_json::from_slice(&output.data).unwrap()
+}
+
+/// Whether `a` and `b` are equal up to the rounding of TeX's scaled points.
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 0.001
+}
+
+#[test]
+fn display_list_has_the_glyphs_rules_and_paths_of_the_page() {
+    let display_list = render_display_list(DOCUMENT);
+    let glyphs = display_list
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            DisplayItem::Glyph {
+                font,
+                unicode,
+                x,
+                y,
+                ..
+            } => Some((font.as_str(), *unicode, *x, *y)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let [("cmr10", 'A', a_x, a_y), ("cmr10", 'B', b_x, b_y)] = glyphs[..] else {
+        panic!("{glyphs:?}");
+    };
+    // `B` follows `A` on the same baseline, at the width of `A` in cmr10.
+    assert!(close(b_x - a_x, 7.50002), "{glyphs:?}");
+    assert_eq!(a_y, b_y);
+
+    // The rule follows `B` and sits on the baseline.
+    let rules = display_list
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            DisplayItem::Rule {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => Some([*x, *y, *width, *height]),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let [[x, y, width, height]] = rules[..] else {
+        panic!("{rules:?}");
+    };
+    assert!(close(x - b_x, 7.08336), "{rules:?}");
+    assert!(close(y + height, b_y), "{rules:?}");
+    assert!(close(width, 10.0) && close(height, 5.0), "{rules:?}");
+
+    // The line is 1cm long and stroked in black with TikZ's default line width.
+    let paths = display_list
+        .items
+        .iter()
+        .filter(|item| matches!(item, DisplayItem::Path { .. }))
+        .collect::<Vec<_>>();
+    let [
+        DisplayItem::Path {
+            segments,
+            fill,
+            stroke,
+            stroke_width,
+            ..
+        },
+    ] = paths[..]
+    else {
+        panic!("{paths:?}");
+    };
+    assert_eq!((*fill, *stroke), (None, Some([0.0, 0.0, 0.0])));
+    assert!(close(*stroke_width, 0.4), "{paths:?}");
+    let [PathSegment::MoveTo(x1, y1), PathSegment::LineTo(x2, y2)] = segments[..] else {
+        panic!("{segments:?}");
+    };
+    assert!(close(x2 - x1, 28.45276) && close(y2, y1), "{segments:?}");
+    // The picture is below the line of text.
+    assert!(y1 > b_y, "{segments:?}");
+}
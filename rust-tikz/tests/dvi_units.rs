@@ -0,0 +1,205 @@
+//! Positions and sizes reported by the DVI interpreter for scaled fonts and magnified documents.
+//! The DVI files are written by hand. The expected values follow from the DVI format, where
+//! magnification multiplies every length by `\mag / 1000`, and from the width of `a` in cmr10
+//! as TeX reports it. They are worked out by hand rather than taken from the output of dvitype
+//! or dvisvgm.
+
+use rust_tikz::dvi::{Glyph, Interpreter, Machine, Rule};
+
+/// A scaled point, the DVI unit of files written by TeX.
+const SP: i32 = 1;
+const PT: i32 = 65536 * SP;
+
+/// Lengths in points are compared up to a scaled point.
+const TOLERANCE: f64 = 1.0 / 65536.0;
+
+/// The width of `a` in cmr10 at 10pt, as TeX reports it with `\showthe\wd`.
+const A_WIDTH: f64 = 5.00002;
+
+/// Writes the DVI instructions that the tests need.
+struct DviWriter {
+    bytes: Vec<u8>,
+}
+
+impl DviWriter {
+    /// Start a DVI file with scaled points as its unit and the given `\mag`.
+    fn new(magnification: u32) -> DviWriter {
+        let mut dvi = DviWriter {
+            bytes: vec![247, 2],
+        };
+        dvi.u32(25_400_000);
+        dvi.u32(473_628_672);
+        dvi.u32(magnification);
+        dvi.bytes.push(0);
+        dvi
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_be_bytes());
+    }
+
+    /// Define font `number` as `name` loaded at `size`, with a design size of `design_size`.
+    fn font(&mut self, number: u8, name: &str, size: i32, design_size: i32) {
+        self.bytes.extend([243, number]);
+        self.u32(0);
+        self.u32(size as u32);
+        self.u32(design_size as u32);
+        self.bytes.extend([0, name.len() as u8]);
+        self.bytes.extend(name.as_bytes());
+        self.bytes.push(171 + number);
+    }
+
+    fn begin_page(&mut self) {
+        self.bytes.push(139);
+        for _ in 0..10 {
+            self.u32(0);
+        }
+        self.bytes.extend((-1i32).to_be_bytes());
+    }
+
+    fn end_page(&mut self) -> Vec<u8> {
+        self.bytes.push(140);
+        std::mem::take(&mut self.bytes)
+    }
+
+    fn right(&mut self, length: i32) {
+        self.bytes.push(146);
+        self.bytes.extend(length.to_be_bytes());
+    }
+
+    fn down(&mut self, length: i32) {
+        self.bytes.push(160);
+        self.bytes.extend(length.to_be_bytes());
+    }
+
+    /// Set the character `code`, which advances the position by its width.
+    fn set(&mut self, code: u8) {
+        assert!(code < 128);
+        self.bytes.push(code);
+    }
+
+    fn put_rule(&mut self, height: i32, width: i32) {
+        self.bytes.push(137);
+        self.bytes.extend(height.to_be_bytes());
+        self.bytes.extend(width.to_be_bytes());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RecordedGlyph {
+    code: u32,
+    h: f64,
+    v: f64,
+    width: f64,
+    size: f64,
+    design_size: f64,
+}
+
+#[derive(Default)]
+struct Recorder {
+    glyphs: Vec<RecordedGlyph>,
+    rules: Vec<Rule>,
+}
+
+impl Machine for Recorder {
+    fn glyph(&mut self, glyph: &Glyph) {
+        self.glyphs.push(RecordedGlyph {
+            code: glyph.code,
+            h: glyph.h,
+            v: glyph.v,
+            width: glyph.width,
+            size: glyph.font.size,
+            design_size: glyph.font.design_size,
+        });
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        self.rules.push(*rule);
+    }
+}
+
+fn interpret(dvi: &[u8]) -> Recorder {
+    let mut recorder = Recorder::default();
+    Interpreter::new().unwrap().run(dvi, &mut recorder).unwrap();
+    recorder
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() <= TOLERANCE,
+        "expected {expected}pt, got {actual}pt"
+    );
+}
+
+/// `aa` in `cmr10` at `size`, 72pt below and `offset` to the right of the reference point,
+/// followed by a rule of 1pt by 2pt.
+fn two_letters(magnification: u32, size: i32, offset: i32) -> Recorder {
+    let mut dvi = DviWriter::new(magnification);
+    dvi.begin_page();
+    dvi.font(0, "cmr10", size, 10 * PT);
+    dvi.right(offset);
+    dvi.down(72 * PT);
+    dvi.set(b'a');
+    dvi.set(b'a');
+    dvi.put_rule(PT, 2 * PT);
+    interpret(&dvi.end_page())
+}
+
+#[test]
+fn glyphs_advance_by_their_width() {
+    let recorder = two_letters(1000, 10 * PT, 0);
+    let [first, second] = recorder.glyphs.as_slice() else {
+        panic!("expected two glyphs, got {:?}", recorder.glyphs);
+    };
+    assert_eq!(first.code, u32::from(b'a'));
+    assert_close(first.h, 0.0);
+    assert_close(first.v, 72.0);
+    assert_close(first.width, A_WIDTH);
+    assert_close(second.h, first.width);
+    assert_close(first.size, 10.0);
+    assert_close(first.design_size, 10.0);
+}
+
+#[test]
+fn scaled_fonts_have_scaled_widths() {
+    let normal = two_letters(1000, 10 * PT, 0);
+    let scaled = two_letters(1000, 20 * PT, 0);
+    assert_close(scaled.glyphs[0].size, 20.0);
+    assert_close(scaled.glyphs[0].design_size, 10.0);
+    // TeX rounds the scaled width down to a whole scaled point, so the width at 20pt may be a
+    // scaled point less than twice the width at 10pt.
+    assert_close(scaled.glyphs[0].width, 2.0 * normal.glyphs[0].width);
+    assert_close(scaled.glyphs[1].h, scaled.glyphs[0].width);
+    assert_close(scaled.rules[0].h, scaled.glyphs[0].width * 2.0);
+}
+
+#[test]
+fn magnification_scales_positions_and_sizes() {
+    let normal = two_letters(1000, 10 * PT, 10 * PT);
+    let magnified = two_letters(2000, 10 * PT, 10 * PT);
+    assert_close(magnified.glyphs[0].h, 20.0);
+    assert_close(magnified.glyphs[0].v, 144.0);
+    assert_close(magnified.glyphs[0].size, 20.0);
+    // The design size is a property of the font and is not magnified.
+    assert_close(magnified.glyphs[0].design_size, 10.0);
+    assert_close(magnified.glyphs[0].width, 2.0 * A_WIDTH);
+    assert_close(magnified.glyphs[1].h, 2.0 * (10.0 + A_WIDTH));
+    assert_close(magnified.glyphs[0].width, 2.0 * normal.glyphs[0].width);
+    assert_close(
+        magnified.glyphs[1].h - magnified.glyphs[0].h,
+        magnified.glyphs[0].width,
+    );
+
+    let rule = magnified.rules[0];
+    assert_close(rule.width, 4.0);
+    assert_close(rule.height, 2.0);
+    assert_close(rule.h, 2.0 * normal.rules[0].h);
+}
+
+#[test]
+fn magnification_and_scaled_fonts_combine() {
+    let recorder = two_letters(1200, 12 * PT, 0);
+    assert_close(recorder.glyphs[0].size, 14.4);
+    assert_close(recorder.glyphs[0].v, 86.4);
+    assert_close(recorder.glyphs[1].h, recorder.glyphs[0].width);
+}
@@ -0,0 +1,67 @@
+//! EPS output of a rendered TikZ picture.
+
+use rust_tikz::{RenderFormat, RenderOptions, Renderer};
+
+const DOCUMENT: &str = r#"\begin{document}
+\begin{tikzpicture}
+\draw[red, thick] (0,0) rectangle (2,1) node[midway] {$x^2$};
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn render_eps(input: &str) -> String {
+    let options = RenderOptions {
+        format: RenderFormat::Eps,
+        ..Default::default()
+    };
+    Renderer::new()
+        .unwrap()
+        .render(input, &options)
+        .unwrap()
+        .into_string()
+        .unwrap()
+}
+
+/// The value of the DSC comment `name`, e.g. `%%BoundingBox:`.
+fn comment<'a>(eps: &'a str, name: &str) -> &'a str {
+    eps.lines()
+        .find_map(|line| line.strip_prefix(name))
+        .unwrap_or_else(|| panic!("{name} is missing from {eps}"))
+        .trim()
+}
+
+#[test]
+fn eps_has_the_bounding_box_of_the_page() {
+    let eps = render_eps(DOCUMENT);
+    assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n"), "{eps}");
+    assert!(eps.ends_with("showpage\n%%EOF\n"), "{eps}");
+
+    let numbers = |name| {
+        comment(&eps, name)
+            .split(' ')
+            .map(|n| n.parse::<f64>().unwrap())
+            .collect::<Vec<_>>()
+    };
+    let bounding_box = numbers("%%BoundingBox:");
+    let hi_res = numbers("%%HiResBoundingBox:");
+    assert_eq!(bounding_box[..2], [0.0, 0.0]);
+    assert_eq!(hi_res[..2], [0.0, 0.0]);
+    // The integer bounding box encloses the exact one.
+    for i in [2, 3] {
+        assert_eq!(bounding_box[i], hi_res[i].ceil(), "{eps}");
+    }
+    // The picture is 2cm by 1cm.
+    assert!(hi_res[2] > 56.0 && hi_res[3] > 28.0, "{eps}");
+}
+
+#[test]
+fn eps_uses_the_tex_fonts_by_name() {
+    let eps = render_eps(DOCUMENT);
+    // The math italic `x` comes first, then the `2` in the script size of Computer Modern.
+    assert_eq!(comment(&eps, "%%DocumentNeededResources:"), "font CMMI10");
+    assert_eq!(comment(&eps, "%%+"), "font CMR7");
+    assert!(eps.contains("/F0 /CMMI10 findfont def"), "{eps}");
+    assert!(eps.contains("(x) show"), "{eps}");
+    // The rectangle is drawn in red.
+    assert!(eps.contains("1 0 0 setrgbcolor"), "{eps}");
+}
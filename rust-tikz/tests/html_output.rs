@@ -0,0 +1,60 @@
+//! HTML output of a page with text and a TikZ picture.
+
+use rust_tikz::{RenderFormat, RenderOptions, Renderer};
+
+const DOCUMENT: &str = r#"\begin{document}
+Hello
+
+\begin{tikzpicture}
+\clip (0,0) rectangle (1,1);
+\draw (0,0) -- (2,2);
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn render_html(input: &str, id_prefix: &str) -> String {
+    let options = RenderOptions {
+        format: RenderFormat::Html,
+        id_prefix: Some(id_prefix.to_string()),
+        ..Default::default()
+    };
+    Renderer::new()
+        .unwrap()
+        .render(input, &options)
+        .unwrap()
+        .into_string()
+        .unwrap()
+}
+
+#[test]
+fn html_has_positioned_text_and_an_inline_svg_picture() {
+    let html = render_html(DOCUMENT, "doc-");
+    assert!(
+        html.starts_with(r#"<div class="rust-tikz" style="position: relative; width: "#),
+        "{html}"
+    );
+    assert!(html.ends_with("</div>"), "{html}");
+
+    // Each character of the text is a span, positioned from the top left corner of the page.
+    let spans = html
+        .lines()
+        .filter(|line| line.starts_with(r#"<span style="position: absolute; left: "#))
+        .collect::<Vec<_>>();
+    assert_eq!(spans.len(), 5, "{html}");
+    assert!(spans[0].ends_with(">H</span>"), "{html}");
+    assert!(spans[4].ends_with(">o</span>"), "{html}");
+    assert!(
+        spans
+            .iter()
+            .all(|span| span.contains("font-family: cmr10, serif;"))
+    );
+
+    // The picture is an inline SVG, and the ids of its clip path have the prefix.
+    assert_eq!(html.matches("<svg xmlns=").count(), 1, "{html}");
+    assert!(html.contains(r#"<clipPath id="doc-pgf-clip-1">"#), "{html}");
+    assert!(
+        html.contains(r#"clip-path="url(#doc-pgf-clip-1)""#),
+        "{html}"
+    );
+    assert!(!html.contains(r#""pgf-clip-1""#), "{html}");
+}
@@ -0,0 +1,115 @@
+//! PDF output of a rendered TikZ picture, checked by reading the file structure back.
+#![cfg(feature = "pdf")]
+
+use rust_tikz::{RenderFormat, RenderOptions, Renderer};
+
+const DOCUMENT: &str = r#"\begin{document}
+\special{html:<a href="https://example.com">}Link\special{html:</a>}
+
+\begin{tikzpicture}
+\draw[red, thick] (0,0) rectangle (2,1) node[midway] {$x^2$};
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn render_pdf(input: &str) -> Vec<u8> {
+    let options = RenderOptions {
+        format: RenderFormat::Pdf,
+        ..Default::default()
+    };
+    Renderer::new()
+        .unwrap()
+        .render(input, &options)
+        .unwrap()
+        .data
+}
+
+/// The text of `pdf` between `start` and the next occurrence of `end`, both excluded.
+fn between<'a>(pdf: &'a str, start: &str, end: &str) -> &'a str {
+    let (_, rest) = pdf
+        .split_once(start)
+        .unwrap_or_else(|| panic!("{start:?} is missing"));
+    rest.split_once(end).unwrap().0
+}
+
+/// The body of the object `id`, read through the cross-reference table.
+fn object(pdf: &[u8], offsets: &[usize], id: usize) -> String {
+    let header = format!("{id} 0 obj\n");
+    let start = offsets[id];
+    assert!(
+        pdf[start..].starts_with(header.as_bytes()),
+        "the offset of object {id} does not point at it"
+    );
+    let body = String::from_utf8_lossy(&pdf[start + header.len()..]);
+    body.split_once("\nendobj").unwrap().0.to_string()
+}
+
+#[test]
+fn pdf_has_a_page_fonts_and_link_annotations() {
+    let pdf = render_pdf(DOCUMENT);
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.starts_with("%PDF-1.7\n"));
+    assert!(text.ends_with("%%EOF\n"));
+
+    // The trailer points at the cross-reference table, which points at every object.
+    let startxref = between(&text, "startxref\n", "\n")
+        .parse::<usize>()
+        .unwrap();
+    assert!(pdf[startxref..].starts_with(b"xref\n0 "));
+    let size = between(&text, "trailer\n<< /Size ", " ")
+        .parse::<usize>()
+        .unwrap();
+    let root = between(&text, " /Root ", " 0 R >>")
+        .parse::<usize>()
+        .unwrap();
+    let xref = String::from_utf8_lossy(&pdf[startxref..]);
+    let xref = between(&xref, "xref\n", "trailer\n");
+    let entries = xref.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(entries.len(), size);
+    let offsets = entries
+        .iter()
+        .map(|entry| entry[..10].parse::<usize>().unwrap())
+        .collect::<Vec<_>>();
+
+    let catalog = object(&pdf, &offsets, root);
+    assert!(
+        catalog.starts_with("<< /Type /Catalog /Pages "),
+        "{catalog}"
+    );
+    let pages = between(&catalog, "/Pages ", " 0 R").parse().unwrap();
+    let pages = object(&pdf, &offsets, pages);
+    let page = between(&pages, "/Kids [", " 0 R]").parse().unwrap();
+    let page = object(&pdf, &offsets, page);
+
+    // TeX's reference point is one inch from the top left corner of the page.
+    let media_box = between(&page, "/MediaBox [", "]")
+        .split(' ')
+        .map(|n| n.parse::<f64>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(media_box[..2], [0.0, 0.0]);
+    assert!(media_box[2] > 72.0 && media_box[3] > 72.0, "{page}");
+
+    // The text and the math are drawn with two different stand-in fonts.
+    let fonts = between(&page, "/Font << ", ">>");
+    assert_eq!(fonts.matches(" 0 R").count(), 2, "{page}");
+    for font in fonts.split(" 0 R").filter(|f| !f.trim().is_empty()) {
+        let (_, id) = font.trim().split_once(' ').unwrap();
+        let font = object(&pdf, &offsets, id.parse().unwrap());
+        assert!(font.contains("/Subtype /Type0"), "{font}");
+        assert!(font.contains("/ToUnicode"), "{font}");
+    }
+    assert_eq!(text.matches("/FontFile2 ").count(), 2);
+
+    let annotations = between(&page, "/Annots [", "]");
+    let annotations = annotations
+        .split(" 0 R")
+        .filter(|a| !a.trim().is_empty())
+        .collect::<Vec<_>>();
+    assert_eq!(annotations.len(), 1, "{page}");
+    let annotation = object(&pdf, &offsets, annotations[0].trim().parse().unwrap());
+    assert!(annotation.starts_with("<< /Type /Annot /Subtype /Link /Rect ["));
+    assert!(
+        annotation.contains("/URI (https://example.com)"),
+        "{annotation}"
+    );
+}
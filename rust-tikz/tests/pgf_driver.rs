@@ -0,0 +1,44 @@
+//! The rust-tikz pgf driver leaves patterns, fadings, transparency groups, blend modes and
+//! invisible paths to the driver the format was built with. Their SVG markup has to reach the
+//! output as it did before the driver was added.
+
+use rust_tikz::{RenderFormat, RenderOptions, Renderer};
+
+const DOCUMENT: &str = r#"\usetikzlibrary{fadings,patterns}
+\begin{document}
+\begin{tikzpicture}
+\fill[blue, path fading=east] (0,0) rectangle (2,1);
+\fill[pattern=north east lines] (0,1.5) rectangle (2,2.5);
+\draw[red] (0,3) -- (2,3);
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn render_svg(input: &str) -> String {
+    let options = RenderOptions {
+        format: RenderFormat::Svg,
+        id_prefix: Some("test-".to_string()),
+        ..Default::default()
+    };
+    Renderer::new()
+        .unwrap()
+        .render(input, &options)
+        .unwrap()
+        .into_string()
+        .unwrap()
+}
+
+#[test]
+fn fadings_and_patterns_are_passed_through_to_svg() {
+    let svg = render_svg(DOCUMENT);
+    assert!(svg.contains("<mask"), "the fading is missing from {svg}");
+    assert!(
+        svg.contains("<pattern"),
+        "the pattern is missing from {svg}"
+    );
+    // The paths around them are still drawn by the rust-tikz driver.
+    assert!(
+        svg.contains(r##"stroke="#ff0000""##),
+        "the red line is missing from {svg}"
+    );
+}
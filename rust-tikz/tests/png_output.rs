@@ -0,0 +1,58 @@
+//! PNG output of a rendered TikZ picture.
+#![cfg(feature = "png")]
+
+use rust_tikz::{RenderFormat, RenderOptions, Renderer};
+
+const DOCUMENT: &str = r#"\begin{document}
+\begin{tikzpicture}
+\fill[red] (0,0) rectangle (2,1);
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn render_png(dpi: f32, background: Option<[u8; 4]>) -> Vec<u8> {
+    let options = RenderOptions {
+        format: RenderFormat::Png { dpi, background },
+        ..Default::default()
+    };
+    Renderer::new()
+        .unwrap()
+        .render(DOCUMENT, &options)
+        .unwrap()
+        .data
+}
+
+/// The width and height in the `IHDR` chunk, which comes right after the signature.
+fn size(png: &[u8]) -> (u32, u32) {
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert_eq!(&png[12..16], b"IHDR");
+    let number = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+    (number(16), number(20))
+}
+
+#[test]
+fn png_size_follows_the_resolution() {
+    let (width, height) = size(&render_png(96.0, None));
+    // The picture is 2cm by 1cm, which is about 75.6 by 37.8 pixels at 96 dpi.
+    assert!(width >= 75 && height >= 37, "{width}x{height}");
+    assert!(width > height, "{width}x{height}");
+
+    // Doubling the resolution doubles the size, up to rounding.
+    let (double_width, double_height) = size(&render_png(192.0, None));
+    assert!(
+        double_width.abs_diff(2 * width) <= 1,
+        "{double_width} is not 2 x {width}"
+    );
+    assert!(
+        double_height.abs_diff(2 * height) <= 1,
+        "{double_height} is not 2 x {height}"
+    );
+}
+
+#[test]
+fn png_background_does_not_change_the_size() {
+    let transparent = render_png(150.0, None);
+    let white = render_png(150.0, Some([255, 255, 255, 255]));
+    assert_eq!(size(&transparent), size(&white));
+    assert_ne!(transparent, white);
+}
@@ -0,0 +1,77 @@
+//! Rendering the same document must give the same bytes, whichever renderer or runner does it
+//! and whenever it happens.
+
+use rust_tikz::{AltText, RenderOptions, Renderer, SvgAccessibility};
+
+const DOCUMENT: &str = r#"\begin{document}
+Rendered on \today.
+
+\begin{tikzpicture}
+\draw[->, thick, dashed] (0,0) -- (1.234567,0.333333) node[right] {$x^2$};
+\fill[red, opacity=0.3] (0,1) circle (0.5);
+\shade[left color=blue, right color=green] (1,1) rectangle (2,1.5);
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn render(renderer: &Renderer, input: &str, options: &RenderOptions) -> String {
+    renderer
+        .render(input, options)
+        .unwrap()
+        .into_string()
+        .unwrap()
+}
+
+#[test]
+fn svg_is_byte_identical_across_runs() {
+    let options = RenderOptions::default();
+    let first_renderer = Renderer::new().unwrap();
+    let first = render(&first_renderer, DOCUMENT, &options);
+    // The second render reuses the runner of the first, which has already run TeX once.
+    let reused = render(&first_renderer, DOCUMENT, &options);
+    let second_renderer = Renderer::new().unwrap();
+    let fresh = render(&second_renderer, DOCUMENT, &options);
+    assert_eq!(first, reused);
+    assert_eq!(first, fresh);
+}
+
+#[test]
+fn source_date_epoch_pins_the_date() {
+    let options = |source_date_epoch| RenderOptions {
+        source_date_epoch,
+        // The typeset text ends up in the title, where it can be read back.
+        accessibility: Some(SvgAccessibility {
+            alt_text: AltText::Text,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let renderer = Renderer::new().unwrap();
+    let document = "\\begin{document}\\the\\year-\\the\\month-\\the\\day\\end{document}\n";
+    // 29 February 2024, 12:00 UTC.
+    let svg = render(&renderer, document, &options(Some(1_709_208_000)));
+    assert!(svg.contains("<title>2024-2-29</title>"), "{svg}");
+    let svg = render(&renderer, document, &options(Some(0)));
+    assert!(svg.contains("<title>1970-1-1</title>"), "{svg}");
+}
+
+#[test]
+fn documents_do_not_see_the_files_of_earlier_documents() {
+    let options = RenderOptions {
+        accessibility: Some(SvgAccessibility {
+            alt_text: AltText::Text,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    // With a pool of one runner, both documents are rendered by the same runner. The first
+    // writes the label to `input.aux`, which the second must not read.
+    let renderer = Renderer::with_pool_size(1).unwrap();
+    let label = "\\begin{document}\\setcounter{page}{6}\\refstepcounter{page}\\label{seven}Label\\end{document}\n";
+    let reference = "\\begin{document}See \\ref{seven}.\\end{document}\n";
+    render(&renderer, label, &options);
+    let second = render(&renderer, reference, &options);
+    let fresh = render(&Renderer::new().unwrap(), reference, &options);
+    assert!(!second.contains("<title>See 7.</title>"), "{second}");
+    assert_eq!(second, fresh);
+}
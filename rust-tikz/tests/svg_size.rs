@@ -0,0 +1,111 @@
+//! Size regression tests for optimized SVG output.
+
+use std::sync::OnceLock;
+
+use rust_tikz::{RenderFormat, RenderOptions, Renderer, SvgOptimization};
+
+const DOCUMENT: &str = r#"\begin{document}
+Hello world, this is a line of text.
+
+\begin{tikzpicture}
+\draw[->, thick] (0,0) -- (1.234567,0.333333) node[right] {$x^2$};
+\fill[blue!30] (0,1) circle (0.5);
+\end{tikzpicture}
+\end{document}
+"#;
+
+fn renderer() -> &'static Renderer {
+    static RENDERER: OnceLock<Renderer> = OnceLock::new();
+    RENDERER.get_or_init(|| Renderer::new().unwrap())
+}
+
+fn render(input: &str, optimize: Option<SvgOptimization>) -> String {
+    let options = RenderOptions {
+        format: RenderFormat::Svg,
+        id_prefix: Some("test-".to_string()),
+        optimize,
+        ..Default::default()
+    };
+    renderer()
+        .render(input, &options)
+        .unwrap()
+        .into_string()
+        .unwrap()
+}
+
+/// The largest number of decimal places of any number in an attribute of `svg`, except for
+/// the SVG version.
+fn max_decimal_places(svg: &str) -> usize {
+    svg.replace(r#"version="1.1""#, "")
+        .split(r#"=""#)
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .flat_map(|value| value.split(|c: char| !c.is_ascii_digit() && c != '.'))
+        .filter_map(|number| number.split_once('.'))
+        .map(|(_, decimals)| decimals.len())
+        .max()
+        .unwrap_or(0)
+}
+
+#[test]
+fn optimized_svg_is_at_most_half_the_size() {
+    let plain = render(DOCUMENT, None);
+    let optimized = render(DOCUMENT, Some(SvgOptimization::default()));
+    assert!(
+        optimized.len() * 2 <= plain.len(),
+        "optimized SVG is {} bytes, unoptimized SVG is {} bytes",
+        optimized.len(),
+        plain.len()
+    );
+}
+
+#[test]
+fn glyphs_on_one_line_share_a_text_element() {
+    let optimized = render(
+        "\\begin{document}\nHello world\n\\end{document}\n",
+        Some(SvgOptimization::default()),
+    );
+    // The words are separated by a space, so that the text can be searched and copied.
+    assert!(optimized.contains(">Hello world</text>"), "{optimized}");
+}
+
+#[test]
+fn coordinates_are_rounded_to_the_precision() {
+    for precision in [0, 1, 3] {
+        let optimized = render(DOCUMENT, Some(SvgOptimization { precision }));
+        assert!(max_decimal_places(&optimized) <= precision, "{optimized}");
+    }
+}
+
+#[test]
+fn optimized_svg_has_no_layout_whitespace() {
+    let optimized = render(DOCUMENT, Some(SvgOptimization::default()));
+    assert!(!optimized.contains('\n'), "{optimized}");
+    assert!(!optimized.contains("> <"), "{optimized}");
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn optimized_svg_can_be_rasterized() {
+    let optimized = render(DOCUMENT, Some(SvgOptimization::default()));
+    rust_tikz::svg2png(&optimized, 96.0, None).unwrap();
+}
+
+/// Optimized SVG sets the font on a `<g>` around the text runs, which must pick the same bundled
+/// font as a font set on each `<text>`.
+#[cfg(feature = "png")]
+#[test]
+fn optimized_text_keeps_its_font_style_in_png() {
+    let svg = |text: &str| {
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="60" height="20" viewBox="0 0 60 20">{text}</svg>"#
+        )
+    };
+    let plain = svg(r#"<text x="2" y="15" font-family="cmbx10" font-size="12">Bold</text>"#);
+    let optimized =
+        svg(r#"<g font-family="cmbx10" font-size="12"><text x="2" y="15">Bold</text></g>"#);
+    let regular = svg(r#"<text x="2" y="15" font-family="cmr10" font-size="12">Bold</text>"#);
+    let png = |svg: &str| rust_tikz::svg2png(svg, 96.0, None).unwrap();
+    assert_eq!(png(&plain), png(&optimized));
+    assert_ne!(png(&plain), png(&regular));
+}
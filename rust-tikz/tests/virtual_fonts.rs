@@ -0,0 +1,351 @@
+//! Characters of virtual fonts, which the DVI interpreter expands into the glyphs and rules of
+//! their packets. The virtual fonts are written by hand and use `cmr10` and `cmbx10`, whose
+//! metrics are compiled in.
+
+use rust_tikz::FontFiles;
+use rust_tikz::dvi::{Glyph, Interpreter, Machine, Rule};
+
+/// A scaled point, the DVI unit of files written by TeX.
+const SP: i32 = 1;
+const PT: i32 = 65536 * SP;
+
+/// A `fix_word` of one, i.e. the size a virtual font is loaded at.
+const ONE: i32 = 1 << 20;
+
+/// The width of `a` in cmr10 at 10pt.
+const A_WIDTH: f64 = 5.00002;
+
+/// Lengths in points are compared up to a scaled point.
+const TOLERANCE: f64 = 1.0 / 65536.0;
+
+/// Writes the parts of DVI files and virtual fonts that the tests need. Packets of virtual
+/// fonts are written with the same instructions as pages.
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    /// Start a DVI file with scaled points as its unit.
+    fn dvi() -> Writer {
+        let mut dvi = Writer {
+            bytes: vec![247, 2],
+        };
+        dvi.u32(25_400_000);
+        dvi.u32(473_628_672);
+        dvi.u32(1000);
+        dvi.bytes.push(0);
+        dvi
+    }
+
+    /// Start a virtual font with a design size of 10pt.
+    fn virtual_font() -> Writer {
+        let mut vf = Writer {
+            bytes: vec![247, 202, 0],
+        };
+        vf.u32(0);
+        vf.i32(10 * ONE);
+        vf
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_be_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes.extend(value.to_be_bytes());
+    }
+
+    /// Define font `number` as `name` loaded at `size` with a design size of `design_size`. In
+    /// a virtual font, the sizes are `fix_word`s.
+    fn font(&mut self, number: u8, name: &str, size: i32, design_size: i32) {
+        self.bytes.extend([243, number]);
+        self.u32(0);
+        self.i32(size);
+        self.i32(design_size);
+        self.bytes.extend([0, name.len() as u8]);
+        self.bytes.extend(name.as_bytes());
+    }
+
+    /// Add the character `code` of a virtual font, which is `width` wide and drawn by `packet`.
+    fn character(&mut self, code: u8, width: i32, packet: Writer) {
+        self.bytes.extend([packet.bytes.len() as u8, code]);
+        self.bytes.extend(&width.to_be_bytes()[1..]);
+        self.bytes.extend(packet.bytes);
+    }
+
+    fn finish_virtual_font(mut self) -> Vec<u8> {
+        self.bytes.push(248);
+        self.bytes
+    }
+
+    fn begin_page(&mut self) {
+        self.bytes.push(139);
+        for _ in 0..10 {
+            self.u32(0);
+        }
+        self.i32(-1);
+    }
+
+    fn end_page(mut self) -> Vec<u8> {
+        self.bytes.push(140);
+        self.bytes
+    }
+
+    fn select(&mut self, number: u8) -> &mut Self {
+        self.bytes.push(171 + number);
+        self
+    }
+
+    /// Set the character `code`, which advances the position by its width.
+    fn set(&mut self, code: u8) -> &mut Self {
+        assert!(code < 128);
+        self.bytes.push(code);
+        self
+    }
+
+    fn right(&mut self, length: i32) -> &mut Self {
+        self.bytes.push(146);
+        self.i32(length);
+        self
+    }
+
+    fn down(&mut self, length: i32) -> &mut Self {
+        self.bytes.push(160);
+        self.i32(length);
+        self
+    }
+
+    fn push(&mut self) -> &mut Self {
+        self.bytes.push(141);
+        self
+    }
+
+    fn pop(&mut self) -> &mut Self {
+        self.bytes.push(142);
+        self
+    }
+
+    fn put_rule(&mut self, height: i32, width: i32) -> &mut Self {
+        self.bytes.push(137);
+        self.i32(height);
+        self.i32(width);
+        self
+    }
+}
+
+fn packet(write: impl FnOnce(&mut Writer)) -> Writer {
+    let mut packet = Writer::default();
+    write(&mut packet);
+    packet
+}
+
+/// The virtual font `twoa`. Its character `A` is `aa` in cmr10, with a rule of 1.25pt by 2.5pt
+/// below the second `a` that does not move the position, and it is as wide as the font size.
+fn twoa() -> Vec<u8> {
+    let mut vf = Writer::virtual_font();
+    vf.font(0, "cmr10", ONE, 10 * ONE);
+    let packet = packet(|packet| {
+        packet
+            .set(b'a')
+            .push()
+            .down(ONE / 8)
+            .put_rule(ONE / 8, ONE / 4)
+            .pop()
+            .set(b'a');
+    });
+    vf.character(b'A', ONE, packet);
+    vf.finish_virtual_font()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RecordedGlyph {
+    code: u32,
+    font: String,
+    h: f64,
+    v: f64,
+    size: f64,
+}
+
+#[derive(Default)]
+struct Recorder {
+    glyphs: Vec<RecordedGlyph>,
+    rules: Vec<Rule>,
+}
+
+impl Machine for Recorder {
+    fn glyph(&mut self, glyph: &Glyph) {
+        self.glyphs.push(RecordedGlyph {
+            code: glyph.code,
+            font: glyph.font.name.clone(),
+            h: glyph.h,
+            v: glyph.v,
+            size: glyph.font.size,
+        });
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        self.rules.push(*rule);
+    }
+}
+
+fn interpret(dvi: &[u8], virtual_fonts: &[(&str, Vec<u8>)]) -> anyhow::Result<Recorder> {
+    let mut font_files = FontFiles::new();
+    for (name, bytes) in virtual_fonts {
+        font_files.insert(format!("{name}.vf"), bytes.as_slice());
+    }
+    let mut interpreter = Interpreter::new()?;
+    interpreter.set_font_files(font_files);
+    let mut recorder = Recorder::default();
+    interpreter.run(dvi, &mut recorder)?;
+    Ok(recorder)
+}
+
+/// A page that sets `text` in the font `name` loaded at `size`, followed by `a` in cmr10.
+fn page(name: &str, size: i32, text: &[u8]) -> Vec<u8> {
+    let mut dvi = Writer::dvi();
+    dvi.begin_page();
+    dvi.font(0, name, size, 10 * PT);
+    dvi.font(1, "cmr10", 10 * PT, 10 * PT);
+    dvi.select(0);
+    for &code in text {
+        dvi.set(code);
+    }
+    dvi.select(1).set(b'a');
+    dvi.end_page()
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() <= TOLERANCE,
+        "expected {expected}pt, got {actual}pt"
+    );
+}
+
+#[test]
+fn characters_are_expanded_into_their_packets() {
+    let recorder = interpret(&page("twoa", 10 * PT, b"AA"), &[("twoa", twoa())]).unwrap();
+    let positions: Vec<f64> = recorder.glyphs.iter().map(|glyph| glyph.h).collect();
+    // Each `A` sets two `a`s with `set_char` and then advances by its own width, 10pt.
+    let expected = [0.0, A_WIDTH, 10.0, 10.0 + A_WIDTH, 20.0];
+    assert_eq!(positions.len(), expected.len(), "{:?}", recorder.glyphs);
+    for (actual, expected) in positions.into_iter().zip(expected) {
+        assert_close(actual, expected);
+    }
+    for glyph in &recorder.glyphs {
+        assert_eq!(glyph.code, u32::from(b'a'));
+        assert_eq!(glyph.font, "cmr10");
+        assert_close(glyph.v, 0.0);
+        assert_close(glyph.size, 10.0);
+    }
+
+    // The rule is drawn below the second `a`, and `pop` restores the position.
+    assert_eq!(recorder.rules.len(), 2);
+    let rule = recorder.rules[0];
+    assert_close(rule.h, A_WIDTH);
+    assert_close(rule.v, 1.25);
+    assert_close(rule.height, 1.25);
+    assert_close(rule.width, 2.5);
+}
+
+#[test]
+fn packets_are_scaled_with_their_font() {
+    let recorder = interpret(&page("twoa", 20 * PT, b"A"), &[("twoa", twoa())]).unwrap();
+    let [first, second, after] = recorder.glyphs.as_slice() else {
+        panic!("expected three glyphs, got {:?}", recorder.glyphs);
+    };
+    assert_close(first.size, 20.0);
+    assert_close(second.h, 2.0 * A_WIDTH);
+    // The `a` after the virtual character is in cmr10 at 10pt again.
+    assert_close(after.size, 10.0);
+    assert_close(after.h, 20.0);
+    let rule = recorder.rules[0];
+    assert_close(rule.v, 2.5);
+    assert_close(rule.width, 5.0);
+}
+
+#[test]
+fn packets_select_fonts_of_their_own() {
+    // The character `B` sets `a` in cmbx10, which is the second font of the virtual font. The
+    // fonts of a virtual font are numbered independently of the fonts of the DVI file.
+    let mut vf = Writer::virtual_font();
+    vf.font(0, "cmr10", ONE, 10 * ONE);
+    vf.font(1, "cmbx10", ONE, 10 * ONE);
+    vf.character(
+        b'B',
+        ONE,
+        packet(|packet| {
+            packet.select(1).set(b'a');
+        }),
+    );
+    let recorder = interpret(
+        &page("bold", 10 * PT, b"B"),
+        &[("bold", vf.finish_virtual_font())],
+    )
+    .unwrap();
+    let fonts: Vec<&str> = recorder
+        .glyphs
+        .iter()
+        .map(|glyph| glyph.font.as_str())
+        .collect();
+    assert_eq!(fonts, ["cmbx10", "cmr10"]);
+}
+
+#[test]
+fn virtual_fonts_can_be_built_from_virtual_fonts() {
+    // The character `C` of `outer` is the character `A` of `twoa`, moved right by 1.25pt.
+    let mut vf = Writer::virtual_font();
+    vf.font(0, "twoa", ONE, 10 * ONE);
+    vf.character(
+        b'C',
+        2 * ONE,
+        packet(|packet| {
+            packet.right(ONE / 8).set(b'A');
+        }),
+    );
+    let virtual_fonts = [("outer", vf.finish_virtual_font()), ("twoa", twoa())];
+    let recorder = interpret(&page("outer", 10 * PT, b"C"), &virtual_fonts).unwrap();
+    let positions: Vec<f64> = recorder.glyphs.iter().map(|glyph| glyph.h).collect();
+    let expected = [1.25, 1.25 + A_WIDTH, 20.0];
+    assert_eq!(positions.len(), expected.len(), "{:?}", recorder.glyphs);
+    for (actual, expected) in positions.into_iter().zip(expected) {
+        assert_close(actual, expected);
+    }
+}
+
+#[test]
+fn virtual_fonts_that_use_themselves_are_errors() {
+    let mut vf = Writer::virtual_font();
+    vf.font(0, "loop", ONE, 10 * ONE);
+    vf.character(
+        b'A',
+        ONE,
+        packet(|packet| {
+            packet.set(b'A');
+        }),
+    );
+    let result = interpret(
+        &page("loop", 10 * PT, b"A"),
+        &[("loop", vf.finish_virtual_font())],
+    );
+    let error = result
+        .err()
+        .expect("a virtual font that uses itself must fail");
+    assert!(error.to_string().contains("nested too deeply"), "{error}");
+}
+
+#[test]
+fn truncated_virtual_fonts_are_errors() {
+    let bytes = twoa();
+    for length in [1, 12, bytes.len() - 4, bytes.len() - 1] {
+        let result = interpret(
+            &page("twoa", 10 * PT, b"A"),
+            &[("twoa", bytes[..length].to_vec())],
+        );
+        let error = result.err().expect("a truncated virtual font must fail");
+        assert!(
+            error.to_string().contains("Cannot read virtual font twoa"),
+            "{error}"
+        );
+    }
+}
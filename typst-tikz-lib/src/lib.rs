@@ -1,20 +1,115 @@
-use rust_tikz::text2svg_simple;
+use rust_tikz::{
+    AltText, RenderFormat, RenderOptions, Renderer, SvgAccessibility, parse_background,
+};
 #[cfg(target_arch = "wasm32")]
 use wasm_minimal_protocol::*;
 
 #[cfg(target_arch = "wasm32")]
 wasm_minimal_protocol::initiate_protocol!();
 
+/// Render `in_str` as an SVG. The render fails if TeX reports an error.
 #[cfg_attr(target_arch = "wasm32", wasm_func)]
 pub fn render_tex(in_str: &[u8]) -> Result<Vec<u8>, String> {
+    let options = RenderOptions {
+        format: RenderFormat::Svg,
+        ..Default::default()
+    };
+    render(in_str, &options)
+}
+
+/// Render `in_str` as an SVG that is marked as an image for screen readers. `title`,
+/// `description` and `label` are the content of the `<title>` and `<desc>` elements and the
+/// `aria-label`, and are left out if empty. `alt_text` is `source`, `text` or empty, and says
+/// where the title comes from if it is empty.
+///
+/// Like the other `render_tex_*` functions, the render fails if TeX reports an error, unless
+/// `best_effort` is `true`.
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn render_tex_svg(
+    in_str: &[u8],
+    title: &[u8],
+    description: &[u8],
+    label: &[u8],
+    alt_text: &[u8],
+    best_effort: &[u8],
+) -> Result<Vec<u8>, String> {
+    let alt_text = match String::from_utf8_lossy(alt_text).trim() {
+        "" | "none" => AltText::None,
+        "source" => AltText::Source,
+        "text" => AltText::Text,
+        _ => return Err("`alt-text` must be `none`, `\"source\"` or `\"text\"`".to_string()),
+    };
+    let options = RenderOptions {
+        format: RenderFormat::Svg,
+        accessibility: Some(SvgAccessibility {
+            title: non_empty(title),
+            description: non_empty(description),
+            label: non_empty(label),
+            alt_text,
+        }),
+        best_effort: flag(best_effort, "best-effort")?,
+        ..Default::default()
+    };
+    render(in_str, &options)
+}
+
+/// Render `in_str` as a PNG. `dpi` is the resolution as a decimal string and `background` is a
+/// color accepted by [`parse_background`] (e.g., `transparent`, `white` or `#ffffff`).
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn render_tex_png(
+    in_str: &[u8],
+    dpi: &[u8],
+    background: &[u8],
+    best_effort: &[u8],
+) -> Result<Vec<u8>, String> {
+    let dpi = String::from_utf8_lossy(dpi)
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| "`dpi` must be a number".to_string())?;
+    let background = parse_background(&String::from_utf8_lossy(background))?;
+    let options = RenderOptions {
+        format: RenderFormat::Png { dpi, background },
+        best_effort: flag(best_effort, "best-effort")?,
+        ..Default::default()
+    };
+    render(in_str, &options)
+}
+
+/// Render `in_str` as a PDF. Text is drawn with bundled fonts that stand in for TeX's fonts.
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn render_tex_pdf(in_str: &[u8], best_effort: &[u8]) -> Result<Vec<u8>, String> {
+    let options = RenderOptions {
+        format: RenderFormat::Pdf,
+        best_effort: flag(best_effort, "best-effort")?,
+        ..Default::default()
+    };
+    render(in_str, &options)
+}
+
+/// Render `in_str` with `options` and return the output file.
+fn render(in_str: &[u8], options: &RenderOptions) -> Result<Vec<u8>, String> {
     let in_str = String::from_utf8_lossy(in_str);
-    let result = text2svg_simple(&in_str);
-    if result.is_err() {
-        let error = result.unwrap_err();
-        // If there is an error, the stdout, etc. will be put in the error message.
-        // We want to have that make its way back to the caller.
-        return Err(format!("{}", error));
-    }
+    let renderer = Renderer::with_pool_size(0).map_err(|e| format!("{}", e))?;
+    // If there is an error, the stdout, etc. will be put in the error message.
+    let output = renderer
+        .render(&in_str, options)
+        .map_err(|e| format!("{}", e))?;
+
+    Ok(output.data)
+}
+
+/// The argument `bytes` as a string, or `None` if it is empty.
+fn non_empty(bytes: &[u8]) -> Option<String> {
+    let string = String::from_utf8_lossy(bytes);
+    (!string.is_empty()).then(|| string.into_owned())
+}
 
-    Ok(result.unwrap().into_bytes())
+/// The boolean argument `bytes`, which is `true` or `false`. `name` is the name of the argument in
+/// the Typst package, for the error message.
+fn flag(bytes: &[u8], name: &str) -> Result<bool, String> {
+    match String::from_utf8_lossy(bytes).trim() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("`{name}` must be `true` or `false`")),
+    }
 }
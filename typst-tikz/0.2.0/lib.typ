@@ -0,0 +1,235 @@
+#let _wasm = plugin("assets/typst_tikz_lib.wasm")
+
+#let _flag(value) = bytes(if value { "true" } else { "false" })
+
+/// Like `typst-tikz` with SVG output, but returns the raw SVG string.
+#let typst-tikz-svg(
+  input,
+  title: none,
+  description: none,
+  aria-label: none,
+  alt-text: none,
+  best-effort: false,
+) = {
+  if type(input) == content {
+    input = input.text
+  }
+  let optional(value) = bytes(if value == none { "" } else { value })
+  let result = _wasm.render_tex_svg(
+    bytes(input),
+    optional(title),
+    optional(description),
+    optional(aria-label),
+    optional(alt-text),
+    _flag(best-effort),
+  )
+  result
+}
+
+/// Render the string `input` as an SVG. `input` is assumed
+/// to be a valid LaTeX document. That is, it starts with
+/// `\begin{document}` and ends with `\end{document}`.
+///
+/// `tikz` is already loaded. Some other libraries can be loaded
+/// by adding `\usepackage{<package>}` to the start of your string.
+///
+/// `format` is `"svg"`, `"png"` or `"pdf"`; any other format is an error.
+/// For PNG output, `dpi` sets the resolution and `background` is
+/// `"transparent"`, `"white"`, `"black"` or a hex color such as `"#ffffff"`.
+///
+/// SVG output is marked as an image for screen readers. `title` and
+/// `description` become its `<title>` and `<desc>`, and `aria-label` its
+/// `aria-label` (the title by default). If there is no title, `alt-text`
+/// derives one: `"source"` uses the TeX source and `"text"` the text
+/// typeset in the document.
+///
+/// TeX errors stop the render with an error that quotes TeX's log. With
+/// `best-effort: true`, whatever TeX produced despite the errors is shown
+/// instead.
+#let typst-tikz(
+  input,
+  format: "svg",
+  dpi: 300,
+  background: "transparent",
+  title: none,
+  description: none,
+  aria-label: none,
+  alt-text: none,
+  best-effort: false,
+) = {
+  if type(input) == content {
+    input = input.text
+  }
+  let alt = if aria-label != none { aria-label } else { title }
+  if format == "png" {
+    let result = _wasm.render_tex_png(
+      bytes(input),
+      bytes(str(dpi)),
+      bytes(background),
+      _flag(best-effort),
+    )
+    image(result, format: "png", alt: alt)
+  } else if format == "pdf" {
+    let result = _wasm.render_tex_pdf(bytes(input), _flag(best-effort))
+    image(result, format: "pdf", alt: alt)
+  } else if format == "svg" {
+    let result = typst-tikz-svg(
+      input,
+      title: title,
+      description: description,
+      aria-label: aria-label,
+      alt-text: alt-text,
+      best-effort: best-effort,
+    )
+    image(result, alt: alt)
+  } else {
+    panic("unknown format: " + repr(format))
+  }
+}
+
+
+#[
+  = `typst-tikz` Examples
+
+  Regular LaTeX can be used:
+  ````typst
+    #typst-tikz("\\begin{document}Hello World!\\end{document}")
+  ````
+  to produce
+
+  #box(
+    stroke: black,
+    inset: 0.5em,
+    typst-tikz("\\begin{document}Hello World!\\end{document}"),
+  )
+
+  A tikz picture can be placed inside the document:
+  ````typst
+  #typst-tikz(```
+      \begin{document}
+          \begin{tikzpicture}
+            \draw (0,0) circle (1in);
+          \end{tikzpicture}
+      \end{document}
+    ```),
+  ````
+  to produce
+
+  #box(
+    stroke: black,
+    inset: 0.5em,
+    typst-tikz(```
+      \begin{document}
+          \begin{tikzpicture}
+            \draw (0,0) circle (1in);
+          \end{tikzpicture}
+      \end{document}
+    ```),
+  )
+
+  #pagebreak()
+  More complicated TikZ figures may take a while to render.
+
+  ````typst
+  #typst-tikz(```
+  \usepackage{tikz-cd}
+
+  \begin{document}
+    \begin{tikzcd}
+        T
+        \arrow[drr, bend left, "x"]
+        \arrow[ddr, bend right, "y"]
+        \arrow[dr, dotted, "{(x,y)}" description] & & \\
+        K & X \times_Z Y \arrow[r, "p"] \arrow[d, "q"]
+        & X \arrow[d, "f"] \\
+        & Y \arrow[r, "g"]
+        & Z
+    \end{tikzcd}
+
+    \quad \quad
+
+    \begin{tikzcd}[row sep=2.5em]
+      A' \arrow[rr,"f'"] \arrow[dr,swap,"a"] \arrow[dd,swap,"g'"] &&
+        B' \arrow[dd,swap,"h'" near start] \arrow[dr,"b"] \\
+      & A \arrow[rr,crossing over,"f" near start] &&
+        B \arrow[dd,"h"] \\
+      C' \arrow[rr,"k'" near end] \arrow[dr,swap,"c"] && D' \arrow[dr,swap,"d"] \\
+      & C \arrow[rr,"k"] \arrow[uu,<-,crossing over,"g" near end]&& D
+    \end{tikzcd}
+  \end{document}
+    ```),
+  ````
+  to produce
+
+  #box(
+    stroke: black,
+    inset: 0.5em,
+    typst-tikz(```
+    \usepackage{tikz-cd}
+
+    \begin{document}
+      \begin{tikzcd}
+          T
+          \arrow[drr, bend left, "x"]
+          \arrow[ddr, bend right, "y"]
+          \arrow[dr, dotted, "{(x,y)}" description] & & \\
+          K & X \times_Z Y \arrow[r, "p"] \arrow[d, "q"]
+          & X \arrow[d, "f"] \\
+          & Y \arrow[r, "g"]
+          & Z
+      \end{tikzcd}
+
+      \quad \quad
+
+      \begin{tikzcd}[row sep=2.5em]
+        A' \arrow[rr,"f'"] \arrow[dr,swap,"a"] \arrow[dd,swap,"g'"] &&
+          B' \arrow[dd,swap,"h'" near start] \arrow[dr,"b"] \\
+        & A \arrow[rr,crossing over,"f" near start] &&
+          B \arrow[dd,"h"] \\
+        C' \arrow[rr,"k'" near end] \arrow[dr,swap,"c"] && D' \arrow[dr,swap,"d"] \\
+        & C \arrow[rr,"k"] \arrow[uu,<-,crossing over,"g" near end]&& D
+      \end{tikzcd}
+    \end{document}
+    ```),
+  )
+
+
+  #pagebreak()
+
+  ````typst
+  #typst-tikz(```
+    \usepackage{circuitikz}
+    \begin{document}
+      \begin{circuitikz}[american, voltage shift=0.5]
+        \draw (0,0)
+          to[isource, l=$I_0$, v=$V_0$] (0,3)
+          to[short, -*, i=$I_0$] (2,3)
+          to[R=$R_1$, i>_=$i_1$] (2,0) -- (0,0);
+          \draw (2,3) -- (4,3)
+          to[R=$R_2$, i>_=$i_2$]
+          (4,0) to[short, -*] (2,0);
+      \end{circuitikz}
+    \end{document}
+    ```),
+  ````
+  to produce
+
+  #box(
+    stroke: black,
+    inset: 0.5em,
+    typst-tikz(```
+    \usepackage{circuitikz}
+    \begin{document}
+      \begin{circuitikz}[american, voltage shift=0.5]
+        \draw (0,0)
+          to[isource, l=$I_0$, v=$V_0$] (0,3)
+          to[short, -*, i=$I_0$] (2,3)
+          to[R=$R_1$, i>_=$i_1$] (2,0) -- (0,0);
+          \draw (2,3) -- (4,3)
+          to[R=$R_2$, i>_=$i_2$]
+          (4,0) to[short, -*] (2,0);
+      \end{circuitikz}
+    \end{document}
+    ```),
+  )
+]